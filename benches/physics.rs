@@ -0,0 +1,19 @@
+//! `cargo bench` steps the drive model a million times and reports how long
+//! each step takes, so a slower physics step shows up before it shows up as
+//! dropped frames.
+
+use drive_sim::profile;
+
+const STEPS: usize = 1_000_000;
+
+fn main() {
+    // once to warm up the caches, then the run that counts
+    profile::bench_observe(STEPS / 10);
+    let (per_step, x) = profile::bench_observe(STEPS);
+    println!(
+        "ActuatedDDMRModel::observe: {:.1} ns/step over {} steps (ended at x = {:.3} m)",
+        per_step * 1e9,
+        STEPS,
+        x
+    );
+}
@@ -0,0 +1,52 @@
+//! Angles in radians, kept in (-pi, pi] so headings that are stepped for a
+//! long time don't grow into large floats that have lost their precision.
+
+use std::f64::consts::PI;
+
+/// Wraps an angle to (-pi, pi]
+pub fn wrap(a: f64) -> f64 {
+    let a = a % (2. * PI);
+    if a > PI {
+        a - 2. * PI
+    } else if a <= -PI {
+        a + 2. * PI
+    } else {
+        a
+    }
+}
+
+/// `wrap` for the game's `f32` headings
+pub fn wrap_f32(a: f32) -> f32 {
+    wrap(f64::from(a)) as f32
+}
+
+/// The smallest turn from `from` to `to`, in (-pi, pi]. Positive is in
+/// whichever direction the angles increase.
+pub fn difference(from: f64, to: f64) -> f64 {
+    wrap(to - from)
+}
+
+#[test]
+fn angle_wrap() {
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-12;
+    assert!(close(wrap(0.5), 0.5));
+    assert!(close(wrap(2. * PI + 0.5), 0.5));
+    assert!(close(wrap(-2. * PI - 0.5), -0.5));
+    assert!(close(wrap(3.5 * PI), -0.5 * PI));
+    // pi is in the range and -pi isn't
+    assert_eq!(wrap(PI), PI);
+    assert_eq!(wrap(-PI), PI);
+    assert!((f64::from(wrap_f32(7.)) - (7. - 2. * PI)).abs() < 1e-6);
+}
+
+#[test]
+fn angle_difference() {
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-12;
+    assert!(close(difference(0.25, 0.75), 0.5));
+    assert!(close(difference(0.75, 0.25), -0.5));
+    // across the seam the short way round is the small one
+    assert!(close(difference(PI - 0.1, -PI + 0.1), 0.2));
+    assert!(close(difference(-PI + 0.1, PI - 0.1), -0.2));
+    // the same heading a few turns apart
+    assert!(close(difference(1., 1. + 6. * PI), 0.));
+}
@@ -0,0 +1,457 @@
+//! Scripted autonomous driving: a list of waypoints and a simple pursuit
+//! controller that turns toward the next one and drives to it, or a `Routine`
+//! of steps like "drive 2 m" and "turn to 90 degrees", each run by its own
+//! proportional controller on the encoders and heading.
+//!
+//! Routine files are plain text, one step per line, with `#` starting a
+//! comment:
+//!
+//! ```text
+//! drive 2.0    # m on the encoders, negative to back up
+//! turn 90      # degrees, to a heading CCW from where the routine started
+//! wait 0.5     # s
+//! ```
+
+use crate::angle;
+use crate::dynamics::{DDMRModel, Pose, Vels};
+
+use dimensioned::si::*;
+use serde_derive::{Deserialize, Serialize};
+
+use std::f64::consts::PI;
+use std::fmt;
+use std::io::{self, Read};
+
+/// A point on the field, in the same frame as the `Pose` fed to `Auton::update`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Waypoint {
+    pub x: Meter<f64>,
+    pub y: Meter<f64>,
+}
+
+impl Waypoint {
+    pub fn new(x: f64, y: f64) -> Self {
+        Self { x: x * M, y: y * M }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PursuitParams {
+    /// A waypoint counts as reached within this distance
+    pub tolerance: Meter<f64>,
+    pub max_lin: MeterPerSecond<f64>,
+    pub max_ang: Hertz<f64>,
+    /// Angular velocity per radian of heading error
+    pub turn_gain: Hertz<f64>,
+    /// Linear velocity per meter left to the last waypoint, so the robot
+    /// slows down instead of overshooting the end
+    pub approach_gain: Hertz<f64>,
+    /// Heading errors past this turn in place before driving, so a waypoint
+    /// behind the robot gets turned toward rather than orbited
+    pub max_drive_error: f64,
+}
+
+impl Default for PursuitParams {
+    fn default() -> Self {
+        Self {
+            tolerance: 0.1 * M,
+            max_lin: 1.5 * MPS,
+            max_ang: 3. / S,
+            turn_gain: 4. / S,
+            approach_gain: 2. / S,
+            max_drive_error: PI / 3.,
+        }
+    }
+}
+
+fn clamp<T: PartialOrd + std::ops::Neg<Output = T> + Copy>(x: T, max: T) -> T {
+    if x > max {
+        max
+    } else if x < -max {
+        -max
+    } else {
+        x
+    }
+}
+
+/// Drives through the waypoints in order, then stops
+#[derive(Debug, Clone)]
+pub struct Auton {
+    waypoints: Vec<Waypoint>,
+    current: usize,
+    params: PursuitParams,
+}
+
+impl Auton {
+    pub fn new(waypoints: Vec<Waypoint>, params: PursuitParams) -> Self {
+        Self {
+            waypoints,
+            current: 0,
+            params,
+        }
+    }
+
+    pub fn waypoints(&self) -> &[Waypoint] {
+        &self.waypoints
+    }
+
+    /// Index of the waypoint being driven to, `None` once all are reached
+    pub fn current(&self) -> Option<usize> {
+        if self.current < self.waypoints.len() {
+            Some(self.current)
+        } else {
+            None
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.current().is_none()
+    }
+
+    /// Starts over from the first waypoint
+    pub fn restart(&mut self) {
+        self.current = 0;
+    }
+
+    /// Carries on from the waypoint `current()` returned earlier, or finishes
+    /// for `None`
+    pub fn resume(&mut self, current: Option<usize>) {
+        self.current = current.unwrap_or_else(|| self.waypoints.len());
+    }
+
+    pub fn params(&self) -> &PursuitParams {
+        &self.params
+    }
+
+    /// The chassis velocities to drive toward the current waypoint from `pose`,
+    /// skipping past any waypoints already within tolerance. `None` once the
+    /// last one is reached.
+    pub fn update(&mut self, pose: Pose) -> Option<Vels> {
+        let p = &self.params;
+        loop {
+            let target = *self.waypoints.get(self.current)?;
+            let dx = *((target.x - pose.x) / M);
+            let dy = *((target.y - pose.y) / M);
+            let dist = dx.hypot(dy) * M;
+            if dist < p.tolerance {
+                self.current += 1;
+                continue;
+            }
+
+            let error = angle::difference(pose.theta, dy.atan2(dx));
+            let ang = clamp(p.turn_gain * error, p.max_ang);
+            let lin = if error.abs() > p.max_drive_error {
+                0. * MPS
+            } else {
+                let last = self.current + 1 == self.waypoints.len();
+                let max = if last && p.approach_gain * dist < p.max_lin {
+                    p.approach_gain * dist
+                } else {
+                    p.max_lin
+                };
+                max * error.cos()
+            };
+            return Some(Vels { lin, ang });
+        }
+    }
+}
+
+/// One thing a `Routine` does. Each step starts once the one before has
+/// finished and the robot has settled.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Step {
+    /// Drives straight on by this far, measured on the wheel encoders, or
+    /// backs up for a negative distance
+    DriveDistance(Meter<f64>),
+    /// Turns in place to a heading, in rad CCW from the heading the robot
+    /// had when the routine started
+    TurnToHeading(f64),
+    /// Holds still
+    Wait(Second<f64>),
+}
+
+#[derive(Debug)]
+pub enum RoutineError {
+    Io(io::Error),
+    /// `line` is 1-based
+    Parse {
+        line: usize,
+        message: String,
+    },
+    Empty,
+}
+
+impl fmt::Display for RoutineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RoutineError::Io(e) => write!(f, "could not read routine file: {}", e),
+            RoutineError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            RoutineError::Empty => write!(f, "routine has no steps"),
+        }
+    }
+}
+
+impl From<io::Error> for RoutineError {
+    fn from(e: io::Error) -> Self {
+        RoutineError::Io(e)
+    }
+}
+
+/// Steps to run in order
+#[derive(Debug, Clone, PartialEq)]
+pub struct Routine {
+    pub steps: Vec<Step>,
+}
+
+impl Routine {
+    pub fn from_reader<R: Read>(mut r: R) -> Result<Self, RoutineError> {
+        let mut s = String::new();
+        r.read_to_string(&mut s)?;
+        Self::from_text(&s)
+    }
+
+    pub fn from_text(s: &str) -> Result<Self, RoutineError> {
+        let mut steps = Vec::new();
+        for (i, line) in s.lines().enumerate() {
+            let err = |message: String| RoutineError::Parse {
+                line: i + 1,
+                message,
+            };
+            let line = line.split('#').next().unwrap_or("");
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let (name, arg) = match words[..] {
+                [] => continue,
+                [name, arg] => (name, arg),
+                _ => return Err(err(format!("expected a step and a number, not {:?}", line))),
+            };
+            let x: f64 = arg
+                .parse()
+                .map_err(|_| err(format!("{:?} is not a number", arg)))?;
+            if !x.is_finite() {
+                return Err(err(format!("{} is not a finite number", x)));
+            }
+            steps.push(match name {
+                "drive" => Step::DriveDistance(x * M),
+                "turn" => Step::TurnToHeading(x.to_radians()),
+                "wait" if x >= 0. => Step::Wait(x * S),
+                "wait" => return Err(err(format!("can't wait {} s", x))),
+                _ => return Err(err(format!("unknown step {:?}", name))),
+            });
+        }
+        if steps.is_empty() {
+            return Err(RoutineError::Empty);
+        }
+        Ok(Self { steps })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RoutineParams {
+    /// A drive counts as done within this distance of its end
+    pub distance_tolerance: Meter<f64>,
+    /// rad, a turn counts as done within this of its heading
+    pub heading_tolerance: f64,
+    /// A step also waits until the robot is slower than these
+    pub settled_lin: MeterPerSecond<f64>,
+    pub settled_ang: Hertz<f64>,
+    /// Linear velocity per meter left to drive
+    pub drive_gain: Hertz<f64>,
+    /// Angular velocity per radian of heading error, while turning and to
+    /// keep straight while driving
+    pub turn_gain: Hertz<f64>,
+    pub max_lin: MeterPerSecond<f64>,
+    pub max_ang: Hertz<f64>,
+}
+
+impl Default for RoutineParams {
+    fn default() -> Self {
+        Self {
+            distance_tolerance: 0.01 * M,
+            heading_tolerance: 0.01,
+            settled_lin: 0.05 * MPS,
+            settled_ang: 0.1 / S,
+            drive_gain: 2. / S,
+            turn_gain: 4. / S,
+            max_lin: 1.5 * MPS,
+            max_ang: 3. / S,
+        }
+    }
+}
+
+/// How far through a routine a `RoutineRun` is, in SI units, for snapshots
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct RoutineProgress {
+    /// Index of the step being run
+    pub step: usize,
+    /// rad, of the robot when the routine started. `None` before then.
+    pub start_heading: Option<f64>,
+    /// rad from `start_heading`, what drives hold and turns go to
+    pub heading: f64,
+    /// m on the encoders where the current step started, `None` before then
+    pub step_start: Option<f64>,
+    /// s into the current step
+    pub elapsed: f64,
+}
+
+/// Runs a `Routine` against the drive model, one control update at a time
+#[derive(Debug, Clone)]
+pub struct RoutineRun {
+    routine: Routine,
+    params: RoutineParams,
+    progress: RoutineProgress,
+}
+
+/// m, the mean of the two wheels' encoder distances
+fn encoder_distance(robot: &DDMRModel) -> f64 {
+    let d = robot.wheel_distances();
+    *((d.l + d.r) / 2. / M)
+}
+
+impl RoutineRun {
+    pub fn new(routine: Routine, params: RoutineParams) -> Self {
+        Self {
+            routine,
+            params,
+            progress: RoutineProgress::default(),
+        }
+    }
+
+    pub fn routine(&self) -> &Routine {
+        &self.routine
+    }
+
+    pub fn params(&self) -> &RoutineParams {
+        &self.params
+    }
+
+    /// The step being run, `None` once they're all done
+    pub fn current(&self) -> Option<usize> {
+        if self.progress.step < self.routine.steps.len() {
+            Some(self.progress.step)
+        } else {
+            None
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.current().is_none()
+    }
+
+    /// Starts over from the first step, with headings from wherever the robot
+    /// is then
+    pub fn restart(&mut self) {
+        self.progress = RoutineProgress::default();
+    }
+
+    pub fn progress(&self) -> RoutineProgress {
+        self.progress
+    }
+
+    /// Carries on from what `progress()` returned earlier
+    pub fn resume(&mut self, progress: RoutineProgress) {
+        self.progress = progress;
+    }
+
+    /// The chassis velocities for the current step, `dt` after the last
+    /// update, moving on to the next step once it's done. `None` once the
+    /// last step is. `heading` is what the robot measures its own to be, in
+    /// rad CCW.
+    pub fn update(&mut self, robot: &DDMRModel, heading: f64, dt: Second<f64>) -> Option<Vels> {
+        let p = &self.params;
+        let distance = encoder_distance(robot);
+        let start_heading = *self.progress.start_heading.get_or_insert(heading);
+        let vel = robot.vel();
+        let settled = vel.lin.abs() < p.settled_lin && vel.ang.abs() < p.settled_ang;
+        loop {
+            let step = *self.routine.steps.get(self.progress.step)?;
+            let step_start = *self.progress.step_start.get_or_insert(distance);
+            if let Step::TurnToHeading(heading) = step {
+                self.progress.heading = heading;
+            }
+            let heading_error = angle::difference(heading, start_heading + self.progress.heading);
+            let hold = clamp(p.turn_gain * heading_error, p.max_ang);
+            let (vels, done) = match step {
+                Step::DriveDistance(target) => {
+                    let left = target - (distance - step_start) * M;
+                    let vels = Vels {
+                        lin: clamp(p.drive_gain * left, p.max_lin),
+                        ang: hold,
+                    };
+                    (vels, left.abs() < p.distance_tolerance && settled)
+                }
+                Step::TurnToHeading(_) => {
+                    let vels = Vels {
+                        lin: 0. * MPS,
+                        ang: hold,
+                    };
+                    (vels, heading_error.abs() < p.heading_tolerance && settled)
+                }
+                Step::Wait(time) => {
+                    self.progress.elapsed += *(dt / S);
+                    let vels = Vels {
+                        lin: 0. * MPS,
+                        ang: 0. / S,
+                    };
+                    (vels, self.progress.elapsed * S >= time)
+                }
+            };
+            if !done {
+                return Some(vels);
+            }
+            self.progress.step += 1;
+            self.progress.step_start = None;
+            self.progress.elapsed = 0.;
+        }
+    }
+}
+
+#[test]
+fn auton_turns_toward_waypoint_behind() {
+    let mut a = Auton::new(vec![Waypoint::new(-2., 0.)], PursuitParams::default());
+    // facing +x, so the waypoint is straight behind
+    let v = a.update(Pose::default()).unwrap();
+    assert_eq!(v.lin, 0. * MPS);
+    assert!(v.ang.value_unsafe.abs() > 0.);
+}
+
+#[test]
+fn auton_finishes() {
+    let mut a = Auton::new(
+        vec![Waypoint::new(0.05, 0.), Waypoint::new(1., 0.)],
+        PursuitParams::default(),
+    );
+    // the first waypoint is already within tolerance
+    let v = a.update(Pose::default()).unwrap();
+    assert_eq!(a.current(), Some(1));
+    assert!(v.lin > 0. * MPS);
+    let at_end = Pose {
+        x: 0.99 * M,
+        ..Pose::default()
+    };
+    assert!(a.update(at_end).is_none());
+    assert!(a.finished());
+}
+
+#[test]
+fn routine_parses_steps() {
+    let r = Routine::from_text("# comment\n\ndrive 1.5\n  turn -90  # right\nwait 0.5\n").unwrap();
+    assert_eq!(r.steps.len(), 3);
+    assert_eq!(r.steps[0], Step::DriveDistance(1.5 * M));
+    match r.steps[1] {
+        Step::TurnToHeading(h) => assert!((h + PI / 2.).abs() < 1e-12),
+        s => panic!("{:?}", s),
+    }
+    assert_eq!(r.steps[2], Step::Wait(0.5 * S));
+    match Routine::from_text("drive 1\nspin 3\n") {
+        Err(RoutineError::Parse { line: 2, .. }) => (),
+        r => panic!("{:?}", r),
+    }
+    match Routine::from_text("wait -1\n") {
+        Err(RoutineError::Parse { line: 1, .. }) => (),
+        r => panic!("{:?}", r),
+    }
+    match Routine::from_text("# nothing\n") {
+        Err(RoutineError::Empty) => (),
+        r => panic!("{:?}", r),
+    }
+}
@@ -0,0 +1,308 @@
+//! A scripted step test for tuning by feel: full voltage from rest until the
+//! speed settles, then zero volts until the robot stops. `StepBenchmark` is
+//! stepped once per control update, hands back the command for the next one,
+//! and measures how quickly the robot gets up to speed and how far it rolls.
+
+use crate::config::BenchmarkConfig;
+use crate::dynamics::{Vels, LR};
+
+use dimensioned::si::*;
+
+/// m/s^2, below which the speed counts as settled
+const SETTLED_ACCEL: f64 = 0.01;
+
+/// m/s, below which the robot counts as stopped
+const STOPPED_SPEED: f64 = 0.01;
+
+/// s, the most either half of the test runs, for a robot that never settles
+/// or never stops
+const MAX_PHASE: f64 = 15.;
+
+/// 1 - 1/e, the fraction of the way to top speed one time constant gets
+const TIME_CONSTANT_FRACTION: f64 = 1. - 1. / std::f64::consts::E;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BenchmarkPhase {
+    /// Full voltage, waiting for the speed to settle
+    Accelerating,
+    /// Zero volts, waiting for the robot to stop
+    Coasting,
+    Done,
+}
+
+/// What a finished benchmark measured
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BenchmarkResult {
+    /// m/s, where the speed settled
+    pub top_speed: f64,
+    /// s from rest to 63% of top speed, the time constant for a first order
+    /// drivetrain
+    pub rise_63: f64,
+    /// s from rest to 95% of top speed
+    pub rise_95: f64,
+    /// A, the most either motor drew getting up to speed
+    pub peak_current: f64,
+    /// m rolled after the voltage was cut
+    pub coast_distance: f64,
+    /// s from cutting the voltage to stopped
+    pub coast_time: f64,
+}
+
+/// One measurement against its target
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BenchmarkRow {
+    pub name: &'static str,
+    pub unit: &'static str,
+    pub value: f64,
+    /// The most that passes, if there is one
+    pub target: Option<f64>,
+}
+
+impl BenchmarkRow {
+    /// `None` without a target
+    pub fn passed(&self) -> Option<bool> {
+        self.target.map(|target| self.value <= target)
+    }
+
+    pub fn line(&self) -> String {
+        let measured = format!("{}: {:.3} {}", self.name, self.value, self.unit);
+        match (self.target, self.passed()) {
+            (Some(target), Some(passed)) => format!(
+                "{} (target {:.3}, {})",
+                measured,
+                target,
+                if passed { "pass" } else { "FAIL" }
+            ),
+            _ => measured,
+        }
+    }
+}
+
+impl BenchmarkResult {
+    /// Each measurement, with its target from `targets`
+    pub fn rows(&self, targets: &BenchmarkConfig) -> Vec<BenchmarkRow> {
+        let row = |name, unit, value, target| BenchmarkRow {
+            name,
+            unit,
+            value,
+            target,
+        };
+        vec![
+            row("top speed", "m/s", self.top_speed, None),
+            row("rise to 63%", "s", self.rise_63, targets.rise_63),
+            row("rise to 95%", "s", self.rise_95, targets.rise_95),
+            row("peak current", "A", self.peak_current, targets.peak_current),
+            row(
+                "coast distance",
+                "m",
+                self.coast_distance,
+                targets.coast_distance,
+            ),
+            row("coast time", "s", self.coast_time, targets.coast_time),
+        ]
+    }
+}
+
+/// When `samples` first reach `level`, interpolated between samples
+fn crossing(samples: &[(f64, f64)], level: f64) -> f64 {
+    let mut before = (0., 0.);
+    for &(time, speed) in samples {
+        if speed >= level {
+            let (t0, v0) = before;
+            return if speed > v0 {
+                t0 + (time - t0) * (level - v0) / (speed - v0)
+            } else {
+                time
+            };
+        }
+        before = (time, speed);
+    }
+    before.0
+}
+
+/// The step test, from the robot at rest
+#[derive(Debug, Clone)]
+pub struct StepBenchmark {
+    voltage: Volt<f64>,
+    /// s between updates
+    dt: f64,
+    phase: BenchmarkPhase,
+    /// s since the phase started
+    time: f64,
+    /// Time and speed at each update while accelerating
+    rise: Vec<(f64, f64)>,
+    last_speed: f64,
+    peak_current: f64,
+    top_speed: f64,
+    coast_distance: f64,
+    result: Option<BenchmarkResult>,
+}
+
+impl StepBenchmark {
+    /// Drives with `voltage` on both sides, updated every `dt`
+    pub fn new(voltage: Volt<f64>, dt: Second<f64>) -> Self {
+        Self {
+            voltage,
+            dt: *(dt / S),
+            phase: BenchmarkPhase::Accelerating,
+            time: 0.,
+            rise: Vec::new(),
+            last_speed: 0.,
+            peak_current: 0.,
+            top_speed: 0.,
+            coast_distance: 0.,
+            result: None,
+        }
+    }
+
+    pub fn phase(&self) -> BenchmarkPhase {
+        self.phase
+    }
+
+    pub fn finished(&self) -> bool {
+        self.phase == BenchmarkPhase::Done
+    }
+
+    /// `None` until finished
+    pub fn result(&self) -> Option<&BenchmarkResult> {
+        self.result.as_ref()
+    }
+
+    /// Takes the robot's velocities and currents since the last command, and
+    /// returns the next command. Zero volts once finished.
+    pub fn update(&mut self, vels: Vels, currents: LR<Ampere<f64>>) -> LR<Volt<f64>> {
+        let speed = *(vels.lin / MPS);
+        let zero = LR {
+            l: 0. * V,
+            r: 0. * V,
+        };
+        match self.phase {
+            BenchmarkPhase::Accelerating => {
+                let amps = (*(currents.l / A)).abs().max((*(currents.r / A)).abs());
+                self.peak_current = self.peak_current.max(amps);
+                self.rise.push((self.time, speed));
+                let accel = (speed - self.last_speed) / self.dt;
+                let settled = speed > STOPPED_SPEED && accel.abs() < SETTLED_ACCEL;
+                self.last_speed = speed;
+                if settled || self.time >= MAX_PHASE {
+                    self.top_speed = speed;
+                    self.phase = BenchmarkPhase::Coasting;
+                    self.time = 0.;
+                    return zero;
+                }
+                self.time += self.dt;
+                LR {
+                    l: self.voltage,
+                    r: self.voltage,
+                }
+            }
+            BenchmarkPhase::Coasting => {
+                // by the trapezoid rule over the last update
+                self.coast_distance += (self.last_speed + speed) / 2. * self.dt;
+                self.last_speed = speed;
+                self.time += self.dt;
+                if speed.abs() < STOPPED_SPEED || self.time >= MAX_PHASE {
+                    self.finish();
+                }
+                zero
+            }
+            BenchmarkPhase::Done => zero,
+        }
+    }
+
+    fn finish(&mut self) {
+        self.result = Some(BenchmarkResult {
+            top_speed: self.top_speed,
+            rise_63: crossing(&self.rise, TIME_CONSTANT_FRACTION * self.top_speed),
+            rise_95: crossing(&self.rise, 0.95 * self.top_speed),
+            peak_current: self.peak_current,
+            coast_distance: self.coast_distance,
+            coast_time: self.time,
+        });
+        self.phase = BenchmarkPhase::Done;
+    }
+}
+
+#[cfg(test)]
+fn run_benchmark(model: &mut crate::dynamics::ActuatedDDMRModel) -> BenchmarkResult {
+    let mut bench = StepBenchmark::new(12. * V, model.ddmr().dt());
+    loop {
+        let volts = bench.update(model.vel(), model.currents());
+        if bench.finished() {
+            return *bench.result().unwrap();
+        }
+        model.observe(volts);
+    }
+}
+
+#[test]
+fn benchmark_matches_time_constant() {
+    use crate::config::RobotConfig;
+    let config = RobotConfig {
+        wheel_friction: None,
+        ..RobotConfig::default()
+    };
+    let mut model = config.build(0.005 * S);
+    let result = run_benchmark(&mut model);
+    let tau = *(model.time_constant() / S);
+    let top = *(model.steady_speed(12. * V) / MPS);
+    assert!((result.top_speed - top).abs() < 0.01 * top, "{:?}", result);
+    assert!(
+        (result.rise_63 - tau).abs() < 0.02 * tau,
+        "{:?} {}",
+        result,
+        tau
+    );
+    // a first order system takes ln 20 time constants to get to 95%
+    let rise_95 = 20f64.ln() * tau;
+    assert!(
+        (result.rise_95 - rise_95).abs() < 0.02 * rise_95,
+        "{:?}",
+        result
+    );
+    // stalled, on the first step
+    let stall = 12. / config.motors().l.resistance;
+    assert!(
+        (result.peak_current - stall).abs() < 1e-6 * stall,
+        "{:?}",
+        result
+    );
+    // braking back down through the same time constant, a little shorter for
+    // the rolling resistance
+    let glide = result.top_speed * tau;
+    assert!(
+        result.coast_distance < glide && result.coast_distance > 0.9 * glide,
+        "{:?}",
+        result
+    );
+    assert!(result.coast_time > 3. * tau);
+}
+
+#[test]
+fn benchmark_rows_against_targets() {
+    let result = BenchmarkResult {
+        top_speed: 4.,
+        rise_63: 0.5,
+        rise_95: 1.5,
+        peak_current: 130.,
+        coast_distance: 2.,
+        coast_time: 3.,
+    };
+    let targets = BenchmarkConfig {
+        rise_63: Some(0.6),
+        peak_current: Some(100.),
+        ..BenchmarkConfig::default()
+    };
+    let rows = result.rows(&targets);
+    let passed: Vec<_> = rows.iter().map(BenchmarkRow::passed).collect();
+    assert_eq!(
+        passed,
+        vec![None, Some(true), None, Some(false), None, None]
+    );
+    assert_eq!(rows[1].line(), "rise to 63%: 0.500 s (target 0.600, pass)");
+    assert_eq!(
+        rows[3].line(),
+        "peak current: 130.000 A (target 100.000, FAIL)"
+    );
+    assert_eq!(rows[0].line(), "top speed: 4.000 m/s");
+}
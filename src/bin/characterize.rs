@@ -0,0 +1,81 @@
+//! Characterizes the simulated drivetrain without opening a window.
+//!
+//! `cargo run --bin characterize [robot.toml] [output dir]` runs a
+//! quasi-static ramp and a step test on the robot from the config file,
+//! prints the fitted feedforward constants next to the ones the model should
+//! give, and writes the raw samples to `quasistatic.csv` and `step.csv`.
+
+use drive_sim::characterize::{self, FeedforwardGains, Sample};
+use drive_sim::config::RobotConfig;
+
+use dimensioned::si::S;
+
+use std::env;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// V/s, slow enough that acceleration barely shows up
+const RAMP_RATE: f64 = 0.25;
+const RAMP_MAX: f64 = 7.;
+const STEP_VOLTAGE: f64 = 7.;
+/// s
+const STEP_DURATION: f64 = 3.;
+
+fn load_config(path: &Path) -> RobotConfig {
+    match RobotConfig::load(path) {
+        Ok(config) => {
+            println!("Loaded robot from {}", path.display());
+            config
+        }
+        Err(e) => {
+            println!("{}: {}, using the built-in robot", path.display(), e);
+            RobotConfig::default()
+        }
+    }
+}
+
+fn write_samples(dir: &Path, name: &str, samples: &[Sample]) {
+    let path = dir.join(name);
+    let result = File::create(&path).and_then(|f| characterize::write_csv(f, samples));
+    match result {
+        Ok(()) => println!("Wrote {} samples to {}", samples.len(), path.display()),
+        Err(e) => println!("Could not write {}: {}", path.display(), e),
+    }
+}
+
+fn print_gains(label: &str, g: &FeedforwardGains) {
+    println!(
+        "{:>9}: kS = {:.4} V, kV = {:.4} V/(m/s), kA = {:.4} V/(m/s^2)",
+        label, g.ks, g.kv, g.ka
+    );
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let config_path = args
+        .get(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("resources/robot.toml"));
+    let out_dir = args
+        .get(2)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("."));
+
+    let config = load_config(&config_path);
+    let mut model = config.build(config.timing.physics_dt * S);
+
+    let ramp = characterize::quasistatic(&mut model, RAMP_RATE, RAMP_MAX);
+    let step = characterize::step(&mut model, STEP_VOLTAGE, STEP_DURATION);
+    write_samples(&out_dir, "quasistatic.csv", &ramp);
+    write_samples(&out_dir, "step.csv", &step);
+
+    let all: Vec<Sample> = ramp.iter().chain(step.iter()).cloned().collect();
+    match characterize::fit(&all) {
+        Some(fitted) => print_gains("fitted", &fitted),
+        None => println!("Not enough motion in the data to fit the constants"),
+    }
+    print_gains("expected", &characterize::expected_gains(&config));
+    if config.wheel_friction.is_some() {
+        println!("(expected assumes the wheels never slip)");
+    }
+}
@@ -0,0 +1,202 @@
+//! Mapping world coordinates onto the screen. The world has y pointing up and
+//! is measured in the sim's own units, `Simulation::px_per_meter` to the
+//! meter; the screen has y pointing down with the origin at the top-left.
+
+use ggez::graphics::Point2;
+
+#[derive(Debug, Clone)]
+pub struct Camera {
+    /// World point shown at the middle of the screen
+    pub center: Point2,
+    /// Screen pixels per meter
+    pub zoom: f32,
+    /// World units per meter
+    pub world_scale: f32,
+    /// Screen size in pixels
+    pub screen: (f32, f32),
+    /// How far `follow` moves toward the target each call, from 0 (never
+    /// moves) to 1 (snaps to it)
+    pub smoothing: f32,
+}
+
+const MIN_ZOOM: f32 = 5.0;
+const MAX_ZOOM: f32 = 1000.0;
+/// Screen pixels between grid lines, at the least
+const MIN_GRID_PX: f32 = 40.0;
+
+/// Moves `p` from world coordinates onto a screen of `screen` pixels, with
+/// `center` in the middle and `scale` screen pixels per world unit
+pub fn world_to_screen(p: Point2, center: Point2, scale: f32, screen: (f32, f32)) -> Point2 {
+    Point2::new(
+        (p.x - center.x) * scale + screen.0 / 2.0,
+        screen.1 / 2.0 - (p.y - center.y) * scale,
+    )
+}
+
+/// The inverse of `world_to_screen`
+pub fn screen_to_world(p: Point2, center: Point2, scale: f32, screen: (f32, f32)) -> Point2 {
+    Point2::new(
+        (p.x - screen.0 / 2.0) / scale + center.x,
+        (screen.1 / 2.0 - p.y) / scale + center.y,
+    )
+}
+
+impl Camera {
+    /// Centered on the world origin at one screen pixel per world unit, which
+    /// is the old fixed view
+    pub fn new(world_scale: f32, screen: (f32, f32)) -> Self {
+        Self {
+            center: Point2::origin(),
+            zoom: world_scale,
+            world_scale,
+            screen,
+            smoothing: 1.0,
+        }
+    }
+
+    /// Screen pixels per world unit
+    pub fn scale(&self) -> f32 {
+        self.zoom / self.world_scale
+    }
+
+    /// What to scale a sprite by so that, drawn `sprite_radius` of its own
+    /// pixels out from its middle, it covers `world_radius` world units
+    pub fn sprite_scale(&self, world_radius: f32, sprite_radius: f32) -> f32 {
+        world_radius * self.scale() / sprite_radius
+    }
+
+    pub fn to_screen(&self, p: Point2) -> Point2 {
+        world_to_screen(p, self.center, self.scale(), self.screen)
+    }
+
+    pub fn to_world(&self, p: Point2) -> Point2 {
+        screen_to_world(p, self.center, self.scale(), self.screen)
+    }
+
+    /// Eases the center toward `target`
+    pub fn follow(&mut self, target: Point2) {
+        self.center += (target - self.center) * self.smoothing;
+    }
+
+    /// Multiplies the zoom by `factor`, keeping the center where it is
+    pub fn zoom_by(&mut self, factor: f32) {
+        self.zoom = (self.zoom * factor).max(MIN_ZOOM).min(MAX_ZOOM);
+    }
+
+    /// Meters between grid lines: a meter, or as many more as it takes to keep
+    /// the lines `MIN_GRID_PX` apart, in steps of 1, 2 and 5 times a power of
+    /// ten so the grid still reads as a scale
+    pub fn grid_spacing(&self) -> f32 {
+        let zoom = self.zoom.max(MIN_ZOOM);
+        let mut decade = 1.0;
+        loop {
+            for &step in &[1.0, 2.0, 5.0] {
+                if step * decade * zoom >= MIN_GRID_PX {
+                    return step * decade;
+                }
+            }
+            decade *= 10.0;
+        }
+    }
+
+    /// Screen-space lines, each a start and end point, spaced `grid_spacing`
+    /// apart in the world and covering the whole screen
+    pub fn grid_lines(&self) -> Vec<[Point2; 2]> {
+        let (w, h) = self.screen;
+        let top_left = self.to_world(Point2::new(0.0, 0.0));
+        let bottom_right = self.to_world(Point2::new(w, h));
+        let spacing = self.grid_spacing() * self.world_scale;
+        let index = |x: f32| x / spacing;
+        let mut lines = Vec::new();
+        let first = index(top_left.x).ceil() as i64;
+        let last = index(bottom_right.x).floor() as i64;
+        for i in first..=last {
+            let x = self.to_screen(Point2::new(i as f32 * spacing, 0.0)).x;
+            lines.push([Point2::new(x, 0.0), Point2::new(x, h)]);
+        }
+        let first = index(bottom_right.y).ceil() as i64;
+        let last = index(top_left.y).floor() as i64;
+        for i in first..=last {
+            let y = self.to_screen(Point2::new(0.0, i as f32 * spacing)).y;
+            lines.push([Point2::new(0.0, y), Point2::new(w, y)]);
+        }
+        lines
+    }
+}
+
+#[test]
+fn camera_transform() {
+    let close = |a: Point2, b: Point2| (a - b).norm() < 1e-4;
+    let screen = (640.0, 480.0);
+    // the origin lands mid-screen, and up in the world is up on screen
+    let p = world_to_screen(Point2::new(10.0, 20.0), Point2::origin(), 1.0, screen);
+    assert!(close(p, Point2::new(330.0, 220.0)));
+    // doubling the scale doubles the distance from the middle
+    let p = world_to_screen(Point2::new(10.0, 20.0), Point2::origin(), 2.0, screen);
+    assert!(close(p, Point2::new(340.0, 200.0)));
+    // the center is what ends up in the middle
+    let c = Point2::new(-50.0, 30.0);
+    assert!(close(
+        world_to_screen(c, c, 3.0, screen),
+        Point2::new(320.0, 240.0)
+    ));
+
+    let mut cam = Camera::new(100.0, screen);
+    cam.center = c;
+    cam.zoom_by(2.0);
+    assert_eq!(cam.scale(), 2.0);
+    let p = Point2::new(123.0, -45.0);
+    assert!(close(cam.to_world(cam.to_screen(p)), p));
+}
+
+#[test]
+fn camera_sprite_scale() {
+    let mut cam = Camera::new(100.0, (640.0, 480.0));
+    // a 32 px sprite for something 0.12 m across its middle, 24 px on screen
+    assert_eq!(cam.sprite_scale(12.0, 16.0), 0.75);
+    cam.zoom_by(2.0);
+    assert_eq!(cam.sprite_scale(12.0, 16.0), 1.5);
+    cam.zoom = 25.0;
+    assert_eq!(cam.sprite_scale(12.0, 16.0), 0.1875);
+    // a sprite drawn at world size only follows the zoom
+    assert_eq!(cam.sprite_scale(30.0, 30.0), cam.scale());
+}
+
+#[test]
+fn camera_follow_and_grid() {
+    let mut cam = Camera::new(100.0, (640.0, 480.0));
+    cam.smoothing = 0.5;
+    cam.follow(Point2::new(100.0, 0.0));
+    assert_eq!(cam.center, Point2::new(50.0, 0.0));
+    cam.follow(Point2::new(100.0, 0.0));
+    assert_eq!(cam.center, Point2::new(75.0, 0.0));
+
+    // 6.4 m by 4.8 m on screen, centered on the origin
+    let cam = Camera::new(100.0, (640.0, 480.0));
+    let lines = cam.grid_lines();
+    let vertical = lines.iter().filter(|l| l[0].x == l[1].x).count();
+    assert_eq!(vertical, 7);
+    assert_eq!(lines.len() - vertical, 5);
+    assert!(lines.iter().any(|l| l[0] == Point2::new(320.0, 0.0)));
+}
+
+#[test]
+fn camera_grid_spreads_out() {
+    let mut cam = Camera::new(100.0, (640.0, 480.0));
+    assert_eq!(cam.grid_spacing(), 1.0);
+    // zoomed right in it stays at a meter
+    cam.zoom_by(8.0);
+    assert_eq!(cam.grid_spacing(), 1.0);
+    cam.zoom = 30.0;
+    assert_eq!(cam.grid_spacing(), 2.0);
+    cam.zoom = 5.0;
+    assert_eq!(cam.grid_spacing(), 10.0);
+    // 128 m by 96 m on screen, so lines every 10 m from -60 to 60 and -40 to 40
+    let lines = cam.grid_lines();
+    let vertical = lines.iter().filter(|l| l[0].x == l[1].x).count();
+    assert_eq!(vertical, 13);
+    assert_eq!(lines.len() - vertical, 9);
+    for pair in lines[..vertical].windows(2) {
+        assert!((pair[1][0].x - pair[0][0].x - 50.0).abs() < 1e-3);
+    }
+}
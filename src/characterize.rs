@@ -0,0 +1,198 @@
+//! Drivetrain characterization the way it's done on a real robot: drive
+//! straight through a slow voltage ramp and a voltage step, then fit the
+//! feedforward model `voltage = kS sign(v) + kV v + kA a` to what was recorded.
+
+use crate::config::RobotConfig;
+use crate::dynamics::{ActuatedDDMRModel, GRAVITY, LR};
+
+use dimensioned::si::*;
+
+use std::io::{self, Write};
+
+/// One step of a test, with velocities in m/s along the chassis
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Sample {
+    /// s since the start of the test
+    pub time: f64,
+    /// V, the average of the two sides
+    pub voltage: f64,
+    /// m/s, at the start of the step
+    pub velocity: f64,
+    /// m/s^2, over the step
+    pub acceleration: f64,
+}
+
+/// Feedforward constants for driving straight
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FeedforwardGains {
+    /// V, to overcome friction
+    pub ks: f64,
+    /// V per m/s
+    pub kv: f64,
+    /// V per m/s^2
+    pub ka: f64,
+}
+
+/// Samples slower than this are left out of the fit, since friction at a
+/// standstill doesn't follow the model
+pub const MIN_VELOCITY: f64 = 0.01;
+
+/// Puts `voltage(time)` on both sides from rest for `duration` seconds
+fn run<F: Fn(f64) -> f64>(model: &mut ActuatedDDMRModel, duration: f64, voltage: F) -> Vec<Sample> {
+    model.reset();
+    let dt = *(model.ddmr().dt() / S);
+    let steps = (duration / dt).round() as usize;
+    (0..steps)
+        .map(|k| {
+            let time = k as f64 * dt;
+            let before = *(model.vel().lin / MPS);
+            let v = voltage(time) * V;
+            let after = *(model.observe(LR { l: v, r: v }).lin / MPS);
+            let applied = model.last_observation().voltage;
+            Sample {
+                time,
+                voltage: *((applied.l + applied.r) / V) / 2.,
+                velocity: before,
+                acceleration: (after - before) / dt,
+            }
+        })
+        .collect()
+}
+
+/// Ramps the voltage up from zero at `rate` volts per second until it reaches
+/// `max`, slowly enough that the robot is always close to its steady speed
+pub fn quasistatic(model: &mut ActuatedDDMRModel, rate: f64, max: f64) -> Vec<Sample> {
+    run(model, max / rate, |t| rate * t)
+}
+
+/// Holds `voltage` from rest for `duration` seconds, recording the transient
+pub fn step(model: &mut ActuatedDDMRModel, voltage: f64, duration: f64) -> Vec<Sample> {
+    run(model, duration, |_| voltage)
+}
+
+/// Solves the 3x3 normal equations `a x = b` by Cramer's rule, `None` if
+/// they're singular
+fn solve3(a: [[f64; 3]; 3], b: [f64; 3]) -> Option<[f64; 3]> {
+    let det = |m: [[f64; 3]; 3]| {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    };
+    let d = det(a);
+    // relative to the diagonal, which bounds the determinant of the normal
+    // equations from above
+    if d.abs() <= 1e-9 * (a[0][0] * a[1][1] * a[2][2]).abs() {
+        return None;
+    }
+    let mut x = [0.; 3];
+    for (col, x) in x.iter_mut().enumerate() {
+        let mut m = a;
+        for (row, b) in m.iter_mut().zip(b.iter()) {
+            row[col] = *b;
+        }
+        *x = det(m) / d;
+    }
+    Some(x)
+}
+
+/// Least squares fit of the feedforward model to the samples faster than
+/// `MIN_VELOCITY`. `None` when they don't pin down all three constants, e.g.
+/// without any acceleration in the data.
+pub fn fit(samples: &[Sample]) -> Option<FeedforwardGains> {
+    // normal equations over the regressors [sign(v), v, a]
+    let mut ata = [[0.; 3]; 3];
+    let mut atb = [0.; 3];
+    for s in samples.iter().filter(|s| s.velocity.abs() > MIN_VELOCITY) {
+        let row = [s.velocity.signum(), s.velocity, s.acceleration];
+        for ((ata, atb), ri) in ata.iter_mut().zip(atb.iter_mut()).zip(row.iter()) {
+            for (a, rj) in ata.iter_mut().zip(row.iter()) {
+                *a += ri * rj;
+            }
+            *atb += ri * s.voltage;
+        }
+    }
+    let [ks, kv, ka] = solve3(ata, atb)?;
+    Some(FeedforwardGains { ks, kv, ka })
+}
+
+/// The constants the drive model should give when driving straight without a
+/// battery or traction limits, worked out from its equations. Both sides are
+/// taken to have the left motor.
+pub fn expected_gains(config: &RobotConfig) -> FeedforwardGains {
+    let c = &config.chassis;
+    let m = config.motors().l;
+    let r = c.wheel_radius;
+    let mass = c.mass + 2. * c.wheel_inertia / r / r;
+    // volts per newton meter of wheel torque
+    let volts_per_torque = m.resistance / (m.torque_constant * m.gear_ratio * config.efficiency);
+    // the rolling resistance torque `ActuatedDDMRModel::observe` applies to each wheel
+    let rolling = config.rolling_resistance * c.mass * GRAVITY * c.wheel_inertia
+        / r
+        / (c.mass - c.chassis_mass)
+        * 2.;
+    FeedforwardGains {
+        ks: volts_per_torque * rolling,
+        kv: m.back_emf * m.gear_ratio / r,
+        ka: volts_per_torque * mass * r / 2.,
+    }
+}
+
+/// Writes the samples as CSV with a header row
+pub fn write_csv<W: Write>(mut out: W, samples: &[Sample]) -> io::Result<()> {
+    writeln!(out, "time,voltage,velocity,acceleration")?;
+    for s in samples {
+        writeln!(
+            out,
+            "{},{},{},{}",
+            s.time, s.voltage, s.velocity, s.acceleration
+        )?;
+    }
+    Ok(())
+}
+
+#[test]
+fn fit_exact_data() {
+    let gains = FeedforwardGains {
+        ks: 0.5,
+        kv: 2.,
+        ka: 0.3,
+    };
+    let samples: Vec<Sample> = (0..200)
+        .map(|k| {
+            let velocity = (k as f64 * 0.1).sin() * 3.;
+            let acceleration = (k as f64 * 0.37).cos();
+            Sample {
+                time: k as f64,
+                voltage: gains.ks * velocity.signum()
+                    + gains.kv * velocity
+                    + gains.ka * acceleration,
+                velocity,
+                acceleration,
+            }
+        })
+        .collect();
+    let fitted = fit(&samples).unwrap();
+    assert!((fitted.ks - gains.ks).abs() < 1e-9);
+    assert!((fitted.kv - gains.kv).abs() < 1e-9);
+    assert!((fitted.ka - gains.ka).abs() < 1e-9);
+    // a steady speed can't tell kV from kS or find kA
+    let steady = vec![samples[5]; 10];
+    assert!(fit(&steady).is_none());
+}
+
+#[test]
+fn characterize_recovers_model() {
+    let config = RobotConfig {
+        wheel_friction: None,
+        ..RobotConfig::default()
+    };
+    let mut model = config.build(0.005 * S);
+    let mut samples = quasistatic(&mut model, 0.25, 6.);
+    samples.extend(step(&mut model, 7., 2.));
+    let fitted = fit(&samples).unwrap();
+    let expected = expected_gains(&config);
+    let close = |a: f64, b: f64| (a - b).abs() < 0.03 * b.abs();
+    assert!(close(fitted.ks, expected.ks), "{:?} {:?}", fitted, expected);
+    assert!(close(fitted.kv, expected.kv), "{:?} {:?}", fitted, expected);
+    assert!(close(fitted.ka, expected.ka), "{:?} {:?}", fitted, expected);
+}
@@ -0,0 +1,118 @@
+//! Geometry and colors for drawing the robot as a chassis with two wheels
+//! rather than a sprite. Everything here is in pixels in the world frame; the
+//! frontend only has to map the points to the screen.
+
+use ggez::graphics::{Color, Point2};
+
+/// Dimensions of the drawn robot, in pixels
+#[derive(Debug, Copy, Clone)]
+pub struct ChassisShape {
+    /// Distance from the center to each wheel, `DDMRParams::L`
+    pub half_track: f32,
+    pub length: f32,
+    pub wheel_radius: f32,
+    pub wheel_width: f32,
+}
+
+/// Outlines in the robot's own frame: x to the right, y forward, the origin
+/// at the center of the wheel axis
+#[derive(Debug, Clone)]
+pub struct ChassisOutline {
+    pub body: Vec<Point2>,
+    pub left_wheel: Vec<Point2>,
+    pub right_wheel: Vec<Point2>,
+}
+
+fn rect(cx: f32, cy: f32, half_w: f32, half_h: f32) -> Vec<Point2> {
+    vec![
+        Point2::new(cx - half_w, cy - half_h),
+        Point2::new(cx + half_w, cy - half_h),
+        Point2::new(cx + half_w, cy + half_h),
+        Point2::new(cx - half_w, cy + half_h),
+    ]
+}
+
+impl ChassisShape {
+    pub fn outline(&self) -> ChassisOutline {
+        // the body sits between the wheels
+        let body_half_w = self.half_track - self.wheel_width / 2.0;
+        ChassisOutline {
+            body: rect(0.0, 0.0, body_half_w, self.length / 2.0),
+            left_wheel: rect(
+                -self.half_track,
+                0.0,
+                self.wheel_width / 2.0,
+                self.wheel_radius,
+            ),
+            right_wheel: rect(
+                self.half_track,
+                0.0,
+                self.wheel_width / 2.0,
+                self.wheel_radius,
+            ),
+        }
+    }
+}
+
+/// Moves points from the robot's frame to the world, with the same convention
+/// as `Actor::facing`: clockwise from up
+pub fn to_world(points: &[Point2], pos: Point2, facing: f32) -> Vec<Point2> {
+    let (sin, cos) = facing.sin_cos();
+    points
+        .iter()
+        .map(|p| Point2::new(pos.x + p.x * cos + p.y * sin, pos.y - p.x * sin + p.y * cos))
+        .collect()
+}
+
+/// Grey at rest, shading to green driving forward and red in reverse, fully
+/// saturated at `max_speed` and beyond
+pub fn wheel_color(speed: f64, max_speed: f64) -> Color {
+    const REST: f32 = 0.3;
+    let t = if max_speed > 0.0 {
+        (speed.abs() / max_speed).min(1.0) as f32
+    } else {
+        0.0
+    };
+    let dim = REST * (1.0 - t);
+    let bright = REST + (1.0 - REST) * t;
+    if speed >= 0.0 {
+        Color::new(dim, bright, dim, 1.0)
+    } else {
+        Color::new(bright, dim, dim, 1.0)
+    }
+}
+
+#[test]
+fn wheel_color_sign_and_clamp() {
+    let fwd = wheel_color(50., 100.);
+    assert!(fwd.g > fwd.r && fwd.g > fwd.b);
+    let rev = wheel_color(-50., 100.);
+    assert!(rev.r > rev.g && rev.r > rev.b);
+    assert_eq!(rev.r, fwd.g);
+
+    let rest = wheel_color(0., 100.);
+    assert_eq!((rest.r, rest.g, rest.b), (0.3, 0.3, 0.3));
+
+    let close = |a: f32, b: f32| (a - b).abs() < 1e-6;
+    let full = wheel_color(100., 100.);
+    assert!(close(full.r, 0.) && close(full.g, 1.) && close(full.b, 0.));
+    let over = wheel_color(-1000., 100.);
+    assert!(close(over.r, 1.) && close(over.g, 0.) && close(over.b, 0.));
+}
+
+#[test]
+fn chassis_to_world() {
+    let forward = [Point2::new(0., 1.)];
+    let p = to_world(&forward, Point2::new(10., 20.), std::f32::consts::PI / 2.);
+    // a quarter turn clockwise points forward along +x
+    assert!((p[0].x - 11.).abs() < 1e-6 && (p[0].y - 20.).abs() < 1e-6);
+    let shape = ChassisShape {
+        half_track: 30.,
+        length: 80.,
+        wheel_radius: 8.,
+        wheel_width: 5.,
+    };
+    let o = shape.outline();
+    assert!(o.left_wheel.iter().all(|p| p.x < 0.));
+    assert!(o.right_wheel.iter().all(|p| p.x > 0.));
+}
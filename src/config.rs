@@ -0,0 +1,1307 @@
+//! Robot parameters loaded from a TOML file, so trying a different robot
+//! doesn't need a recompile. Everything in the file is a plain number in SI
+//! units, converted into the dimensioned types on load.
+
+use crate::disturbances::{Bump, Carpet, Disturbances, Shove};
+use crate::dynamics::{
+    ActuatedDDMRModel, Battery, DCMotorParams, DDMRParams, FrictionParams, ThermalParams,
+    TractionParams, LR, ZERO_CELSIUS,
+};
+use crate::gamepad::{StickShape, DEADZONE};
+use crate::sensors::{ChannelNoise, Noise};
+
+use dimensioned::si::*;
+use serde_derive::{Deserialize, Serialize};
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    /// Missing fields, non-numeric values and the like. The message names the field.
+    Parse(toml::de::Error),
+    /// Values that parse but can't be simulated
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "could not read robot config: {}", e),
+            ConfigError::Parse(e) => write!(f, "invalid robot config: {}", e),
+            ConfigError::Invalid(e) => write!(f, "invalid robot config: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(e: toml::de::Error) -> Self {
+        ConfigError::Parse(e)
+    }
+}
+
+/// Mirrors `DDMRParams`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChassisConfig {
+    /// m
+    pub wheel_radius: f64,
+    /// kg, including wheels and actuators
+    pub mass: f64,
+    /// kg, without wheels and actuators
+    pub chassis_mass: f64,
+    /// m, distance of the center of mass in front of the wheels
+    pub cg_offset: f64,
+    /// m, half the wheel base
+    pub half_wheelbase: f64,
+    /// kg m^2, about the center of rotation
+    pub moment_of_inertia: f64,
+    /// kg m^2, of each wheel about its axle
+    pub wheel_inertia: f64,
+    /// m, front to back. Only used for drawing.
+    #[serde(default = "default_length")]
+    pub length: f64,
+}
+
+fn default_length() -> f64 {
+    0.8
+}
+
+fn default_max_voltage() -> f64 {
+    12.
+}
+
+/// Mirrors `DCMotorParams`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MotorConfig {
+    /// Ohm
+    pub resistance: f64,
+    /// H
+    pub inductance: f64,
+    /// rotor turns per wheel turn
+    pub gear_ratio: f64,
+    /// V s
+    pub back_emf: f64,
+    /// N m / A
+    pub torque_constant: f64,
+    /// Fraction of the motor's torque its gearbox passes on, on top of the
+    /// drivetrain's `efficiency`
+    #[serde(default = "default_gear_efficiency")]
+    pub gear_efficiency: f64,
+    /// kg m^2, of the rotor about its own axis
+    #[serde(default)]
+    pub rotor_inertia: f64,
+}
+
+fn default_gear_efficiency() -> f64 {
+    1.
+}
+
+impl MotorConfig {
+    pub fn params(&self) -> DCMotorParams {
+        DCMotorParams {
+            Ra: self.resistance * OHM,
+            La: self.inductance * H,
+            N: self.gear_ratio,
+            Kb: self.back_emf * V * S,
+            Kt: self.torque_constant * N * M / A,
+            eta: self.gear_efficiency,
+            Jr: self.rotor_inertia * KG * M * M,
+        }
+    }
+}
+
+/// A battery that sags under load, in place of an ideal voltage source
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatteryConfig {
+    /// V, open circuit
+    pub nominal: f64,
+    /// Ohm, internal resistance including the wiring
+    pub resistance: f64,
+}
+
+/// How each motor's windings heat up and cool down, the same for every motor
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ThermalConfig {
+    /// J/K, of the windings
+    pub heat_capacity: f64,
+    /// K/W, between the windings and the air
+    pub resistance: f64,
+    /// C, where the windings start and cool towards
+    #[serde(default = "default_ambient")]
+    pub ambient: f64,
+    /// C, past which the game warns that a motor is overheating
+    #[serde(default = "default_warning_temperature")]
+    pub warning: f64,
+    /// C, past which the motor controller stops driving the motor until it
+    /// cools. Leave out to never cut out.
+    #[serde(default)]
+    pub cutoff: Option<f64>,
+    /// C, how far below `cutoff` the motor has to cool to drive again
+    #[serde(default = "default_cutoff_hysteresis")]
+    pub hysteresis: f64,
+}
+
+fn default_ambient() -> f64 {
+    25.
+}
+
+fn default_warning_temperature() -> f64 {
+    100.
+}
+
+fn default_cutoff_hysteresis() -> f64 {
+    10.
+}
+
+impl ThermalConfig {
+    pub fn params(&self) -> ThermalParams {
+        ThermalParams {
+            heat_capacity: self.heat_capacity * J / K,
+            resistance: self.resistance * K / W,
+            ambient: (self.ambient + ZERO_CELSIUS) * K,
+            cutoff: self.cutoff.map(|c| (c + ZERO_CELSIUS) * K),
+            hysteresis: self.hysteresis * K,
+        }
+    }
+
+    pub fn warning_temperature(&self) -> Kelvin<f64> {
+        (self.warning + ZERO_CELSIUS) * K
+    }
+}
+
+/// How finely the drive model is stepped and how often its commands are
+/// recomputed, independent of the frame rate
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimingConfig {
+    /// s, timestep of the drive model
+    pub physics_dt: f64,
+    /// s, between updates of the motor commands from the input, auton or
+    /// velocity loop. Each command is held until the next update.
+    pub control_period: f64,
+    /// s, how late the wheel speeds reach the velocity loop, a whole number
+    /// of control periods
+    #[serde(default)]
+    pub sensor_delay: f64,
+    /// s, how late each motor command reaches the motors, a whole number of
+    /// physics steps
+    #[serde(default)]
+    pub command_delay: f64,
+}
+
+impl Default for TimingConfig {
+    /// One physics step and one control update per frame
+    fn default() -> Self {
+        Self {
+            physics_dt: 1. / 60.,
+            control_period: 1. / 60.,
+            sensor_delay: 0.,
+            command_delay: 0.,
+        }
+    }
+}
+
+/// The path drawn behind the robot. Only used for drawing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrailConfig {
+    /// Points kept, zero for no trail
+    pub length: usize,
+    /// Simulation steps per point
+    pub stride: usize,
+}
+
+impl Default for TrailConfig {
+    /// 100 seconds of path at the sim rate
+    fn default() -> Self {
+        Self {
+            length: 2000,
+            stride: 3,
+        }
+    }
+}
+
+/// How the controller sticks are read. Only used for driving by gamepad.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GamepadConfig {
+    /// Fraction of stick travel around center that reads as zero
+    pub deadband: f64,
+    /// Power the rest of the stick travel is raised to. 1 is linear, above 1
+    /// gives finer control at low speed.
+    pub expo: f64,
+}
+
+impl Default for GamepadConfig {
+    fn default() -> Self {
+        Self {
+            deadband: DEADZONE,
+            expo: 1.,
+        }
+    }
+}
+
+impl GamepadConfig {
+    pub fn shape(&self) -> StickShape {
+        StickShape {
+            deadzone: self.deadband,
+            expo: self.expo,
+        }
+    }
+}
+
+/// Targets for the step benchmark, each the most that passes. Leave one out
+/// to only measure it. Only used for the game's benchmark.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct BenchmarkConfig {
+    /// s, from rest to 63% of top speed
+    #[serde(default)]
+    pub rise_63: Option<f64>,
+    /// s, from rest to 95% of top speed
+    #[serde(default)]
+    pub rise_95: Option<f64>,
+    /// A, in either motor
+    #[serde(default)]
+    pub peak_current: Option<f64>,
+    /// m, rolled after the voltage is cut
+    #[serde(default)]
+    pub coast_distance: Option<f64>,
+    /// s, from cutting the voltage to stopped
+    #[serde(default)]
+    pub coast_time: Option<f64>,
+}
+
+/// Noise on what the control loops measure, mirrors `Noise`. Each bias is a
+/// constant offset on top of the noise, like a drifting gyro's. The default
+/// is noiseless.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct SensorConfig {
+    /// rad/s, standard deviation on each wheel speed
+    #[serde(default)]
+    pub wheel_sigma: f64,
+    /// rad/s
+    #[serde(default)]
+    pub wheel_bias: f64,
+    /// rad/s, standard deviation on the gyro
+    #[serde(default)]
+    pub gyro_sigma: f64,
+    /// rad/s
+    #[serde(default)]
+    pub gyro_bias: f64,
+    /// rad, standard deviation on the heading
+    #[serde(default)]
+    pub heading_sigma: f64,
+    /// rad
+    #[serde(default)]
+    pub heading_bias: f64,
+    /// Runs with the same seed see the same noise
+    #[serde(default)]
+    pub seed: u64,
+}
+
+impl SensorConfig {
+    pub fn noise(&self) -> Noise {
+        Noise {
+            wheel: ChannelNoise {
+                sigma: self.wheel_sigma,
+                bias: self.wheel_bias,
+            },
+            gyro: ChannelNoise {
+                sigma: self.gyro_sigma,
+                bias: self.gyro_bias,
+            },
+            heading: ChannelNoise {
+                sigma: self.heading_sigma,
+                bias: self.heading_bias,
+            },
+            seed: self.seed,
+            ..Noise::default()
+        }
+    }
+}
+
+/// Outside forces on the player's robot, mirrors `Disturbances`. Carpet and
+/// bumps are off unless given a force, and a shove only happens on demand.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DisturbanceConfig {
+    /// N against the direction of travel
+    #[serde(default)]
+    pub carpet: f64,
+    /// N along the heading, backward for a negative one, for the first half
+    /// of every `bump_period`
+    #[serde(default)]
+    pub bump: f64,
+    /// s
+    #[serde(default = "default_bump_period")]
+    pub bump_period: f64,
+    /// N along the heading
+    #[serde(default)]
+    pub shove_force: f64,
+    /// N m, counterclockwise positive
+    #[serde(default = "default_shove_torque")]
+    pub shove_torque: f64,
+    /// s
+    #[serde(default = "default_shove_duration")]
+    pub shove_duration: f64,
+}
+
+fn default_bump_period() -> f64 {
+    1.
+}
+
+/// A sharp twist, like being clipped by another robot
+fn default_shove_torque() -> f64 {
+    40.
+}
+
+fn default_shove_duration() -> f64 {
+    0.2
+}
+
+impl Default for DisturbanceConfig {
+    fn default() -> Self {
+        Self {
+            carpet: 0.,
+            bump: 0.,
+            bump_period: default_bump_period(),
+            shove_force: 0.,
+            shove_torque: default_shove_torque(),
+            shove_duration: default_shove_duration(),
+        }
+    }
+}
+
+impl DisturbanceConfig {
+    pub fn disturbances(&self) -> Disturbances {
+        let carpet = if self.carpet > 0. {
+            Some(Carpet {
+                force: self.carpet * N,
+            })
+        } else {
+            None
+        };
+        let bump = if self.bump != 0. {
+            Some(Bump {
+                force: self.bump * N,
+                period: self.bump_period * S,
+            })
+        } else {
+            None
+        };
+        Disturbances::new(
+            carpet,
+            bump,
+            Shove {
+                force: self.shove_force * N,
+                torque: self.shove_torque * N * M,
+                duration: self.shove_duration * S,
+            },
+        )
+    }
+}
+
+/// A walled field of a fixed size, in place of one the size of the window
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArenaConfig {
+    /// m
+    pub width: f64,
+    /// m
+    pub height: f64,
+    /// Fraction of its speed the robot bounces off a wall with. Zero stops it
+    /// dead.
+    #[serde(default)]
+    pub restitution: f64,
+}
+
+/// An obstacle on the field, either round with a `radius` or a rectangle
+/// lined up with the field with a `width` and `height`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObstacleConfig {
+    /// m, from the center of the field
+    pub x: f64,
+    /// m, from the center of the field, up positive
+    pub y: f64,
+    /// m
+    #[serde(default)]
+    pub radius: Option<f64>,
+    /// m, across
+    #[serde(default)]
+    pub width: Option<f64>,
+    /// m, up and down
+    #[serde(default)]
+    pub height: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RobotConfig {
+    /// Fraction of motor torque that reaches the wheels
+    pub efficiency: f64,
+    /// Coefficient of rolling resistance
+    pub rolling_resistance: f64,
+    /// N m s, friction torque in each side of the drivetrain per rad/s of
+    /// wheel speed
+    #[serde(default)]
+    pub viscous_friction: f64,
+    /// N m, friction torque in each side of the drivetrain while moving, and
+    /// what it takes to get a wheel moving from standstill
+    #[serde(default)]
+    pub coulomb_friction: f64,
+    /// Coefficient of friction between the wheels and the floor. Without it
+    /// the wheels never slip.
+    #[serde(default)]
+    pub wheel_friction: Option<f64>,
+    /// Motors mounted turning backwards, which the motor controller inverts
+    /// again so positive still drives forward
+    #[serde(default)]
+    pub invert_left: bool,
+    #[serde(default)]
+    pub invert_right: bool,
+    /// V/s, the most the motor controllers let the applied voltage change.
+    /// Without it the voltage changes as soon as the command does.
+    #[serde(default)]
+    pub voltage_ramp: Option<f64>,
+    /// A, the most current the motor controllers let each motor draw while
+    /// driving. Without it the current is whatever the voltage drives.
+    #[serde(default)]
+    pub current_limit: Option<f64>,
+    /// V, what the driver's full stick commands, and the most the velocity
+    /// loop asks for
+    #[serde(default = "default_max_voltage")]
+    pub max_voltage: f64,
+    pub chassis: ChassisConfig,
+    /// Both sides, unless overridden by `left_motor` or `right_motor`
+    #[serde(default)]
+    pub motor: Option<MotorConfig>,
+    #[serde(default)]
+    pub left_motor: Option<MotorConfig>,
+    #[serde(default)]
+    pub right_motor: Option<MotorConfig>,
+    /// Without one the motors get whatever voltage they're commanded. With
+    /// one, commands are a fraction of its nominal voltage.
+    #[serde(default)]
+    pub battery: Option<BatteryConfig>,
+    /// Without it the motors never heat up
+    #[serde(default)]
+    pub thermal: Option<ThermalConfig>,
+    #[serde(default)]
+    pub timing: TimingConfig,
+    #[serde(default)]
+    pub trail: TrailConfig,
+    #[serde(default)]
+    pub gamepad: GamepadConfig,
+    #[serde(default)]
+    pub benchmark: BenchmarkConfig,
+    #[serde(default)]
+    pub sensors: SensorConfig,
+    #[serde(default)]
+    pub disturbances: DisturbanceConfig,
+    /// Not part of the robot either, see `obstacles`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arena: Option<ArenaConfig>,
+    /// Not part of the robot, but this is the one file the sim is set up from
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub obstacles: Vec<ObstacleConfig>,
+}
+
+impl Default for RobotConfig {
+    fn default() -> Self {
+        Self {
+            efficiency: 0.8,
+            rolling_resistance: 0.0035,
+            viscous_friction: 0.,
+            coulomb_friction: 0.,
+            wheel_friction: Some(1.1),
+            invert_left: false,
+            invert_right: false,
+            voltage_ramp: None,
+            current_limit: None,
+            max_voltage: default_max_voltage(),
+            chassis: ChassisConfig {
+                wheel_radius: 0.1524 / 2.,
+                mass: 32.5,
+                chassis_mass: 32.5 - 4.53592,
+                cg_offset: 0.06,
+                half_wheelbase: 0.63684 / 2.,
+                moment_of_inertia: 4.29,
+                wheel_inertia: 0.00063651 * 3.,
+                length: default_length(),
+            },
+            // https://www.systemvision.com/blog/first-robotics-frc-motor-modeling-may-6-2016
+            motor: Some(MotorConfig {
+                resistance: 12. / 133.,
+                inductance: 0.,
+                gear_ratio: 5.10,
+                back_emf: 2.11E-2,
+                torque_constant: 2.4 / 133.,
+                gear_efficiency: default_gear_efficiency(),
+                rotor_inertia: 0.,
+            }),
+            left_motor: None,
+            right_motor: None,
+            battery: None,
+            thermal: None,
+            timing: TimingConfig::default(),
+            trail: TrailConfig::default(),
+            gamepad: GamepadConfig::default(),
+            benchmark: BenchmarkConfig::default(),
+            sensors: SensorConfig::default(),
+            disturbances: DisturbanceConfig::default(),
+            arena: None,
+            obstacles: Vec::new(),
+        }
+    }
+}
+
+impl RobotConfig {
+    pub fn from_toml(s: &str) -> Result<Self, ConfigError> {
+        let config: Self = toml::from_str(s)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), ConfigError> {
+        let t = &self.timing;
+        if !t.physics_dt.is_finite() || t.physics_dt <= 0. {
+            return Err(ConfigError::Invalid(format!(
+                "physics_dt must be positive, not {}",
+                t.physics_dt
+            )));
+        }
+        if t.control_period < t.physics_dt {
+            return Err(ConfigError::Invalid(format!(
+                "control_period {} is shorter than physics_dt {}",
+                t.control_period, t.physics_dt
+            )));
+        }
+        for (name, delay, step, step_name) in &[
+            (
+                "sensor_delay",
+                t.sensor_delay,
+                t.control_period,
+                "control_period",
+            ),
+            ("command_delay", t.command_delay, t.physics_dt, "physics_dt"),
+        ] {
+            if !delay.is_finite() || *delay < 0. {
+                return Err(ConfigError::Invalid(format!(
+                    "{} must be zero or more, not {}",
+                    name, delay
+                )));
+            }
+            let steps = delay / step;
+            if (steps - steps.round()).abs() > 1e-6 {
+                return Err(ConfigError::Invalid(format!(
+                    "{} {} isn't a whole number of {} {}",
+                    name, delay, step_name, step
+                )));
+            }
+        }
+        if let Some(ramp) = self.voltage_ramp {
+            if !ramp.is_finite() || ramp <= 0. {
+                return Err(ConfigError::Invalid(format!(
+                    "voltage_ramp must be positive, not {}",
+                    ramp
+                )));
+            }
+        }
+        if let Some(limit) = self.current_limit {
+            if !limit.is_finite() || limit <= 0. {
+                return Err(ConfigError::Invalid(format!(
+                    "current_limit must be positive, not {}",
+                    limit
+                )));
+            }
+        }
+        for (name, f) in &[
+            ("viscous_friction", self.viscous_friction),
+            ("coulomb_friction", self.coulomb_friction),
+        ] {
+            if !f.is_finite() || *f < 0. {
+                return Err(ConfigError::Invalid(format!(
+                    "{} must be zero or more, not {}",
+                    name, f
+                )));
+            }
+        }
+        if let Some(ref b) = self.battery {
+            if !b.nominal.is_finite() || b.nominal <= 0. {
+                return Err(ConfigError::Invalid(format!(
+                    "battery nominal voltage must be positive, not {}",
+                    b.nominal
+                )));
+            }
+            if !b.resistance.is_finite() || b.resistance < 0. {
+                return Err(ConfigError::Invalid(format!(
+                    "battery resistance must be zero or more, not {}",
+                    b.resistance
+                )));
+            }
+        }
+        if let Some(ref t) = self.thermal {
+            for (name, x) in &[
+                ("thermal heat_capacity", t.heat_capacity),
+                ("thermal resistance", t.resistance),
+            ] {
+                if !x.is_finite() || *x <= 0. {
+                    return Err(ConfigError::Invalid(format!(
+                        "{} must be positive, not {}",
+                        name, x
+                    )));
+                }
+            }
+            if !t.ambient.is_finite() || t.ambient <= -ZERO_CELSIUS {
+                return Err(ConfigError::Invalid(format!(
+                    "thermal ambient {} C is colder than absolute zero",
+                    t.ambient
+                )));
+            }
+            if let Some(c) = t.cutoff {
+                if !c.is_finite() || c <= t.ambient {
+                    return Err(ConfigError::Invalid(format!(
+                        "thermal cutoff {} C must be hotter than ambient {} C",
+                        c, t.ambient
+                    )));
+                }
+                // it only cools toward ambient, so it has to cut back in above
+                if !t.hysteresis.is_finite() || t.hysteresis < 0. || c - t.hysteresis <= t.ambient {
+                    return Err(ConfigError::Invalid(format!(
+                        "thermal hysteresis {} C must be at least 0 and leave the cutoff above ambient",
+                        t.hysteresis
+                    )));
+                }
+            }
+        }
+        let g = &self.gamepad;
+        if !g.deadband.is_finite() || g.deadband < 0. || g.deadband >= 1. {
+            return Err(ConfigError::Invalid(format!(
+                "gamepad deadband must be at least 0 and below 1, not {}",
+                g.deadband
+            )));
+        }
+        if !g.expo.is_finite() || g.expo <= 0. {
+            return Err(ConfigError::Invalid(format!(
+                "gamepad expo must be positive, not {}",
+                g.expo
+            )));
+        }
+        let b = &self.benchmark;
+        for (name, target) in &[
+            ("rise_63", b.rise_63),
+            ("rise_95", b.rise_95),
+            ("peak_current", b.peak_current),
+            ("coast_distance", b.coast_distance),
+            ("coast_time", b.coast_time),
+        ] {
+            if let Some(x) = target {
+                if !x.is_finite() || *x <= 0. {
+                    return Err(ConfigError::Invalid(format!(
+                        "benchmark {} must be positive, not {}",
+                        name, x
+                    )));
+                }
+            }
+        }
+        if !self.max_voltage.is_finite() || self.max_voltage <= 0. {
+            return Err(ConfigError::Invalid(format!(
+                "max_voltage must be positive, not {}",
+                self.max_voltage
+            )));
+        }
+        for (side, m) in &[("left", &self.left_motor), ("right", &self.right_motor)] {
+            if m.is_none() && self.motor.is_none() {
+                return Err(ConfigError::Invalid(format!(
+                    "missing {}_motor, and no motor to fall back on",
+                    side
+                )));
+            }
+        }
+        let c = &self.chassis;
+        for (name, x) in &[
+            ("wheel_radius", c.wheel_radius),
+            ("mass", c.mass),
+            ("chassis_mass", c.chassis_mass),
+            ("half_wheelbase", c.half_wheelbase),
+            ("moment_of_inertia", c.moment_of_inertia),
+            ("wheel_inertia", c.wheel_inertia),
+        ] {
+            if !x.is_finite() || *x <= 0. {
+                return Err(ConfigError::Invalid(format!(
+                    "{} must be positive, not {}",
+                    name, x
+                )));
+            }
+        }
+        if c.chassis_mass >= c.mass {
+            return Err(ConfigError::Invalid(format!(
+                "chassis_mass {} leaves nothing of mass {} for the wheels and actuators",
+                c.chassis_mass, c.mass
+            )));
+        }
+        if !c.cg_offset.is_finite() {
+            return Err(ConfigError::Invalid(format!(
+                "cg_offset must be a number, not {}",
+                c.cg_offset
+            )));
+        }
+        if !self.efficiency.is_finite() || self.efficiency <= 0. || self.efficiency > 1. {
+            return Err(ConfigError::Invalid(format!(
+                "efficiency must be above 0 and at most 1, not {}",
+                self.efficiency
+            )));
+        }
+        if !self.rolling_resistance.is_finite() || self.rolling_resistance < 0. {
+            return Err(ConfigError::Invalid(format!(
+                "rolling_resistance must be zero or more, not {}",
+                self.rolling_resistance
+            )));
+        }
+        let motors = self.motors();
+        for (side, m) in &[("left", motors.l), ("right", motors.r)] {
+            for (name, x) in &[
+                ("resistance", m.resistance),
+                ("gear_ratio", m.gear_ratio),
+                ("back_emf", m.back_emf),
+                ("torque_constant", m.torque_constant),
+            ] {
+                if !x.is_finite() || *x <= 0. {
+                    return Err(ConfigError::Invalid(format!(
+                        "{} motor {} must be positive, not {}",
+                        side, name, x
+                    )));
+                }
+            }
+            for (name, x) in &[
+                ("inductance", m.inductance),
+                ("rotor_inertia", m.rotor_inertia),
+            ] {
+                if !x.is_finite() || *x < 0. {
+                    return Err(ConfigError::Invalid(format!(
+                        "{} motor {} must be zero or more, not {}",
+                        side, name, x
+                    )));
+                }
+            }
+            if !m.gear_efficiency.is_finite() || m.gear_efficiency <= 0. || m.gear_efficiency > 1. {
+                return Err(ConfigError::Invalid(format!(
+                    "{} motor gear_efficiency must be above 0 and at most 1, not {}",
+                    side, m.gear_efficiency
+                )));
+            }
+        }
+        if self.trail.stride == 0 {
+            return Err(ConfigError::Invalid(
+                "trail stride must be at least one step".to_string(),
+            ));
+        }
+        let n = &self.sensors;
+        for (name, sigma) in &[
+            ("wheel_sigma", n.wheel_sigma),
+            ("gyro_sigma", n.gyro_sigma),
+            ("heading_sigma", n.heading_sigma),
+        ] {
+            if !sigma.is_finite() || *sigma < 0. {
+                return Err(ConfigError::Invalid(format!(
+                    "sensor {} must be zero or more, not {}",
+                    name, sigma
+                )));
+            }
+        }
+        for (name, bias) in &[
+            ("wheel_bias", n.wheel_bias),
+            ("gyro_bias", n.gyro_bias),
+            ("heading_bias", n.heading_bias),
+        ] {
+            if !bias.is_finite() {
+                return Err(ConfigError::Invalid(format!(
+                    "sensor {} must be a number, not {}",
+                    name, bias
+                )));
+            }
+        }
+        let d = &self.disturbances;
+        if !d.carpet.is_finite() || d.carpet < 0. {
+            return Err(ConfigError::Invalid(format!(
+                "disturbance carpet must be at least 0, not {}",
+                d.carpet
+            )));
+        }
+        if !d.bump_period.is_finite() || d.bump_period <= 0. {
+            return Err(ConfigError::Invalid(format!(
+                "disturbance bump_period must be positive, not {}",
+                d.bump_period
+            )));
+        }
+        if !d.shove_duration.is_finite() || d.shove_duration < 0. {
+            return Err(ConfigError::Invalid(format!(
+                "disturbance shove_duration must be at least 0, not {}",
+                d.shove_duration
+            )));
+        }
+        for (name, x) in &[
+            ("bump", d.bump),
+            ("shove_force", d.shove_force),
+            ("shove_torque", d.shove_torque),
+        ] {
+            if !x.is_finite() {
+                return Err(ConfigError::Invalid(format!(
+                    "disturbance {} must be a number, not {}",
+                    name, x
+                )));
+            }
+        }
+        if let Some(ref a) = self.arena {
+            for (name, x) in &[("width", a.width), ("height", a.height)] {
+                if !x.is_finite() || *x <= 0. {
+                    return Err(ConfigError::Invalid(format!(
+                        "arena {} must be positive, not {}",
+                        name, x
+                    )));
+                }
+            }
+            if !a.restitution.is_finite() || a.restitution < 0. || a.restitution > 1. {
+                return Err(ConfigError::Invalid(format!(
+                    "arena restitution must be from 0 to 1, not {}",
+                    a.restitution
+                )));
+            }
+        }
+        for o in &self.obstacles {
+            match (o.radius, o.width, o.height) {
+                (Some(radius), None, None) => {
+                    if !radius.is_finite() || radius <= 0. {
+                        return Err(ConfigError::Invalid(format!(
+                            "obstacle radius must be positive, not {}",
+                            radius
+                        )));
+                    }
+                }
+                (None, Some(width), Some(height)) => {
+                    if !width.is_finite() || width <= 0. || !height.is_finite() || height <= 0. {
+                        return Err(ConfigError::Invalid(format!(
+                            "obstacle width and height must be positive, not {} by {}",
+                            width, height
+                        )));
+                    }
+                }
+                _ => {
+                    return Err(ConfigError::Invalid(
+                        "an obstacle needs either a radius or a width and height".to_string(),
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn from_reader<R: Read>(mut r: R) -> Result<Self, ConfigError> {
+        let mut s = String::new();
+        r.read_to_string(&mut s)?;
+        Self::from_toml(&s)
+    }
+
+    /// Reads a config file from disk, outside of ggez's resource path
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        Self::from_reader(File::open(path)?)
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("RobotConfig is always representable as TOML")
+    }
+
+    pub fn ddmr_params(&self) -> DDMRParams {
+        let c = &self.chassis;
+        DDMRParams {
+            R: c.wheel_radius * M,
+            m: c.mass * KG,
+            mc: c.chassis_mass * KG,
+            d: c.cg_offset * M,
+            L: c.half_wheelbase * M,
+            I: c.moment_of_inertia * KG * M * M,
+            Iw: c.wheel_inertia * KG * M * M,
+        }
+    }
+
+    /// The motor on each side, falling back to `motor` for a side without
+    /// its own. Panics if a side has neither, which `from_toml` rejects.
+    pub fn motors(&self) -> LR<&MotorConfig> {
+        let shared = self.motor.as_ref();
+        LR {
+            l: self.left_motor.as_ref().or(shared).expect("a left motor"),
+            r: self.right_motor.as_ref().or(shared).expect("a right motor"),
+        }
+    }
+
+    pub fn motor_params(&self) -> LR<DCMotorParams> {
+        let m = self.motors();
+        LR {
+            l: m.l.params(),
+            r: m.r.params(),
+        }
+    }
+
+    /// Sets the gear ratio of every motor block, leaving the rest of each
+    pub fn set_gear_ratio(&mut self, gear_ratio: f64) {
+        for m in [&mut self.motor, &mut self.left_motor, &mut self.right_motor]
+            .iter_mut()
+            .filter_map(|m| m.as_mut())
+        {
+            m.gear_ratio = gear_ratio;
+        }
+    }
+
+    /// The drive model stepped at `dt`, usually `timing.physics_dt`
+    pub fn build(&self, dt: Second<f64>) -> ActuatedDDMRModel {
+        let mut model = ActuatedDDMRModel::new_per_side(
+            dt,
+            self.ddmr_params(),
+            self.motor_params(),
+            self.efficiency,
+            self.rolling_resistance,
+        );
+        model.set_inverted(LR {
+            l: self.invert_left,
+            r: self.invert_right,
+        });
+        model.set_ramp(self.voltage_ramp.map(|rate| rate * V / S));
+        model.set_max_voltage(Some(self.max_voltage * V));
+        model.set_friction(FrictionParams {
+            viscous: self.viscous_friction * N * M * S,
+            coulomb: self.coulomb_friction * N * M,
+        });
+        model.set_traction(self.wheel_friction.map(|mu| TractionParams { mu }));
+        model.set_battery(
+            self.battery
+                .as_ref()
+                .map(|b| Battery::new(b.nominal * V, b.resistance * OHM)),
+        );
+        model.set_current_limit(self.current_limit.map(|limit| limit * A));
+        model.set_thermal(self.thermal.as_ref().map(ThermalConfig::params));
+        model
+    }
+}
+
+#[test]
+fn config_round_trip() {
+    let c = RobotConfig::default();
+    let parsed = RobotConfig::from_toml(&c.to_toml()).unwrap();
+    assert_eq!(parsed, c);
+}
+
+#[test]
+fn config_missing_field() {
+    let toml = RobotConfig::default()
+        .to_toml()
+        .lines()
+        .filter(|l| !l.starts_with("gear_ratio"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let err = RobotConfig::from_toml(&toml).unwrap_err();
+    assert!(err.to_string().contains("gear_ratio"), "{}", err);
+}
+
+#[test]
+fn config_not_a_number() {
+    let toml = RobotConfig::default()
+        .to_toml()
+        .replace("mass = 32.5", "mass = \"heavy\"");
+    let err = RobotConfig::from_toml(&toml).unwrap_err();
+    assert!(err.to_string().contains("mass"), "{}", err);
+}
+
+#[test]
+fn config_unsimulatable_values() {
+    let toml = RobotConfig::default().to_toml();
+    for (from, to, name) in &[
+        (
+            "wheel_radius = 0.0762",
+            "wheel_radius = 0.0",
+            "wheel_radius",
+        ),
+        ("mass = 32.5", "mass = -32.5", "mass"),
+        ("gear_ratio = 5.1", "gear_ratio = 0.0", "gear_ratio"),
+        ("efficiency = 0.8", "efficiency = 1.5", "efficiency"),
+        (
+            "gear_efficiency = 1.0",
+            "gear_efficiency = 1.2",
+            "gear_efficiency",
+        ),
+        (
+            "rotor_inertia = 0.0",
+            "rotor_inertia = -0.001",
+            "rotor_inertia",
+        ),
+        ("gyro_sigma = 0.0", "gyro_sigma = -0.1", "gyro_sigma"),
+    ] {
+        assert!(toml.contains(from), "{}", from);
+        let err = RobotConfig::from_toml(&toml.replace(from, to)).unwrap_err();
+        match err {
+            ConfigError::Invalid(ref e) => assert!(e.contains(name), "{}", e),
+            _ => panic!("{} should be invalid, got {}", to, err),
+        }
+    }
+}
+
+#[test]
+fn config_timing() {
+    let without = RobotConfig::default()
+        .to_toml()
+        .split("[timing]")
+        .next()
+        .unwrap()
+        .to_string();
+    let parsed = RobotConfig::from_toml(&without).unwrap();
+    assert_eq!(parsed.timing, TimingConfig::default());
+
+    let fast_control = without.clone() + "[timing]\nphysics_dt = 0.01\ncontrol_period = 0.001\n";
+    let err = RobotConfig::from_toml(&fast_control).unwrap_err();
+    assert!(err.to_string().contains("control_period"), "{}", err);
+    let zero = without.clone() + "[timing]\nphysics_dt = 0.0\ncontrol_period = 0.02\n";
+    assert!(RobotConfig::from_toml(&zero).is_err());
+    let negative = without.clone()
+        + "[timing]\nphysics_dt = 0.01\ncontrol_period = 0.02\nsensor_delay = -0.1\n";
+    let err = RobotConfig::from_toml(&negative).unwrap_err();
+    assert!(err.to_string().contains("sensor_delay"), "{}", err);
+
+    let delayed = without.clone()
+        + "[timing]\nphysics_dt = 0.005\ncontrol_period = 0.02\nsensor_delay = 0.06\ncommand_delay = 0.015\n";
+    let parsed = RobotConfig::from_toml(&delayed).unwrap();
+    assert_eq!(parsed.timing.command_delay, 0.015);
+    let between = delayed.replace("command_delay = 0.015", "command_delay = 0.0125");
+    let err = RobotConfig::from_toml(&between).unwrap_err();
+    assert!(err.to_string().contains("command_delay"), "{}", err);
+    let between = delayed.replace("sensor_delay = 0.06", "sensor_delay = 0.05");
+    let err = RobotConfig::from_toml(&between).unwrap_err();
+    assert!(err.to_string().contains("sensor_delay"), "{}", err);
+}
+
+#[test]
+fn config_trail() {
+    let toml = RobotConfig::default().to_toml();
+    assert!(toml.contains("[trail]"));
+    let without = toml.replace("[trail]\nlength = 2000\nstride = 3\n", "");
+    assert!(!without.contains("[trail]"));
+    assert_eq!(
+        RobotConfig::from_toml(&without).unwrap().trail,
+        TrailConfig::default()
+    );
+    let every_step = toml.replace("stride = 3", "stride = 0");
+    let err = RobotConfig::from_toml(&every_step).unwrap_err();
+    assert!(err.to_string().contains("stride"), "{}", err);
+}
+
+#[test]
+fn config_drivetrain_friction() {
+    let mut c = RobotConfig::default();
+    assert_eq!(c.build(0.005 * S).friction().coulomb, 0. * N * M);
+    c.coulomb_friction = 5.;
+    c.viscous_friction = 0.01;
+    let parsed = RobotConfig::from_toml(&c.to_toml()).unwrap();
+    let friction = parsed.build(0.005 * S).friction().clone();
+    assert_eq!(friction.coulomb, 5. * N * M);
+    assert_eq!(friction.viscous, 0.01 * N * M * S);
+    c.coulomb_friction = -1.;
+    let err = RobotConfig::from_toml(&c.to_toml()).unwrap_err();
+    assert!(err.to_string().contains("coulomb_friction"), "{}", err);
+}
+
+#[test]
+fn config_battery() {
+    let ideal = RobotConfig::default();
+    assert!(ideal.build(0.005 * S).battery().is_none());
+    let with = ideal.to_toml() + "[battery]\nnominal = 12.5\nresistance = 0.02\n";
+    let parsed = RobotConfig::from_toml(&with).unwrap();
+    let model = parsed.build(0.005 * S);
+    let b = model.battery().unwrap();
+    assert_eq!((b.nominal, b.resistance), (12.5 * V, 0.02 * OHM));
+    assert_eq!(RobotConfig::from_toml(&parsed.to_toml()).unwrap(), parsed);
+    let flat = ideal.to_toml() + "[battery]\nnominal = 0.0\nresistance = 0.02\n";
+    let err = RobotConfig::from_toml(&flat).unwrap_err();
+    assert!(err.to_string().contains("nominal"), "{}", err);
+}
+
+#[test]
+fn config_current_limit_and_thermal() {
+    let plain = RobotConfig::default();
+    let model = plain.build(0.005 * S);
+    assert!(model.current_limit().is_none() && model.thermal().is_none());
+    let hot = "current_limit = 40.0\n".to_string()
+        + &plain.to_toml()
+        + "[thermal]\nheat_capacity = 150.0\nresistance = 0.5\n";
+    let parsed = RobotConfig::from_toml(&hot).unwrap();
+    let model = parsed.build(0.005 * S);
+    assert_eq!(model.current_limit(), Some(40. * A));
+    let t = model.thermal().unwrap();
+    assert_eq!(t.resistance, 0.5 * K / W);
+    // starting at the default ambient of 25 C
+    assert!((*(model.temperatures().l / K) - 298.15).abs() < 1e-9);
+    assert_eq!(RobotConfig::from_toml(&parsed.to_toml()).unwrap(), parsed);
+    let err = RobotConfig::from_toml(&hot.replace("current_limit = 40.0", "current_limit = -1.0"))
+        .unwrap_err();
+    assert!(err.to_string().contains("current_limit"), "{}", err);
+    let err = RobotConfig::from_toml(&hot.replace("heat_capacity = 150.0", "heat_capacity = 0.0"))
+        .unwrap_err();
+    assert!(err.to_string().contains("heat_capacity"), "{}", err);
+    assert!(t.cutoff.is_none());
+    let cut = RobotConfig::from_toml(&(hot.clone() + "cutoff = 80.0\n")).unwrap();
+    let params = cut.thermal.as_ref().unwrap().params();
+    assert!((*(params.cutoff.unwrap() / K) - 353.15).abs() < 1e-9);
+    assert_eq!(params.hysteresis, 10. * K);
+    let err =
+        RobotConfig::from_toml(&(hot.clone() + "cutoff = 80.0\nhysteresis = 60.0\n")).unwrap_err();
+    assert!(err.to_string().contains("hysteresis"), "{}", err);
+    let err = RobotConfig::from_toml(&(hot + "cutoff = 20.0\n")).unwrap_err();
+    assert!(err.to_string().contains("cutoff"), "{}", err);
+}
+
+#[test]
+fn config_gamepad() {
+    let plain = RobotConfig::default().to_toml();
+    assert_eq!(
+        RobotConfig::from_toml(&plain).unwrap().gamepad.shape(),
+        StickShape::default()
+    );
+    let with = |g: &str| {
+        RobotConfig::from_toml(&(plain.replace("[gamepad]\ndeadband = 0.1\nexpo = 1.0\n", "") + g))
+    };
+    let shaped = with("[gamepad]\ndeadband = 0.05\nexpo = 2.0\n").unwrap();
+    assert_eq!((shaped.gamepad.deadband, shaped.gamepad.expo), (0.05, 2.));
+    assert_eq!(with("").unwrap().gamepad, GamepadConfig::default());
+    let err = with("[gamepad]\ndeadband = 1.0\nexpo = 2.0\n").unwrap_err();
+    assert!(err.to_string().contains("deadband"), "{}", err);
+    let err = with("[gamepad]\ndeadband = 0.1\nexpo = 0.0\n").unwrap_err();
+    assert!(err.to_string().contains("expo"), "{}", err);
+}
+
+#[test]
+fn config_max_voltage() {
+    let without = RobotConfig::default()
+        .to_toml()
+        .replace("max_voltage = 12.0\n", "");
+    assert!(!without.contains("max_voltage"));
+    assert_eq!(RobotConfig::from_toml(&without).unwrap().max_voltage, 12.);
+    let low = "max_voltage = 6.0\n".to_string() + &without;
+    assert_eq!(RobotConfig::from_toml(&low).unwrap().max_voltage, 6.);
+    let zero = "max_voltage = 0.0\n".to_string() + &without;
+    let err = RobotConfig::from_toml(&zero).unwrap_err();
+    assert!(err.to_string().contains("max_voltage"), "{}", err);
+}
+
+#[test]
+fn config_obstacles() {
+    let toml = RobotConfig::default().to_toml()
+        + "[[obstacles]]\nx = 1.0\ny = -0.5\nradius = 0.25\n\n[[obstacles]]\nx = 0.0\ny = 2.0\nradius = 0.5\n";
+    let parsed = RobotConfig::from_toml(&toml).unwrap();
+    assert_eq!(parsed.obstacles.len(), 2);
+    assert_eq!(
+        parsed.obstacles[0],
+        ObstacleConfig {
+            x: 1.,
+            y: -0.5,
+            radius: Some(0.25),
+            width: None,
+            height: None,
+        }
+    );
+    assert_eq!(RobotConfig::from_toml(&parsed.to_toml()).unwrap(), parsed);
+    let flat = toml.replace("radius = 0.5", "radius = 0.0");
+    assert!(RobotConfig::from_toml(&flat).is_err());
+
+    let wall = toml.replace("radius = 0.5", "width = 3.0\nheight = 0.25");
+    let parsed = RobotConfig::from_toml(&wall).unwrap();
+    assert_eq!(parsed.obstacles[1].radius, None);
+    assert_eq!(parsed.obstacles[1].width, Some(3.));
+    assert_eq!(RobotConfig::from_toml(&parsed.to_toml()).unwrap(), parsed);
+    for bad in &["width = 3.0", "radius = 0.5\nwidth = 3.0\nheight = 1.0"] {
+        let err = RobotConfig::from_toml(&toml.replace("radius = 0.5", bad)).unwrap_err();
+        assert!(err.to_string().contains("obstacle"), "{}", err);
+    }
+}
+
+#[test]
+fn config_arena() {
+    let base = RobotConfig::default().to_toml();
+    assert!(!base.contains("[arena]"));
+    let toml = base.clone() + "[arena]\nwidth = 8.0\nheight = 4.0\n";
+    let parsed = RobotConfig::from_toml(&toml).unwrap();
+    assert_eq!(
+        parsed.arena,
+        Some(ArenaConfig {
+            width: 8.,
+            height: 4.,
+            restitution: 0.
+        })
+    );
+    assert_eq!(RobotConfig::from_toml(&parsed.to_toml()).unwrap(), parsed);
+    let bouncy = toml.clone() + "restitution = 1.5\n";
+    let err = RobotConfig::from_toml(&bouncy).unwrap_err();
+    assert!(err.to_string().contains("restitution"), "{}", err);
+    let flat = toml.replace("height = 4.0", "height = 0.0");
+    assert!(RobotConfig::from_toml(&flat).is_err());
+}
+
+#[test]
+fn config_per_side_motors() {
+    let base = RobotConfig::default();
+    let motor = base.motor.clone().unwrap();
+    let weak = MotorConfig {
+        torque_constant: motor.torque_constant * 0.95,
+        ..motor.clone()
+    };
+    // a shared block with one side overridden
+    let toml = base.to_toml()
+        + "[right_motor]\nresistance = 0.09\ninductance = 0.0\ngear_ratio = 5.1\nback_emf = 0.0211\ntorque_constant = 0.017\n";
+    let parsed = RobotConfig::from_toml(&toml).unwrap();
+    assert_eq!(*parsed.motors().l, motor);
+    assert_eq!(parsed.motors().r.torque_constant, 0.017);
+
+    // separate blocks without a shared one
+    let split = RobotConfig {
+        invert_right: true,
+        motor: None,
+        left_motor: Some(motor.clone()),
+        right_motor: Some(weak.clone()),
+        ..base.clone()
+    };
+    let parsed = RobotConfig::from_toml(&split.to_toml()).unwrap();
+    assert_eq!(parsed, split);
+    assert_eq!(*parsed.motors().r, weak);
+    assert!(parsed.build(0.01 * S).inverted().r);
+
+    let one_sided = RobotConfig {
+        right_motor: None,
+        ..split
+    };
+    let err = RobotConfig::from_toml(&one_sided.to_toml()).unwrap_err();
+    assert!(err.to_string().contains("right_motor"), "{}", err);
+}
+
+#[test]
+fn config_disturbances() {
+    let plain = RobotConfig::default().to_toml();
+    let parsed = RobotConfig::from_toml(&plain).unwrap().disturbances;
+    assert_eq!(parsed, DisturbanceConfig::default());
+    let quiet = parsed.disturbances();
+    assert_eq!((quiet.carpet, quiet.bump), (None, None));
+    let start = plain.find("[disturbances]").unwrap();
+    let end = start + plain[start..].find("\n\n").unwrap_or(plain.len() - start);
+    let without = format!("{}{}", &plain[..start], &plain[end..]);
+    let with = |d: &str| RobotConfig::from_toml(&(without.clone() + d));
+    assert_eq!(with("").unwrap().disturbances, DisturbanceConfig::default());
+    let rough = with("[disturbances]\ncarpet = 15.0\nbump = -30.0\nbump_period = 0.5\n")
+        .unwrap()
+        .disturbances;
+    assert_eq!(rough.shove_duration, default_shove_duration());
+    let d = rough.disturbances();
+    assert_eq!(d.carpet.unwrap().force, 15. * N);
+    assert_eq!(d.bump.unwrap().period, 0.5 * S);
+    let err = with("[disturbances]\ncarpet = -1.0\n").unwrap_err();
+    assert!(err.to_string().contains("carpet"), "{}", err);
+    let err = with("[disturbances]\nbump_period = 0.0\n").unwrap_err();
+    assert!(err.to_string().contains("bump_period"), "{}", err);
+}
@@ -0,0 +1,591 @@
+//! Closed-loop controllers that run against the drive model, for trying out
+//! gains before they go on a robot.
+
+use crate::dynamics::{
+    ActuatedDDMRModel, DCMotorParams, Differentiator, Integrator, VoltSecond, LR,
+};
+use crate::sensors::Delay;
+use crate::signals::Lowpass;
+
+use dimensioned::si::*;
+use dimensioned::tarr;
+use dimensioned::traits::Abs;
+use dimensioned::typenum::{N1, P1, P2, Z0};
+use serde_derive::{Deserialize, Serialize};
+
+/// Gains in output units per unit of error, e.g. volts per rad/s.
+/// `kf` is a feedforward on the setpoint in the same units.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct PidGains {
+    pub kp: f64,
+    pub ki: f64,
+    pub kd: f64,
+    pub kf: f64,
+}
+
+/// The history a `Pid` carries from one step to the next
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PidState {
+    pub integral: f64,
+    pub d_filtered: f64,
+    pub last_measurement: Option<f64>,
+}
+
+/// The plain numbers a `Pid` works on, as far as the filters are concerned
+type Number = tarr![Z0, Z0, Z0, Z0, Z0, Z0, Z0];
+
+/// rad/s, what a `VelocityPid` integrates and differentiates
+type AngularVelocity = tarr![Z0, Z0, N1, Z0, Z0, Z0, Z0];
+
+/// V s^2, volts per rad/s^2
+pub type VoltSecond2<V> = SI<V, tarr![P2, P1, N1, N1, Z0, Z0, Z0]>;
+
+/// A PID loop on plain numbers; callers strip units at the boundary, see
+/// `WheelVelocityController`.
+///
+/// The derivative is taken on the measurement rather than the error so setpoint
+/// steps don't kick it, and runs through a first order low-pass since a
+/// quantized measurement would otherwise make it mostly noise. The integral
+/// stops accumulating while the output is saturated in the direction of the
+/// error, so it doesn't wind up against the limit.
+#[derive(Debug, Clone)]
+pub struct Pid {
+    gains: PidGains,
+    dt: f64,
+    output_limit: Option<f64>,
+    integral_limit: Option<f64>,
+    integral: f64,
+    d_filter: Lowpass<Number>,
+    last_measurement: Option<f64>,
+}
+
+fn clamp_sym(x: f64, limit: Option<f64>) -> f64 {
+    match limit {
+        Some(l) if x > l => l,
+        Some(l) if x < -l => -l,
+        _ => x,
+    }
+}
+
+impl Pid {
+    pub fn new(gains: PidGains, dt: Second<f64>) -> Self {
+        Self {
+            gains,
+            dt: *(dt / S),
+            output_limit: None,
+            integral_limit: None,
+            integral: 0.,
+            d_filter: Lowpass::new(dt, 0. * S, Unitless::new(0.)),
+            last_measurement: None,
+        }
+    }
+
+    /// Clamps the output to [-limit, limit]
+    pub fn with_output_limit(self, limit: f64) -> Self {
+        Self {
+            output_limit: Some(limit.abs()),
+            ..self
+        }
+    }
+
+    /// Clamps the integral term's contribution to the output to [-limit, limit]
+    pub fn with_integral_limit(self, limit: f64) -> Self {
+        Self {
+            integral_limit: Some(limit.abs()),
+            ..self
+        }
+    }
+
+    /// Low-passes the derivative with time constant `tau`. Zero turns the filter off.
+    pub fn with_derivative_filter(self, tau: Second<f64>) -> Self {
+        Self {
+            d_filter: Lowpass::new(self.dt * S, tau, Unitless::new(0.)),
+            ..self
+        }
+    }
+
+    pub fn gains(&self) -> PidGains {
+        self.gains
+    }
+
+    pub fn set_gains(&mut self, gains: PidGains) {
+        self.gains = gains;
+    }
+
+    /// Forgets the integral and derivative history, e.g. after the loop was disabled
+    pub fn reset(&mut self) {
+        self.integral = 0.;
+        self.d_filter.reset();
+        self.last_measurement = None;
+    }
+
+    pub fn state(&self) -> PidState {
+        PidState {
+            integral: self.integral,
+            d_filtered: *self.d_filter.get(),
+            last_measurement: self.last_measurement,
+        }
+    }
+
+    /// Picks up from a `state` taken earlier, keeping the current gains
+    pub fn restore(&mut self, state: PidState) {
+        self.integral = state.integral;
+        self.d_filter.set(Unitless::new(state.d_filtered));
+        self.last_measurement = state.last_measurement;
+    }
+
+    /// Runs one timestep and returns the output
+    pub fn calculate(&mut self, setpoint: f64, measurement: f64) -> f64 {
+        let g = self.gains;
+        let error = setpoint - measurement;
+
+        let raw_d = match self.last_measurement {
+            Some(last) => -(measurement - last) / self.dt,
+            None => 0.,
+        };
+        self.last_measurement = Some(measurement);
+        let d = *self.d_filter.add(Unitless::new(raw_d));
+
+        let rest = g.kp * error + g.kd * d + g.kf * setpoint;
+        let mut integral = self.integral + error * self.dt;
+        if let (Some(limit), true) = (self.integral_limit, g.ki != 0.) {
+            integral = clamp_sym(integral, Some(limit / g.ki.abs()));
+        }
+        let unclamped = rest + g.ki * integral;
+        let out = clamp_sym(unclamped, self.output_limit);
+        // only integrate if it doesn't push further into saturation
+        if out == unclamped || (unclamped - out).signum() != error.signum() {
+            self.integral = integral;
+        }
+        clamp_sym(rest + g.ki * self.integral, self.output_limit)
+    }
+}
+
+/// `PidGains` for a `VelocityPid`, with their units
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VelocityPidGains {
+    /// Per rad/s of error
+    pub kp: VoltSecond<f64>,
+    /// Per rad of error built up over time
+    pub ki: Volt<f64>,
+    /// Per rad/s^2 the measurement is changing at
+    pub kd: VoltSecond2<f64>,
+    /// Per rad/s of setpoint
+    pub kf: VoltSecond<f64>,
+}
+
+impl From<PidGains> for VelocityPidGains {
+    /// Reads `gains` as volts per rad/s of wheel speed, like
+    /// `WheelVelocityController` does
+    fn from(gains: PidGains) -> Self {
+        Self {
+            kp: gains.kp * V * S,
+            ki: gains.ki * V,
+            kd: gains.kd * V * S * S,
+            kf: gains.kf * V * S,
+        }
+    }
+}
+
+/// `Pid` on a wheel speed, with its integral and derivative kept by an
+/// `Integrator` and a `Differentiator` so their units are checked. Takes rad/s
+/// and gives volts. The derivative is of the measurement and the integral
+/// doesn't wind up, as in `Pid`.
+#[derive(Debug, Clone)]
+pub struct VelocityPid {
+    gains: VelocityPidGains,
+    dt: Second<f64>,
+    output_limit: Option<Volt<f64>>,
+    integral_limit: Option<Volt<f64>>,
+    /// Of the error
+    integral: Integrator<AngularVelocity>,
+    /// Of the measurement
+    derivative: Differentiator<AngularVelocity>,
+    /// Whether the derivative has a measurement to go from yet
+    measured: bool,
+}
+
+fn clamp_volts(x: Volt<f64>, limit: Option<Volt<f64>>) -> Volt<f64> {
+    clamp_sym(*(x / V), limit.map(|l| *(l / V))) * V
+}
+
+impl VelocityPid {
+    pub fn new(gains: VelocityPidGains, dt: Second<f64>) -> Self {
+        Self {
+            gains,
+            dt,
+            output_limit: None,
+            integral_limit: None,
+            integral: Integrator::new(dt, Unitless::new(0.)),
+            derivative: Differentiator::new(dt, 0. * HZ),
+            measured: false,
+        }
+    }
+
+    /// Clamps the output to [-limit, limit]
+    pub fn with_output_limit(self, limit: Volt<f64>) -> Self {
+        Self {
+            output_limit: Some(limit.abs()),
+            ..self
+        }
+    }
+
+    /// Clamps the integral term's contribution to the output to [-limit, limit]
+    pub fn with_integral_limit(self, limit: Volt<f64>) -> Self {
+        Self {
+            integral_limit: Some(limit.abs()),
+            ..self
+        }
+    }
+
+    /// See `Differentiator::with_time_constant`
+    pub fn with_derivative_filter(self, tau: Second<f64>) -> Self {
+        Self {
+            derivative: Differentiator::with_time_constant(self.dt, 0. * HZ, tau),
+            ..self
+        }
+    }
+
+    pub fn gains(&self) -> VelocityPidGains {
+        self.gains
+    }
+
+    pub fn set_gains(&mut self, gains: VelocityPidGains) {
+        self.gains = gains;
+    }
+
+    /// Forgets the integral and derivative history
+    pub fn reset(&mut self) {
+        self.integral.reset();
+        self.derivative.reset();
+        self.measured = false;
+    }
+
+    /// Runs one timestep and returns the voltage to apply
+    pub fn calculate(&mut self, setpoint: Hertz<f64>, measurement: Hertz<f64>) -> Volt<f64> {
+        let g = self.gains;
+        let error = setpoint - measurement;
+
+        let d = if self.measured {
+            self.derivative.add(measurement)
+        } else {
+            self.measured = true;
+            self.derivative.set(measurement);
+            self.derivative.get()
+        };
+
+        let rest = g.kp * error - g.kd * d + g.kf * setpoint;
+        let before = self.integral.get();
+        let mut integral = self.integral.add(error);
+        if let (Some(limit), true) = (self.integral_limit, g.ki != 0. * V) {
+            let bound = *(limit / g.ki.abs());
+            integral = Unitless::new(clamp_sym(*integral, Some(bound)));
+        }
+        let unclamped = rest + g.ki * integral;
+        let out = clamp_volts(unclamped, self.output_limit);
+        // only integrate if it doesn't push further into saturation
+        let pushing = (*((unclamped - out) / V)).signum() == (*(error / HZ)).signum();
+        if out != unclamped && pushing {
+            integral = before;
+        }
+        self.integral.set(integral);
+        clamp_volts(rest + g.ki * integral, self.output_limit)
+    }
+}
+
+/// Everything a `WheelVelocityController` carries between updates
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ControllerState {
+    pub pid: LR<PidState>,
+    /// rad/s, wheel speeds still in the delay line, oldest first
+    pub pending: Vec<LR<f64>>,
+    /// rad/s, what the last update acted on
+    pub measured: LR<f64>,
+}
+
+fn zero_wheels() -> LR<Hertz<f64>> {
+    LR {
+        l: 0. * HZ,
+        r: 0. * HZ,
+    }
+}
+
+/// Runs a velocity loop on each wheel, producing voltages for
+/// `ActuatedDDMRModel::observe`.
+#[derive(Debug, Clone)]
+pub struct WheelVelocityController {
+    pid: LR<Pid>,
+    dt: Second<f64>,
+    /// Between the model's wheel speeds and what the loop sees
+    delay: Delay<LR<Hertz<f64>>>,
+    measured: LR<Hertz<f64>>,
+}
+
+impl WheelVelocityController {
+    /// `gains` are in volts per rad/s of wheel speed. The output and the integral
+    /// are both limited to `max_voltage`.
+    pub fn new(gains: PidGains, dt: Second<f64>, max_voltage: Volt<f64>) -> Self {
+        let max = *(max_voltage / V);
+        let pid = Pid::new(gains, dt)
+            .with_output_limit(max)
+            .with_integral_limit(max);
+        Self {
+            pid: LR {
+                l: pid.clone(),
+                r: pid,
+            },
+            dt,
+            delay: Delay::new(0, zero_wheels()),
+            measured: zero_wheels(),
+        }
+    }
+
+    /// See `Pid::with_derivative_filter`
+    pub fn with_derivative_filter(self, tau: Second<f64>) -> Self {
+        Self {
+            pid: LR {
+                l: self.pid.l.with_derivative_filter(tau),
+                r: self.pid.r.with_derivative_filter(tau),
+            },
+            ..self
+        }
+    }
+
+    /// Has `update` act on wheel speeds from `delay` ago, rounded to whole
+    /// updates, the way a real loop sees its sensors late
+    pub fn with_delay(self, delay: Second<f64>) -> Self {
+        Self {
+            delay: Delay::from_duration(delay, self.dt, zero_wheels()),
+            ..self
+        }
+    }
+
+    /// In updates
+    pub fn delay(&self) -> usize {
+        self.delay.depth()
+    }
+
+    /// The wheel speeds the last `update` acted on, after the delay
+    pub fn measured(&self) -> LR<Hertz<f64>> {
+        self.measured
+    }
+
+    /// Also empties the delay line, which reads as the wheels at rest
+    pub fn reset(&mut self) {
+        self.pid.l.reset();
+        self.pid.r.reset();
+        self.delay.fill(zero_wheels());
+        self.measured = zero_wheels();
+    }
+
+    pub fn state(&self) -> ControllerState {
+        let raw = |w: LR<Hertz<f64>>| LR {
+            l: w.l.value_unsafe,
+            r: w.r.value_unsafe,
+        };
+        ControllerState {
+            pid: LR {
+                l: self.pid.l.state(),
+                r: self.pid.r.state(),
+            },
+            pending: self.delay.pending().into_iter().map(raw).collect(),
+            measured: raw(self.measured),
+        }
+    }
+
+    pub fn restore(&mut self, state: &ControllerState) {
+        let hz = |w: LR<f64>| LR {
+            l: w.l * HZ,
+            r: w.r * HZ,
+        };
+        self.pid.l.restore(state.pid.l);
+        self.pid.r.restore(state.pid.r);
+        let pending: Vec<_> = state.pending.iter().map(|&w| hz(w)).collect();
+        self.delay.restore(&pending);
+        self.measured = hz(state.measured);
+    }
+
+    pub fn calculate(&mut self, target: LR<Hertz<f64>>, measured: LR<Hertz<f64>>) -> LR<Volt<f64>> {
+        LR {
+            l: self.pid.l.calculate(*(target.l / HZ), *(measured.l / HZ)) * V,
+            r: self.pid.r.calculate(*(target.r / HZ), *(measured.r / HZ)) * V,
+        }
+    }
+
+    /// Closes the loop on the model's wheel speeds, as they were `delay`
+    /// updates ago
+    pub fn update(&mut self, target: LR<Hertz<f64>>, model: &ActuatedDDMRModel) -> LR<Volt<f64>> {
+        self.update_measured(target, model.ddmr().wheels())
+    }
+
+    /// `update`, on wheel speeds from somewhere other than the model itself,
+    /// e.g. noisy sensors. They're still delayed.
+    pub fn update_measured(
+        &mut self,
+        target: LR<Hertz<f64>>,
+        wheels: LR<Hertz<f64>>,
+    ) -> LR<Volt<f64>> {
+        self.measured = self.delay.push(wheels);
+        let measured = self.measured;
+        self.calculate(target, measured)
+    }
+}
+
+/// Volts per rad/s of wheel speed the motor needs to overcome its back EMF,
+/// which makes a good `kf`
+pub fn back_emf_feedforward(p: &DCMotorParams) -> f64 {
+    *(p.Kb * p.N / (V * S))
+}
+
+/// Free speed of the wheels at `voltage`, ignoring friction
+pub fn free_wheel_speed(p: &DCMotorParams, voltage: Volt<f64>) -> Hertz<f64> {
+    voltage / (p.Kb * p.N)
+}
+
+#[cfg(test)]
+fn run_step(
+    controller: &mut WheelVelocityController,
+    model: &mut ActuatedDDMRModel,
+    target: Hertz<f64>,
+    steps: usize,
+) -> Vec<f64> {
+    let target = LR {
+        l: target,
+        r: target,
+    };
+    (0..steps)
+        .map(|_| {
+            let v = controller.update(target, model);
+            model.observe(v);
+            *(model.ddmr().wheels().l / HZ)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+fn test_gains(model: &ActuatedDDMRModel) -> PidGains {
+    PidGains {
+        kp: 1.,
+        ki: 5.,
+        kd: 0.,
+        kf: back_emf_feedforward(&model.motor_params().l),
+    }
+}
+
+#[test]
+fn pid_step_response_settles() {
+    let dt = 1. / 60. * S;
+    let mut model = crate::config::RobotConfig::default().build(dt);
+    let mut c = WheelVelocityController::new(test_gains(&model), dt, 12. * V);
+    let target = 50.;
+    let speeds = run_step(&mut c, &mut model, target * HZ, 5 * 60);
+    // within 2% after 2 seconds and staying there
+    for w in &speeds[2 * 60..] {
+        assert!((w - target).abs() < 0.02 * target, "{}", w);
+    }
+}
+
+#[test]
+fn pid_no_windup() {
+    let dt = 1. / 60. * S;
+    let mut model = crate::config::RobotConfig::default().build(dt);
+    let mut c = WheelVelocityController::new(test_gains(&model), dt, 12. * V);
+    let free = *(free_wheel_speed(&model.motor_params().l, 12. * V) / HZ);
+    // saturated for a while chasing an unreachable speed
+    run_step(&mut c, &mut model, 2. * free * HZ, 5 * 60);
+    let target = 50.;
+    let speeds = run_step(&mut c, &mut model, target * HZ, 5 * 60);
+    for w in &speeds[2 * 60..] {
+        assert!((w - target).abs() < 0.02 * target, "{}", w);
+    }
+}
+
+#[test]
+fn velocity_pid_step_response_settles() {
+    let dt = 1. / 60. * S;
+    let mut model = crate::config::RobotConfig::default().build(dt);
+    let gains = VelocityPidGains::from(test_gains(&model));
+    let pid = VelocityPid::new(gains, dt)
+        .with_output_limit(12. * V)
+        .with_integral_limit(12. * V);
+    let mut pid = LR {
+        l: pid.clone(),
+        r: pid,
+    };
+    let target = 50. * HZ;
+    for step in 0..5 * 60 {
+        let wheels = model.ddmr().wheels();
+        model.observe(LR {
+            l: pid.l.calculate(target, wheels.l),
+            r: pid.r.calculate(target, wheels.r),
+        });
+        // within 2% after 2 seconds and staying there
+        if step >= 2 * 60 {
+            let w = model.ddmr().wheels().l;
+            assert!((w - target).abs() < 0.02 * target, "{:?}", w);
+        }
+    }
+}
+
+#[test]
+fn velocity_pid_matches_pid() {
+    let gains = PidGains {
+        kp: 0.8,
+        ki: 6.,
+        kd: 0.05,
+        kf: 0.1,
+    };
+    let dt = 1. / 60. * S;
+    let mut plain = Pid::new(gains, dt)
+        .with_output_limit(12.)
+        .with_integral_limit(6.);
+    let mut typed = VelocityPid::new(gains.into(), dt)
+        .with_output_limit(12. * V)
+        .with_integral_limit(6. * V);
+    for i in 0..600 {
+        // far enough off for long enough to saturate, then close
+        let setpoint = if i < 300 { 200. } else { 40. };
+        let measurement = 30. + (i % 7) as f64;
+        let a = plain.calculate(setpoint, measurement);
+        let b = typed.calculate(setpoint * HZ, measurement * HZ);
+        assert!((a - *(b / V)).abs() < 1e-9, "{} {:?} at {}", a, b, i);
+    }
+}
+
+#[test]
+fn pid_derivative_filter() {
+    let gains = PidGains {
+        kd: 1.,
+        ..Default::default()
+    };
+    let dt = 1. / 60. * S;
+    let mut raw = Pid::new(gains, dt);
+    let mut filtered = Pid::new(gains, dt).with_derivative_filter(0.1 * S);
+    let mut raw_peak: f64 = 0.;
+    let mut filtered_peak: f64 = 0.;
+    for i in 0..600 {
+        // a measurement dithering by one count, like a quantized encoder
+        let m = 10. + (i % 2) as f64;
+        raw_peak = raw_peak.max(raw.calculate(10., m).abs());
+        let f = filtered.calculate(10., m).abs();
+        if i > 60 {
+            filtered_peak = filtered_peak.max(f);
+        }
+    }
+    assert!(filtered_peak < 0.25 * raw_peak);
+}
+
+#[test]
+fn delay_makes_loop_ring() {
+    let dt = 1. / 60. * S;
+    let target = 50.;
+    let overshoot = |cycles: f64| {
+        let mut model = crate::config::RobotConfig::default().build(dt);
+        let mut c =
+            WheelVelocityController::new(test_gains(&model), dt, 12. * V).with_delay(cycles * dt);
+        let speeds = run_step(&mut c, &mut model, target * HZ, 5 * 60);
+        speeds.iter().cloned().fold(0., f64::max) - target
+    };
+    let o: Vec<f64> = [0., 2., 5.].iter().map(|&d| overshoot(d)).collect();
+    assert!(o[0] < o[1] && o[1] < o[2], "{:?}", o);
+}
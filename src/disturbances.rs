@@ -0,0 +1,319 @@
+//! Outside forces on the robot, for checking how well a controller holds up
+//! against them: carpet dragging on it, bumps at a steady rhythm, and a shove
+//! on demand. `Disturbances` adds up whichever are active into one
+//! `Disturbance` each physics step, for `DDMRModel::apply_disturbance`.
+
+use crate::dynamics::{Disturbance, NewtonMeter, Vels};
+
+use dimensioned::si::*;
+use serde_derive::{Deserialize, Serialize};
+
+/// A constant force against the direction of travel, on top of the model's
+/// own rolling resistance. None at rest.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Carpet {
+    pub force: Newton<f64>,
+}
+
+impl Carpet {
+    /// The drag on a robot of `mass` moving at `vels` for the next `dt`. Never
+    /// more than would stop it within the step, otherwise it would chatter
+    /// around zero instead of coming to rest.
+    pub fn at(&self, vels: Vels, mass: Kilogram<f64>, dt: Second<f64>) -> Disturbance {
+        let stop = mass * vels.lin.abs() / dt;
+        let drag = if stop < self.force { stop } else { self.force };
+        Disturbance {
+            force: -(*(vels.lin / MPS)).signum() * drag,
+            ..Disturbance::default()
+        }
+    }
+}
+
+/// A force along the heading for the first half of every period and none for
+/// the second, like driving over a row of cables
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Bump {
+    /// Backward for a negative one
+    pub force: Newton<f64>,
+    pub period: Second<f64>,
+}
+
+impl Bump {
+    /// `time` since the bumps started
+    pub fn at(&self, time: Second<f64>) -> Disturbance {
+        let force = if (*(time / self.period)).fract() < 0.5 {
+            self.force
+        } else {
+            0. * N
+        };
+        Disturbance {
+            force,
+            ..Disturbance::default()
+        }
+    }
+}
+
+/// A force and a torque held for `duration` once started, like someone
+/// shoving the robot
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Shove {
+    pub force: Newton<f64>,
+    /// Counterclockwise positive
+    pub torque: NewtonMeter<f64>,
+    pub duration: Second<f64>,
+}
+
+/// How far along the disturbances are, for snapshots
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct DisturbanceProgress {
+    /// s since the bumps started
+    pub time: f64,
+    /// s left of the shove in progress
+    pub shove_left: f64,
+}
+
+/// Every disturbance on one robot. They all add up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Disturbances {
+    pub carpet: Option<Carpet>,
+    pub bump: Option<Bump>,
+    /// Only while shoving, see `start_shove`
+    pub shove: Shove,
+    progress: DisturbanceProgress,
+}
+
+impl Disturbances {
+    pub fn new(carpet: Option<Carpet>, bump: Option<Bump>, shove: Shove) -> Self {
+        Self {
+            carpet,
+            bump,
+            shove,
+            progress: DisturbanceProgress::default(),
+        }
+    }
+
+    /// Starts the shove over from the next `update`
+    pub fn start_shove(&mut self) {
+        self.progress.shove_left = *(self.shove.duration / S);
+    }
+
+    pub fn shoving(&self) -> bool {
+        self.progress.shove_left > 0.
+    }
+
+    pub fn progress(&self) -> DisturbanceProgress {
+        self.progress
+    }
+
+    pub fn resume(&mut self, progress: DisturbanceProgress) {
+        self.progress = progress;
+    }
+
+    /// Back to the start of the bumps, without a shove
+    pub fn reset(&mut self) {
+        self.progress = DisturbanceProgress::default();
+    }
+
+    /// Everything on a robot of `mass` for the next step of `dt`, with it
+    /// moving at `vels` at the start of the step
+    pub fn update(&mut self, vels: Vels, mass: Kilogram<f64>, dt: Second<f64>) -> Disturbance {
+        let mut total = Disturbance::default();
+        if let Some(ref carpet) = self.carpet {
+            total = total + carpet.at(vels, mass, dt);
+        }
+        if let Some(ref bump) = self.bump {
+            total = total + bump.at(self.progress.time * S);
+        }
+        if self.shoving() {
+            total = total
+                + Disturbance {
+                    force: self.shove.force,
+                    torque: self.shove.torque,
+                };
+            self.progress.shove_left -= *(dt / S);
+        }
+        self.progress.time += *(dt / S);
+        total
+    }
+}
+
+#[cfg(test)]
+fn quiet_shove() -> Shove {
+    Shove {
+        force: 0. * N,
+        torque: 0. * N * M,
+        duration: 0. * S,
+    }
+}
+
+#[test]
+fn carpet_lowers_steady_speed() {
+    use crate::config::RobotConfig;
+    use crate::dynamics::LR;
+    let config = RobotConfig {
+        wheel_friction: None,
+        ..RobotConfig::default()
+    };
+    let dt = 0.005 * S;
+    let mut model = config.build(dt);
+    let drag = 20. * N;
+    let mut disturbances = Disturbances::new(Some(Carpet { force: drag }), None, quiet_shove());
+    for _ in 0..2000 {
+        let d = disturbances.update(model.vel(), model.ddmr().params().m, dt);
+        model.apply_disturbance(d.force, d.torque);
+        model.observe(LR {
+            l: 12. * V,
+            r: 12. * V,
+        });
+    }
+    assert_eq!(model.ddmr().disturbance().force, -drag);
+    // the drag takes R / 2 of torque off each wheel, and the steady speed is
+    // linear in the torque, as in the voltage
+    let (fast, slow) = (
+        *(model.steady_speed(12. * V) / MPS),
+        *(model.steady_speed(6. * V) / MPS),
+    );
+    let stall = model.stall_torque(6. * V);
+    let r = *(model.ddmr().params().R / M);
+    let expected = fast - *(drag / N) * r * (fast - slow) / *((stall.l + stall.r) / (N * M));
+    let speed = *(model.vel().lin / MPS);
+    assert!(expected < 0.9 * fast);
+    assert!(
+        (speed - expected).abs() < 0.01 * (fast - expected),
+        "{} {}",
+        speed,
+        expected
+    );
+}
+
+#[test]
+fn velocity_loop_holds_speed_on_carpet() {
+    use crate::config::RobotConfig;
+    use crate::control::{back_emf_feedforward, PidGains, WheelVelocityController};
+    use crate::dynamics::LR;
+    let config = RobotConfig {
+        wheel_friction: None,
+        ..RobotConfig::default()
+    };
+    let dt = 0.005 * S;
+    let volts = 6. * V;
+    let carpet = || Disturbances::new(Some(Carpet { force: 20. * N }), None, quiet_shove());
+
+    // open loop, the voltage that would hold the speed without the carpet
+    let mut model = config.build(dt);
+    let setpoint = model.steady_speed(volts);
+    let mut disturbances = carpet();
+    for _ in 0..4000 {
+        let d = disturbances.update(model.vel(), model.ddmr().params().m, dt);
+        model.apply_disturbance(d.force, d.torque);
+        model.observe(LR { l: volts, r: volts });
+    }
+    let open = model.vel().lin;
+    assert!(open < 0.95 * setpoint, "{:?} {:?}", open, setpoint);
+
+    // closed loop on the wheel speeds that make the same setpoint
+    let mut model = config.build(dt);
+    let target = setpoint / model.ddmr().params().R;
+    let gains = PidGains {
+        kp: 1.,
+        ki: 5.,
+        kd: 0.,
+        kf: back_emf_feedforward(&model.motor_params().l),
+    };
+    let mut controller = WheelVelocityController::new(gains, dt, 12. * V);
+    let mut disturbances = carpet();
+    for _ in 0..4000 {
+        let d = disturbances.update(model.vel(), model.ddmr().params().m, dt);
+        model.apply_disturbance(d.force, d.torque);
+        let v = controller.update(
+            LR {
+                l: target,
+                r: target,
+            },
+            &model,
+        );
+        model.observe(v);
+    }
+    let closed = model.vel().lin;
+    assert!(
+        (closed - setpoint).abs() < 0.01 * setpoint,
+        "{:?} {:?}",
+        closed,
+        setpoint
+    );
+}
+
+#[test]
+fn disturbances_add_up() {
+    // exact in binary, so the bumps switch on the steps they should
+    let dt = 0.125 * S;
+    let moving = Vels {
+        lin: 1. * MPS,
+        ..Vels::default()
+    };
+    let mut disturbances = Disturbances::new(
+        Some(Carpet { force: 10. * N }),
+        Some(Bump {
+            force: 5. * N,
+            period: 0.5 * S,
+        }),
+        Shove {
+            force: 100. * N,
+            torque: 30. * N * M,
+            duration: 0.3 * S,
+        },
+    );
+    let forces = |d: &mut Disturbances| -> Vec<(f64, f64)> {
+        (0..4)
+            .map(|_| {
+                let total = d.update(moving, 50. * KG, dt);
+                (*(total.force / N), *(total.torque / (N * M)))
+            })
+            .collect()
+    };
+    assert_eq!(
+        forces(&mut disturbances),
+        vec![(-5., 0.), (-5., 0.), (-10., 0.), (-10., 0.)]
+    );
+    disturbances.start_shove();
+    assert!(disturbances.shoving());
+    assert_eq!(
+        forces(&mut disturbances),
+        vec![(95., 30.), (95., 30.), (90., 30.), (-10., 0.)]
+    );
+    assert!(!disturbances.shoving());
+    // nothing drags on the robot at rest
+    disturbances.reset();
+    assert_eq!(
+        *(disturbances.update(Vels::default(), 50. * KG, dt).force / N),
+        5.
+    );
+}
+
+#[test]
+fn carpet_lets_robot_settle() {
+    use crate::config::RobotConfig;
+    use crate::dynamics::LR;
+    let config = RobotConfig {
+        wheel_friction: None,
+        ..RobotConfig::default()
+    };
+    let dt = 0.005 * S;
+    let mut model = config.build(dt);
+    model.set_vels(Vels {
+        lin: 1. * MPS,
+        ..Vels::default()
+    });
+    let mut disturbances = Disturbances::new(Some(Carpet { force: 200. * N }), None, quiet_shove());
+    for _ in 0..400 {
+        let d = disturbances.update(model.vel(), model.ddmr().params().m, dt);
+        model.apply_disturbance(d.force, d.torque);
+        model.observe(LR {
+            l: 0. * V,
+            r: 0. * V,
+        });
+        // slowing down, never pushed back the other way
+        assert!(model.vel().lin >= 0. * MPS, "{}", model.vel().lin);
+    }
+    assert!(model.vel().lin < 1e-9 * MPS, "{}", model.vel().lin);
+}
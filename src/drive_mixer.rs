@@ -0,0 +1,103 @@
+//! Turning joystick axes into left and right wheel outputs in [-1, 1], which
+//! the simulation then scales by the max voltage or free speed. Positive x
+//! turns right, which means the left wheel runs faster.
+
+use crate::dynamics::LR;
+
+/// Below this much throttle, curvature drive turns in place instead
+pub const QUICK_TURN_THROTTLE: f64 = 0.1;
+
+fn clamp_unit(x: f64) -> f64 {
+    if x > 1.0 {
+        1.0
+    } else if x < -1.0 {
+        -1.0
+    } else {
+        x
+    }
+}
+
+/// Scales both sides down together until neither is past 1, so a saturated
+/// command keeps the ratio between the wheels instead of losing the turn
+fn desaturate(l: f64, r: f64) -> LR<f64> {
+    let biggest = l.abs().max(r.abs()).max(1.0);
+    LR {
+        l: l / biggest,
+        r: r / biggest,
+    }
+}
+
+/// Maps the per-side axes straight onto the wheels, clamped to [-1, 1]
+pub fn tank(left_axis: f64, right_axis: f64) -> LR<f64> {
+    LR {
+        l: clamp_unit(left_axis),
+        r: clamp_unit(right_axis),
+    }
+}
+
+/// One axis for throttle, one for turning, added together. The turn rate
+/// stays the same at any speed, so turns get wider the faster the robot goes.
+pub fn arcade(xaxis: f64, yaxis: f64) -> LR<f64> {
+    let (x, y) = (clamp_unit(xaxis), clamp_unit(yaxis));
+    desaturate(y + x, y - x)
+}
+
+/// Like arcade, but the turn is scaled by the throttle, so the stick picks a
+/// curve that's the same at any speed. That leaves no way to turn while
+/// stopped, so with `quick_turn` it drives as arcade instead.
+pub fn curvature(xaxis: f64, yaxis: f64, quick_turn: bool) -> LR<f64> {
+    if quick_turn {
+        return arcade(xaxis, yaxis);
+    }
+    let (x, y) = (clamp_unit(xaxis), clamp_unit(yaxis));
+    let turn = x * y.abs();
+    desaturate(y + turn, y - turn)
+}
+
+#[test]
+fn arcade_mix_signs() {
+    let fwd = arcade(0., 1.);
+    assert_eq!((fwd.l, fwd.r), (1., 1.));
+    let right = arcade(1., 0.);
+    assert_eq!((right.l, right.r), (1., -1.));
+    let left = arcade(-1., 0.);
+    assert_eq!((left.l, left.r), (-1., 1.));
+    let sat = arcade(1., 1.);
+    assert_eq!((sat.l, sat.r), (1., 0.));
+    // scaled down together rather than clipped
+    let part = arcade(0.5, 1.);
+    assert_eq!(part.l, 1.);
+    assert!((part.r - 1. / 3.).abs() < 1e-12);
+}
+
+#[test]
+fn tank_mix_signs() {
+    let out = tank(1., -0.5);
+    assert_eq!((out.l, out.r), (1., -0.5));
+    let out = tank(-2., 2.);
+    assert_eq!((out.l, out.r), (-1., 1.));
+}
+
+#[test]
+fn curvature_mix_holds_curve() {
+    let fwd = curvature(0., 1., false);
+    assert_eq!((fwd.l, fwd.r), (1., 1.));
+    // no throttle, no turn, unless turning in place
+    let still = curvature(1., 0., false);
+    assert_eq!((still.l, still.r), (0., 0.));
+    let spin = curvature(1., 0., true);
+    assert_eq!((spin.l, spin.r), (1., -1.));
+    // the same stick gives the same ratio between the wheels at any speed
+    let slow = curvature(0.5, 0.2, false);
+    let fast = curvature(0.5, 0.8, false);
+    assert!((slow.r / slow.l - fast.r / fast.l).abs() < 1e-12);
+    // backing up with the stick right swings the nose right, as going forward
+    let back = curvature(0.5, -0.8, false);
+    assert!(back.l > back.r);
+    assert_eq!(back.r, -1.);
+    for &(x, y) in &[(1., 1.), (-1., 1.), (1., -1.), (3., -2.)] {
+        let out = curvature(x, y, false);
+        assert!(out.l.abs() <= 1. && out.r.abs() <= 1.);
+        assert_eq!(out.l.abs().max(out.r.abs()), 1.);
+    }
+}
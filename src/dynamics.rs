@@ -1,36 +1,122 @@
 //! https://www.omicsonline.org/open-access/dynamic-modelling-of-differentialdrive-mobile-robots-using-lagrange-and-newtoneuler-methodologies-a-unified-framework-2168-9695.1000107.pdf
 
+use crate::signals::{Lowpass, SlewLimiter};
+
 use dimensioned::si::*;
 use dimensioned::tarr;
-use dimensioned::typenum::{N1, N2, P1, P2, Z0};
+use dimensioned::traits::Abs;
+use dimensioned::typenum::{N1, N2, N3, N4, P1, P2, P3, Z0};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use std::fmt::Debug;
 use std::ops::{Add, Div, Mul, Sub};
 
+pub mod raw;
+pub mod state;
+
+/// How an `Integrator` turns samples into area, or how a `DDMRModel` steps
+/// its velocities and pose
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum IntegrationMethod {
+    /// Forward Euler on the latest sample
+    Euler,
+    /// Average of the previous and latest sample, exact for linear inputs.
+    /// The model averages the rates at the start and the Euler-predicted end
+    /// of the step (Heun's method).
+    Trapezoidal,
+    /// Classic fourth order Runge-Kutta. An `Integrator` only ever sees one
+    /// sample per step, not the rates within it, so treats this as `Trapezoidal`.
+    RK4,
+}
+
 #[derive(Debug, Clone)]
 pub struct Integrator<U>
 where
     U: Add<tarr![Z0, Z0, P1, Z0, Z0, Z0, Z0]>,
     <SI<f64, U> as Mul<Second<f64>>>::Output: Debug + Copy + Clone,
+    SI<f64, U>: Copy + Clone + Debug,
 {
     dt: Second<f64>,
+    initial: <SI<f64, U> as Mul<Second<f64>>>::Output,
     acc: <SI<f64, U> as Mul<Second<f64>>>::Output,
+    method: IntegrationMethod,
+    last: SI<f64, U>,
 }
 
 impl<U> Integrator<U>
 where
     U: Add<tarr![Z0, Z0, P1, Z0, Z0, Z0, Z0]>,
     <SI<f64, U> as Mul<Second<f64>>>::Output: Debug + Copy + Clone,
+    SI<f64, U>: Copy + Clone + Debug,
 {
     pub fn new(dt: Second<f64>, initial: <SI<f64, U> as Mul<Second<f64>>>::Output) -> Self {
-        Self { dt, acc: initial }
+        Self::with_method(dt, initial, IntegrationMethod::Euler)
+    }
+
+    pub fn new_trapezoidal(
+        dt: Second<f64>,
+        initial: <SI<f64, U> as Mul<Second<f64>>>::Output,
+    ) -> Self {
+        Self::with_method(dt, initial, IntegrationMethod::Trapezoidal)
+    }
+
+    /// The trapezoidal method assumes the sample before the first `add` was zero
+    pub fn with_method(
+        dt: Second<f64>,
+        initial: <SI<f64, U> as Mul<Second<f64>>>::Output,
+        method: IntegrationMethod,
+    ) -> Self {
+        Self {
+            dt,
+            initial,
+            acc: initial,
+            method,
+            last: SI::new(0.),
+        }
     }
 
     pub fn get(&self) -> <SI<f64, U> as Mul<Second<f64>>>::Output {
         self.acc
     }
 
+    /// Overwrites the accumulated value, e.g. when something outside the model
+    /// constrains it
+    pub fn set(&mut self, val: <SI<f64, U> as Mul<Second<f64>>>::Output) {
+        self.acc = val;
+    }
+
+    /// Back to the state it was constructed in
+    pub fn reset(&mut self) {
+        self.acc = self.initial;
+        self.last = SI::new(0.);
+    }
+
+    pub fn dt(&self) -> Second<f64> {
+        self.dt
+    }
+
+    /// The timestep `add` assumes from now on
+    pub fn set_dt(&mut self, dt: Second<f64>) {
+        self.dt = dt;
+    }
+
     pub fn add(&mut self, val: SI<f64, U>) -> <SI<f64, U> as Mul<Second<f64>>>::Output {
-        self.acc += val.mul(self.dt);
+        let dt = self.dt;
+        self.add_dt(val, dt)
+    }
+
+    /// `add`, for a sample `dt` after the last one
+    pub fn add_dt(
+        &mut self,
+        val: SI<f64, U>,
+        dt: Second<f64>,
+    ) -> <SI<f64, U> as Mul<Second<f64>>>::Output {
+        let rate = match self.method {
+            IntegrationMethod::Euler => val,
+            IntegrationMethod::Trapezoidal | IntegrationMethod::RK4 => (self.last + val) * 0.5,
+        };
+        self.last = val;
+        self.acc += rate.mul(dt);
         self.get()
     }
 }
@@ -43,6 +129,59 @@ fn integrator() {
     assert_eq!(i.get(), 0.005 * (1.0 + 0.05) * M);
 }
 
+#[test]
+fn integrator_ramp() {
+    use dimensioned::traits::Abs;
+    let dt = 0.005 * S;
+    let slope = 3. * MPS2;
+    let mut euler = Integrator::new(dt, 0. * M);
+    let mut trap = Integrator::new_trapezoidal(dt, 0. * M);
+    // integrating v = a * t should give x = a * t^2 / 2
+    for k in 1..=200 {
+        let v = slope * (k as f64 * dt);
+        euler.add(v);
+        trap.add(v);
+    }
+    let t = 200. * dt;
+    let exact = 0.5 * slope * t * t;
+    assert!((trap.get() - exact).abs() < 1e-9 * M);
+    assert!((euler.get() - exact).abs() > 1e-3 * M);
+}
+
+#[test]
+fn integrator_variable_dt() {
+    use dimensioned::traits::Abs;
+    let slope = 3. * MPS2;
+    let mut euler = Integrator::new(0.01 * S, 0. * M);
+    let mut trap = Integrator::new_trapezoidal(0.01 * S, 0. * M);
+    let mut t = 0. * S;
+    for k in 0..300 {
+        // the step size changes partway through, both ways
+        let dt = match k {
+            0..=99 => 0.01 * S,
+            100..=199 => 0.002 * S,
+            _ => 0.005 * S,
+        };
+        if k == 100 {
+            euler.set_dt(dt);
+        }
+        t += dt;
+        if k < 100 {
+            euler.add(2. * MPS);
+        } else {
+            euler.add_dt(2. * MPS, dt);
+        }
+        trap.add_dt(slope * t, dt);
+    }
+    // 1 s, 0.2 s and 0.5 s
+    assert!((euler.get() - 2. * MPS * 1.7 * S).abs() < 1e-9 * M);
+    // still exact for a ramp with uneven steps
+    assert!((trap.get() - 0.5 * slope * t * t).abs() < 1e-9 * M);
+}
+
+/// Backward difference of the samples it's given. By default that's just the
+/// last two samples; `with_window` spans more of them and `with_filter` low-passes
+/// the signal first, both trading lag for less noise.
 #[derive(Debug, Clone)]
 pub struct Differentiator<U>
 where
@@ -50,8 +189,13 @@ where
     SI<f64, U>: Copy + Clone + Debug,
 {
     dt: Second<f64>,
-    last: SI<f64, U>,
-    two: SI<f64, U>,
+    initial: SI<f64, U>,
+    /// Oldest first. With a filter these are filtered values, and there are always two.
+    samples: VecDeque<SI<f64, U>>,
+    /// The time between each pair of `samples`, oldest first
+    spans: VecDeque<Second<f64>>,
+    /// Always holds the newest sample
+    filter: Option<Lowpass<U>>,
 }
 
 impl<U> Differentiator<U>
@@ -60,33 +204,195 @@ where
     SI<f64, U>: Copy + Clone + Debug,
 {
     pub fn new(dt: Second<f64>, initial: SI<f64, U>) -> Self {
+        Self::with_window(dt, initial, 2)
+    }
+
+    /// Differences the newest sample against the one `n - 1` samples back
+    pub fn with_window(dt: Second<f64>, initial: SI<f64, U>, n: usize) -> Self {
+        assert!(n >= 2, "a derivative needs at least two samples");
         Self {
             dt,
-            last: initial,
-            two: initial,
+            initial,
+            samples: std::iter::repeat(initial).take(n).collect(),
+            spans: std::iter::repeat(dt).take(n - 1).collect(),
+            filter: None,
+        }
+    }
+
+    /// Runs the samples through a first order low-pass with the given cutoff
+    /// frequency, in cycles per second, before differencing them
+    pub fn with_filter(dt: Second<f64>, initial: SI<f64, U>, cutoff: Hertz<f64>) -> Self {
+        let tau = 1. / (2. * std::f64::consts::PI * *(cutoff * S));
+        Self::with_time_constant(dt, initial, tau * S)
+    }
+
+    /// `with_filter`, given the low-pass time constant instead of its cutoff.
+    /// A step of `h` then reads as at most `h / tau` rather than `h / dt`.
+    pub fn with_time_constant(dt: Second<f64>, initial: SI<f64, U>, tau: Second<f64>) -> Self {
+        Self {
+            filter: Some(Lowpass::new(dt, tau, initial)),
+            ..Self::new(dt, initial)
+        }
+    }
+
+    /// Forgets the history, so the signal reads as having sat at `val` forever
+    /// and the derivative is zero
+    pub fn set(&mut self, val: SI<f64, U>) {
+        for s in self.samples.iter_mut() {
+            *s = val;
+        }
+        if let Some(ref mut f) = self.filter {
+            f.set(val);
+        }
+    }
+
+    /// Back to the state it was constructed in, but at the current `dt`
+    pub fn reset(&mut self) {
+        let initial = self.initial;
+        self.set(initial);
+        self.reset_spans();
+    }
+
+    /// Takes every sample held to have been `dt` apart
+    fn reset_spans(&mut self) {
+        let dt = self.dt;
+        for s in self.spans.iter_mut() {
+            *s = dt;
         }
     }
 
+    /// The timestep `add` assumes from now on. Samples already taken keep
+    /// the spacing they were added with.
+    pub fn set_dt(&mut self, dt: Second<f64>) {
+        self.dt = dt;
+        if let Some(ref mut f) = self.filter {
+            f.set_dt(dt);
+        }
+    }
+
+    /// The latest sample, after the filter if there is one
+    pub fn newest(&self) -> SI<f64, U> {
+        self.samples[self.samples.len() - 1]
+    }
+
     pub fn get(&self) -> <SI<f64, U> as Div<Second<f64>>>::Output {
-        (self.last - self.two) / self.dt
+        let span = self.spans.iter().fold(0. * S, |total, &dt| total + dt);
+        (self.samples[self.samples.len() - 1] - self.samples[0]) / span
     }
 
     pub fn add(&mut self, val: SI<f64, U>) -> <SI<f64, U> as Div<Second<f64>>>::Output {
-        self.two = self.last;
-        self.last = val;
+        let dt = self.dt;
+        self.add_dt(val, dt)
+    }
+
+    /// `add`, for a sample `dt` after the last one
+    pub fn add_dt(
+        &mut self,
+        val: SI<f64, U>,
+        dt: Second<f64>,
+    ) -> <SI<f64, U> as Div<Second<f64>>>::Output {
+        let val = match self.filter {
+            Some(ref mut f) => f.add_dt(val, dt),
+            None => val,
+        };
+        self.samples.pop_front();
+        self.samples.push_back(val);
+        self.spans.pop_front();
+        self.spans.push_back(dt);
         self.get()
     }
 }
 
 #[test]
 fn differentiator() {
-    use dimensioned::traits::Abs;
     let mut d = Differentiator::new(0.005 * S, 0. * M);
     assert_eq!(d.get(), 0.0 * MPS);
     d.add(1.0 * M);
     assert!((d.add(1.2 * M) - 40. * MPS).abs() < 0.0001 * MPS);
 }
 
+#[test]
+fn differentiator_default_is_two_sample_difference() {
+    let dt = 0.01 * S;
+    let mut d = Differentiator::new(dt, 0. * M);
+    let mut prev = 0. * M;
+    for k in 0..50u64 {
+        let x = (k * k % 7) as f64 * 0.3 * M - k as f64 * 0.01 * M;
+        // bit for bit, not just close
+        assert_eq!(d.add(x), (x - prev) / dt);
+        prev = x;
+    }
+}
+
+#[test]
+fn differentiator_spreads_a_step() {
+    let dt = 0.005 * S;
+    let tau = 0.05 * S;
+    let mut raw = Differentiator::new(dt, 0. * M);
+    let mut filtered = Differentiator::with_time_constant(dt, 0. * M, tau);
+    let raw_out: Vec<f64> = (0..400).map(|_| *(raw.add(1. * M) / MPS)).collect();
+    let out: Vec<f64> = (0..400).map(|_| *(filtered.add(1. * M) / MPS)).collect();
+    // unfiltered, the whole step lands in one sample
+    assert!((raw_out[0] - 200.).abs() < 1e-9);
+    assert!(raw_out[1..].iter().all(|&v| v == 0.));
+    let peak = out.iter().cloned().fold(0., f64::max);
+    assert!(peak <= 1. / *(tau / S), "{}", peak);
+    assert!(out.iter().all(|&v| v >= 0.));
+    // still a good part of the peak a time constant later
+    assert!(out[10] > 0.3 * peak, "{} of {}", out[10], peak);
+    // and it still adds up to the step
+    let total = out.iter().sum::<f64>() * *(dt / S);
+    assert!((total - 1.).abs() < 1e-6, "{}", total);
+}
+
+#[test]
+fn differentiator_smoothing() {
+    let dt = 0.005 * S;
+    let slope = 2. * MPS;
+    // deterministic jitter of up to a millimeter
+    let noise = |k: u64| (((k * k * 31 + 7 * k) % 101) as f64 / 100. - 0.5) * 0.002 * M;
+    let stats = |mut d: Differentiator<_>| {
+        let out: Vec<f64> = (1..1000u64)
+            .map(|k| *(d.add(slope * (k as f64 * dt) + noise(k)) / MPS))
+            .skip(200)
+            .collect();
+        let mean = out.iter().sum::<f64>() / out.len() as f64;
+        let var = out.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / out.len() as f64;
+        (mean, var)
+    };
+    let (raw_mean, raw_var) = stats(Differentiator::new(dt, 0. * M));
+    let (win_mean, win_var) = stats(Differentiator::with_window(dt, 0. * M, 10));
+    let (filt_mean, filt_var) = stats(Differentiator::with_filter(dt, 0. * M, 5. * HZ));
+    for mean in &[raw_mean, win_mean, filt_mean] {
+        assert!((mean - 2.).abs() < 0.01, "{}", mean);
+    }
+    assert!(win_var < raw_var / 10.);
+    assert!(filt_var < raw_var / 10.);
+}
+
+#[test]
+fn differentiator_variable_dt() {
+    let slope = 3. * MPS;
+    let mut d = Differentiator::new(0.01 * S, 0. * M);
+    let mut window = Differentiator::with_window(0.01 * S, 0. * M, 4);
+    let mut t = 0. * S;
+    for k in 0..40 {
+        let dt = if k < 20 { 0.01 * S } else { 0.002 * S };
+        if k == 20 {
+            d.set_dt(dt);
+        }
+        t += dt;
+        let out = d.add(slope * t);
+        let win = window.add_dt(slope * t, dt);
+        let (out, win) = (*(out / MPS), *(win / MPS));
+        assert!((out - 3.).abs() < 1e-9, "{} at {}", out, k);
+        // once it's full of the ramp, including while it straddles both step sizes
+        if k >= 3 {
+            assert!((win - 3.).abs() < 1e-9, "{} at {}", win, k);
+        }
+    }
+}
+
 /// Moment of Inertia
 pub type KilogramMeter2<V> = SI<V, tarr![P2, P1, Z0, Z0, Z0, Z0, Z0]>;
 /// Torque constant
@@ -95,6 +401,14 @@ pub type NewtonMeterPerAmpere<V> = SI<V, tarr![P2, P1, N2, N1, Z0, Z0, Z0]>;
 pub type VoltSecond<V> = SI<V, tarr![P2, P1, N2, N1, Z0, Z0, Z0]>;
 /// Torque
 pub type NewtonMeter<V> = SI<V, tarr![P2, P1, N2, Z0, Z0, Z0, Z0]>;
+/// Viscous friction coefficient, torque per angular velocity
+pub type NewtonMeterSecond<V> = SI<V, tarr![P2, P1, N1, Z0, Z0, Z0, Z0]>;
+/// Voltage ramp rate
+pub type VoltPerSecond<V> = SI<V, tarr![P2, P1, N4, N1, Z0, Z0, Z0]>;
+/// Heat capacity
+pub type JoulePerKelvin<V> = SI<V, tarr![P2, P1, N2, Z0, N1, Z0, Z0]>;
+/// Thermal resistance, temperature rise per power carried away
+pub type KelvinPerWatt<V> = SI<V, tarr![N2, N1, P3, Z0, P1, Z0, Z0]>;
 
 #[allow(non_snake_case)]
 #[derive(Debug, Clone)]
@@ -128,6 +442,17 @@ pub struct DCMotorParams {
     pub Kb: VoltSecond<f64>,
     /// Kt = Torque constance, such that `tau_m = K_t * i_a`
     pub Kt: NewtonMeterPerAmpere<f64>,
+    /// eta = gearbox efficiency, such that `tau_w = N * eta * tau_m`
+    pub eta: f64,
+    /// Jr = moment of inertia of the rotor about its own axis
+    pub Jr: KilogramMeter2<f64>,
+}
+
+impl DCMotorParams {
+    /// The rotor's inertia as the wheel feels it through the gearbox, `N^2 * Jr`
+    pub fn reflected_inertia(&self) -> KilogramMeter2<f64> {
+        self.N * self.N * self.Jr
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -146,32 +471,283 @@ impl Default for Vels {
     }
 }
 
-#[derive(Debug, Copy, Clone, Default)]
+/// Outside forces on the chassis: a force along its heading, backward for a
+/// negative one, and a torque about its center, counterclockwise positive
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Disturbance {
+    pub force: Newton<f64>,
+    pub torque: NewtonMeter<f64>,
+}
+
+impl Default for Disturbance {
+    fn default() -> Self {
+        Self {
+            force: 0. * N,
+            torque: 0. * N * M,
+        }
+    }
+}
+
+impl Add for Disturbance {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        Self {
+            force: self.force + other.force,
+            torque: self.torque + other.torque,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct LR<T> {
     pub l: T,
     pub r: T,
 }
 
+/// Position of the robot in world coordinates
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Pose {
+    pub x: Meter<f64>,
+    pub y: Meter<f64>,
+    /// Heading in radians, counterclockwise from the x axis
+    pub theta: f64,
+}
+
+impl Default for Pose {
+    fn default() -> Self {
+        Self {
+            x: 0. * M,
+            y: 0. * M,
+            theta: 0.,
+        }
+    }
+}
+
+/// How the pose is advanced from the chassis velocities each timestep
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PoseIntegration {
+    /// Drive along the heading at the start of the step
+    Euler,
+    /// Drive along the heading halfway through the step (RK2), which matters when
+    /// the heading changes a lot within one step
+    Midpoint,
+}
+
 type Acceleration = tarr![P1, Z0, N2, Z0, Z0, Z0, Z0];
 type AngularAcceleration = tarr![Z0, Z0, N2, Z0, Z0, Z0, Z0];
 type Current = tarr![Z0, Z0, Z0, P1, Z0, Z0, Z0];
+type Voltage = tarr![P2, P1, N3, N1, Z0, Z0, Z0];
+type AngularVelocity = tarr![Z0, Z0, N1, Z0, Z0, Z0, Z0];
+
+/// What a higher order step of the chassis advances
+#[derive(Debug, Copy, Clone)]
+struct ChassisState {
+    vel: Vels,
+    pose: Pose,
+}
+
+/// Rate of change of a `ChassisState`
+#[derive(Debug, Copy, Clone)]
+struct ChassisRate {
+    lin: MeterPerSecond2<f64>,
+    ang: SI<f64, AngularAcceleration>,
+    x: MeterPerSecond<f64>,
+    y: MeterPerSecond<f64>,
+    theta: Hertz<f64>,
+}
+
+impl ChassisState {
+    fn advance(&self, rate: &ChassisRate, dt: Second<f64>) -> Self {
+        Self {
+            vel: Vels {
+                lin: self.vel.lin + rate.lin * dt,
+                ang: self.vel.ang + rate.ang * dt,
+            },
+            pose: Pose {
+                x: self.pose.x + rate.x * dt,
+                y: self.pose.y + rate.y * dt,
+                theta: self.pose.theta + *(rate.theta * dt),
+            },
+        }
+    }
+}
+
+impl ChassisRate {
+    /// The weighted sum of the stages of a step
+    fn weighted(stages: &[(f64, &ChassisRate)]) -> Self {
+        let zero = Self {
+            lin: 0. * MPS2,
+            ang: SI::new(0.),
+            x: 0. * MPS,
+            y: 0. * MPS,
+            theta: 0. * HZ,
+        };
+        stages.iter().fold(zero, |sum, &(w, k)| Self {
+            lin: sum.lin + w * k.lin,
+            ang: sum.ang + w * k.ang,
+            x: sum.x + w * k.x,
+            y: sum.y + w * k.y,
+            theta: sum.theta + w * k.theta,
+        })
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct DDMRModel {
     p: DDMRParams,
+    dt: Second<f64>,
     linv: Integrator<Acceleration>,
     angv: Integrator<AngularAcceleration>,
+    pose: Pose,
+    pose_method: PoseIntegration,
+    method: IntegrationMethod,
+    traction: Option<TractionParams>,
+    slipping: LR<bool>,
+    /// How much faster each wheel turns than the ground under it, zero while
+    /// it grips
+    slip: LR<Hertz<f64>>,
+    wheel_angle: LR<Integrator<AngularVelocity>>,
+    odometry: Pose,
+    /// Turning with each wheel on top of `Iw`, e.g. its motor's rotor
+    extra_inertia: LR<KilogramMeter2<f64>>,
+    /// Pushes the chassis for the next step only
+    pending: Disturbance,
+    /// What pushed the chassis during the last step
+    disturbance: Disturbance,
 }
 
 impl DDMRModel {
     pub fn new(dt: Second<f64>, param: DDMRParams) -> Self {
+        Self::new_with_method(dt, param, IntegrationMethod::Euler)
+    }
+
+    /// With `Trapezoidal` or `RK4` the velocities and pose are stepped together
+    /// and the `PoseIntegration` is unused. The torques are held for the
+    /// whole step either way.
+    pub fn new_with_method(dt: Second<f64>, param: DDMRParams, method: IntegrationMethod) -> Self {
         Self {
             p: param,
+            dt,
             linv: Integrator::new(dt, 0. * MPS),
             angv: Integrator::new(dt, 0. * HZ),
+            pose: Pose::default(),
+            pose_method: PoseIntegration::Euler,
+            method,
+            traction: None,
+            slipping: LR { l: false, r: false },
+            slip: LR {
+                l: 0. * HZ,
+                r: 0. * HZ,
+            },
+            wheel_angle: LR {
+                l: Integrator::new(dt, 0. * HZ * S),
+                r: Integrator::new(dt, 0. * HZ * S),
+            },
+            odometry: Pose::default(),
+            extra_inertia: LR {
+                l: 0. * KG * M * M,
+                r: 0. * KG * M * M,
+            },
+            pending: Disturbance::default(),
+            disturbance: Disturbance::default(),
+        }
+    }
+
+    /// The same robot with a different timestep, at rest at the origin
+    pub fn with_dt(&self, dt: Second<f64>) -> Self {
+        Self {
+            traction: self.traction.clone(),
+            pose_method: self.pose_method,
+            extra_inertia: self.extra_inertia,
+            ..Self::new_with_method(dt, self.p.clone(), self.method)
+        }
+    }
+
+    /// Inertia that turns with each wheel besides the wheel itself, like the
+    /// rotor behind a gearbox, slowing the robot's acceleration. Zero unless
+    /// set.
+    pub fn set_extra_inertia(&mut self, inertia: LR<KilogramMeter2<f64>>) {
+        self.extra_inertia = inertia;
+    }
+
+    /// Each wheel's `Iw` plus its extra inertia
+    pub fn wheel_inertia(&self) -> LR<KilogramMeter2<f64>> {
+        LR {
+            l: self.p.Iw + self.extra_inertia.l,
+            r: self.p.Iw + self.extra_inertia.r,
         }
     }
 
+    /// Steps by `dt` from now on, carrying on from the current state
+    pub fn set_dt(&mut self, dt: Second<f64>) {
+        self.dt = dt;
+        self.linv.set_dt(dt);
+        self.angv.set_dt(dt);
+        self.wheel_angle.l.set_dt(dt);
+        self.wheel_angle.r.set_dt(dt);
+    }
+
+    /// Without traction params the wheels never slip. Any wheel slipping now
+    /// grips again straight away.
+    pub fn set_traction(&mut self, traction: Option<TractionParams>) {
+        self.traction = traction;
+        self.slipping = LR { l: false, r: false };
+        self.slip = LR {
+            l: 0. * HZ,
+            r: 0. * HZ,
+        };
+    }
+
+    pub fn traction(&self) -> Option<&TractionParams> {
+        self.traction.as_ref()
+    }
+
+    /// Which wheels are turning at a different speed than the ground under
+    /// them after the last `observe`
+    pub fn slipping(&self) -> LR<bool> {
+        self.slipping
+    }
+
+    /// How much faster each wheel turns than its ground speed, negative when
+    /// it's dragged along or spun backwards
+    pub fn slip(&self) -> LR<Hertz<f64>> {
+        self.slip
+    }
+
+    pub fn set_pose_integration(&mut self, method: PoseIntegration) {
+        self.pose_method = method;
+    }
+
+    pub fn integration_method(&self) -> IntegrationMethod {
+        self.method
+    }
+
+    pub fn pose(&self) -> Pose {
+        self.pose
+    }
+
+    /// Puts the robot at `pose` without changing how it's moving. Odometry
+    /// starts over from there too, as when a real robot is told where it is.
+    pub fn reset_pose(&mut self, pose: Pose) {
+        self.pose = pose;
+        self.odometry = pose;
+    }
+
+    /// Advances the pose by one timestep at the given chassis velocities.
+    /// The robot can only drive along its heading (the nonholonomic constraint),
+    /// so the displacement is always projected onto the heading.
+    fn integrate_pose(&mut self, v: Vels) {
+        let dtheta = *(v.ang * self.dt);
+        let heading = match self.pose_method {
+            PoseIntegration::Euler => self.pose.theta,
+            PoseIntegration::Midpoint => self.pose.theta + dtheta / 2.,
+        };
+        let dist = v.lin * self.dt;
+        self.pose.x += dist * heading.cos();
+        self.pose.y += dist * heading.sin();
+        self.pose.theta += dtheta;
+    }
+
     pub fn vel(&self) -> Vels {
         Vels {
             lin: self.linv.get(),
@@ -179,19 +755,199 @@ impl DDMRModel {
         }
     }
 
+    /// A force on the chassis along its heading during the next `observe`
+    /// only, backward for a negative one, like the kick of something fired
+    /// off the robot. Same as `apply_disturbance` without a torque.
+    pub fn push(&mut self, force: Newton<f64>) {
+        self.apply_disturbance(force, 0. * N * M);
+    }
+
+    /// A force along the heading and a torque about the center, counterclockwise
+    /// positive, during the next `observe` only. They're added on as torques
+    /// at each wheel where the wheels meet the ground, `R * force / 2` on both
+    /// and `R * torque / 2L` on opposite sides, so they never make them slip.
+    /// Disturbances before the same step add up.
+    pub fn apply_disturbance(&mut self, force: Newton<f64>, torque_about_center: NewtonMeter<f64>) {
+        self.pending = self.pending
+            + Disturbance {
+                force,
+                torque: torque_about_center,
+            };
+    }
+
+    /// What was pushing on the chassis during the last `observe`
+    pub fn disturbance(&self) -> Disturbance {
+        self.disturbance
+    }
+
+    /// Forces the chassis velocities, e.g. to stop the robot against a wall
+    pub fn set_vels(&mut self, v: Vels) {
+        self.linv.set(v.lin);
+        self.angv.set(v.ang);
+    }
+
+    /// Back at the origin and at rest, as if freshly constructed
+    pub fn reset(&mut self) {
+        self.linv.reset();
+        self.angv.reset();
+        self.pose = Pose::default();
+        self.slipping = LR { l: false, r: false };
+        self.slip = LR {
+            l: 0. * HZ,
+            r: 0. * HZ,
+        };
+        self.wheel_angle.l.reset();
+        self.wheel_angle.r.reset();
+        self.odometry = Pose::default();
+        self.pending = Disturbance::default();
+        self.disturbance = Disturbance::default();
+    }
+
+    /// Total rotation of each wheel in radians, forward positive
+    pub fn wheel_angles(&self) -> LR<f64> {
+        LR {
+            l: *self.wheel_angle.l.get(),
+            r: *self.wheel_angle.r.get(),
+        }
+    }
+
+    /// How far each wheel has rolled
+    pub fn wheel_distances(&self) -> LR<Meter<f64>> {
+        let a = self.wheel_angles();
+        LR {
+            l: a.l * self.p.R,
+            r: a.r * self.p.R,
+        }
+    }
+
+    /// The pose dead-reckoned from the wheel distances alone, the way a robot
+    /// would track itself. Matches `pose` as long as the wheels roll perfectly.
+    pub fn odometry_pose(&self) -> Pose {
+        self.odometry
+    }
+
+    /// Standard differential-drive odometry on one step's wheel travel,
+    /// using the heading halfway through the step
+    fn integrate_odometry(&mut self, before: LR<Meter<f64>>) {
+        let after = self.wheel_distances();
+        let dl = after.l - before.l;
+        let dr = after.r - before.r;
+        let dist = (dl + dr) / 2.;
+        let dtheta = *((dr - dl) / (2. * self.p.L));
+        let heading = self.odometry.theta + dtheta / 2.;
+        self.odometry.x += dist * heading.cos();
+        self.odometry.y += dist * heading.sin();
+        self.odometry.theta += dtheta;
+    }
+
     // equation 47
     pub fn observe(&mut self, tau: LR<NewtonMeter<f64>>) -> Vels {
+        let applied = tau;
+        let spin_before = self.wheels();
+        let tau = match self.traction {
+            Some(ref t) => {
+                let max = t.max_torque(&self.p);
+                LR {
+                    l: contact_torque(tau.l, max.l, self.slip.l),
+                    r: contact_torque(tau.r, max.r, self.slip.r),
+                }
+            }
+            None => tau,
+        };
+        self.disturbance = self.pending;
+        self.pending = Disturbance::default();
+        let push = self.disturbance.force * self.p.R / 2.;
+        let twist = self.disturbance.torque * self.p.R / (2. * self.p.L);
+        let chassis = LR {
+            l: tau.l + push - twist,
+            r: tau.r + push + twist,
+        };
+        let v = match self.method {
+            IntegrationMethod::Euler => {
+                let (vdot, wdot) = self.accel(chassis, self.vel());
+                let v = Vels {
+                    lin: self.linv.add(vdot),
+                    ang: self.angv.add(wdot),
+                };
+                self.integrate_pose(v);
+                v
+            }
+            IntegrationMethod::Trapezoidal | IntegrationMethod::RK4 => self.step_chassis(chassis),
+        };
+        if self.traction.is_some() {
+            let ground = self.vels_to_wheel(v);
+            let iw = self.wheel_inertia();
+            self.slip = LR {
+                l: self.slip_after(applied.l, tau.l, spin_before.l, ground.l, self.slip.l, iw.l),
+                r: self.slip_after(applied.r, tau.r, spin_before.r, ground.r, self.slip.r, iw.r),
+            };
+            self.slipping = LR {
+                l: self.slip.l != 0. * HZ,
+                r: self.slip.r != 0. * HZ,
+            };
+        }
+
+        let before = self.wheel_distances();
+        let wheels = self.wheels();
+        self.wheel_angle.l.add(wheels.l);
+        self.wheel_angle.r.add(wheels.r);
+        self.integrate_odometry(before);
+        v
+    }
+
+    /// The chassis accelerations under `tau` when moving at `v`
+    fn accel(
+        &self,
+        tau: LR<NewtonMeter<f64>>,
+        v: Vels,
+    ) -> (MeterPerSecond2<f64>, SI<f64, AngularAcceleration>) {
         let p = &self.p;
-        let vdot: MeterPerSecond2<f64> = ((tau.r + tau.l) / p.R
-            + p.mc * p.d * self.angv.get() * self.angv.get())
-            / (p.m + 2. * p.Iw / p.R / p.R);
+        let iw = self.wheel_inertia();
+        let vdot: MeterPerSecond2<f64> = ((tau.r + tau.l) / p.R + p.mc * p.d * v.ang * v.ang)
+            / (p.m + (iw.l + iw.r) / p.R / p.R);
         let wdot: SI<f64, AngularAcceleration> = ((tau.r - tau.l) * p.L / p.R
-            - p.mc * p.d * self.angv.get() * self.linv.get())
-            / (p.I + 2. * p.L * p.L * p.Iw / p.R / p.R);
+            - p.mc * p.d * v.ang * v.lin)
+            / (p.I + p.L * p.L * (iw.l + iw.r) / p.R / p.R);
+        (vdot, wdot)
+    }
+
+    fn chassis_rate(&self, tau: LR<NewtonMeter<f64>>, s: &ChassisState) -> ChassisRate {
+        let (lin, ang) = self.accel(tau, s.vel);
+        ChassisRate {
+            lin,
+            ang,
+            x: s.vel.lin * s.pose.theta.cos(),
+            y: s.vel.lin * s.pose.theta.sin(),
+            theta: s.vel.ang,
+        }
+    }
 
+    /// One `Trapezoidal` or `RK4` step of the velocities and pose together
+    fn step_chassis(&mut self, tau: LR<NewtonMeter<f64>>) -> Vels {
+        let dt = self.dt;
+        let start = ChassisState {
+            vel: self.vel(),
+            pose: self.pose,
+        };
+        let k1 = self.chassis_rate(tau, &start);
+        let rate = if self.method == IntegrationMethod::RK4 {
+            let k2 = self.chassis_rate(tau, &start.advance(&k1, dt / 2.));
+            let k3 = self.chassis_rate(tau, &start.advance(&k2, dt / 2.));
+            let k4 = self.chassis_rate(tau, &start.advance(&k3, dt));
+            ChassisRate::weighted(&[
+                (1. / 6., &k1),
+                (1. / 3., &k2),
+                (1. / 3., &k3),
+                (1. / 6., &k4),
+            ])
+        } else {
+            let k2 = self.chassis_rate(tau, &start.advance(&k1, dt));
+            ChassisRate::weighted(&[(0.5, &k1), (0.5, &k2)])
+        };
+        self.pose = start.advance(&rate, dt).pose;
         Vels {
-            lin: self.linv.add(vdot),
-            ang: self.angv.add(wdot),
+            lin: self.linv.add(rate.lin),
+            ang: self.angv.add(rate.ang),
         }
     }
 
@@ -202,68 +958,2115 @@ impl DDMRModel {
         }
     }
 
+    /// How fast the wheels actually turn, which is faster or slower than the
+    /// chassis moves them while they slip
     pub fn wheels(&self) -> LR<Hertz<f64>> {
-        self.vels_to_wheel(self.vel())
+        let ground = self.vels_to_wheel(self.vel());
+        LR {
+            l: ground.l + self.slip.l,
+            r: ground.r + self.slip.r,
+        }
+    }
+
+    /// One wheel's slip after a step in which it was `applied` a torque and
+    /// put `contact` of it into the ground. Whatever doesn't reach the ground
+    /// spins the wheel's own `inertia`, until it turns at its ground speed
+    /// again.
+    fn slip_after(
+        &self,
+        applied: NewtonMeter<f64>,
+        contact: NewtonMeter<f64>,
+        spin_before: Hertz<f64>,
+        ground: Hertz<f64>,
+        slip: Hertz<f64>,
+        inertia: KilogramMeter2<f64>,
+    ) -> Hertz<f64> {
+        let zero = 0. * HZ;
+        if slip == zero && contact == applied {
+            return zero;
+        }
+        let direction = if slip == zero {
+            (applied - contact).value_unsafe.signum()
+        } else {
+            slip.value_unsafe.signum()
+        };
+        let after = spin_before + (applied - contact) / inertia * self.dt - ground;
+        // caught up with the ground, or would have passed it
+        if after.value_unsafe * direction <= 0. {
+            zero
+        } else {
+            after
+        }
     }
 
     pub fn params(&self) -> &DDMRParams {
         &self.p
     }
+
+    pub fn dt(&self) -> Second<f64> {
+        self.dt
+    }
+
+    /// The smallest inertia a single wheel can see through the chassis, which is
+    /// when both wheels push together, either driving straight or turning in place.
+    /// A torque that stops a wheel with this inertia can never overshoot.
+    pub fn min_wheel_inertia(&self) -> KilogramMeter2<f64> {
+        let p = &self.p;
+        let iw = self.wheel_inertia();
+        let straight = p.R * p.R * (p.m + (iw.l + iw.r) / p.R / p.R) / 2.;
+        let turn = p.R * p.R * (p.I + p.L * p.L * (iw.l + iw.r) / p.R / p.R) / (2. * p.L * p.L);
+        if straight < turn {
+            straight
+        } else {
+            turn
+        }
+    }
 }
 
+/// m/s^2
+pub const GRAVITY: f64 = 9.81;
+
+/// Limits the torque each wheel can put into the ground to what friction can
+/// hold. Past that the wheel slips: the rest of its torque spins the wheel up
+/// on its own inertia, and the ground only gets kinetic friction from it until
+/// the wheel is back to the speed the chassis rolls it at.
 #[derive(Debug, Clone)]
-pub struct ActuatedDDMRModel {
-    ddmr: DDMRModel,
-    p: DCMotorParams,
-    di: LR<Differentiator<Current>>,
-    crr: f64,
-    eff: f64,
+pub struct TractionParams {
+    /// Coefficient of friction between the wheels and the floor
+    pub mu: f64,
 }
 
-impl ActuatedDDMRModel {
-    pub fn new(
-        dt: Second<f64>,
-        ddmr_par: DDMRParams,
-        params: DCMotorParams,
-        eff: f64,
-        crr: f64,
-    ) -> Self {
-        Self {
-            ddmr: DDMRModel::new(dt, ddmr_par),
-            p: params,
-            di: LR {
-                l: Differentiator::new(dt, 0. * A),
-                r: Differentiator::new(dt, 0. * A),
-            },
-            crr,
-            eff,
+impl TractionParams {
+    /// The static weight on each wheel. The center of mass is on the center line,
+    /// so the two sides split it evenly; `d` only moves it fore and aft, which
+    /// doesn't change the split between the drive wheels.
+    pub fn normal_forces(&self, p: &DDMRParams) -> LR<Newton<f64>> {
+        let each = p.m * GRAVITY * MPS2 / 2.;
+        LR { l: each, r: each }
+    }
+
+    pub fn max_torque(&self, p: &DDMRParams) -> LR<NewtonMeter<f64>> {
+        let n = self.normal_forces(p);
+        LR {
+            l: self.mu * n.l * p.R,
+            r: self.mu * n.r * p.R,
         }
     }
+}
 
-    pub fn ddmr(&self) -> &DDMRModel {
-        &self.ddmr
+/// The torque a wheel puts into the ground. A gripping wheel passes on what
+/// it's given, up to what friction holds; a slipping one only ever gets the
+/// full friction against the way it slips, however hard it's driven.
+fn contact_torque(
+    tau: NewtonMeter<f64>,
+    max: NewtonMeter<f64>,
+    slip: Hertz<f64>,
+) -> NewtonMeter<f64> {
+    if slip == 0. * HZ {
+        clamp_torque(tau, max)
+    } else {
+        max * slip.value_unsafe.signum()
     }
+}
 
-    pub fn observe(&mut self, v: LR<Volt<f64>>) -> Vels {
-        let p = &self.p;
-        let phidot = self.ddmr.wheels();
-        let ial: Ampere<f64> = (v.l - p.Kb * p.N * phidot.l - p.La * self.di.l.get()) / p.Ra;
-        let iar: Ampere<f64> = (v.r - p.Kb * p.N * phidot.r - p.La * self.di.r.get()) / p.Ra;
-        self.di.l.add(ial);
-        self.di.r.add(iar);
-        let pd = self.ddmr.params();
-        let frictl = (phidot.l.value_unsafe.signum()) * self.crr * pd.m * 9.81 * MPS2;
-        let frictr = (phidot.r.value_unsafe.signum()) * self.crr * pd.m * 9.81 * MPS2;
-        let frictl = frictl * pd.Iw / pd.R / (pd.m - pd.mc) * 2.;
-        let frictr = frictr * pd.Iw / pd.R / (pd.m - pd.mc) * 2.;
-
-        self.ddmr.observe(LR {
-            l: ial * p.Kt * p.N * self.eff - frictl,
-            r: iar * p.Kt * p.N * self.eff - frictr,
-        })
+fn clamp_torque(tau: NewtonMeter<f64>, max: NewtonMeter<f64>) -> NewtonMeter<f64> {
+    if tau > max {
+        max
+    } else if tau < -max {
+        -max
+    } else {
+        tau
     }
+}
 
-    pub fn vel(&self) -> Vels {
-        self.ddmr.vel()
+#[cfg(test)]
+fn test_ddmr_params() -> DDMRParams {
+    DDMRParams {
+        R: 0.0762 * M,
+        m: 32.5 * KG,
+        mc: 28. * KG,
+        d: 0. * M,
+        L: 0.32 * M,
+        I: 4.29 * KG * M * M,
+        Iw: 0.0019 * KG * M * M,
+    }
+}
+
+#[test]
+fn pose_circle() {
+    use std::f64::consts::PI;
+    let dt = 0.001 * S;
+    for &method in &[PoseIntegration::Euler, PoseIntegration::Midpoint] {
+        let mut m = DDMRModel::new(dt, test_ddmr_params());
+        m.set_pose_integration(method);
+        let zero = 0. * N * M;
+        for _ in 0..200 {
+            m.observe(LR {
+                l: 1. * N * M,
+                r: 2. * N * M,
+            });
+        }
+        // with d = 0 and no torque the robot coasts along a perfect circle
+        let start = m.pose();
+        let w = m.vel().ang;
+        let steps = (2. * PI / *(w * dt)).round() as usize;
+        for _ in 0..steps {
+            m.observe(LR { l: zero, r: zero });
+        }
+        let end = m.pose();
+        assert!((end.x - start.x).abs() < 0.005 * M);
+        assert!((end.y - start.y).abs() < 0.005 * M);
+        assert!((end.theta - start.theta - 2. * PI).abs() < 0.01);
+    }
+}
+
+#[test]
+fn pose_straight_under_constant_torque() {
+    let dt = 0.001 * S;
+    let p = test_ddmr_params();
+    let mut m = DDMRModel::new(dt, p.clone());
+    let start = Pose {
+        x: 1. * M,
+        y: -2. * M,
+        theta: 0.,
+    };
+    m.reset_pose(start);
+    let tau = 3. * N * M;
+    for _ in 0..1000 {
+        m.observe(LR { l: tau, r: tau });
+    }
+    // half a t^2 over one second, from the equations of motion
+    let accel = 2. * tau / p.R / (p.m + 2. * p.Iw / p.R / p.R);
+    let expected = accel * (1. * S) * (1. * S) / 2.;
+    let moved = m.pose().x - start.x;
+    let error = *((moved - expected) / expected);
+    assert!(error.abs() < 0.002, "{:?} {:?}", moved, expected);
+    assert_eq!(m.pose().y, start.y);
+    assert_eq!(m.pose().theta, 0.);
+    assert_eq!(m.odometry_pose().y, start.y);
+}
+
+#[test]
+fn pose_spin_accumulates() {
+    let mut m = DDMRModel::new(0.001 * S, test_ddmr_params());
+    let tau = 2. * N * M;
+    let mut last = m.pose().theta;
+    for _ in 0..3000 {
+        m.observe(LR { l: -tau, r: tau });
+        let theta = m.pose().theta;
+        // counterclockwise throughout, with no jump back at a half or full turn
+        assert!(theta > last && theta - last < 0.1, "{} {}", last, theta);
+        last = theta;
+    }
+    assert!(last > 2. * std::f64::consts::PI, "{}", last);
+    let pose = m.pose();
+    assert!(pose.x.value_unsafe.abs() < 1e-9 && pose.y.value_unsafe.abs() < 1e-9);
+}
+
+#[test]
+fn pose_across_dt_change() {
+    let p = test_ddmr_params();
+    let tau = 3. * N * M;
+    let accel = 2. * tau / p.R / (p.m + 2. * p.Iw / p.R / p.R);
+    let mut m = DDMRModel::new(0.002 * S, p);
+    for _ in 0..250 {
+        m.observe(LR { l: tau, r: tau });
+    }
+    m.set_dt(0.0005 * S);
+    assert_eq!(m.dt(), 0.0005 * S);
+    for _ in 0..1000 {
+        m.observe(LR { l: tau, r: tau });
+    }
+    // half a second at each rate
+    let v = m.vel().lin;
+    assert!((v - accel * 1. * S).value_unsafe.abs() < 1e-9, "{:?}", v);
+    let expected = accel * (1. * S) * (1. * S) / 2.;
+    let error = *((m.pose().x - expected) / expected);
+    assert!(error.abs() < 0.005, "{:?} {:?}", m.pose().x, expected);
+}
+
+#[test]
+fn integration_methods_constant_torque() {
+    let p = test_ddmr_params();
+    let tau = 3. * N * M;
+    let accel = 2. * tau / p.R / (p.m + 2. * p.Iw / p.R / p.R);
+    let exact = accel * (1. * S) * (1. * S) / 2.;
+    // a second in coarse steps
+    let error = |method| {
+        let mut m = DDMRModel::new_with_method(0.05 * S, test_ddmr_params(), method);
+        for _ in 0..20 {
+            m.observe(LR { l: tau, r: tau });
+        }
+        assert_eq!((m.pose().y, m.pose().theta), (0. * M, 0.));
+        (*((m.pose().x - exact) / exact)).abs()
+    };
+    let euler = error(IntegrationMethod::Euler);
+    let trap = error(IntegrationMethod::Trapezoidal);
+    let rk4 = error(IntegrationMethod::RK4);
+    assert!(euler > 0.01, "{}", euler);
+    assert!(trap < 1e-9, "{}", trap);
+    assert!(rk4 < 1e-9, "{}", rk4);
+}
+
+#[test]
+fn rk4_converges_faster_on_an_arc() {
+    // the center of mass off the axle, so the mc d w v terms couple the turn
+    // into the drive
+    let params = || DDMRParams {
+        d: 0.1 * M,
+        ..test_ddmr_params()
+    };
+    let tau = LR {
+        l: 1. * N * M,
+        r: 5. * N * M,
+    };
+    let end = |method, dt: Second<f64>| {
+        let mut m = DDMRModel::new_with_method(dt, params(), method);
+        let steps = (*(1. * S / dt)).round() as usize;
+        for _ in 0..steps {
+            m.observe(tau);
+        }
+        m.pose()
+    };
+    let reference = end(IntegrationMethod::RK4, 0.0005 * S);
+    assert!(reference.theta > 1., "{}", reference.theta);
+    let error = |method, dt| {
+        let p = end(method, dt);
+        let (dx, dy) = (*((p.x - reference.x) / M), *((p.y - reference.y) / M));
+        (dx * dx + dy * dy).sqrt() + (p.theta - reference.theta).abs()
+    };
+    let dts = [0.04 * S, 0.02 * S, 0.01 * S];
+    let euler: Vec<f64> = dts
+        .iter()
+        .map(|&dt| error(IntegrationMethod::Euler, dt))
+        .collect();
+    let rk4: Vec<f64> = dts
+        .iter()
+        .map(|&dt| error(IntegrationMethod::RK4, dt))
+        .collect();
+    for (r, e) in rk4.iter().zip(&euler) {
+        assert!(r < e, "{:?} {:?}", rk4, euler);
+    }
+    // halving the step halves Euler's error, and takes RK4's down by about 16
+    for (e, r) in euler.windows(2).zip(rk4.windows(2)) {
+        let (euler_gain, rk4_gain) = (e[0] / e[1], r[0] / r[1]);
+        assert!(euler_gain > 1.5 && euler_gain < 3., "{:?}", euler);
+        assert!(rk4_gain > 8., "{:?}", rk4);
+    }
+}
+
+#[test]
+fn odometry_straight() {
+    let mut m = ActuatedDDMRModel::new(0.005 * S, test_ddmr_params(), test_motor_params(), 0.8, 0.);
+    while m.pose().x < 1. * M {
+        m.observe(LR {
+            l: 6. * V,
+            r: 6. * V,
+        });
+    }
+    let ddmr = m.ddmr();
+    let dist = ddmr.wheel_distances();
+    let pose = ddmr.pose();
+    assert!((dist.l - pose.x).abs() < 1e-9 * M);
+    assert!((dist.r - pose.x).abs() < 1e-9 * M);
+    let odom = ddmr.odometry_pose();
+    assert!((odom.x - pose.x).abs() < 1e-9 * M);
+    assert_eq!(odom.y, 0. * M);
+    assert_eq!(odom.theta, 0.);
+}
+
+#[test]
+fn odometry_rotation() {
+    let mut m = ActuatedDDMRModel::new(0.005 * S, test_ddmr_params(), test_motor_params(), 0.8, 0.);
+    for _ in 0..400 {
+        m.observe(LR {
+            l: -6. * V,
+            r: 6. * V,
+        });
+    }
+    let ddmr = m.ddmr();
+    let dist = ddmr.wheel_distances();
+    assert!(dist.r > 0.1 * M);
+    assert_eq!(dist.l, -dist.r);
+    let a = ddmr.wheel_angles();
+    assert_eq!(a.l, -a.r);
+    // spinning in place, so odometry agrees with the heading
+    let odom = ddmr.odometry_pose();
+    assert!((odom.theta - ddmr.pose().theta).abs() < 1e-9);
+    assert!(odom.x.abs() < 1e-12 * M && odom.y.abs() < 1e-12 * M);
+}
+
+/// Each side of `v` clipped to `max` either way
+pub fn clip_volts(v: LR<Volt<f64>>, max: Volt<f64>) -> LR<Volt<f64>> {
+    let clip = |v: Volt<f64>| {
+        if v > max {
+            max
+        } else if v < -max {
+            -max
+        } else {
+            v
+        }
+    };
+    LR {
+        l: clip(v.l),
+        r: clip(v.r),
+    }
+}
+
+/// Below this wheel speed in rad/s a wheel is held by stiction
+const STICTION_SPEED: f64 = 1e-3;
+
+/// Friction in each side of the drivetrain, on top of rolling resistance
+#[derive(Debug, Clone)]
+pub struct FrictionParams {
+    /// Viscous friction, such that `tau_f = viscous * wheel_ang_vel`
+    pub viscous: NewtonMeterSecond<f64>,
+    /// Coulomb friction while moving, and the breakaway torque from standstill
+    pub coulomb: NewtonMeter<f64>,
+}
+
+impl Default for FrictionParams {
+    fn default() -> Self {
+        Self {
+            viscous: 0. * N * M * S,
+            coulomb: 0. * N * M,
+        }
+    }
+}
+
+impl FrictionParams {
+    /// Friction torque on a wheel turning at `speed` with `applied` torque driving it.
+    /// Friction may stop the wheel within one step but never reverse it, otherwise
+    /// the wheel would chatter around zero instead of coming to rest.
+    fn torque(
+        &self,
+        applied: NewtonMeter<f64>,
+        speed: Hertz<f64>,
+        inertia: KilogramMeter2<f64>,
+        dt: Second<f64>,
+    ) -> NewtonMeter<f64> {
+        // the friction that would leave the wheel exactly at rest after this step
+        let stop = -(applied + inertia * speed / dt);
+        if speed.value_unsafe.abs() < STICTION_SPEED {
+            if stop.abs() <= self.coulomb {
+                stop
+            } else {
+                self.coulomb * stop.value_unsafe.signum()
+            }
+        } else {
+            let dir = speed.value_unsafe.signum();
+            let full = self.coulomb + self.viscous * speed.abs();
+            let limit = -dir * stop;
+            if limit < 0. * N * M {
+                0. * N * M
+            } else if full < limit {
+                -dir * full
+            } else {
+                -dir * limit
+            }
+        }
+    }
+}
+
+/// A battery with internal resistance that sags under load, and optionally drains
+#[derive(Debug, Clone)]
+pub struct Battery {
+    /// Open circuit voltage when full
+    pub nominal: Volt<f64>,
+    /// Open circuit voltage when empty
+    pub empty: Volt<f64>,
+    pub resistance: Ohm<f64>,
+    /// `None` for a battery that never drains
+    pub capacity: Option<Coulomb<f64>>,
+    drawn: Coulomb<f64>,
+    bus: Volt<f64>,
+}
+
+impl Battery {
+    pub fn new(nominal: Volt<f64>, resistance: Ohm<f64>) -> Self {
+        Self {
+            nominal,
+            empty: nominal,
+            resistance,
+            capacity: None,
+            drawn: 0. * C,
+            bus: nominal,
+        }
+    }
+
+    /// Open circuit voltage falls linearly from `nominal` to `empty` as it drains
+    pub fn with_capacity(self, capacity: Coulomb<f64>, empty: Volt<f64>) -> Self {
+        Self {
+            capacity: Some(capacity),
+            empty,
+            ..self
+        }
+    }
+
+    pub fn state_of_charge(&self) -> f64 {
+        match self.capacity {
+            Some(cap) => (1. - *(self.drawn / cap)).max(0.),
+            None => 1.,
+        }
+    }
+
+    pub fn open_circuit_voltage(&self) -> Volt<f64> {
+        self.empty + (self.nominal - self.empty) * self.state_of_charge()
+    }
+
+    /// Terminal voltage during the last step
+    pub fn bus_voltage(&self) -> Volt<f64> {
+        self.bus
+    }
+
+    fn reset(&mut self) {
+        self.drawn = 0. * C;
+        self.bus = self.nominal;
+    }
+
+    /// Solves for the bus voltage when each armature gets `duty * bus` volts.
+    /// `free` is the part of each armature current that doesn't depend on the applied
+    /// voltage (back-EMF and inductance), so `i = duty * bus / ra + free`. The battery
+    /// supplies `sum(duty * i)`, and `bus = ocv - resistance * sum(duty * i)` is linear in `bus`.
+    fn solve(&mut self, duty: LR<f64>, ra: LR<Ohm<f64>>, free: LR<Ampere<f64>>) -> Volt<f64> {
+        let rr = LR {
+            l: *(self.resistance / ra.l),
+            r: *(self.resistance / ra.r),
+        };
+        let bus = (self.open_circuit_voltage()
+            - self.resistance * (duty.l * free.l + duty.r * free.r))
+            / (1. + rr.l * duty.l * duty.l + rr.r * duty.r * duty.r);
+        self.bus = if bus < 0. * V { 0. * V } else { bus };
+        self.bus
+    }
+
+    fn drain(&mut self, current: Ampere<f64>, dt: Second<f64>) {
+        if self.capacity.is_some() {
+            self.drawn += current * dt;
+        }
+    }
+}
+
+/// What a motor controller does with the motor when commanded exactly zero volts
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BrakeMode {
+    /// The terminals are shorted, so the back-EMF drives a current that
+    /// opposes the motion. This is what zero volts always did in this model.
+    Brake,
+    /// The winding freewheels through a flyback diode, which only conducts
+    /// once the back-EMF beats its forward drop
+    Coast,
+    /// The circuit is open and no current flows at all
+    Neutral,
+}
+
+impl BrakeMode {
+    /// Brake, then coast, then neutral, then back around
+    pub fn next(self) -> Self {
+        match self {
+            BrakeMode::Brake => BrakeMode::Coast,
+            BrakeMode::Coast => BrakeMode::Neutral,
+            BrakeMode::Neutral => BrakeMode::Brake,
+        }
+    }
+}
+
+/// Forward voltage of the diode a coasting winding freewheels through
+const DIODE_DROP: f64 = 0.7;
+
+/// Armature current on a zero command. `shorted` is the current with the
+/// terminals shorted, driven by the back-EMF alone.
+fn zero_command_current(mode: BrakeMode, shorted: Ampere<f64>, ra: Ohm<f64>) -> Ampere<f64> {
+    match mode {
+        BrakeMode::Brake => shorted,
+        BrakeMode::Coast => {
+            let drop = DIODE_DROP * V / ra;
+            if shorted.abs() <= drop {
+                0. * A
+            } else {
+                shorted - drop * shorted.value_unsafe.signum()
+            }
+        }
+        BrakeMode::Neutral => 0. * A,
+    }
+}
+
+/// Kelvin at 0 C
+pub const ZERO_CELSIUS: f64 = 273.15;
+
+/// Kelvin at 25 C, where the windings sit without a thermal model
+pub const ROOM_TEMPERATURE: f64 = ZERO_CELSIUS + 25.;
+
+/// Fractional rise in copper's resistance per kelvin
+pub const COPPER_TEMPCO: f64 = 0.00393;
+
+/// A first order thermal model of each motor's windings. They heat up with
+/// the power lost in the armature resistance and cool towards ambient, and
+/// the resistance rises with temperature, so a hot motor is a weaker one.
+#[derive(Debug, Clone)]
+pub struct ThermalParams {
+    /// Of the windings
+    pub heat_capacity: JoulePerKelvin<f64>,
+    /// Between the windings and the air
+    pub resistance: KelvinPerWatt<f64>,
+    /// Where the windings start and cool towards. `DCMotorParams::Ra` is the
+    /// resistance at this temperature.
+    pub ambient: Kelvin<f64>,
+    /// Past which the motor controller stops driving the motor and lets it
+    /// coast, until it cools back below. `None` never cuts out.
+    pub cutoff: Option<Kelvin<f64>>,
+    /// How far below `cutoff` the windings have to cool before the motor
+    /// drives again, so it doesn't cut in and out every step at the cutoff
+    pub hysteresis: Kelvin<f64>,
+}
+
+impl ThermalParams {
+    /// The armature resistance at `temperature`, for `ra` at ambient
+    fn resistance_at(&self, ra: Ohm<f64>, temperature: Kelvin<f64>) -> Ohm<f64> {
+        ra * (1. + COPPER_TEMPCO * *((temperature - self.ambient) / K))
+    }
+
+    /// The temperature `dt` after `temperature` with `current` through
+    /// resistance `ra`
+    fn heat(
+        &self,
+        temperature: Kelvin<f64>,
+        current: Ampere<f64>,
+        ra: Ohm<f64>,
+        dt: Second<f64>,
+    ) -> Kelvin<f64> {
+        let heating = current * current * ra;
+        let cooling = (temperature - self.ambient) / self.resistance;
+        temperature + (heating - cooling) / self.heat_capacity * dt
+    }
+}
+
+/// Everything computed during one `ActuatedDDMRModel::observe` call
+#[derive(Debug, Copy, Clone)]
+pub struct Observation {
+    /// Voltage applied to the armatures
+    pub voltage: LR<Volt<f64>>,
+    /// Armature currents
+    pub current: LR<Ampere<f64>>,
+    pub back_emf: LR<Volt<f64>>,
+    /// Net torque on each wheel after friction, as passed to the chassis model
+    pub torque: LR<NewtonMeter<f64>>,
+    pub vels: Vels,
+    /// Whether the current limit held each side back
+    pub limited: LR<bool>,
+    /// Whether each side was too hot to drive
+    pub cut_out: LR<bool>,
+}
+
+impl Default for Observation {
+    fn default() -> Self {
+        Self {
+            voltage: LR {
+                l: 0. * V,
+                r: 0. * V,
+            },
+            current: LR {
+                l: 0. * A,
+                r: 0. * A,
+            },
+            back_emf: LR {
+                l: 0. * V,
+                r: 0. * V,
+            },
+            torque: LR {
+                l: 0. * N * M,
+                r: 0. * N * M,
+            },
+            vels: Vels::default(),
+            limited: LR { l: false, r: false },
+            cut_out: LR { l: false, r: false },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ActuatedDDMRModel {
+    ddmr: DDMRModel,
+    p: LR<DCMotorParams>,
+    inverted: LR<bool>,
+    di: LR<Differentiator<Current>>,
+    crr: f64,
+    eff: f64,
+    friction: FrictionParams,
+    battery: Option<Battery>,
+    brake_mode: LR<BrakeMode>,
+    ramp_rate: Option<VoltPerSecond<f64>>,
+    /// The commands as ramped so far, with a ramp rate
+    ramp: Option<LR<SlewLimiter<Voltage>>>,
+    max_voltage: Option<Volt<f64>>,
+    current_limit: Option<Ampere<f64>>,
+    thermal: Option<ThermalParams>,
+    /// Of the windings, held at `ROOM_TEMPERATURE` without a thermal model
+    temperature: LR<Kelvin<f64>>,
+    /// Past the thermal cutoff, and not yet cooled by its hysteresis
+    cut_out: LR<bool>,
+    last: Observation,
+}
+
+impl ActuatedDDMRModel {
+    /// The same motor on both sides
+    pub fn new(
+        dt: Second<f64>,
+        ddmr_par: DDMRParams,
+        params: DCMotorParams,
+        eff: f64,
+        crr: f64,
+    ) -> Self {
+        let params = LR {
+            l: params.clone(),
+            r: params,
+        };
+        Self::new_per_side(dt, ddmr_par, params, eff, crr)
+    }
+
+    /// A different motor on each side, e.g. with one gearbox a little weaker
+    pub fn new_per_side(
+        dt: Second<f64>,
+        ddmr_par: DDMRParams,
+        params: LR<DCMotorParams>,
+        eff: f64,
+        crr: f64,
+    ) -> Self {
+        let mut ddmr = DDMRModel::new(dt, ddmr_par);
+        ddmr.set_extra_inertia(LR {
+            l: params.l.reflected_inertia(),
+            r: params.r.reflected_inertia(),
+        });
+        Self {
+            ddmr,
+            p: params,
+            inverted: LR { l: false, r: false },
+            di: LR {
+                l: Differentiator::new(dt, 0. * A),
+                r: Differentiator::new(dt, 0. * A),
+            },
+            crr,
+            eff,
+            friction: FrictionParams::default(),
+            battery: None,
+            brake_mode: LR {
+                l: BrakeMode::Brake,
+                r: BrakeMode::Brake,
+            },
+            ramp_rate: None,
+            ramp: None,
+            max_voltage: None,
+            current_limit: None,
+            thermal: None,
+            temperature: LR {
+                l: ROOM_TEMPERATURE * K,
+                r: ROOM_TEMPERATURE * K,
+            },
+            cut_out: LR { l: false, r: false },
+            last: Observation::default(),
+        }
+    }
+
+    /// The same robot, friction and battery with a different timestep, reset
+    /// as with `reset`
+    pub fn with_dt(&self, dt: Second<f64>) -> Self {
+        let mut model = Self {
+            ddmr: self.ddmr.with_dt(dt),
+            di: LR {
+                l: Differentiator::new(dt, 0. * A),
+                r: Differentiator::new(dt, 0. * A),
+            },
+            ..self.clone()
+        };
+        model.set_ramp(self.ramp_rate);
+        model.reset();
+        model
+    }
+
+    /// Steps by `dt` from now on, carrying on from the current state unlike
+    /// `with_dt`
+    pub fn set_dt(&mut self, dt: Second<f64>) {
+        self.ddmr.set_dt(dt);
+        self.di.l.set_dt(dt);
+        self.di.r.set_dt(dt);
+        let ramped = self.ramp.as_ref().map(|r| LR {
+            l: r.l.get(),
+            r: r.r.get(),
+        });
+        self.set_ramp(self.ramp_rate);
+        if let (Some(ramp), Some(v)) = (self.ramp.as_mut(), ramped) {
+            ramp.l.set(v.l);
+            ramp.r.set(v.r);
+        }
+    }
+
+    pub fn set_friction(&mut self, friction: FrictionParams) {
+        self.friction = friction;
+    }
+
+    pub fn friction(&self) -> &FrictionParams {
+        &self.friction
+    }
+
+    /// With a battery, commanded voltages are taken as a fraction of its nominal
+    /// voltage, i.e. a duty cycle, and applied to whatever the sagging bus provides.
+    pub fn set_battery(&mut self, battery: Option<Battery>) {
+        self.battery = battery;
+    }
+
+    pub fn battery(&self) -> Option<&Battery> {
+        self.battery.as_ref()
+    }
+
+    /// `None` without a battery, where the commanded voltage is always available
+    pub fn bus_voltage(&self) -> Option<Volt<f64>> {
+        self.battery.as_ref().map(Battery::bus_voltage)
+    }
+
+    /// Clips each command to `max` either way before anything else, since a
+    /// motor controller can't put out more than it's given. Without it the
+    /// commands are applied as they come, or clipped to the battery's duty
+    /// cycle with one.
+    pub fn set_max_voltage(&mut self, max: Option<Volt<f64>>) {
+        self.max_voltage = max;
+    }
+
+    pub fn max_voltage(&self) -> Option<Volt<f64>> {
+        self.max_voltage
+    }
+
+    /// Clamps each armature current to `limit` either way while driving, by
+    /// cutting the duty cycle the way a smart motor controller does. It can't
+    /// hold back what the back-emf drives on its own, nor braking current.
+    pub fn set_current_limit(&mut self, limit: Option<Ampere<f64>>) {
+        self.current_limit = limit;
+    }
+
+    pub fn current_limit(&self) -> Option<Ampere<f64>> {
+        self.current_limit
+    }
+
+    /// Starts the windings at the new ambient temperature, or at
+    /// `ROOM_TEMPERATURE` without a thermal model
+    pub fn set_thermal(&mut self, thermal: Option<ThermalParams>) {
+        self.thermal = thermal;
+        self.cool_down();
+    }
+
+    pub fn thermal(&self) -> Option<&ThermalParams> {
+        self.thermal.as_ref()
+    }
+
+    /// Of each motor's windings
+    pub fn temperatures(&self) -> LR<Kelvin<f64>> {
+        self.temperature
+    }
+
+    /// Whether the current limit held each side back during the last `observe`
+    pub fn is_current_limited(&self) -> LR<bool> {
+        self.last.limited
+    }
+
+    /// Whether each motor was past its thermal cutoff during the last
+    /// `observe`
+    pub fn is_cut_out(&self) -> LR<bool> {
+        self.last.cut_out
+    }
+
+    fn cool_down(&mut self) {
+        let ambient = match self.thermal {
+            Some(ref t) => t.ambient,
+            None => ROOM_TEMPERATURE * K,
+        };
+        self.temperature = LR {
+            l: ambient,
+            r: ambient,
+        };
+        self.cut_out = LR { l: false, r: false };
+    }
+
+    pub fn ddmr(&self) -> &DDMRModel {
+        &self.ddmr
+    }
+
+    /// See `DDMRModel::push`
+    pub fn push(&mut self, force: Newton<f64>) {
+        self.ddmr.push(force);
+    }
+
+    /// See `DDMRModel::apply_disturbance`
+    pub fn apply_disturbance(&mut self, force: Newton<f64>, torque_about_center: NewtonMeter<f64>) {
+        self.ddmr.apply_disturbance(force, torque_about_center);
+    }
+
+    /// Limits how fast the applied voltage can change, the way a motor
+    /// controller's ramp setting does. Starts from zero volts.
+    pub fn set_ramp(&mut self, rate: Option<VoltPerSecond<f64>>) {
+        let dt = self.ddmr.dt();
+        let limiter = |rate: VoltPerSecond<f64>| -> SlewLimiter<Voltage> {
+            SlewLimiter::new(dt, rate, 0. * V)
+        };
+        self.ramp_rate = rate;
+        self.ramp = rate.map(|rate| LR {
+            l: limiter(rate),
+            r: limiter(rate),
+        });
+    }
+
+    pub fn ramp(&self) -> Option<VoltPerSecond<f64>> {
+        self.ramp_rate
+    }
+
+    /// Sets the same mode on both sides
+    pub fn set_brake_mode(&mut self, mode: BrakeMode) {
+        self.brake_mode = LR { l: mode, r: mode };
+    }
+
+    pub fn set_brake_modes(&mut self, modes: LR<BrakeMode>) {
+        self.brake_mode = modes;
+    }
+
+    pub fn brake_mode(&self) -> LR<BrakeMode> {
+        self.brake_mode
+    }
+
+    pub fn set_traction(&mut self, traction: Option<TractionParams>) {
+        self.ddmr.set_traction(traction);
+    }
+
+    pub fn slipping(&self) -> LR<bool> {
+        self.ddmr.slipping()
+    }
+
+    pub fn motor_params(&self) -> &LR<DCMotorParams> {
+        &self.p
+    }
+
+    /// Marks motors that are mounted turning the other way. Their commands and
+    /// speeds are flipped on the way in and out, the way a motor controller's
+    /// invert setting does, so positive still drives the robot forward.
+    pub fn set_inverted(&mut self, inverted: LR<bool>) {
+        self.inverted = inverted;
+    }
+
+    pub fn inverted(&self) -> LR<bool> {
+        self.inverted
+    }
+
+    /// Everything in the returned `Observation` is the robot's way round, with
+    /// positive forward, whichever way the motors turn.
+    pub fn observe(&mut self, v: LR<Volt<f64>>) -> Vels {
+        let v = match self.max_voltage {
+            Some(max) => clip_volts(v, max),
+            None => v,
+        };
+        let v = match self.ramp {
+            Some(ref mut ramp) => LR {
+                l: ramp.l.add(v.l),
+                r: ramp.r.add(v.r),
+            },
+            None => v,
+        };
+        let p = &self.p;
+        let sign = |inverted: bool| if inverted { -1. } else { 1. };
+        let sign = LR {
+            l: sign(self.inverted.l),
+            r: sign(self.inverted.r),
+        };
+        // from here on voltages, currents and speeds are the motors' own way round
+        let v = LR {
+            l: v.l * sign.l,
+            r: v.r * sign.r,
+        };
+        let phidot = self.ddmr.wheels();
+        let back_emf = LR {
+            l: p.l.Kb * p.l.N * phidot.l * sign.l,
+            r: p.r.Kb * p.r.N * phidot.r * sign.r,
+        };
+        // the windings' resistance, which rises as they heat up
+        let winding = match self.thermal {
+            Some(ref t) => LR {
+                l: t.resistance_at(p.l.Ra, self.temperature.l),
+                r: t.resistance_at(p.r.Ra, self.temperature.r),
+            },
+            None => LR {
+                l: p.l.Ra,
+                r: p.r.Ra,
+            },
+        };
+        // Backward Euler on `La di/dt = v - e - Ra i`, which is stable at any
+        // timestep. The inductance acts as an extra `La / dt` of resistance
+        // with the last step's current pushing through it.
+        let dt = self.ddmr.dt();
+        let ra = LR {
+            l: winding.l + p.l.La / dt,
+            r: winding.r + p.r.La / dt,
+        };
+        let free = LR {
+            l: (-back_emf.l + p.l.La / dt * self.di.l.newest()) / ra.l,
+            r: (-back_emf.r + p.r.La / dt * self.di.r.newest()) / ra.r,
+        };
+        let mut duty = match self.battery {
+            Some(ref b) => LR {
+                l: (*(v.l / b.nominal)).max(-1.).min(1.),
+                r: (*(v.r / b.nominal)).max(-1.).min(1.),
+            },
+            None => LR { l: 1., r: 1. },
+        };
+        let mut v = match self.battery {
+            Some(ref mut b) => {
+                let bus = b.solve(duty, ra, free);
+                LR {
+                    l: duty.l * bus,
+                    r: duty.r * bus,
+                }
+            }
+            None => v,
+        };
+        let current = |v: Volt<f64>, free: Ampere<f64>, mode: BrakeMode, ra: Ohm<f64>| {
+            if v == 0. * V {
+                zero_command_current(mode, free, ra)
+            } else {
+                v / ra + free
+            }
+        };
+        let ial: Ampere<f64> = current(v.l, free.l, self.brake_mode.l, ra.l);
+        let iar: Ampere<f64> = current(v.r, free.r, self.brake_mode.r, ra.r);
+        // The fraction of the voltage that brings the current back to the
+        // limit. It can't go below zero, even when the back-emf alone drives
+        // more. The battery has already sagged for the full voltage.
+        let limit = self.current_limit;
+        let scale = |i: Ampere<f64>, v: Volt<f64>, free: Ampere<f64>, ra: Ohm<f64>| match limit {
+            Some(max) if v != 0. * V && i.abs() > max => Some(
+                (*((max * i.value_unsafe.signum() - free) * ra / v))
+                    .max(0.)
+                    .min(1.),
+            ),
+            _ => None,
+        };
+        let limited = LR {
+            l: scale(ial, v.l, free.l, ra.l),
+            r: scale(iar, v.r, free.r, ra.r),
+        };
+        if let Some(k) = limited.l {
+            v.l *= k;
+            duty.l *= k;
+        }
+        if let Some(k) = limited.r {
+            v.r *= k;
+            duty.r *= k;
+        }
+        let ial = limited.l.map_or(ial, |_| v.l / ra.l + free.l);
+        let iar = limited.r.map_or(iar, |_| v.r / ra.r + free.r);
+        // a motor past its cutoff gets no current at all, back-emf or not
+        let cutoff = self
+            .thermal
+            .as_ref()
+            .and_then(|t| t.cutoff.map(|c| (c, t.hysteresis)));
+        let past = |was_cut: bool, temperature: Kelvin<f64>| match cutoff {
+            Some((c, h)) if was_cut => temperature > c - h,
+            Some((c, _)) => temperature >= c,
+            None => false,
+        };
+        let cut_out = LR {
+            l: past(self.cut_out.l, self.temperature.l),
+            r: past(self.cut_out.r, self.temperature.r),
+        };
+        self.cut_out = cut_out;
+        if cut_out.l {
+            v.l = 0. * V;
+            duty.l = 0.;
+        }
+        if cut_out.r {
+            v.r = 0. * V;
+            duty.r = 0.;
+        }
+        let ial = if cut_out.l { 0. * A } else { ial };
+        let iar = if cut_out.r { 0. * A } else { iar };
+        if let Some(ref t) = self.thermal {
+            self.temperature = LR {
+                l: t.heat(self.temperature.l, ial, winding.l, dt),
+                r: t.heat(self.temperature.r, iar, winding.r, dt),
+            };
+        }
+        self.di.l.add(ial);
+        self.di.r.add(iar);
+        let rolling = self.rolling_torque();
+        let frictl = rolling * phidot.l.value_unsafe.signum();
+        let frictr = rolling * phidot.r.value_unsafe.signum();
+
+        let tau = LR {
+            l: ial * p.l.Kt * p.l.N * p.l.eta * self.eff * sign.l - frictl,
+            r: iar * p.r.Kt * p.r.N * p.r.eta * self.eff * sign.r - frictr,
+        };
+        let inertia = self.ddmr.min_wheel_inertia();
+        if let Some(ref mut b) = self.battery {
+            b.drain(duty.l * ial + duty.r * iar, dt);
+        }
+        let torque = LR {
+            l: tau.l + self.friction.torque(tau.l, phidot.l, inertia, dt),
+            r: tau.r + self.friction.torque(tau.r, phidot.r, inertia, dt),
+        };
+        let vels = self.ddmr.observe(torque);
+
+        self.last = Observation {
+            voltage: LR {
+                l: v.l * sign.l,
+                r: v.r * sign.r,
+            },
+            current: LR {
+                l: ial * sign.l,
+                r: iar * sign.r,
+            },
+            back_emf: LR {
+                l: back_emf.l * sign.l,
+                r: back_emf.r * sign.r,
+            },
+            torque,
+            vels,
+            limited: LR {
+                l: limited.l.is_some(),
+                r: limited.r.is_some(),
+            },
+            cut_out,
+        };
+        vels
+    }
+
+    pub fn vel(&self) -> Vels {
+        self.ddmr.vel()
+    }
+
+    pub fn set_vels(&mut self, v: Vels) {
+        self.ddmr.set_vels(v);
+    }
+
+    /// Resets the chassis, clears the current history so the next `observe`
+    /// doesn't see a jump in current, recharges the battery, cools the motors
+    /// and ramps up from zero volts again
+    pub fn reset(&mut self) {
+        self.ddmr.reset();
+        self.cool_down();
+        self.di.l.reset();
+        self.di.r.reset();
+        if let Some(ref mut b) = self.battery {
+            b.reset();
+        }
+        if let Some(ref mut ramp) = self.ramp {
+            ramp.l.reset();
+            ramp.r.reset();
+        }
+        self.last = Observation::default();
+    }
+
+    /// Armature currents from the last `observe`
+    pub fn currents(&self) -> LR<Ampere<f64>> {
+        self.last.current
+    }
+
+    pub fn last_observation(&self) -> &Observation {
+        &self.last
+    }
+
+    pub fn pose(&self) -> Pose {
+        self.ddmr.pose()
+    }
+
+    pub fn reset_pose(&mut self, pose: Pose) {
+        self.ddmr.reset_pose(pose);
+    }
+
+    /// Rolling resistance on each wheel while it turns
+    fn rolling_torque(&self) -> NewtonMeter<f64> {
+        let pd = self.ddmr.params();
+        self.crr * pd.m * 9.81 * MPS2 * pd.Iw / pd.R / (pd.m - pd.mc) * 2.
+    }
+
+    /// The torque each wheel gets from its motor when held still at `voltage`,
+    /// before any friction, from an ideal voltage source
+    pub fn stall_torque(&self, voltage: Volt<f64>) -> LR<NewtonMeter<f64>> {
+        let stall = |p: &DCMotorParams| voltage / p.Ra * p.Kt * p.N * p.eta * self.eff;
+        LR {
+            l: stall(&self.p.l),
+            r: stall(&self.p.r),
+        }
+    }
+
+    /// Wheel torque per rad/s of wheel speed lost to back-emf and viscous
+    /// friction, both sides together
+    fn straight_damping(&self) -> NewtonMeterSecond<f64> {
+        let damping = |p: &DCMotorParams| {
+            p.Kb * p.N / p.Ra * p.Kt * p.N * p.eta * self.eff + self.friction.viscous
+        };
+        damping(&self.p.l) + damping(&self.p.r)
+    }
+
+    /// The speed the robot settles at driving straight with `voltage` on both
+    /// sides, worked out from the parameters rather than by simulating. Takes
+    /// in friction and rolling resistance, but not a battery or traction.
+    /// Zero if friction holds it still.
+    pub fn steady_speed(&self, voltage: Volt<f64>) -> MeterPerSecond<f64> {
+        let stall = self.stall_torque(voltage);
+        let drag = 2. * (self.friction.coulomb + self.rolling_torque());
+        let drive = stall.l + stall.r - drag;
+        if drive <= 0. * N * M {
+            return 0. * MPS;
+        }
+        drive / self.straight_damping() * self.ddmr.params().R
+    }
+
+    /// How long it takes driving straight to get 63% of the way to a new
+    /// steady speed. The armature inductance is assumed fast enough not to
+    /// matter.
+    pub fn time_constant(&self) -> Second<f64> {
+        let p = self.ddmr.params();
+        let iw = self.ddmr.wheel_inertia();
+        let mass = p.m + (iw.l + iw.r) / p.R / p.R;
+        mass * p.R * p.R / self.straight_damping()
+    }
+}
+
+#[cfg(test)]
+fn test_motor_params() -> DCMotorParams {
+    DCMotorParams {
+        Ra: 12. * V / 133. / A,
+        Kt: 2.4 * N * M / 133. / A,
+        N: 5.10,
+        La: 0. * H,
+        Kb: 2.11E-2 * V * S,
+        eta: 1.,
+        Jr: 0. * KG * M * M,
+    }
+}
+
+#[test]
+fn friction_stops_robot() {
+    let mut m = ActuatedDDMRModel::new(0.005 * S, test_ddmr_params(), test_motor_params(), 0.8, 0.);
+    m.set_friction(FrictionParams {
+        viscous: 0.01 * N * M * S,
+        coulomb: 0.5 * N * M,
+    });
+    let full = LR {
+        l: 12. * V,
+        r: 12. * V,
+    };
+    for _ in 0..400 {
+        m.observe(full);
+    }
+    assert!(m.vel().lin > 1. * MPS);
+    let zero = LR {
+        l: 0. * V,
+        r: 0. * V,
+    };
+    for _ in 0..2000 {
+        m.observe(zero);
+    }
+    assert!(m.vel().lin.abs() < 1e-6 * MPS);
+    assert!(m.vel().ang.abs() < 1e-6 * HZ);
+}
+
+#[test]
+fn stiction_holds_robot() {
+    let mut m = ActuatedDDMRModel::new(0.005 * S, test_ddmr_params(), test_motor_params(), 0.8, 0.);
+    m.set_friction(FrictionParams {
+        viscous: 0. * N * M * S,
+        coulomb: 5. * N * M,
+    });
+    for _ in 0..500 {
+        m.observe(LR {
+            l: 0.5 * V,
+            r: 0.5 * V,
+        });
+    }
+    assert_eq!(m.vel().lin, 0. * MPS);
+    assert_eq!(m.vel().ang, 0. * HZ);
+}
+
+#[test]
+fn stall_current() {
+    let heavy = DDMRParams {
+        m: 1e6 * KG,
+        mc: 1e6 * KG - 4.5 * KG,
+        I: 1e6 * KG * M * M,
+        ..test_ddmr_params()
+    };
+    let mut m = ActuatedDDMRModel::new(0.005 * S, heavy, test_motor_params(), 0.8, 0.);
+    let v = LR {
+        l: 12. * V,
+        r: -12. * V,
+    };
+    for _ in 0..10 {
+        m.observe(v);
+    }
+    let stall = 12. * V / test_motor_params().Ra;
+    let i = m.currents();
+    assert!((i.l - stall).abs() < 0.001 * stall);
+    assert!((i.r + stall).abs() < 0.001 * stall);
+    let obs = m.last_observation();
+    assert!(obs.back_emf.l.abs() < 0.01 * V);
+    assert_eq!(obs.voltage.l, 12. * V);
+}
+
+#[test]
+fn battery_stall_sag() {
+    let heavy = DDMRParams {
+        m: 1e6 * KG,
+        mc: 1e6 * KG - 4.5 * KG,
+        I: 1e6 * KG * M * M,
+        ..test_ddmr_params()
+    };
+    let mut m = ActuatedDDMRModel::new(0.005 * S, heavy, test_motor_params(), 0.8, 0.);
+    let rb = 0.02 * OHM;
+    m.set_battery(Some(Battery::new(12. * V, rb)));
+    m.observe(LR {
+        l: 12. * V,
+        r: 12. * V,
+    });
+    // at stall there's no back-EMF, so the two armatures and the battery
+    // form a plain resistive divider
+    let ra = test_motor_params().Ra;
+    let expected = 12. * V / (1. + 2. * *(rb / ra));
+    let bus = m.bus_voltage().unwrap();
+    assert!((bus - expected).abs() < 1e-9 * V);
+    assert!((m.currents().l - expected / ra).abs() < 1e-9 * A);
+}
+
+#[test]
+fn battery_sag_slows_launch() {
+    let dt = 0.005 * S;
+    let full = LR {
+        l: 12. * V,
+        r: 12. * V,
+    };
+    let mut ideal = ActuatedDDMRModel::new(dt, test_ddmr_params(), test_motor_params(), 0.8, 0.);
+    let mut weak = ideal.clone();
+    weak.set_battery(Some(Battery::new(12. * V, 0.05 * OHM)));
+    for _ in 0..10 {
+        ideal.observe(full);
+        weak.observe(full);
+    }
+    assert!(weak.vel().lin < 0.9 * ideal.vel().lin);
+    let launch = weak.bus_voltage().unwrap();
+    assert!(launch < 10. * V, "{:?}", launch);
+    // the current falls off as the motors come up to speed, and the bus with it
+    for _ in 0..1000 {
+        weak.observe(full);
+    }
+    let cruise = weak.bus_voltage().unwrap();
+    assert!(cruise > launch + 1. * V, "{:?} {:?}", launch, cruise);
+    assert!(cruise < 12. * V);
+}
+
+#[test]
+fn battery_drains() {
+    let mut m = ActuatedDDMRModel::new(0.005 * S, test_ddmr_params(), test_motor_params(), 0.8, 0.);
+    let battery = Battery::new(12.5 * V, 0.015 * OHM).with_capacity(1000. * C, 11. * V);
+    m.set_battery(Some(battery));
+    for _ in 0..200 {
+        m.observe(LR {
+            l: 12. * V,
+            r: 12. * V,
+        });
+    }
+    let b = m.battery().unwrap();
+    assert!(b.state_of_charge() < 1.);
+    assert!(b.open_circuit_voltage() < 12.5 * V);
+}
+
+#[test]
+fn traction_limits_acceleration() {
+    let mu = 0.3;
+    let dt = 0.005 * S;
+    let accel = |motor: DCMotorParams| {
+        let mut m = ActuatedDDMRModel::new(dt, test_ddmr_params(), motor, 0.8, 0.);
+        m.set_traction(Some(TractionParams { mu }));
+        for _ in 0..100 {
+            m.observe(LR {
+                l: 12. * V,
+                r: 12. * V,
+            });
+        }
+        assert!(m.slipping().l && m.slipping().r);
+        m.vel().lin / (100. * dt)
+    };
+    let normal = accel(test_motor_params());
+    let strong = accel(DCMotorParams {
+        Kt: test_motor_params().Kt * 4.,
+        ..test_motor_params()
+    });
+    // the wheels' own inertia takes a couple percent off mu * g
+    let limit = mu * GRAVITY * MPS2;
+    assert!(normal < limit && normal > 0.97 * limit);
+    assert!((strong - normal).abs() < 1e-9 * MPS2);
+}
+
+#[test]
+fn traction_unchanged_below_limit() {
+    let dt = 0.005 * S;
+    let mut free = ActuatedDDMRModel::new(dt, test_ddmr_params(), test_motor_params(), 0.8, 0.);
+    let mut gripping = free.clone();
+    gripping.set_traction(Some(TractionParams { mu: 1.1 }));
+    for k in 0..400 {
+        let v = if k < 200 { 6. * V } else { -4. * V };
+        let v = LR { l: v, r: 0.7 * v };
+        let (a, b) = (free.observe(v), gripping.observe(v));
+        assert_eq!((a.lin, a.ang), (b.lin, b.ang));
+        assert_eq!(gripping.slipping(), LR { l: false, r: false });
+    }
+    let (a, b) = (free.pose(), gripping.pose());
+    assert_eq!((a.x, a.y, a.theta), (b.x, b.y, b.theta));
+    assert_eq!(free.ddmr().wheels(), gripping.ddmr().wheels());
+}
+
+#[test]
+fn traction_spins_wheels() {
+    let dt = 0.001 * S;
+    let mut m = DDMRModel::new(dt, test_ddmr_params());
+    m.set_traction(Some(TractionParams { mu: 0.5 }));
+    let max = TractionParams { mu: 0.5 }.max_torque(m.params());
+    let huge = LR {
+        l: 20. * max.l,
+        r: 20. * max.r,
+    };
+    for _ in 0..20 {
+        m.observe(huge);
+    }
+    assert!(m.slipping().l && m.slipping().r);
+    let ground = m.vels_to_wheel(m.vel());
+    let wheels = m.wheels();
+    assert!(wheels.l > 10. * ground.l, "{:?} {:?}", wheels, ground);
+    assert_eq!(wheels.l, wheels.r);
+    // the chassis only ever got the traction limit
+    let limit = 0.5 * GRAVITY * MPS2;
+    assert!(m.vel().lin / (20. * dt) < limit);
+
+    // once the torque is off friction pulls the wheels back to the ground
+    for _ in 0..1000 {
+        m.observe(LR {
+            l: 0. * N * M,
+            r: 0. * N * M,
+        });
+    }
+    assert_eq!(m.slipping(), LR { l: false, r: false });
+    assert_eq!(m.wheels(), m.vels_to_wheel(m.vel()));
+}
+
+#[test]
+fn reset_matches_fresh_model() {
+    let dt = 0.005 * S;
+    let params = DCMotorParams {
+        // so the current carries over between steps
+        La: 0.0002 * H,
+        ..test_motor_params()
+    };
+    let new_model = || {
+        let mut m = ActuatedDDMRModel::new(dt, test_ddmr_params(), params.clone(), 0.8, 0.0035);
+        m.set_battery(Some(Battery::new(12.5 * V, 0.015 * OHM)));
+        m
+    };
+    let mut used = new_model();
+    for i in 0..500 {
+        let v = if i % 100 < 50 { 12. * V } else { -12. * V };
+        used.observe(LR { l: v, r: 0.5 * v });
+    }
+    used.reset();
+    let mut fresh = new_model();
+    for _ in 0..10 {
+        let input = LR {
+            l: 9. * V,
+            r: 3. * V,
+        };
+        let a = used.observe(input);
+        let b = fresh.observe(input);
+        assert_eq!(a.lin, b.lin);
+        assert_eq!(a.ang, b.ang);
+        assert_eq!(used.currents(), fresh.currents());
+    }
+    assert_eq!(used.pose().x, fresh.pose().x);
+    assert_eq!(used.pose().theta, fresh.pose().theta);
+}
+
+#[test]
+fn inductance_stable_when_substepped() {
+    // an electrical time constant of a couple of milliseconds
+    let params = DCMotorParams {
+        La: 0.0002 * H,
+        ..test_motor_params()
+    };
+    let stall = *(12. * V / params.Ra / A);
+    let full = LR {
+        l: 12. * V,
+        r: 12. * V,
+    };
+    // the current at the end of the first 60 Hz frame, and the most over half a second
+    let run = |substeps: usize| {
+        let dt = (1. / 60.) / substeps as f64 * S;
+        let mut m = ActuatedDDMRModel::new(dt, test_ddmr_params(), params.clone(), 0.8, 0.);
+        let mut first = 0.;
+        let mut peak = 0f64;
+        for step in 1..=30 * substeps {
+            m.observe(full);
+            let i = *(m.currents().l / A);
+            assert!(i.is_finite());
+            peak = peak.max(i.abs());
+            if step == substeps {
+                first = i;
+            }
+        }
+        (first, peak)
+    };
+    let (exact, _) = run(1000);
+    for &substeps in &[1, 17, 167] {
+        let (_, peak) = run(substeps);
+        // never rings past what the armature draws at stall
+        assert!(peak <= stall * 1.001, "{} at {} substeps", peak, substeps);
+    }
+    // it takes the substeps to resolve the current rising within a frame
+    let (khz, _) = run(17);
+    let (frame, _) = run(1);
+    assert!((khz - exact).abs() < 0.01 * exact, "{} {}", khz, exact);
+    assert!((frame - exact).abs() > 0.05 * exact, "{} {}", frame, exact);
+}
+
+#[test]
+fn brake_modes_stopping() {
+    let speed_after = |mode: BrakeMode| {
+        let dt = 0.005 * S;
+        let mut m = ActuatedDDMRModel::new(dt, test_ddmr_params(), test_motor_params(), 0.8, 0.);
+        m.set_brake_mode(mode);
+        m.set_vels(Vels {
+            lin: 2. * MPS,
+            ang: 0. / S,
+        });
+        for _ in 0..40 {
+            m.observe(LR {
+                l: 0. * V,
+                r: 0. * V,
+            });
+        }
+        m.vel().lin
+    };
+    let brake = speed_after(BrakeMode::Brake);
+    let coast = speed_after(BrakeMode::Coast);
+    let neutral = speed_after(BrakeMode::Neutral);
+    assert!(brake >= 0. * MPS);
+    assert!(brake < coast, "{} {}", brake, coast);
+    assert!(coast < neutral, "{} {}", coast, neutral);
+    // nothing but the motors slows it down here
+    assert_eq!(neutral, 2. * MPS);
+}
+
+/// A pair of quadrature encoders on the wheels, stepped alongside the model with
+/// its wheel velocities. Ticks are quantized from the integrated wheel angle rather
+/// than accumulated per step, so fractional ticks carry over and none get lost.
+#[derive(Debug, Clone)]
+pub struct Encoder {
+    ticks_per_rev: f64,
+    dt: Second<f64>,
+    angle: LR<Integrator<AngularVelocity>>,
+    ticks: LR<i64>,
+    last_ticks: LR<i64>,
+}
+
+impl Encoder {
+    pub fn new(dt: Second<f64>, ticks_per_rev: u32) -> Self {
+        Self {
+            ticks_per_rev: ticks_per_rev as f64,
+            dt,
+            angle: LR {
+                l: Integrator::new(dt, 0. * HZ * S),
+                r: Integrator::new(dt, 0. * HZ * S),
+            },
+            ticks: LR { l: 0, r: 0 },
+            last_ticks: LR { l: 0, r: 0 },
+        }
+    }
+
+    /// Samples the wheels once, e.g. with `DDMRModel::wheels()` after each `observe`
+    pub fn update(&mut self, wheels: LR<Hertz<f64>>) {
+        self.last_ticks = self.ticks;
+        let l = *self.angle.l.add(wheels.l);
+        let r = *self.angle.r.add(wheels.r);
+        self.ticks = LR {
+            l: self.quantize(l),
+            r: self.quantize(r),
+        };
+    }
+
+    fn quantize(&self, angle: f64) -> i64 {
+        (angle * self.ticks_per_rev / (2. * std::f64::consts::PI)).floor() as i64
+    }
+
+    pub fn ticks(&self) -> LR<i64> {
+        self.ticks
+    }
+
+    pub fn ticks_per_rev(&self) -> u32 {
+        self.ticks_per_rev as u32
+    }
+
+    /// Ticks per second, from the change in ticks over the last sample period. At
+    /// low speed this steps between whole ticks per period, like a real encoder.
+    pub fn rate(&self) -> LR<Hertz<f64>> {
+        LR {
+            l: (self.ticks.l - self.last_ticks.l) as f64 / self.dt,
+            r: (self.ticks.r - self.last_ticks.r) as f64 / self.dt,
+        }
+    }
+
+    /// Wheel velocities the way a robot would measure them, from `rate`
+    pub fn velocity_from_ticks(&self) -> LR<Hertz<f64>> {
+        let rad_per_tick = 2. * std::f64::consts::PI / self.ticks_per_rev;
+        let rate = self.rate();
+        LR {
+            l: rate.l * rad_per_tick,
+            r: rate.r * rad_per_tick,
+        }
+    }
+
+    /// Zeroes the count on both sides
+    pub fn reset(&mut self) {
+        self.reset_sides(LR { l: true, r: true });
+    }
+
+    /// Zeroes the count on just the sides given, as if that encoder had
+    /// been power cycled
+    pub fn reset_sides(&mut self, sides: LR<bool>) {
+        if sides.l {
+            self.angle.l.reset();
+            self.ticks.l = 0;
+            self.last_ticks.l = 0;
+        }
+        if sides.r {
+            self.angle.r.reset();
+            self.ticks.r = 0;
+            self.last_ticks.r = 0;
+        }
+    }
+}
+
+#[test]
+fn encoder_counts_a_revolution() {
+    use std::f64::consts::PI;
+    let dt = 0.001 * S;
+    // a revolution every 1000 steps, and 0.36 of a tick per step
+    let speed = 2. * PI * HZ;
+    let mut e = Encoder::new(dt, 360);
+    let spin = |e: &mut Encoder, wheels: LR<Hertz<f64>>, steps: usize| {
+        for _ in 0..steps {
+            e.update(wheels);
+        }
+        e.ticks()
+    };
+    let forward = LR { l: speed, r: speed };
+    assert_eq!(spin(&mut e, forward, 999).l, 359);
+    assert_eq!(spin(&mut e, forward, 2).l, 360);
+    for rev in 2..6 {
+        let ticks = spin(&mut e, forward, 1000);
+        assert_eq!(
+            ticks,
+            LR {
+                l: 360 * rev,
+                r: 360 * rev
+            }
+        );
+    }
+    // back a whole revolution takes off exactly as many
+    let back = LR {
+        l: -speed,
+        r: -speed,
+    };
+    assert_eq!(spin(&mut e, back, 1000).l, 360 * 4);
+    e.reset_sides(LR { l: true, r: false });
+    assert_eq!(e.ticks(), LR { l: 0, r: 360 * 4 });
+    assert_eq!(spin(&mut e, forward, 1001).l, 360);
+    e.reset();
+    assert_eq!(e.ticks(), LR { l: 0, r: 0 });
+    assert_eq!(
+        e.rate(),
+        LR {
+            l: 0. * HZ,
+            r: 0. * HZ
+        }
+    );
+}
+
+#[test]
+fn encoder_rate_steps_at_low_speed() {
+    use std::f64::consts::PI;
+    let dt = 0.005 * S;
+    // a tick every 3.5 steps
+    let true_rate = 1. / (3.5 * dt);
+    let speed = true_rate * 2. * PI / 360.;
+    let mut e = Encoder::new(dt, 360);
+    let rates: Vec<f64> = (0..700)
+        .map(|_| {
+            e.update(LR { l: speed, r: speed });
+            *(e.rate().l / HZ)
+        })
+        .collect();
+    // never the true rate, only none or one tick per period
+    let one_tick = |r: f64| (r - 200.).abs() < 1e-9;
+    assert!(rates.iter().all(|&r| r == 0. || one_tick(r)), "{:?}", rates);
+    assert!(rates.iter().any(|&r| r == 0.));
+    assert!(rates.iter().any(|&r| one_tick(r)));
+    let mean = rates.iter().sum::<f64>() / rates.len() as f64;
+    assert!(
+        (mean - *(true_rate / HZ)).abs() < 0.01 * *(true_rate / HZ),
+        "{}",
+        mean
+    );
+}
+
+#[test]
+fn encoder_no_lost_ticks() {
+    use std::f64::consts::PI;
+    let dt = 0.005 * S;
+    let mut m = ActuatedDDMRModel::new(dt, test_ddmr_params(), test_motor_params(), 0.8, 0.);
+    let mut e = Encoder::new(dt, 360);
+    for i in 0..4000 {
+        // forward then back, so ticks have to count down again
+        let v = if i < 2000 { 6. * V } else { -9. * V };
+        m.observe(LR { l: v, r: v });
+        e.update(m.ddmr().wheels());
+    }
+    // driving straight, so the wheels turned exactly as far as the robot went
+    let angle = *(m.pose().x / test_ddmr_params().R);
+    let expected = angle * 360. / (2. * PI);
+    let ticks = e.ticks();
+    assert!((ticks.l as f64 - expected).abs() <= 1.);
+    assert!((ticks.r as f64 - expected).abs() <= 1.);
+    assert!(e.velocity_from_ticks().l < 0. * HZ);
+}
+
+#[test]
+fn weaker_right_motor_curves_right() {
+    let weak = DCMotorParams {
+        Kt: test_motor_params().Kt * 0.95,
+        ..test_motor_params()
+    };
+    let params = LR {
+        l: test_motor_params(),
+        r: weak,
+    };
+    let mut m = ActuatedDDMRModel::new_per_side(0.005 * S, test_ddmr_params(), params, 0.8, 0.0035);
+    for _ in 0..600 {
+        m.observe(LR {
+            l: 12. * V,
+            r: 12. * V,
+        });
+    }
+    // clockwise, toward the weaker side
+    assert!(m.pose().theta < -0.02, "{:?}", m.pose());
+    assert!(m.pose().y < 0. * M);
+}
+
+#[test]
+fn rotor_inertia_slows_acceleration() {
+    let heavy = DCMotorParams {
+        Jr: 0.002 * KG * M * M,
+        ..test_motor_params()
+    };
+    let full = LR {
+        l: 12. * V,
+        r: 12. * V,
+    };
+    let mut plain =
+        ActuatedDDMRModel::new(0.005 * S, test_ddmr_params(), test_motor_params(), 0.8, 0.);
+    let mut slow = ActuatedDDMRModel::new(0.005 * S, test_ddmr_params(), heavy, 0.8, 0.);
+    for _ in 0..20 {
+        plain.observe(full);
+        slow.observe(full);
+    }
+    assert!(slow.vel().lin < 0.8 * plain.vel().lin, "{:?}", slow.vel());
+    // more to spin up, but the same top speed
+    for _ in 0..4000 {
+        plain.observe(full);
+        slow.observe(full);
+    }
+    assert!((slow.vel().lin - plain.vel().lin).abs() < 1e-6 * MPS);
+}
+
+#[test]
+fn gear_efficiency_slows_loaded_robot() {
+    let p = DCMotorParams {
+        eta: 0.8,
+        ..test_motor_params()
+    };
+    let viscous = 0.05 * N * M * S;
+    let mut m = ActuatedDDMRModel::new(0.005 * S, test_ddmr_params(), p.clone(), 0.8, 0.);
+    m.set_friction(FrictionParams {
+        viscous,
+        coulomb: 0. * N * M,
+    });
+    for _ in 0..4000 {
+        m.observe(LR {
+            l: 12. * V,
+            r: 12. * V,
+        });
+    }
+    // where the motor torque left over after back-emf meets the friction
+    let k = 0.8 * 0.8 * p.Kt * p.N / p.Ra;
+    let expected = k * 12. * V / (viscous + k * p.Kb * p.N);
+    let wheels = m.ddmr().wheels();
+    assert!(
+        (wheels.l - expected).abs() < 1e-3 * expected,
+        "{:?}",
+        wheels
+    );
+    assert!((wheels.r - expected).abs() < 1e-3 * expected);
+}
+
+#[test]
+fn analytic_steady_speed_matches() {
+    let motor = DCMotorParams {
+        eta: 0.9,
+        ..test_motor_params()
+    };
+    let mut m = ActuatedDDMRModel::new(0.005 * S, test_ddmr_params(), motor, 0.8, 0.0035);
+    m.set_friction(FrictionParams {
+        viscous: 0.02 * N * M * S,
+        coulomb: 0.3 * N * M,
+    });
+    for &volts in &[12., 4.] {
+        for _ in 0..6000 {
+            m.observe(LR {
+                l: volts * V,
+                r: volts * V,
+            });
+        }
+        let expected = m.steady_speed(volts * V);
+        assert!(
+            (m.vel().lin - expected).abs() < 0.01 * expected,
+            "{:?} {:?}",
+            m.vel().lin,
+            expected
+        );
+    }
+    // not enough to break away
+    assert_eq!(m.steady_speed(0.01 * V), 0. * MPS);
+}
+
+#[test]
+fn analytic_time_constant_matches() {
+    let dt = 0.001 * S;
+    let mut m = ActuatedDDMRModel::new(dt, test_ddmr_params(), test_motor_params(), 0.8, 0.);
+    let full = LR {
+        l: 12. * V,
+        r: 12. * V,
+    };
+    let target = 0.632 * m.steady_speed(12. * V);
+    let mut steps = 0;
+    while m.vel().lin < target {
+        m.observe(full);
+        steps += 1;
+    }
+    let tau = m.time_constant();
+    let took = steps as f64 * dt;
+    assert!((took - tau).abs() < 0.02 * tau, "{:?} {:?}", took, tau);
+    assert_eq!(m.stall_torque(12. * V).l, m.stall_torque(12. * V).r);
+}
+
+#[test]
+fn viscous_coast_decays_exponentially() {
+    let dt = 0.001 * S;
+    let viscous = 0.02 * N * M * S;
+    let coast = |mode: BrakeMode| {
+        let mut m = ActuatedDDMRModel::new(dt, test_ddmr_params(), test_motor_params(), 0.8, 0.);
+        m.set_friction(FrictionParams {
+            viscous,
+            coulomb: 0. * N * M,
+        });
+        m.set_brake_mode(mode);
+        m.set_vels(Vels {
+            lin: 2. * MPS,
+            ang: 0. / S,
+        });
+        m
+    };
+    let p = test_ddmr_params();
+    let mass = p.m + 2. * p.Iw / p.R / p.R;
+    // with the circuit open only the viscous friction slows it down
+    let open = mass * p.R * p.R / (2. * viscous);
+    let mut neutral = coast(BrakeMode::Neutral);
+    // shorted, the back-emf brakes it as well
+    let mut brake = coast(BrakeMode::Brake);
+    let shorted = brake.time_constant();
+    assert!(shorted < open);
+    let zero = LR {
+        l: 0. * V,
+        r: 0. * V,
+    };
+    let check = |m: &mut ActuatedDDMRModel, tau: Second<f64>| {
+        let mut steps = 0;
+        for n in 1..4 {
+            while (steps as f64) * dt < n as f64 * tau {
+                m.observe(zero);
+                steps += 1;
+            }
+            let t = steps as f64 * dt;
+            let expected = 2. * (-*(t / tau)).exp();
+            let lin = *(m.vel().lin / MPS);
+            assert!(
+                (lin - expected).abs() < 0.01 * expected,
+                "{} {} after {:?}",
+                lin,
+                expected,
+                t
+            );
+            assert_eq!(m.vel().ang, 0. / S);
+        }
+    };
+    check(&mut neutral, open);
+    check(&mut brake, shorted);
+}
+
+#[test]
+fn inverted_motors_still_drive_forward() {
+    let new_model = || {
+        let mut m = ActuatedDDMRModel::new(
+            0.005 * S,
+            test_ddmr_params(),
+            test_motor_params(),
+            0.8,
+            0.0035,
+        );
+        m.set_battery(Some(Battery::new(12.5 * V, 0.015 * OHM)));
+        m
+    };
+    let mut plain = new_model();
+    let mut inverted = new_model();
+    inverted.set_inverted(LR { l: true, r: true });
+    for _ in 0..200 {
+        let v = LR {
+            l: 10. * V,
+            r: 8. * V,
+        };
+        let a = plain.observe(v);
+        let b = inverted.observe(v);
+        assert_eq!(a.lin, b.lin);
+        assert_eq!(a.ang, b.ang);
+    }
+    assert!(inverted.pose().x > 0. * M);
+    assert_eq!(plain.currents(), inverted.currents());
+    assert_eq!(plain.ddmr().wheels(), inverted.ddmr().wheels());
+}
+
+#[test]
+fn ramp_limits_applied_voltage() {
+    let dt = 0.005 * S;
+    let mut m = ActuatedDDMRModel::new(dt, test_ddmr_params(), test_motor_params(), 0.8, 0.);
+    m.set_ramp(Some(24. * V / S));
+    let full = LR {
+        l: 12. * V,
+        r: -12. * V,
+    };
+    for _ in 0..50 {
+        m.observe(full);
+    }
+    // a quarter second at 24 V/s
+    let applied = m.last_observation().voltage;
+    assert!(
+        (applied.l - 6. * V).value_unsafe.abs() < 1e-9,
+        "{:?}",
+        applied
+    );
+    assert!(
+        (applied.r + 6. * V).value_unsafe.abs() < 1e-9,
+        "{:?}",
+        applied
+    );
+    for _ in 0..60 {
+        m.observe(full);
+    }
+    assert_eq!(m.last_observation().voltage, full);
+    m.reset();
+    m.observe(full);
+    assert!(m.last_observation().voltage.l < 0.2 * V);
+}
+
+#[test]
+fn ramp_softens_current_spike() {
+    let dt = 0.005 * S;
+    let peak = |ramp: Option<VoltPerSecond<f64>>| {
+        let mut m = ActuatedDDMRModel::new(dt, test_ddmr_params(), test_motor_params(), 0.8, 0.);
+        m.set_ramp(ramp);
+        (0..600)
+            .map(|_| {
+                m.observe(LR {
+                    l: 12. * V,
+                    r: 12. * V,
+                });
+                *(m.currents().l / A)
+            })
+            .fold(0., f64::max)
+    };
+    let instant = peak(None);
+    // two seconds to full voltage, about the drivetrain's time constant, so
+    // the back-EMF builds up while the voltage is still ramping
+    let ramped = peak(Some(6. * V / S));
+    assert!(ramped < 0.6 * instant, "{} {}", ramped, instant);
+}
+
+#[test]
+fn max_voltage_clips_each_side() {
+    let dt = 0.005 * S;
+    let new_model = || ActuatedDDMRModel::new(dt, test_ddmr_params(), test_motor_params(), 0.8, 0.);
+    let mut clipped = new_model();
+    clipped.set_max_voltage(Some(12. * V));
+    let mut reference = new_model();
+    // only the left side is over, the other way round after a second
+    for i in 0..400 {
+        let (over, under, sign) = if i < 200 {
+            (18. * V, 6. * V, 1.)
+        } else {
+            (-30. * V, -6. * V, -1.)
+        };
+        let a = clipped.observe(LR { l: over, r: under });
+        let b = reference.observe(LR {
+            l: sign * 12. * V,
+            r: under,
+        });
+        assert_eq!((a.lin, a.ang), (b.lin, b.ang));
+    }
+    assert_eq!(
+        clipped.last_observation().voltage,
+        LR {
+            l: -12. * V,
+            r: -6. * V,
+        }
+    );
+}
+
+#[test]
+fn stalled_motor_limits_current_and_heats_up() {
+    let dt = 0.005 * S;
+    let full = LR {
+        l: 12. * V,
+        r: 12. * V,
+    };
+    let model = || {
+        let mut m = ActuatedDDMRModel::new(dt, test_ddmr_params(), test_motor_params(), 0.8, 0.);
+        m.set_friction(FrictionParams {
+            viscous: 0. * N * M * S,
+            coulomb: 1. * N * M,
+        });
+        m.set_current_limit(Some(40. * A));
+        m.set_thermal(Some(ThermalParams {
+            heat_capacity: 150. * J / K,
+            resistance: 2. * K / W,
+            ambient: ROOM_TEMPERATURE * K,
+            cutoff: None,
+            hysteresis: 0. * K,
+        }));
+        m
+    };
+    let top_speed = |m: &mut ActuatedDDMRModel| {
+        for _ in 0..3000 {
+            m.observe(full);
+        }
+        m.vel().lin
+    };
+    let cold = top_speed(&mut model());
+
+    let mut m = model();
+    let mut last = m.temperatures().l;
+    // two minutes against a wall
+    for _ in 0..24000 {
+        m.observe(full);
+        m.set_vels(Vels::default());
+        assert!((m.currents().l - 40. * A).abs() < 1e-9 * A);
+        assert_eq!(m.is_current_limited(), LR { l: true, r: true });
+        let t = m.temperatures().l;
+        assert!(t > last);
+        last = t;
+    }
+    assert!(last > (ROOM_TEMPERATURE + 40.) * K, "{:?}", last);
+    // the hot windings have more resistance to push the friction current through
+    let hot = top_speed(&mut m);
+    assert!(hot < 0.98 * cold, "{:?} {:?}", hot, cold);
+    assert!(m.temperatures().l > ROOM_TEMPERATURE * K);
+    assert_eq!(m.is_current_limited(), LR { l: false, r: false });
+}
+
+#[test]
+fn thermal_cutoff_holds_temperature() {
+    let dt = 0.005 * S;
+    let full = LR {
+        l: 12. * V,
+        r: 12. * V,
+    };
+    let cutoff = (ROOM_TEMPERATURE + 30.) * K;
+    let hysteresis = 2. * K;
+    let mut m = ActuatedDDMRModel::new(dt, test_ddmr_params(), test_motor_params(), 0.8, 0.);
+    m.set_thermal(Some(ThermalParams {
+        heat_capacity: 150. * J / K,
+        resistance: 2. * K / W,
+        ambient: ROOM_TEMPERATURE * K,
+        cutoff: Some(cutoff),
+        hysteresis,
+    }));
+    let mut cut_steps = 0;
+    let mut switches = 0;
+    let mut hottest = ROOM_TEMPERATURE * K;
+    // two minutes against a wall
+    for _ in 0..24000 {
+        let was_cut = m.is_cut_out().l;
+        let before = m.temperatures().l;
+        m.observe(full);
+        m.set_vels(Vels::default());
+        let cut = m.is_cut_out().l;
+        if cut {
+            cut_steps += 1;
+            assert_eq!(m.currents().l, 0. * A);
+            assert_eq!(m.last_observation().voltage.l, 0. * V);
+        } else {
+            assert!(m.currents().l > 0. * A);
+        }
+        // it cuts out at the cutoff and stays out until it's cooled below
+        // the hysteresis
+        if cut != was_cut {
+            switches += 1;
+            if cut {
+                assert!(before >= cutoff, "{:?}", before);
+            } else {
+                assert!(before <= cutoff - hysteresis, "{:?}", before);
+            }
+        }
+        if m.temperatures().l > hottest {
+            hottest = m.temperatures().l;
+        }
+    }
+    assert!(cut_steps > 0);
+    // out, back in and out again, but nowhere near every step
+    assert!(switches >= 3 && switches < 20, "{}", switches);
+    assert!(hottest < cutoff + 0.5 * K, "{:?}", hottest);
+    assert!(m.temperatures().l > cutoff - hysteresis - 0.5 * K);
+}
+
+#[test]
+fn windings_heat_toward_steady_state() {
+    let thermal = ThermalParams {
+        heat_capacity: 150. * J / K,
+        resistance: 2. * K / W,
+        ambient: ROOM_TEMPERATURE * K,
+        cutoff: None,
+        hysteresis: 0. * K,
+    };
+    let (current, ra) = (20. * A, 0.1 * OHM);
+    // I^2 R of heating against cooling through R_th
+    let rise = *(current * current * ra * thermal.resistance / K);
+    let tau = *(thermal.heat_capacity * thermal.resistance / S);
+    let dt = 0.01 * S;
+    let mut t = thermal.ambient;
+    let mut time = 0.;
+    for &check in &[tau, 3. * tau, 10. * tau] {
+        while time < check {
+            t = thermal.heat(t, current, ra, dt);
+            time += *(dt / S);
+        }
+        let expected = rise * (1. - (-time / tau).exp());
+        let got = *((t - thermal.ambient) / K);
+        assert!((got - expected).abs() < 1e-3 * rise, "{} {}", got, expected);
     }
 }
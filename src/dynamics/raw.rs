@@ -0,0 +1,247 @@
+//! The drive model on plain `f64`s in SI units, for scripts and the f32 game
+//! code. Everything converts to the dimensioned types and calls through to
+//! them, so the two APIs can't drift apart.
+
+use super::{
+    ActuatedDDMRModel, DCMotorParams, DDMRParams, KilogramMeter2, NewtonMeterPerAmpere, Pose, Vels,
+    VoltSecond, LR,
+};
+
+use dimensioned::si::*;
+use serde_derive::{Deserialize, Serialize};
+
+/// `DDMRParams` in meters, kilograms and kg m^2
+#[allow(non_snake_case)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RawDDMRParams {
+    pub R: f64,
+    pub m: f64,
+    pub mc: f64,
+    pub d: f64,
+    pub L: f64,
+    pub I: f64,
+    pub Iw: f64,
+}
+
+impl From<RawDDMRParams> for DDMRParams {
+    fn from(p: RawDDMRParams) -> Self {
+        Self {
+            R: Meter::new(p.R),
+            m: Kilogram::new(p.m),
+            mc: Kilogram::new(p.mc),
+            d: Meter::new(p.d),
+            L: Meter::new(p.L),
+            I: KilogramMeter2::new(p.I),
+            Iw: KilogramMeter2::new(p.Iw),
+        }
+    }
+}
+
+impl From<DDMRParams> for RawDDMRParams {
+    fn from(p: DDMRParams) -> Self {
+        Self {
+            R: p.R.value_unsafe,
+            m: p.m.value_unsafe,
+            mc: p.mc.value_unsafe,
+            d: p.d.value_unsafe,
+            L: p.L.value_unsafe,
+            I: p.I.value_unsafe,
+            Iw: p.Iw.value_unsafe,
+        }
+    }
+}
+
+/// `DCMotorParams` in ohms, henries, V s, N m / A and kg m^2
+#[allow(non_snake_case)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RawDCMotorParams {
+    pub Ra: f64,
+    pub La: f64,
+    pub N: f64,
+    pub Kb: f64,
+    pub Kt: f64,
+    pub eta: f64,
+    pub Jr: f64,
+}
+
+impl From<RawDCMotorParams> for DCMotorParams {
+    fn from(p: RawDCMotorParams) -> Self {
+        Self {
+            Ra: Ohm::new(p.Ra),
+            La: Henry::new(p.La),
+            N: p.N,
+            Kb: VoltSecond::new(p.Kb),
+            Kt: NewtonMeterPerAmpere::new(p.Kt),
+            eta: p.eta,
+            Jr: KilogramMeter2::new(p.Jr),
+        }
+    }
+}
+
+impl From<DCMotorParams> for RawDCMotorParams {
+    fn from(p: DCMotorParams) -> Self {
+        Self {
+            Ra: p.Ra.value_unsafe,
+            La: p.La.value_unsafe,
+            N: p.N,
+            Kb: p.Kb.value_unsafe,
+            Kt: p.Kt.value_unsafe,
+            eta: p.eta,
+            Jr: p.Jr.value_unsafe,
+        }
+    }
+}
+
+/// `Vels` in m/s and rad/s
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct RawVels {
+    pub lin: f64,
+    pub ang: f64,
+}
+
+impl From<Vels> for RawVels {
+    fn from(v: Vels) -> Self {
+        Self {
+            lin: v.lin.value_unsafe,
+            ang: v.ang.value_unsafe,
+        }
+    }
+}
+
+impl From<RawVels> for Vels {
+    fn from(v: RawVels) -> Self {
+        Self {
+            lin: MeterPerSecond::new(v.lin),
+            ang: Hertz::new(v.ang),
+        }
+    }
+}
+
+/// `Pose` with the position in meters
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct RawPose {
+    pub x: f64,
+    pub y: f64,
+    pub theta: f64,
+}
+
+impl From<Pose> for RawPose {
+    fn from(p: Pose) -> Self {
+        Self {
+            x: p.x.value_unsafe,
+            y: p.y.value_unsafe,
+            theta: p.theta,
+        }
+    }
+}
+
+impl From<RawPose> for Pose {
+    fn from(p: RawPose) -> Self {
+        Self {
+            x: Meter::new(p.x),
+            y: Meter::new(p.y),
+            theta: p.theta,
+        }
+    }
+}
+
+fn raw_lr<U>(lr: LR<SI<f64, U>>) -> LR<f64> {
+    LR {
+        l: lr.l.value_unsafe,
+        r: lr.r.value_unsafe,
+    }
+}
+
+/// `ActuatedDDMRModel` taking volts and returning SI values
+#[derive(Debug, Clone)]
+pub struct RawActuatedModel {
+    model: ActuatedDDMRModel,
+}
+
+impl RawActuatedModel {
+    /// `dt` in seconds
+    pub fn new(
+        dt: f64,
+        ddmr_par: RawDDMRParams,
+        params: RawDCMotorParams,
+        eff: f64,
+        crr: f64,
+    ) -> Self {
+        Self {
+            model: ActuatedDDMRModel::new(
+                Second::new(dt),
+                ddmr_par.into(),
+                params.into(),
+                eff,
+                crr,
+            ),
+        }
+    }
+
+    /// Voltages across the left and right motors
+    pub fn observe(&mut self, v: LR<f64>) -> RawVels {
+        self.model
+            .observe(LR {
+                l: Volt::new(v.l),
+                r: Volt::new(v.r),
+            })
+            .into()
+    }
+
+    pub fn vel(&self) -> RawVels {
+        self.model.vel().into()
+    }
+
+    pub fn set_vels(&mut self, v: RawVels) {
+        self.model.set_vels(v.into());
+    }
+
+    pub fn reset(&mut self) {
+        self.model.reset();
+    }
+
+    /// Wheel speeds in rad/s
+    pub fn wheels(&self) -> LR<f64> {
+        raw_lr(self.model.ddmr().wheels())
+    }
+
+    /// Armature currents in amps
+    pub fn currents(&self) -> LR<f64> {
+        raw_lr(self.model.currents())
+    }
+
+    pub fn pose(&self) -> RawPose {
+        self.model.pose().into()
+    }
+
+    pub fn model(&self) -> &ActuatedDDMRModel {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut ActuatedDDMRModel {
+        &mut self.model
+    }
+}
+
+impl From<ActuatedDDMRModel> for RawActuatedModel {
+    fn from(model: ActuatedDDMRModel) -> Self {
+        Self { model }
+    }
+}
+
+#[test]
+fn raw_matches_dimensioned() {
+    let ddmr = super::test_ddmr_params();
+    let motor = super::test_motor_params();
+    let mut model = ActuatedDDMRModel::new(0.005 * S, ddmr.clone(), motor.clone(), 0.8, 0.0035);
+    let mut raw = RawActuatedModel::new(0.005, ddmr.into(), motor.into(), 0.8, 0.0035);
+    for i in 0..2000 {
+        let l = if i < 1000 { 12. } else { -4. };
+        let r = if i < 500 { 6. } else { 9. };
+        let v = model.observe(LR { l: l * V, r: r * V });
+        let rv = raw.observe(LR { l, r });
+        assert_eq!(RawVels::from(v), rv);
+    }
+    assert_eq!(RawPose::from(model.pose()), raw.pose());
+    assert_eq!(raw_lr(model.currents()), raw.currents());
+}
@@ -0,0 +1,416 @@
+//! The drive model's internal state, so a run can be saved and picked up again
+//! exactly where it left off. Restoring a state into a model built from the
+//! same parameters continues bit for bit the same as the model it was taken
+//! from.
+//!
+//! The states keep their units, so a velocity can't be restored into a
+//! current. They're only stripped to plain `f64`s in SI units when a state is
+//! serialized, by the `Raw` structs at the bottom.
+
+use super::raw::RawPose;
+use super::{Acceleration, AngularAcceleration, AngularVelocity};
+use super::{ActuatedDDMRModel, DDMRModel, Differentiator, Disturbance, Integrator, Pose, LR};
+
+use dimensioned::si::*;
+use dimensioned::tarr;
+use dimensioned::typenum::{P1, Z0};
+use serde_derive::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::ops::{Add, Div, Mul, Sub};
+
+/// The running total of an `Integrator` and the last sample it was given
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct IntegratorState<U>
+where
+    U: Add<tarr![Z0, Z0, P1, Z0, Z0, Z0, Z0]>,
+    <SI<f64, U> as Mul<Second<f64>>>::Output: Debug + Copy + Clone,
+    SI<f64, U>: Copy + Clone + Debug,
+{
+    pub acc: <SI<f64, U> as Mul<Second<f64>>>::Output,
+    pub last: SI<f64, U>,
+}
+
+impl<U> Integrator<U>
+where
+    U: Add<tarr![Z0, Z0, P1, Z0, Z0, Z0, Z0]>,
+    <SI<f64, U> as Mul<Second<f64>>>::Output: Debug + Copy + Clone,
+    SI<f64, U>: Copy + Clone + Debug,
+{
+    pub fn state(&self) -> IntegratorState<U> {
+        IntegratorState {
+            acc: self.acc,
+            last: self.last,
+        }
+    }
+
+    pub fn restore(&mut self, state: IntegratorState<U>) {
+        self.acc = state.acc;
+        self.last = state.last;
+    }
+}
+
+impl<U> Differentiator<U>
+where
+    U: Sub<tarr![Z0, Z0, P1, Z0, Z0, Z0, Z0]>,
+    SI<f64, U>: Copy + Clone + Debug,
+{
+    /// The samples it's holding, oldest first
+    pub fn state(&self) -> Vec<SI<f64, U>> {
+        self.samples.iter().cloned().collect()
+    }
+
+    /// Panics unless there's one sample for each one the window holds. They're
+    /// taken to be `dt` apart.
+    pub fn restore(&mut self, samples: &[SI<f64, U>]) {
+        assert_eq!(
+            samples.len(),
+            self.samples.len(),
+            "differentiator window size changed"
+        );
+        for (s, &val) in self.samples.iter_mut().zip(samples) {
+            *s = val;
+        }
+        self.reset_spans();
+        // the filter carries on from the newest sample
+        if let (Some(f), Some(&last)) = (self.filter.as_mut(), self.samples.back()) {
+            f.set(last);
+        }
+    }
+}
+
+/// Everything in a `DDMRModel` that changes as it's stepped
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "RawDDMRState", into = "RawDDMRState")]
+pub struct DDMRState {
+    pub linv: IntegratorState<Acceleration>,
+    pub angv: IntegratorState<AngularAcceleration>,
+    pub pose: Pose,
+    pub slipping: LR<bool>,
+    pub slip: LR<Hertz<f64>>,
+    pub wheel_angle: LR<IntegratorState<AngularVelocity>>,
+    pub odometry: Pose,
+    /// Pushing on the next step
+    pub pending: Disturbance,
+}
+
+impl DDMRModel {
+    pub fn state(&self) -> DDMRState {
+        DDMRState {
+            linv: self.linv.state(),
+            angv: self.angv.state(),
+            pose: self.pose,
+            slipping: self.slipping,
+            slip: self.slip,
+            wheel_angle: LR {
+                l: self.wheel_angle.l.state(),
+                r: self.wheel_angle.r.state(),
+            },
+            odometry: self.odometry,
+            pending: self.pending,
+        }
+    }
+
+    pub fn restore(&mut self, state: &DDMRState) {
+        self.linv.restore(state.linv);
+        self.angv.restore(state.angv);
+        self.pose = state.pose;
+        self.slipping = state.slipping;
+        self.slip = state.slip;
+        self.wheel_angle.l.restore(state.wheel_angle.l);
+        self.wheel_angle.r.restore(state.wheel_angle.r);
+        self.odometry = state.odometry;
+        self.pending = state.pending;
+    }
+}
+
+/// How far a `Battery` has drained and its bus voltage
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BatteryState {
+    pub drawn: Coulomb<f64>,
+    pub bus: Volt<f64>,
+}
+
+/// Everything in an `ActuatedDDMRModel` that changes as it's stepped. The
+/// parameters, friction, brake modes and battery size aren't included; they
+/// come from the model it's restored into.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "RawModelState", into = "RawModelState")]
+pub struct ModelState {
+    pub ddmr: DDMRState,
+    /// The current history behind each armature's inductance term
+    pub di: LR<Vec<Ampere<f64>>>,
+    /// `None` without a battery
+    pub battery: Option<BatteryState>,
+    /// What the ramp has reached on each side, `None` without one
+    pub ramp: Option<LR<Volt<f64>>>,
+    /// Of each motor's windings, `None` without a thermal model
+    pub temperature: Option<LR<Kelvin<f64>>>,
+    /// Whether each motor is cut out until it cools
+    pub cut_out: LR<bool>,
+}
+
+impl ActuatedDDMRModel {
+    pub fn state(&self) -> ModelState {
+        ModelState {
+            ddmr: self.ddmr.state(),
+            di: LR {
+                l: self.di.l.state(),
+                r: self.di.r.state(),
+            },
+            battery: self.battery.as_ref().map(|b| BatteryState {
+                drawn: b.drawn,
+                bus: b.bus,
+            }),
+            ramp: self.ramp.as_ref().map(|r| LR {
+                l: r.l.get(),
+                r: r.r.get(),
+            }),
+            temperature: self.thermal.as_ref().map(|_| self.temperature),
+            cut_out: self.cut_out,
+        }
+    }
+
+    /// Picks up from `state`. A battery, ramp or thermal model is only restored
+    /// if both have one.
+    /// `last_observation` still describes the step before the restore.
+    pub fn restore(&mut self, state: &ModelState) {
+        self.ddmr.restore(&state.ddmr);
+        self.di.l.restore(&state.di.l);
+        self.di.r.restore(&state.di.r);
+        if let (Some(b), Some(s)) = (self.battery.as_mut(), state.battery) {
+            b.drawn = s.drawn;
+            b.bus = s.bus;
+        }
+        if let (Some(r), Some(s)) = (self.ramp.as_mut(), state.ramp) {
+            r.l.set(s.l);
+            r.r.set(s.r);
+        }
+        if let Some(t) = state.temperature.filter(|_| self.thermal.is_some()) {
+            self.temperature = t;
+            self.cut_out = state.cut_out;
+        }
+    }
+}
+
+fn raw_lr<U>(lr: LR<SI<f64, U>>) -> LR<f64> {
+    LR {
+        l: lr.l.value_unsafe,
+        r: lr.r.value_unsafe,
+    }
+}
+
+fn si_lr<U>(lr: LR<f64>) -> LR<SI<f64, U>> {
+    LR {
+        l: SI::new(lr.l),
+        r: SI::new(lr.r),
+    }
+}
+
+/// `IntegratorState` as saved, in SI units
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+struct RawIntegratorState {
+    acc: f64,
+    last: f64,
+}
+
+impl<U> From<IntegratorState<U>> for RawIntegratorState
+where
+    U: Add<tarr![Z0, Z0, P1, Z0, Z0, Z0, Z0]>,
+    <SI<f64, U> as Mul<Second<f64>>>::Output:
+        Debug + Copy + Clone + Div<Second<f64>, Output = SI<f64, U>>,
+    SI<f64, U>: Copy + Clone + Debug,
+{
+    fn from(s: IntegratorState<U>) -> Self {
+        // dividing and multiplying by exactly one second is lossless
+        Self {
+            acc: (s.acc / (1. * S)).value_unsafe,
+            last: s.last.value_unsafe,
+        }
+    }
+}
+
+impl<U> From<RawIntegratorState> for IntegratorState<U>
+where
+    U: Add<tarr![Z0, Z0, P1, Z0, Z0, Z0, Z0]>,
+    <SI<f64, U> as Mul<Second<f64>>>::Output: Debug + Copy + Clone,
+    SI<f64, U>: Copy + Clone + Debug,
+{
+    fn from(s: RawIntegratorState) -> Self {
+        Self {
+            acc: SI::<f64, U>::new(s.acc) * (1. * S),
+            last: SI::new(s.last),
+        }
+    }
+}
+
+/// `DDMRState` as saved, in SI units
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct RawDDMRState {
+    linv: RawIntegratorState,
+    angv: RawIntegratorState,
+    pose: RawPose,
+    slipping: LR<bool>,
+    /// rad/s
+    #[serde(default)]
+    slip: LR<f64>,
+    wheel_angle: LR<RawIntegratorState>,
+    odometry: RawPose,
+    /// N, pushing on the next step
+    #[serde(default)]
+    push: f64,
+    /// N m, turning it on the next step
+    #[serde(default)]
+    push_torque: f64,
+}
+
+impl From<DDMRState> for RawDDMRState {
+    fn from(s: DDMRState) -> Self {
+        Self {
+            linv: s.linv.into(),
+            angv: s.angv.into(),
+            pose: s.pose.into(),
+            slipping: s.slipping,
+            slip: raw_lr(s.slip),
+            wheel_angle: LR {
+                l: s.wheel_angle.l.into(),
+                r: s.wheel_angle.r.into(),
+            },
+            odometry: s.odometry.into(),
+            push: s.pending.force.value_unsafe,
+            push_torque: s.pending.torque.value_unsafe,
+        }
+    }
+}
+
+impl From<RawDDMRState> for DDMRState {
+    fn from(s: RawDDMRState) -> Self {
+        Self {
+            linv: s.linv.into(),
+            angv: s.angv.into(),
+            pose: s.pose.into(),
+            slipping: s.slipping,
+            slip: si_lr(s.slip),
+            wheel_angle: LR {
+                l: s.wheel_angle.l.into(),
+                r: s.wheel_angle.r.into(),
+            },
+            odometry: s.odometry.into(),
+            pending: Disturbance {
+                force: s.push * N,
+                torque: s.push_torque * N * M,
+            },
+        }
+    }
+}
+
+/// `BatteryState` as saved, in coulombs and volts
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+struct RawBatteryState {
+    drawn: f64,
+    bus: f64,
+}
+
+/// `ModelState` as saved, in SI units
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct RawModelState {
+    ddmr: RawDDMRState,
+    /// A
+    di: LR<Vec<f64>>,
+    battery: Option<RawBatteryState>,
+    /// V
+    #[serde(default)]
+    ramp: Option<LR<f64>>,
+    /// K
+    #[serde(default)]
+    temperature: Option<LR<f64>>,
+    #[serde(default)]
+    cut_out: LR<bool>,
+}
+
+impl From<ModelState> for RawModelState {
+    fn from(s: ModelState) -> Self {
+        let amps = |di: &[Ampere<f64>]| di.iter().map(|i| i.value_unsafe).collect();
+        Self {
+            ddmr: s.ddmr.into(),
+            di: LR {
+                l: amps(&s.di.l),
+                r: amps(&s.di.r),
+            },
+            battery: s.battery.map(|b| RawBatteryState {
+                drawn: b.drawn.value_unsafe,
+                bus: b.bus.value_unsafe,
+            }),
+            ramp: s.ramp.map(raw_lr),
+            temperature: s.temperature.map(raw_lr),
+            cut_out: s.cut_out,
+        }
+    }
+}
+
+impl From<RawModelState> for ModelState {
+    fn from(s: RawModelState) -> Self {
+        let amps = |di: &[f64]| di.iter().map(|&i| i * A).collect();
+        Self {
+            ddmr: s.ddmr.into(),
+            di: LR {
+                l: amps(&s.di.l),
+                r: amps(&s.di.r),
+            },
+            battery: s.battery.map(|b| BatteryState {
+                drawn: b.drawn * C,
+                bus: b.bus * V,
+            }),
+            ramp: s.ramp.map(si_lr),
+            temperature: s.temperature.map(si_lr),
+            cut_out: s.cut_out,
+        }
+    }
+}
+
+#[test]
+fn state_restores_exactly() {
+    use crate::config::RobotConfig;
+    let config = RobotConfig::default();
+    let mut model = config.build(0.005 * S);
+    let drive = |model: &mut ActuatedDDMRModel, k: usize| {
+        let t = k as f64 * 0.01;
+        model.observe(LR {
+            l: (8. + 3. * t.sin()) * V,
+            r: (6. - 4. * t.cos()) * V,
+        })
+    };
+    for k in 0..200 {
+        drive(&mut model, k);
+    }
+    let saved = model.state();
+    let mut fresh = config.build(0.005 * S);
+    fresh.restore(&saved);
+    assert_eq!(fresh.state(), saved);
+    for k in 200..400 {
+        let a = drive(&mut model, k);
+        let b = drive(&mut fresh, k);
+        assert_eq!(a.lin, b.lin);
+        assert_eq!(a.ang, b.ang);
+    }
+    assert_eq!(model.state(), fresh.state());
+}
+
+#[test]
+fn state_saves_as_plain_numbers() {
+    use crate::config::RobotConfig;
+    let mut model = RobotConfig::default().build(0.005 * S);
+    for _ in 0..50 {
+        model.observe(LR {
+            l: 9. * V,
+            r: 4. * V,
+        });
+    }
+    model.push(2. * N);
+    let saved = model.state();
+    let json = serde_json::to_value(&saved).unwrap();
+    assert_eq!(json["ddmr"]["push"], 2.);
+    assert_eq!(json["ddmr"]["linv"]["acc"], *(saved.ddmr.linv.acc / MPS));
+    assert_eq!(json["di"]["l"][0], *(saved.di.l[0] / A));
+    let loaded: ModelState = serde_json::from_value(json).unwrap();
+    assert_eq!(loaded, saved);
+}
@@ -0,0 +1,109 @@
+//! Turning raw controller stick readings into the -1..1 axes of `InputState`.
+
+/// Fraction of stick travel around center that reads as zero, so a stick
+/// that doesn't quite recenter doesn't creep the robot
+pub const DEADZONE: f64 = 0.1;
+
+/// SDL reports the Y axes positive down; flip them so pushing a stick
+/// forward drives forward
+pub const INVERT_Y: bool = true;
+
+/// Scales a raw SDL axis reading to [-1, 1]
+pub fn axis_value(raw: i16) -> f64 {
+    (raw as f64 / i16::max_value() as f64).max(-1.).min(1.)
+}
+
+/// Zeroes `value` within `deadzone` of center and rescales the rest, so
+/// output rises from 0 at the edge of the deadzone to ±1 at full deflection
+pub fn apply_deadzone(value: f64, deadzone: f64) -> f64 {
+    let mag = value.abs();
+    if mag <= deadzone {
+        0.
+    } else {
+        value.signum() * ((mag - deadzone) / (1. - deadzone)).min(1.)
+    }
+}
+
+/// Raises `value` to the power `expo`, keeping its sign. Above 1 this gives
+/// finer control near center while still reaching ±1 at full deflection.
+pub fn apply_expo(value: f64, expo: f64) -> f64 {
+    value.signum() * value.abs().powf(expo)
+}
+
+/// How a stick reading is shaped before it reaches the drive mixer
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StickShape {
+    /// See `apply_deadzone`
+    pub deadzone: f64,
+    /// See `apply_expo`, applied after the deadzone
+    pub expo: f64,
+}
+
+impl Default for StickShape {
+    /// The deadzone alone, linear past it
+    fn default() -> Self {
+        Self {
+            deadzone: DEADZONE,
+            expo: 1.,
+        }
+    }
+}
+
+impl StickShape {
+    /// `value` in [-1, 1] with the deadzone and then the expo applied
+    pub fn apply(&self, value: f64) -> f64 {
+        apply_expo(apply_deadzone(value, self.deadzone), self.expo)
+    }
+}
+
+/// A raw stick reading as it should land in `InputState`
+pub fn stick_value(raw: i16, invert: bool, shape: &StickShape) -> f64 {
+    let value = shape.apply(axis_value(raw));
+    if invert {
+        -value
+    } else {
+        value
+    }
+}
+
+#[test]
+fn deadzone_edges() {
+    assert_eq!(apply_deadzone(0.0, 0.1), 0.);
+    assert_eq!(apply_deadzone(0.099, 0.1), 0.);
+    assert_eq!(apply_deadzone(-0.099, 0.1), 0.);
+    assert_eq!(apply_deadzone(1.0, 0.1), 1.);
+    assert_eq!(apply_deadzone(-1.0, 0.1), -1.);
+    assert!((apply_deadzone(0.55, 0.1) - 0.5).abs() < 1e-12);
+    assert!(apply_deadzone(0.11, 0.1) > 0.);
+}
+
+#[test]
+fn stick_full_deflection() {
+    let shape = StickShape::default();
+    assert_eq!(stick_value(i16::max_value(), false, &shape), 1.);
+    assert_eq!(stick_value(i16::min_value(), false, &shape), -1.);
+    assert_eq!(stick_value(i16::min_value(), true, &shape), 1.);
+    assert_eq!(stick_value(1000, false, &shape), 0.);
+}
+
+#[test]
+fn stick_shape_expo() {
+    let shape = StickShape {
+        deadzone: 0.1,
+        expo: 3.,
+    };
+    // rises from zero at the edge of the deadzone rather than jumping
+    assert_eq!(shape.apply(0.1), 0.);
+    assert!(shape.apply(0.1 + 1e-6) < 1e-12);
+    // finer near center than linear, the same at full deflection
+    assert!((shape.apply(0.55) - 0.125).abs() < 1e-12);
+    assert_eq!(shape.apply(1.), 1.);
+    for i in 0..=200 {
+        let x = -1. + i as f64 / 100.;
+        let y = shape.apply(x);
+        assert_eq!(shape.apply(-x), -y);
+        assert!(y.abs() <= 1., "{} {}", x, y);
+    }
+    // out of range readings are still held to ±1
+    assert_eq!(shape.apply(1.5), 1.);
+}
@@ -0,0 +1,199 @@
+//! A recorded run drawn as a see-through robot next to the live one, to compare
+//! two tunings by eye. The recording is any CSV with `time`, `x`, `y` and
+//! `theta` columns in the drive model's frame, which is what `Telemetry`
+//! writes. Its names with the units on the end, like `x_m`, work too.
+
+use crate::angle;
+use crate::dynamics::Pose;
+use crate::playback::{read_columns, PlaybackError};
+
+use dimensioned::si::M;
+use ggez::graphics::Point2;
+
+use std::io::Read;
+
+/// What the ghost does once its recording runs out
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GhostEnd {
+    /// Stays where the recording ended
+    Freeze,
+    /// Starts over from the beginning
+    Loop,
+}
+
+impl GhostEnd {
+    pub fn next(self) -> Self {
+        match self {
+            GhostEnd::Freeze => GhostEnd::Loop,
+            GhostEnd::Loop => GhostEnd::Freeze,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct GhostRow {
+    time: f64,
+    x: f64,
+    y: f64,
+    theta: f64,
+}
+
+/// A recorded path, played back on the simulation's clock
+#[derive(Debug, Clone)]
+pub struct Ghost {
+    rows: Vec<GhostRow>,
+    end: GhostEnd,
+    /// Seconds since the ghost started
+    time: f64,
+}
+
+impl Ghost {
+    pub fn from_reader<R: Read>(mut r: R) -> Result<Self, PlaybackError> {
+        let mut s = String::new();
+        r.read_to_string(&mut s)?;
+        Self::from_csv(&s)
+    }
+
+    pub fn from_csv(s: &str) -> Result<Self, PlaybackError> {
+        let rows = read_columns(s, |col| {
+            ["time", "x", "y", "theta"]
+                .iter()
+                .map(|&name| col(name).ok_or(PlaybackError::MissingColumn(name)))
+                .collect()
+        })?;
+        let rows = rows
+            .iter()
+            .map(|row| GhostRow {
+                time: row[0],
+                x: row[1],
+                y: row[2],
+                theta: row[3],
+            })
+            .collect();
+        Ok(Self {
+            rows,
+            end: GhostEnd::Freeze,
+            time: 0.,
+        })
+    }
+
+    pub fn end(&self) -> GhostEnd {
+        self.end
+    }
+
+    pub fn set_end(&mut self, end: GhostEnd) {
+        self.end = end;
+    }
+
+    /// Time of the last row
+    pub fn end_time(&self) -> f64 {
+        self.rows[self.rows.len() - 1].time
+    }
+
+    /// Moves the ghost on by one simulation step
+    pub fn advance(&mut self, dt: f64) {
+        self.time += dt;
+    }
+
+    /// Back to the start of the recording
+    pub fn restart(&mut self) {
+        self.time = 0.;
+    }
+
+    /// Whether a `Freeze` ghost has reached its last row. A `Loop` one never
+    /// finishes.
+    pub fn finished(&self) -> bool {
+        self.end == GhostEnd::Freeze && self.time >= self.end_time()
+    }
+
+    /// Where the recording was at the ghost's time, interpolated between rows.
+    /// Before the first row it's at the first row.
+    pub fn pose(&self) -> Pose {
+        let end = self.end_time();
+        let time = match self.end {
+            GhostEnd::Loop if end > 0. => self.time % end,
+            _ => self.time,
+        };
+        let next = self.rows.iter().position(|row| row.time > time);
+        let (a, b) = match next {
+            Some(0) => (self.rows[0], self.rows[0]),
+            Some(i) => (self.rows[i - 1], self.rows[i]),
+            None => (
+                self.rows[self.rows.len() - 1],
+                self.rows[self.rows.len() - 1],
+            ),
+        };
+        let t = if b.time > a.time {
+            (time - a.time) / (b.time - a.time)
+        } else {
+            0.
+        };
+        Pose {
+            x: (a.x + (b.x - a.x) * t) * M,
+            y: (a.y + (b.y - a.y) * t) * M,
+            // the short way round, in case the recording wrapped its heading
+            theta: a.theta + angle::difference(a.theta, b.theta) * t,
+        }
+    }
+
+    /// `pose` as a position in pixels and a facing for drawing, with the
+    /// recording started where the game starts the player: at the center,
+    /// facing up
+    pub fn actor_pose(&self, px_per_meter: f32) -> (Point2, f32) {
+        let pose = self.pose();
+        // the model drives along its +x, which the game draws as up
+        let x = -pose.y.value_unsafe as f32 * px_per_meter;
+        let y = pose.x.value_unsafe as f32 * px_per_meter;
+        (Point2::new(x, y), angle::wrap_f32(-pose.theta as f32))
+    }
+}
+
+#[test]
+fn ghost_interpolates_pose() {
+    let mut g = Ghost::from_csv(
+        "time,volts_l,x,y,theta\n0.5,1,0,0,0\n1.5,1,2,-1,1\n2.5,1,2,-1,3.1\n3.5,1,2,-1,-3.1\n",
+    )
+    .unwrap();
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+    // held at the first row before it starts
+    assert!(close(g.pose().x.value_unsafe, 0.));
+    g.advance(1.);
+    let p = g.pose();
+    assert!(close(p.x.value_unsafe, 1.) && close(p.y.value_unsafe, -0.5));
+    assert!(close(p.theta, 0.5));
+    // across the seam at pi it goes the short way, not back through zero
+    g.advance(2.);
+    assert!(close(
+        angle::wrap(g.pose().theta).abs(),
+        std::f64::consts::PI
+    ));
+    assert!(!g.finished());
+    g.advance(10.);
+    assert!(g.finished());
+    assert!(close(angle::wrap(g.pose().theta), -3.1));
+
+    g.set_end(GhostEnd::Loop);
+    assert!(!g.finished());
+    g.restart();
+    g.advance(3.5 + 1.);
+    assert!(close(g.pose().x.value_unsafe, 1.));
+
+    match Ghost::from_csv("time,x,y\n0,0,0\n") {
+        Err(PlaybackError::MissingColumn("theta")) => (),
+        r => panic!("{:?}", r),
+    }
+}
+
+#[test]
+fn ghost_starts_with_player() {
+    let g = Ghost::from_csv("time,x,y,theta\n0,1,0,0\n").unwrap();
+    // a meter forward from the start is a meter up the screen
+    let (pos, facing) = g.actor_pose(100.);
+    assert!(pos.x.abs() < 1e-4 && (pos.y - 100.).abs() < 1e-4);
+    assert_eq!(facing, 0.);
+    let turned = Ghost::from_csv("time,x,y,theta\n0,0,1,1.5707963\n").unwrap();
+    let (pos, facing) = turned.actor_pose(100.);
+    // to the model's left is to the player's left
+    assert!((pos.x + 100.).abs() < 1e-4);
+    assert!((facing + std::f32::consts::FRAC_PI_2).abs() < 1e-4);
+}
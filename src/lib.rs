@@ -0,0 +1,30 @@
+//! A differential drive robot simulator, wrapped in an Asteroids-ish game.
+//! Everything but the window and the drawing lives here, so the model can be
+//! driven from tests and other binaries as well as the game.
+
+pub mod angle;
+pub mod auton;
+pub mod benchmark;
+pub mod camera;
+pub mod characterize;
+pub mod chassis;
+pub mod config;
+pub mod control;
+pub mod disturbances;
+pub mod drive_mixer;
+pub mod dynamics;
+pub mod gamepad;
+pub mod ghost;
+pub mod params;
+pub mod playback;
+pub mod profile;
+pub mod remote;
+pub mod replay;
+pub mod sensors;
+pub mod signals;
+pub mod sim;
+pub mod sweep;
+pub mod sysid;
+pub mod telemetry;
+pub mod time_control;
+pub mod trail;
@@ -7,146 +7,42 @@
 extern crate ggez;
 extern crate rand;
 
-pub mod dynamics;
-
 use ggez::audio;
 use ggez::conf;
-use ggez::event::{self, EventHandler, Keycode, Mod};
+use ggez::event::{self, Axis, Button, EventHandler, Keycode, Mod, MouseButton};
 use ggez::graphics;
-use ggez::graphics::{Point2, Vector2};
-use ggez::nalgebra as na;
+use ggez::graphics::Point2;
 use ggez::timer;
 use ggez::{Context, ContextBuilder, GameResult};
 
-use std::env;
-use std::path;
-
-/// *********************************************************************
-/// Basic stuff, make some helpers for vector functions.
-/// ggez includes the nalgebra math library to provide lots of
-/// math stuff  We just add some helpers.
-/// **********************************************************************
-
-/// Create a unit vector representing the
-/// given angle (in radians)
-fn vec_from_angle(angle: f32) -> Vector2 {
-    let vx = angle.sin();
-    let vy = angle.cos();
-    Vector2::new(vx, vy)
-}
-
-#[derive(Debug)]
-struct Actor {
-    pos: Point2,
-    facing: f32,
-    sim: dynamics::ActuatedDDMRModel,
-}
-
-/// *********************************************************************
-/// Now we have some constructor functions for different game objects.
-/// **********************************************************************
-
-fn create_player() -> Actor {
-    use dimensioned::si::*;
-    Actor {
-        pos: Point2::origin(),
-        facing: 0.,
-        sim: dynamics::ActuatedDDMRModel::new(
-            1. / SIM_FPS as f64 * S,
-            dynamics::DDMRParams {
-                R: 0.1524 / 2. * M,
-                m: 32.5 * KG,
-                mc: 32.5 * KG - 4.53592 * KG,
-                d: 0.06 * M,
-                // d: 0.0 * M,
-                L: 0.63684 / 2. * M,
-                I: 4.29 * KG * M * M,
-                Iw: 0.00063651 * KG * M * M * 3.,
-            },
-            // https://www.systemvision.com/blog/first-robotics-frc-motor-modeling-may-6-2016
-            dynamics::DCMotorParams {
-                Ra: 12. * V / 133. / A,
-                Kt: 2.4 * N * M / 133. / A,
-                N: 5.10,
-                La: 0. * H,
-                Kb: 2.11E-2 * V * S,
-            },
-            0.8,
-            0.0035,
-        ),
-    }
-}
-
-fn player_handle_input(actor: &mut Actor, input: &InputState, dt: f32) {
-    let l = input.yaxis - input.xaxis;
-    let r = input.yaxis + input.xaxis;
-    let l = if l > 1.0 {
-        1.0
-    } else if l < -1.0 {
-        -1.0
-    } else {
-        l
-    };
-    let r = if r > 1.0 {
-        1.0
-    } else if r < -1.0 {
-        -1.0
-    } else {
-        r
-    };
-
-    println!("l: {}, r: {}", l, r);
-
-    actor.sim.observe(dynamics::LR {
-        l: l * 12. * dimensioned::si::V,
-        r: r * 12. * dimensioned::si::V,
-    });
-}
-
-use std::ops::Deref;
-
-const PX_PER_METER: u32 = 100;
+use drive_sim::auton::Routine;
+use drive_sim::camera::Camera;
+use drive_sim::chassis::ChassisShape;
+use drive_sim::config::RobotConfig;
+use drive_sim::dynamics::{LR, ZERO_CELSIUS};
+use drive_sim::ghost::Ghost;
+use drive_sim::params::ParamEditor;
+use drive_sim::playback::Playback;
+use drive_sim::profile::{FrameStats, Section, FRAME_WINDOW};
+use drive_sim::remote::RemoteLink;
+use drive_sim::replay::{Replay, ReplayRun};
+use drive_sim::sim::{
+    Actor, ActorType, Boundary, ControlMode, InputState, Obstacle, PhysicsMode, Session, Shape,
+    SimEvent, Simulation,
+};
+use drive_sim::sweep::{Maneuver, Variation};
+use drive_sim::sysid::ChirpConfig;
+use drive_sim::telemetry::Telemetry;
+use drive_sim::time_control::TimeControl;
+use drive_sim::trail::Trail;
+use drive_sim::{chassis, gamepad, sim, sweep, sysid};
 
-fn update_actor_position(actor: &mut Actor, dt: f32) {
-    let dynamics::Vels { lin, ang } = actor.sim.vel();
-    let dv = vec_from_angle(actor.facing)
-        * *(lin / dimensioned::si::MPS) as f32
-        * (dt)
-        * PX_PER_METER as f32;
-    actor.pos += dv;
-    actor.facing += *(ang * dimensioned::si::S) as f32 * (dt);
-}
-
-/// Takes an actor and wraps its position to the bounds of the
-/// screen, so if it goes off the left side of the screen it
-/// will re-enter on the right side and so on.
-fn wrap_actor_position(actor: &mut Actor, sx: f32, sy: f32) {
-    // Wrap screen
-    let screen_x_bounds = sx / 2.0;
-    let screen_y_bounds = sy / 2.0;
-    if actor.pos.x > screen_x_bounds {
-        actor.pos.x -= sx;
-    } else if actor.pos.x < -screen_x_bounds {
-        actor.pos.x += sx;
-    };
-    if actor.pos.y > screen_y_bounds {
-        actor.pos.y -= sy;
-    } else if actor.pos.y < -screen_y_bounds {
-        actor.pos.y += sy;
-    }
-}
+use dimensioned::si::Hertz;
 
-/// Translates the world coordinate system, which
-/// has Y pointing up and the origin at the center,
-/// to the screen coordinate system, which has Y
-/// pointing downward and the origin at the top-left,
-fn world_to_screen_coords(screen_width: u32, screen_height: u32, point: Point2) -> Point2 {
-    let width = screen_width as f32;
-    let height = screen_height as f32;
-    let x = point.x + width / 2.0;
-    let y = height - (point.y + height / 2.0);
-    Point2::new(x, y)
-}
+use std::env;
+use std::path;
+use std::thread;
+use std::time::Duration;
 
 /// **********************************************************************
 /// So that was the real meat of our game.  Now we just need a structure
@@ -157,11 +53,13 @@ fn world_to_screen_coords(screen_width: u32, screen_height: u32, point: Point2)
 
 struct Assets {
     player_image: graphics::Mesh,
-    // shot_image: graphics::Image,
-    // rock_image: graphics::Image,
+    /// Of the circle in `player_image`
+    player_radius: f32,
+    shot_image: graphics::Image,
+    rock_image: graphics::Image,
     font: graphics::Font,
-    // shot_sound: audio::Source,
-    // hit_sound: audio::Source,
+    shot_sound: audio::Source,
+    hit_sound: audio::Source,
 }
 
 impl Assets {
@@ -179,34 +77,40 @@ impl Assets {
                 Point2::new(0.0, -1.5 * player_wb_r),
             ])
             .build(ctx)?;
-        // let shot_image = graphics::Image::new(ctx, "/shot.png")?;
-        // let rock_image = graphics::Image::new(ctx, "/rock.png")?;
+        let shot_image = graphics::Image::new(ctx, "/shot.png")?;
+        let rock_image = graphics::Image::new(ctx, "/rock.png")?;
         let font = graphics::Font::new(ctx, "/DejaVuSerif.ttf", 18)?;
 
-        // let shot_sound = audio::Source::new(ctx, "/pew.ogg")?;
-        // let hit_sound = audio::Source::new(ctx, "/boom.ogg")?;
+        let shot_sound = audio::Source::new(ctx, "/pew.ogg")?;
+        let hit_sound = audio::Source::new(ctx, "/boom.ogg")?;
         Ok(Assets {
             player_image,
-            // shot_image,
-            // rock_image,
+            player_radius: player_wb_r,
+            shot_image,
+            rock_image,
             font,
-            // shot_sound,
-            // hit_sound,
+            shot_sound,
+            hit_sound,
         })
     }
 
-    fn actor_image(&mut self, _actor: &Actor) -> &mut graphics::Mesh {
-        &mut self.player_image
+    /// The image for `actor`, and how far it reaches from its middle in its
+    /// own pixels
+    fn actor_image(&mut self, actor: &Actor) -> (&mut dyn graphics::Drawable, f32) {
+        match actor.tag {
+            ActorType::Robot => (&mut self.player_image, self.player_radius),
+            ActorType::Rock => {
+                let radius = self.rock_image.width() as f32 / 2.0;
+                (&mut self.rock_image, radius)
+            }
+            ActorType::Shot => {
+                let radius = self.shot_image.width() as f32 / 2.0;
+                (&mut self.shot_image, radius)
+            }
+        }
     }
 }
 
-/// Simulates joystick axes for arcade drive
-#[derive(Debug, Default)]
-struct InputState {
-    xaxis: f64,
-    yaxis: f64,
-}
-
 /// **********************************************************************
 /// Now we're getting into the actual game loop.  The `MainState` is our
 /// game's "global" state, it keeps track of everything we need for
@@ -218,15 +122,76 @@ struct InputState {
 /// this small it hardly matters.
 /// **********************************************************************
 
+/// Which device last moved, and so owns the axes in `InputState`
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum InputDevice {
+    Keyboard,
+    Gamepad,
+}
+
+/// A robot besides the player's, as the game sees it. Its drive model and so
+/// on are in `Simulation::others` at the same index.
+struct OtherRobot {
+    config: RobotConfig,
+    /// From WASD for the first one, nothing for any after that
+    input: InputState,
+    trail: Trail,
+    chassis: ChassisShape,
+    /// Its sprite, chassis and trail are drawn in this color
+    tint: graphics::Color,
+}
+
 struct MainState {
-    player: Actor,
+    sim: Simulation,
     assets: Assets,
-    screen_width: u32,
-    screen_height: u32,
     input: InputState,
+    input_device: InputDevice,
+    time: TimeControl,
+    telemetry: Option<Telemetry<ggez::filesystem::File>>,
     gui_dirty: bool,
     xpos_display: graphics::Text,
     ypos_display: graphics::Text,
+    score_display: graphics::Text,
+    /// REC or REPLAY while either is going
+    status_display: graphics::Text,
+    show_overlay: bool,
+    overlay_display: Vec<graphics::Text>,
+    /// Seconds since the overlay text was last rebuilt
+    overlay_age: f32,
+    /// Frame timings, shown on the overlay while enabled
+    stats: FrameStats,
+    trail: Trail,
+    show_trail: bool,
+    chassis: ChassisShape,
+    /// Draw the player as a chassis with wheels instead of the sprite
+    show_chassis: bool,
+    camera: Camera,
+    /// Whether the camera tracks the player. The field has no edges while it does.
+    follow_camera: bool,
+    /// Put back when the camera stops following
+    boundary_before_follow: Boundary,
+    /// The robot being driven, as loaded and tuned since, for the gear ratio
+    /// sweep
+    config: RobotConfig,
+    /// Input since recording started, saved to `REPLAY_PATH` when it stops
+    recording: Option<Replay>,
+    /// Drives the simulation instead of the input while a replay plays
+    replaying: Option<ReplayRun>,
+    /// A recorded run drawn alongside the player
+    ghost: Option<Ghost>,
+    /// Another program driving the robot over UDP, set up by `--listen`
+    remote: Option<RemoteLink>,
+    /// The robot's parameters, open for tuning while it drives
+    params: Option<ParamEditor>,
+    /// Only rebuilt when the editor changes
+    params_display: Vec<graphics::Text>,
+    /// Loaded from `/robot2.toml` if it's there
+    others: Vec<OtherRobot>,
+    /// A line per robot while there's more than one, rebuilt with the overlay
+    robots_display: Vec<graphics::Text>,
+    /// The benchmark's progress or results, with whether each passed, rebuilt
+    /// with the overlay
+    benchmark_display: Vec<(graphics::Text, Option<bool>)>,
 }
 
 impl MainState {
@@ -238,32 +203,86 @@ impl MainState {
 
         print_instructions();
 
-        let player = create_player();
+        let screen_width = ctx.conf.window_mode.width;
+        let screen_height = ctx.conf.window_mode.height;
+        let config = load_robot_config(ctx);
+        let mut sim = Simulation::with_config(screen_width as f32, screen_height as f32, &config);
+        sim.set_routine(load_routine(ctx));
+        let trail_range = screen_width.min(screen_height) as f32 / 2.0;
+        let others: Vec<OtherRobot> = load_second_robot(ctx)
+            .into_iter()
+            .enumerate()
+            .map(|(i, config)| {
+                sim.add_robot(&config);
+                OtherRobot {
+                    input: InputState::default(),
+                    trail: Trail::with_stride(
+                        config.trail.length,
+                        trail_range,
+                        config.trail.stride,
+                    ),
+                    chassis: chassis_shape(&config, sim.px_per_meter()),
+                    tint: ROBOT_TINTS[i % ROBOT_TINTS.len()],
+                    config,
+                }
+            })
+            .collect();
 
-        let assets = Assets::new(
-            ctx,
-            *(player.sim.ddmr().params().L / dimensioned::si::M * PX_PER_METER as f64) as f32,
-        )?;
+        let chassis = chassis_shape(&config, sim.px_per_meter());
+
+        let assets = Assets::new(ctx, sim.player().bbox_size)?;
         let xpos_display = graphics::Text::new(ctx, "x: ", &assets.font)?;
         let ypos_display = graphics::Text::new(ctx, "y: ", &assets.font)?;
+        let score_display = graphics::Text::new(ctx, "score", &assets.font)?;
+        let status_display = graphics::Text::new(ctx, " ", &assets.font)?;
+        let mut camera = Camera::new(
+            sim.px_per_meter(),
+            (screen_width as f32, screen_height as f32),
+        );
+        camera.smoothing = CAMERA_SMOOTHING;
+        let boundary_before_follow = sim.boundary();
 
         let s = MainState {
-            player,
+            sim,
             assets,
-            screen_width: ctx.conf.window_mode.width,
-            screen_height: ctx.conf.window_mode.height,
             input: InputState::default(),
+            input_device: InputDevice::Keyboard,
+            time: TimeControl::default(),
+            telemetry: None,
             gui_dirty: true,
             xpos_display,
             ypos_display,
+            score_display,
+            status_display,
+            show_overlay: false,
+            overlay_display: Vec::new(),
+            overlay_age: OVERLAY_PERIOD,
+            stats: FrameStats::default(),
+            trail: Trail::with_stride(config.trail.length, trail_range, config.trail.stride),
+            show_trail: true,
+            chassis,
+            show_chassis: true,
+            camera,
+            follow_camera: false,
+            boundary_before_follow,
+            config,
+            recording: None,
+            replaying: None,
+            ghost: None,
+            remote: None,
+            params: None,
+            params_display: Vec::new(),
+            others,
+            robots_display: Vec::new(),
+            benchmark_display: Vec::new(),
         };
 
         Ok(s)
     }
 
     fn update_ui(&mut self, ctx: &mut Context) {
-        let y_str = format!("y: {}", self.player.pos.x);
-        let x_str = format!("x: {}", self.player.pos.y);
+        let y_str = format!("y: {}", self.sim.player().pos.x);
+        let x_str = format!("x: {}", self.sim.player().pos.y);
         // let y_str = "";
         // let x_str = "";
         let y_text = graphics::Text::new(ctx, &y_str, &self.assets.font).unwrap();
@@ -271,6 +290,385 @@ impl MainState {
 
         self.xpos_display = x_text;
         self.ypos_display = y_text;
+
+        let score_str = format!("Score: {}  Level: {}", self.sim.score(), self.sim.level());
+        let score_text = graphics::Text::new(ctx, &score_str, &self.assets.font).unwrap();
+        self.score_display = score_text;
+
+        let status = if self.recording.is_some() {
+            "REC"
+        } else if self.replaying.is_some() {
+            "REPLAY"
+        } else {
+            // Text can't be empty
+            " "
+        };
+        self.status_display = graphics::Text::new(ctx, status, &self.assets.font).unwrap();
+    }
+
+    fn update_overlay(&mut self, ctx: &mut Context) {
+        let font = &self.assets.font;
+        let mut lines = overlay_lines(&self.sim);
+        lines.insert(
+            0,
+            format!("time: {:.1} s, {}", self.sim.time(), self.time.label()),
+        );
+        lines.insert(1, format!("grid: {} m", self.camera.grid_spacing()));
+        if self.stats.enabled() {
+            lines.extend(self.stats.lines());
+        }
+        self.overlay_display = lines
+            .iter()
+            .map(|line| graphics::Text::new(ctx, line, font).unwrap())
+            .collect();
+    }
+
+    fn update_robots_display(&mut self, ctx: &mut Context) {
+        let font = &self.assets.font;
+        self.robots_display = robot_lines(&self.sim)
+            .iter()
+            .map(|line| graphics::Text::new(ctx, line, font).unwrap())
+            .collect();
+    }
+
+    fn update_benchmark_display(&mut self, ctx: &mut Context) {
+        let font = &self.assets.font;
+        self.benchmark_display = benchmark_lines(&self.sim, &self.config)
+            .iter()
+            .map(|&(ref line, passed)| (graphics::Text::new(ctx, line, font).unwrap(), passed))
+            .collect();
+    }
+
+    /// Every robot's trail, for when the robots jump somewhere new
+    fn clear_trails(&mut self) {
+        self.trail.clear();
+        for other in &mut self.others {
+            other.trail.clear();
+        }
+    }
+
+    fn update_params_display(&mut self, ctx: &mut Context) {
+        let font = &self.assets.font;
+        self.params_display = match self.params {
+            Some(ref editor) => editor
+                .lines(&self.config)
+                .iter()
+                .map(|line| graphics::Text::new(ctx, line, font).unwrap())
+                .collect(),
+            None => Vec::new(),
+        };
+    }
+
+    /// Opens the parameter editor, or closes it
+    fn toggle_params(&mut self, ctx: &mut Context) {
+        self.params = match self.params {
+            Some(_) => None,
+            None => Some(ParamEditor::default()),
+        };
+        self.update_params_display(ctx);
+    }
+
+    /// Arrow keys while the editor is open: up and down pick a parameter, left
+    /// and right nudge it and rebuild the robot without stopping it
+    fn edit_params(&mut self, ctx: &mut Context, keycode: Keycode) {
+        let editor = match self.params {
+            Some(ref mut editor) => editor,
+            None => return,
+        };
+        match keycode {
+            Keycode::Up => editor.select_prev(),
+            Keycode::Down => editor.select_next(),
+            Keycode::Left | Keycode::Right => {
+                match editor.nudge(&mut self.config, keycode == Keycode::Right) {
+                    Ok(()) => {
+                        self.sim.set_robot_config(&self.config);
+                        self.chassis = chassis_shape(&self.config, self.sim.px_per_meter());
+                    }
+                    Err(e) => println!("Not changed: {}", e),
+                }
+            }
+            _ => return,
+        }
+        self.update_params_display(ctx);
+    }
+
+    /// Hands the robot back to the driver if the auton or the benchmark has it
+    fn take_over(&mut self) {
+        if self.sim.auton_enabled() {
+            self.sim.set_auton(false);
+            println!("Auton: off, driver took over");
+        }
+        if self.sim.benchmark_running() {
+            self.sim.cancel_benchmark();
+            println!("Benchmark: cancelled, driver took over");
+        }
+    }
+
+    /// Following lifts the field's edges so the robot can drive anywhere.
+    /// Stopping puts them back and recenters the view on the field.
+    fn toggle_follow_camera(&mut self) {
+        self.follow_camera = !self.follow_camera;
+        if self.follow_camera {
+            self.boundary_before_follow = self.sim.boundary();
+            self.sim.set_boundary(Boundary::Open);
+        } else {
+            self.sim.set_boundary(self.boundary_before_follow);
+            self.camera.center = Point2::origin();
+        }
+        println!(
+            "Camera: {}",
+            if self.follow_camera {
+                "following"
+            } else {
+                "fixed"
+            }
+        );
+    }
+
+    /// Starts logging to `/telemetry.csv` in the user data directory
+    fn start_telemetry(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let file = ctx.filesystem.create("/telemetry.csv")?;
+        self.telemetry = Some(Telemetry::new(file)?);
+        println!("Recording telemetry");
+        Ok(())
+    }
+
+    fn stop_telemetry(&mut self) {
+        if let Some(mut t) = self.telemetry.take() {
+            match t.flush() {
+                Ok(()) => println!("Wrote {} rows of telemetry", t.rows()),
+                Err(e) => println!("Could not write telemetry: {}", e),
+            }
+        }
+    }
+
+    fn record_telemetry(&mut self, dt: f32) {
+        if self.sim.physics() != PhysicsMode::Dynamics {
+            return;
+        }
+        let result = match self.telemetry {
+            Some(ref mut t) => t.record(self.sim.robot(), f64::from(dt)),
+            None => return,
+        };
+        if let Err(e) = result {
+            println!("Could not write telemetry: {}", e);
+            self.telemetry = None;
+        }
+    }
+
+    /// Sends the remote program the state after the last step
+    fn report_remote(&mut self, dt: f32) {
+        let result = match self.remote {
+            Some(ref mut link) => link.report(self.sim.robot(), f64::from(dt)),
+            None => return,
+        };
+        if let Err(e) = result {
+            println!("Could not send state to the remote program: {}", e);
+        }
+    }
+
+    /// Plays the sounds for whatever happened in the last step
+    fn handle_events(&mut self) {
+        for event in self.sim.take_events() {
+            match event {
+                SimEvent::ShotFired => {
+                    let _ = self.assets.shot_sound.play();
+                }
+                SimEvent::RockDestroyed => {
+                    let _ = self.assets.hit_sound.play();
+                    self.gui_dirty = true;
+                }
+                SimEvent::PlayerHit => {
+                    let _ = self.assets.hit_sound.play();
+                }
+                SimEvent::PlaybackFinished => {
+                    println!("Playback finished");
+                }
+                SimEvent::AutonFinished => {
+                    println!("Auton finished");
+                }
+                SimEvent::BenchmarkFinished => {
+                    for (line, _) in benchmark_lines(&self.sim, &self.config) {
+                        println!("{}", line);
+                    }
+                }
+                SimEvent::LevelCleared => {
+                    println!("Level {}", self.sim.level());
+                    self.gui_dirty = true;
+                }
+                SimEvent::MotorOverheated => {
+                    println!("Motor overheating");
+                    let _ = self.assets.hit_sound.play();
+                }
+            }
+        }
+    }
+
+    /// Writes the whole simulation and the trails to `SNAPSHOT_PATH`, stamped
+    /// with the robot config it's running
+    fn save_snapshot(&mut self, ctx: &mut Context) {
+        let mut session = Session::new(self.sim.snapshot(), &self.config);
+        session.trails = std::iter::once(&self.trail)
+            .chain(self.others.iter().map(|other| &other.trail))
+            .map(Trail::state)
+            .collect();
+        let result = ctx
+            .filesystem
+            .create(SNAPSHOT_PATH)
+            .map_err(|e| e.to_string())
+            .and_then(|f| session.save(f).map_err(|e| e.to_string()));
+        match result {
+            Ok(()) => println!("Saved {}", SNAPSHOT_PATH),
+            Err(e) => println!("Could not save {}: {}", SNAPSHOT_PATH, e),
+        }
+    }
+
+    /// Picks the simulation up from the last `save_snapshot`, unless it was
+    /// saved by another version or with another robot config
+    fn load_snapshot(&mut self, ctx: &mut Context) {
+        let config = &self.config;
+        let loaded = ctx
+            .filesystem
+            .open(SNAPSHOT_PATH)
+            .map_err(|e| e.to_string())
+            .and_then(|f| Session::load(f, config).map_err(|e| e.to_string()));
+        match loaded {
+            Ok(session) => {
+                self.sim.restore(&session.snapshot);
+                // any the session has no trail for starts over
+                self.clear_trails();
+                let trails = std::iter::once(&mut self.trail)
+                    .chain(self.others.iter_mut().map(|other| &mut other.trail));
+                for (trail, state) in trails.zip(&session.trails) {
+                    trail.resume(state);
+                }
+                self.gui_dirty = true;
+                println!("Loaded {}", SNAPSHOT_PATH);
+            }
+            Err(e) => println!("{}: {}", SNAPSHOT_PATH, e),
+        }
+    }
+
+    /// Starts recording the input from here, or stops and writes what was
+    /// recorded to `REPLAY_PATH`
+    fn toggle_recording(&mut self, ctx: &mut Context) {
+        self.gui_dirty = true;
+        let replay = match self.recording.take() {
+            Some(replay) => replay,
+            None => {
+                let dt = 1.0 / sim::SIM_FPS as f32;
+                self.recording = Some(Replay::record(&self.sim, &self.config, dt));
+                self.replaying = None;
+                println!("Recording");
+                return;
+            }
+        };
+        let result = ctx
+            .filesystem
+            .create(REPLAY_PATH)
+            .map_err(|e| e.to_string())
+            .and_then(|f| replay.save(f).map_err(|e| e.to_string()));
+        match result {
+            Ok(()) => println!("Saved {} steps to {}", replay.steps(), REPLAY_PATH),
+            Err(e) => println!("Could not save {}: {}", REPLAY_PATH, e),
+        }
+    }
+
+    /// Rebuilds the run saved in `REPLAY_PATH` and plays it, or stops the
+    /// replay and hands control back to the input
+    fn toggle_replay(&mut self, ctx: &mut Context) {
+        self.gui_dirty = true;
+        if self.replaying.take().is_some() {
+            println!("Replay stopped");
+            return;
+        }
+        let loaded = ctx
+            .filesystem
+            .open(REPLAY_PATH)
+            .map_err(|e| e.to_string())
+            .and_then(|f| Replay::load(f).map_err(|e| e.to_string()));
+        match loaded {
+            Ok(replay) => {
+                let (run, sim) = ReplayRun::start(&replay);
+                self.sim = sim;
+                // the replay only has the player's input, so the rest sit still
+                for other in &self.others {
+                    self.sim.add_robot(&other.config);
+                }
+                self.config = replay.config.clone();
+                self.recording = None;
+                self.replaying = Some(run);
+                self.clear_trails();
+                println!("Replaying {} steps", replay.steps());
+            }
+            Err(e) => println!("{}: {}", REPLAY_PATH, e),
+        }
+    }
+
+    /// Loads `GHOST_PATH` from the resource path and starts it along with a
+    /// fresh game, or removes the ghost
+    fn toggle_ghost(&mut self, ctx: &mut Context) {
+        if self.ghost.take().is_some() {
+            println!("Ghost removed");
+            return;
+        }
+        let loaded = ctx
+            .filesystem
+            .open(GHOST_PATH)
+            .map_err(|e| e.to_string())
+            .and_then(|f| Ghost::from_reader(f).map_err(|e| e.to_string()));
+        match loaded {
+            Ok(ghost) => {
+                println!(
+                    "Ghost of {:.1} s, {:?} at the end",
+                    ghost.end_time(),
+                    ghost.end()
+                );
+                self.sim.reset();
+                self.clear_trails();
+                self.gui_dirty = true;
+                self.ghost = Some(ghost);
+            }
+            Err(e) => println!("{}: {}", GHOST_PATH, e),
+        }
+    }
+
+    /// Runs a gear ratio sweep around the current robot on another thread and
+    /// prints the results when it's done, without holding up the game
+    fn start_sweep(&self) {
+        let base = self.config.clone();
+        let variations: Vec<Variation> = SWEEP_RATIOS
+            .iter()
+            .map(|f| Variation::gear_ratio(base.motors().l.gear_ratio * f))
+            .collect();
+        println!("Sweeping {} gear ratios", variations.len());
+        thread::spawn(move || {
+            let results = sweep::run(&base, &variations, &Maneuver::default());
+            println!("{}", sweep::format_table(&results));
+        });
+    }
+
+    /// Starts replaying `/playback.csv` from the resource path, or stops the
+    /// current playback
+    fn toggle_playback(&mut self, ctx: &mut Context) {
+        if self.sim.playing_back() {
+            self.sim.stop_playback();
+            println!("Playback stopped");
+            return;
+        }
+        let loaded = ctx
+            .filesystem
+            .open("/playback.csv")
+            .map_err(|e| e.to_string())
+            .and_then(|f| Playback::from_reader(f).map_err(|e| e.to_string()));
+        match loaded {
+            Ok(playback) => {
+                println!("Playing back {:?} commands", playback.kind());
+                self.sim.start_playback(playback);
+                self.clear_trails();
+            }
+            Err(e) => println!("/playback.csv: {}", e),
+        }
     }
 }
 
@@ -278,29 +676,497 @@ impl MainState {
 /// A couple of utility functions.
 /// **********************************************************************
 
+/// Seconds between rebuilds of the overlay text. Building `graphics::Text` is
+/// too slow to do every frame, so the overlay lags the model slightly.
+const OVERLAY_PERIOD: f32 = 0.1;
+
+/// The drive state shown by the debug overlay, one string per line
+fn overlay_lines(sim: &Simulation) -> Vec<String> {
+    let robot = sim.robot();
+    let vel = robot.vel();
+    let wheels = robot.ddmr().wheels();
+    let measured = sim.measured_wheels();
+    let sensors = sim.sensor_readings();
+    let obs = robot.last_observation();
+    let pose = robot.pose();
+    let slip = robot.slipping();
+    let slip_str = |s: bool| if s { "SLIP" } else { "grip" };
+    let limit_mark = |limited: bool| if limited { "*" } else { " " };
+    let cut_mark = |cut: bool| if cut { " CUT" } else { "" };
+    let temps = robot.temperatures();
+    let mode = match sim.physics() {
+        PhysicsMode::Dynamics => "drive model",
+        PhysicsMode::Arcade => "arcade (drive model idle)",
+    };
+    vec![
+        format!(
+            "physics: {}, {:?} drive, {:?} control",
+            mode,
+            sim.drive_mode(),
+            sim.control_mode()
+        ),
+        format!(
+            "vel: {:+6.2} m/s {:+6.2} rad/s",
+            vel.lin.value_unsafe, vel.ang.value_unsafe
+        ),
+        format!(
+            "wheels: L {:+6.1} R {:+6.1} rad/s",
+            wheels.l.value_unsafe, wheels.r.value_unsafe
+        ),
+        format!(
+            "loop sees: L {:+6.1} R {:+6.1} rad/s",
+            measured.l.value_unsafe, measured.r.value_unsafe
+        ),
+        format!(
+            "gyro: {:+6.2} rad/s, heading {:+5.2} rad",
+            sensors.gyro.value_unsafe, sensors.heading
+        ),
+        format!(
+            "volts: L {:+6.2} R {:+6.2} V",
+            obs.voltage.l.value_unsafe, obs.voltage.r.value_unsafe
+        ),
+        format!(
+            "amps: L {:+6.1}{} R {:+6.1}{} A",
+            obs.current.l.value_unsafe,
+            limit_mark(obs.limited.l),
+            obs.current.r.value_unsafe,
+            limit_mark(obs.limited.r)
+        ),
+        format!(
+            "motors: L {:5.1}{} R {:5.1}{} C",
+            temps.l.value_unsafe - ZERO_CELSIUS,
+            cut_mark(obs.cut_out.l),
+            temps.r.value_unsafe - ZERO_CELSIUS,
+            cut_mark(obs.cut_out.r)
+        ),
+        format!(
+            "pose: {:+6.2} m {:+6.2} m {:+5.2} rad",
+            pose.x.value_unsafe, pose.y.value_unsafe, pose.theta
+        ),
+        format!("traction: L {} R {}", slip_str(slip.l), slip_str(slip.r)),
+        format!("zero command: {:?}", sim.brake_mode()),
+        match robot.bus_voltage() {
+            Some(bus) => format!("battery: {:5.2} V", bus.value_unsafe),
+            None => "battery: ideal".to_string(),
+        },
+        // worked out from the parameters, to sanity check a loaded robot
+        format!(
+            "expected: top {:5.2} m/s, tau {:4.2} s",
+            robot.steady_speed(sim.max_voltage()).value_unsafe,
+            robot.time_constant().value_unsafe
+        ),
+    ]
+}
+
+/// The running benchmark's phase, or each of the finished one's
+/// measurements and whether it met its target in `config`
+fn benchmark_lines(sim: &Simulation, config: &RobotConfig) -> Vec<(String, Option<bool>)> {
+    let bench = match sim.benchmark() {
+        Some(bench) => bench,
+        None => return Vec::new(),
+    };
+    match bench.result() {
+        Some(result) => std::iter::once(("benchmark:".to_string(), None))
+            .chain(
+                result
+                    .rows(&config.benchmark)
+                    .iter()
+                    .map(|row| (row.line(), row.passed())),
+            )
+            .collect(),
+        None => vec![(format!("benchmark: {:?}", bench.phase()), None)],
+    }
+}
+
+/// A line per robot with its speed and where it is, or none with only the
+/// player's
+fn robot_lines(sim: &Simulation) -> Vec<String> {
+    if sim.others().is_empty() {
+        return Vec::new();
+    }
+    let px = sim.px_per_meter();
+    let robots = std::iter::once((sim.player(), sim.robot()))
+        .chain(sim.others().iter().map(|r| (r.actor(), r.model())));
+    robots
+        .enumerate()
+        .map(|(i, (actor, model))| {
+            let vel = model.vel();
+            format!(
+                "robot {}: {:+5.2} m/s {:+5.2} rad/s at ({:+5.2}, {:+5.2}) m",
+                i + 1,
+                vel.lin.value_unsafe,
+                vel.ang.value_unsafe,
+                actor.pos.x / px,
+                actor.pos.y / px
+            )
+        })
+        .collect()
+}
+
+/// The chassis drawn for `config`
+fn chassis_shape(config: &RobotConfig, px_per_meter: f32) -> ChassisShape {
+    let px = f64::from(px_per_meter);
+    ChassisShape {
+        half_track: (config.chassis.half_wheelbase * px) as f32,
+        length: (config.chassis.length * px) as f32,
+        wheel_radius: (config.chassis.wheel_radius * px) as f32,
+        wheel_width: (WHEEL_WIDTH * px) as f32,
+    }
+}
+
 fn print_instructions() {
     println!();
 }
 
+/// Reads `/robot.toml` from the resource path, falling back to the built-in
+/// robot if it's missing or broken
+fn load_robot_config(ctx: &mut Context) -> RobotConfig {
+    const PATH: &str = "/robot.toml";
+    if !ctx.filesystem.exists(PATH) {
+        println!("No {} found, using the built-in robot", PATH);
+        return RobotConfig::default();
+    }
+    let loaded = ctx
+        .filesystem
+        .open(PATH)
+        .map_err(|e| e.to_string())
+        .and_then(|f| RobotConfig::from_reader(f).map_err(|e| e.to_string()));
+    match loaded {
+        Ok(config) => {
+            println!("Loaded robot from {}", PATH);
+            config
+        }
+        Err(e) => {
+            println!("{}: {}, using the built-in robot", PATH, e);
+            RobotConfig::default()
+        }
+    }
+}
+
+/// Reads `/robot2.toml` from the resource path for a second robot, driven
+/// with WASD. Without one, or if it's broken, the player drives alone.
+fn load_second_robot(ctx: &mut Context) -> Option<RobotConfig> {
+    const PATH: &str = "/robot2.toml";
+    if !ctx.filesystem.exists(PATH) {
+        return None;
+    }
+    let loaded = ctx
+        .filesystem
+        .open(PATH)
+        .map_err(|e| e.to_string())
+        .and_then(|f| RobotConfig::from_reader(f).map_err(|e| e.to_string()));
+    match loaded {
+        Ok(config) => {
+            println!("Loaded a second robot from {}, on WASD", PATH);
+            Some(config)
+        }
+        Err(e) => {
+            println!("{}: {}, driving one robot", PATH, e);
+            None
+        }
+    }
+}
+
+/// Reads `/routine.txt` from the resource path for the auton to run. Without
+/// one, or if it's broken, the auton follows its waypoints.
+fn load_routine(ctx: &mut Context) -> Option<Routine> {
+    const PATH: &str = "/routine.txt";
+    if !ctx.filesystem.exists(PATH) {
+        println!("No {} found, the auton will follow its waypoints", PATH);
+        return None;
+    }
+    let loaded = ctx
+        .filesystem
+        .open(PATH)
+        .map_err(|e| e.to_string())
+        .and_then(|f| Routine::from_reader(f).map_err(|e| e.to_string()));
+    match loaded {
+        Ok(routine) => {
+            println!(
+                "Loaded a {} step routine from {}",
+                routine.steps.len(),
+                PATH
+            );
+            Some(routine)
+        }
+        Err(e) => {
+            println!("{}: {}, the auton will follow its waypoints", PATH, e);
+            None
+        }
+    }
+}
+
 fn draw_actor(
     assets: &mut Assets,
     ctx: &mut Context,
     actor: &Actor,
-    world_coords: (u32, u32),
+    camera: &Camera,
 ) -> GameResult<()> {
-    let (screen_w, screen_h) = world_coords;
-    let pos = world_to_screen_coords(screen_w, screen_h, actor.pos);
-    let image = assets.actor_image(actor);
+    let pos = camera.to_screen(actor.pos);
+    let (image, radius) = assets.actor_image(actor);
+    let scale = camera.sprite_scale(actor.bbox_size, radius);
     let drawparams = graphics::DrawParam {
         dest: pos,
         rotation: actor.facing as f32,
         offset: graphics::Point2::new(0.5, 0.5),
+        scale: graphics::Point2::new(scale, scale),
         ..Default::default()
     };
     graphics::draw_ex(ctx, image, drawparams)
 }
 
-const SIM_FPS: u32 = 60;
+/// m, only used for drawing
+const WHEEL_WIDTH: f64 = 0.05;
+
+/// Draws the player as its chassis outline and two wheels, each colored by its
+/// speed with an arrow showing which way it turns
+fn draw_chassis(
+    ctx: &mut Context,
+    shape: &ChassisShape,
+    body_color: graphics::Color,
+    actor: &Actor,
+    wheels: LR<Hertz<f64>>,
+    max_speed: Hertz<f64>,
+    camera: &Camera,
+) -> GameResult<()> {
+    let screen = |points: &[Point2]| -> Vec<Point2> {
+        chassis::to_world(points, actor.pos, actor.facing)
+            .into_iter()
+            .map(|p| camera.to_screen(p))
+            .collect()
+    };
+    let outline = shape.outline();
+    let color = graphics::get_color(ctx);
+
+    graphics::set_color(ctx, body_color)?;
+    let mut body = screen(&outline.body);
+    body.push(body[0]);
+    graphics::line(ctx, &body, 2.0)?;
+
+    let sides = [
+        (&outline.left_wheel, -shape.half_track, wheels.l),
+        (&outline.right_wheel, shape.half_track, wheels.r),
+    ];
+    for (wheel, x, speed) in sides.iter() {
+        let frac = *(*speed / max_speed);
+        graphics::set_color(ctx, chassis::wheel_color(frac, 1.0))?;
+        graphics::polygon(ctx, graphics::DrawMode::Fill, &screen(&wheel[..]))?;
+        let arrow = frac.max(-1.0).min(1.0) as f32 * shape.length / 2.0;
+        graphics::line(
+            ctx,
+            &screen(&[Point2::new(*x, 0.0), Point2::new(*x, arrow)]),
+            2.0,
+        )?;
+    }
+    graphics::set_color(ctx, color)
+}
+
+/// Draws the player's image see-through at the ghost's pose
+fn draw_ghost(
+    assets: &Assets,
+    ctx: &mut Context,
+    ghost: &Ghost,
+    px_per_meter: f32,
+    camera: &Camera,
+) -> GameResult<()> {
+    let (pos, facing) = ghost.actor_pose(px_per_meter);
+    let scale = camera.scale();
+    let drawparams = graphics::DrawParam {
+        dest: camera.to_screen(pos),
+        rotation: facing,
+        offset: graphics::Point2::new(0.5, 0.5),
+        scale: graphics::Point2::new(scale, scale),
+        ..Default::default()
+    };
+    let color = graphics::get_color(ctx);
+    graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, GHOST_ALPHA))?;
+    graphics::draw_ex(ctx, &assets.player_image, drawparams)?;
+    graphics::set_color(ctx, color)
+}
+
+/// Draws the auton waypoints as dots, with the one being driven to larger and
+/// highlighted
+fn draw_waypoints(ctx: &mut Context, sim: &Simulation, camera: &Camera) -> GameResult<()> {
+    let px = sim.px_per_meter() as f64;
+    let auton = sim.auton();
+    let color = graphics::get_color(ctx);
+    for (i, wp) in auton.waypoints().iter().enumerate() {
+        let pos = Point2::new(
+            (wp.x.value_unsafe * px) as f32,
+            (wp.y.value_unsafe * px) as f32,
+        );
+        let pos = camera.to_screen(pos);
+        let (c, radius) = if auton.current() == Some(i) {
+            (graphics::Color::new(1.0, 0.9, 0.2, 1.0), 6.0)
+        } else {
+            (graphics::Color::new(0.5, 0.5, 0.5, 1.0), 4.0)
+        };
+        graphics::set_color(ctx, c)?;
+        graphics::circle(ctx, graphics::DrawMode::Fill, pos, radius, 0.5)?;
+    }
+    graphics::set_color(ctx, color)
+}
+
+/// Benchmark results that met their targets
+const PASS_COLOR: graphics::Color = graphics::Color {
+    r: 0.3,
+    g: 0.9,
+    b: 0.3,
+    a: 1.0,
+};
+
+/// Benchmark results that missed their targets
+const FAIL_COLOR: graphics::Color = graphics::Color {
+    r: 0.95,
+    g: 0.3,
+    b: 0.3,
+    a: 1.0,
+};
+
+/// Number of brightness steps the trail fades out in
+const TRAIL_BANDS: usize = 8;
+/// The player's trail, the other robots' are in their tint
+const TRAIL_COLOR: graphics::Color = graphics::Color {
+    r: 0.2,
+    g: 0.8,
+    b: 0.2,
+    a: 1.0,
+};
+/// The player's chassis outline
+const CHASSIS_COLOR: graphics::Color = graphics::Color {
+    r: 0.6,
+    g: 0.6,
+    b: 0.7,
+    a: 1.0,
+};
+/// The other robots are drawn in these, in the order they were added
+const ROBOT_TINTS: [graphics::Color; 3] = [
+    graphics::Color {
+        r: 1.0,
+        g: 0.6,
+        b: 0.2,
+        a: 1.0,
+    },
+    graphics::Color {
+        r: 0.3,
+        g: 0.7,
+        b: 1.0,
+        a: 1.0,
+    },
+    graphics::Color {
+        r: 0.9,
+        g: 0.3,
+        b: 0.9,
+        a: 1.0,
+    },
+];
+
+fn draw_trail(
+    ctx: &mut Context,
+    trail: &Trail,
+    tint: graphics::Color,
+    camera: &Camera,
+) -> GameResult<()> {
+    let color = graphics::get_color(ctx);
+    for (brightness, points) in trail.polylines(TRAIL_BANDS) {
+        let points: Vec<Point2> = points.into_iter().map(|p| camera.to_screen(p)).collect();
+        graphics::set_color(
+            ctx,
+            graphics::Color {
+                a: brightness,
+                ..tint
+            },
+        )?;
+        graphics::line(ctx, &points, 2.0)?;
+    }
+    graphics::set_color(ctx, color)
+}
+
+fn draw_walls(ctx: &mut Context, field: (f32, f32), camera: &Camera) -> GameResult<()> {
+    // inset by half the line width so the whole line is on screen
+    let x = field.0 / 2.0 - 1.0;
+    let y = field.1 / 2.0 - 1.0;
+    let corners: Vec<Point2> = [(-x, -y), (x, -y), (x, y), (-x, y), (-x, -y)]
+        .iter()
+        .map(|&(x, y)| camera.to_screen(Point2::new(x, y)))
+        .collect();
+    graphics::line(ctx, &corners, 2.0)
+}
+
+fn draw_obstacles(ctx: &mut Context, sim: &Simulation, camera: &Camera) -> GameResult<()> {
+    let color = graphics::get_color(ctx);
+    graphics::set_color(ctx, graphics::Color::new(0.6, 0.4, 0.2, 1.0))?;
+    for o in sim.obstacles() {
+        match o.shape {
+            Shape::Circle { radius } => {
+                let center = camera.to_screen(o.center());
+                let radius = radius * camera.scale();
+                graphics::circle(ctx, graphics::DrawMode::Fill, center, radius, 0.5)?;
+            }
+            Shape::Rect {
+                half_width,
+                half_height,
+            } => {
+                let corners: Vec<_> = [(-1., -1.), (1., -1.), (1., 1.), (-1., 1.)]
+                    .iter()
+                    .map(|&(sx, sy)| {
+                        camera.to_screen(Point2::new(o.x + sx * half_width, o.y + sy * half_height))
+                    })
+                    .collect();
+                graphics::polygon(ctx, graphics::DrawMode::Fill, &corners)?;
+            }
+        }
+    }
+    graphics::set_color(ctx, color)
+}
+
+/// m, of the obstacles placed with the mouse
+const OBSTACLE_RADIUS: f32 = 0.3;
+
+/// Fraction of the way the camera moves toward the player each frame
+const CAMERA_SMOOTHING: f32 = 0.1;
+/// Zoom change per press of + or -
+const ZOOM_STEP: f32 = 1.25;
+/// Where F5 saves the simulation and F9 loads it from, in the user data directory
+const SNAPSHOT_PATH: &str = "/snapshot.json";
+/// Where F7 records to and F8 replays from, in the user data directory
+const REPLAY_PATH: &str = "/replay.json";
+/// The run F10 draws as a ghost, in the resource path. Telemetry files work.
+const GHOST_PATH: &str = "/ghost.csv";
+/// How opaque the ghost is drawn
+const GHOST_ALPHA: f32 = 0.35;
+/// Gear ratios the sweep tries, as multiples of the robot's own
+const SWEEP_RATIOS: [f64; 7] = [0.5, 0.67, 0.8, 1.0, 1.25, 1.5, 2.0];
+
+/// Faint lines a meter apart, so motion shows up even on an empty field
+fn draw_grid(ctx: &mut Context, camera: &Camera) -> GameResult<()> {
+    let color = graphics::get_color(ctx);
+    graphics::set_color(ctx, graphics::Color::new(0.2, 0.2, 0.25, 1.0))?;
+    for line in camera.grid_lines() {
+        graphics::line(ctx, &line, 1.0)?;
+    }
+    graphics::set_color(ctx, color)
+}
+
+/// Sets the second robot's input from WASD: W and S drive it forward and
+/// back, on both sides in tank drive, and A and D turn it. Returns whether
+/// `keycode` was one of them. That leaves A off the auton while there's a
+/// second robot.
+fn wasd_input(input: &mut InputState, keycode: Keycode, pressed: bool) -> bool {
+    let value = if pressed { 1.0 } else { 0.0 };
+    match keycode {
+        Keycode::W | Keycode::S => {
+            let value = if keycode == Keycode::W { value } else { -value };
+            input.yaxis = value;
+            input.left_axis = value;
+            input.right_axis = value;
+        }
+        Keycode::A => input.xaxis = -value,
+        Keycode::D => input.xaxis = value,
+        _ => return false,
+    }
+    true
+}
 
 /// **********************************************************************
 /// Now we implement the `EventHandler` trait from `ggez::event`, which provides
@@ -309,7 +1175,7 @@ const SIM_FPS: u32 = 60;
 /// **********************************************************************
 impl EventHandler for MainState {
     fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
-        const DESIRED_FPS: u32 = SIM_FPS;
+        const DESIRED_FPS: u32 = sim::SIM_FPS;
 
         while timer::check_update_time(ctx, DESIRED_FPS) {
             const seconds: f32 = 1.0 / (DESIRED_FPS as f32);
@@ -321,32 +1187,82 @@ impl EventHandler for MainState {
                 // return Ok(());
             }
 
-            // Update the player state based on the user input.
-            player_handle_input(&mut self.player, &self.input, seconds);
+            // A fixed number of fixed-size steps per tick however long the
+            // frame took, so a recording replays the same on any machine
+            let started = self.stats.start();
+            for _ in 0..self.time.steps_this_tick() {
+                let input = match self.replaying.as_mut().map(ReplayRun::next_input) {
+                    Some(Some(input)) => input,
+                    Some(None) => {
+                        println!("Replay finished");
+                        self.replaying = None;
+                        self.gui_dirty = true;
+                        self.input
+                    }
+                    None => self.input,
+                };
+                if let Some(ref link) = self.remote {
+                    // zero once the program goes quiet, never its last command
+                    self.sim.set_remote_command(Some(link.volts()));
+                }
+                let others: Vec<InputState> = self.others.iter().map(|o| o.input).collect();
+                self.sim.step_all(&input, &others, seconds);
+                if let Some(ref mut replay) = self.recording {
+                    replay.push(&input);
+                }
+                self.trail.push(self.sim.player().pos);
+                for (other, robot) in self.others.iter_mut().zip(self.sim.others()) {
+                    other.trail.push(robot.actor().pos);
+                }
+                if let Some(ref mut ghost) = self.ghost {
+                    ghost.advance(f64::from(seconds));
+                }
+                self.handle_events();
+                self.record_telemetry(seconds);
+                self.report_remote(seconds);
+            }
+            self.stats.finish(Section::Physics, started);
 
-            // Update the physics for all actors.
-            // First the player...
-            update_actor_position(&mut self.player, seconds);
-            wrap_actor_position(
-                &mut self.player,
-                self.screen_width as f32,
-                self.screen_height as f32,
-            );
+            if self.follow_camera {
+                self.camera.follow(self.sim.player().pos);
+            }
+
+            // real time, so the overlay keeps up while paused or slowed down
+            let started = self.stats.start();
+            self.overlay_age += seconds;
+            if self.overlay_age >= OVERLAY_PERIOD {
+                if self.show_overlay {
+                    self.update_overlay(ctx);
+                }
+                self.update_robots_display(ctx);
+                self.update_benchmark_display(ctx);
+                self.overlay_age = 0.0;
+            }
 
             // println!("pos: {:?}", self.player.pos);
             // Using a gui_dirty flag here is a little
             // messy but fine here.
-            // if self.gui_dirty {
-            // self.update_ui(ctx);
-            // self.gui_dirty = false;
-            // }
+            if self.gui_dirty {
+                self.update_ui(ctx);
+                self.gui_dirty = false;
+            }
+            self.stats.finish(Section::Text, started);
             // std::thread::sleep_ms(5);
+
+            // Finally we check for our end state.
+            // I want to have a nice death screen eventually,
+            // but for now we just quit.
+            if self.sim.game_over() {
+                println!("Game over!");
+                let _ = ctx.quit();
+            }
         }
 
         Ok(())
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let started = self.stats.start();
         // Our drawing is quite simple.
         // Just clear the screen...
         graphics::clear(ctx);
@@ -354,10 +1270,76 @@ impl EventHandler for MainState {
         // Loop over all objects drawing them...
         {
             let assets = &mut self.assets;
-            let coords = (self.screen_width, self.screen_height);
+            let camera = &self.camera;
+
+            draw_grid(ctx, camera)?;
 
-            let p = &self.player;
-            draw_actor(assets, ctx, p, coords)?;
+            if self.show_trail {
+                draw_trail(ctx, &self.trail, TRAIL_COLOR, camera)?;
+                for other in &self.others {
+                    draw_trail(ctx, &other.trail, other.tint, camera)?;
+                }
+            }
+
+            if self.sim.boundary() == Boundary::Walls {
+                draw_walls(ctx, self.sim.field_size(), camera)?;
+            }
+
+            draw_obstacles(ctx, &self.sim, camera)?;
+
+            if self.sim.auton_enabled() && self.sim.routine().is_none() {
+                draw_waypoints(ctx, &self.sim, camera)?;
+            }
+
+            if let Some(ref ghost) = self.ghost {
+                draw_ghost(assets, ctx, ghost, self.sim.px_per_meter(), camera)?;
+            }
+
+            let p = self.sim.player();
+            if self.show_chassis {
+                let robot = self.sim.robot();
+                let max_speed = sim::max_wheel_speed(robot, self.sim.max_voltage());
+                draw_chassis(
+                    ctx,
+                    &self.chassis,
+                    CHASSIS_COLOR,
+                    p,
+                    robot.ddmr().wheels(),
+                    max_speed,
+                    camera,
+                )?;
+            } else {
+                draw_actor(assets, ctx, p, camera)?;
+            }
+
+            for (other, robot) in self.others.iter().zip(self.sim.others()) {
+                if self.show_chassis {
+                    let model = robot.model();
+                    let max_speed = sim::max_wheel_speed(model, robot.max_voltage());
+                    draw_chassis(
+                        ctx,
+                        &other.chassis,
+                        other.tint,
+                        robot.actor(),
+                        model.ddmr().wheels(),
+                        max_speed,
+                        camera,
+                    )?;
+                } else {
+                    let color = graphics::get_color(ctx);
+                    graphics::set_color(ctx, other.tint)?;
+                    draw_actor(assets, ctx, robot.actor(), camera)?;
+                    graphics::set_color(ctx, color)?;
+                }
+            }
+
+            for s in self.sim.shots() {
+                draw_actor(assets, ctx, s, camera)?;
+            }
+
+            for r in self.sim.rocks() {
+                draw_actor(assets, ctx, r, camera)?;
+            }
         }
 
         // And draw the GUI elements in the right places.
@@ -365,9 +1347,46 @@ impl EventHandler for MainState {
         let score_dest = graphics::Point2::new(200.0, 10.0);
         graphics::draw(ctx, &self.xpos_display, level_dest, 0.0)?;
         graphics::draw(ctx, &self.ypos_display, score_dest, 0.0)?;
+        let points_dest = graphics::Point2::new(10.0, 30.0);
+        graphics::draw(ctx, &self.score_display, points_dest, 0.0)?;
+        let status_dest = graphics::Point2::new(ctx.conf.window_mode.width as f32 - 90.0, 10.0);
+        graphics::draw(ctx, &self.status_display, status_dest, 0.0)?;
+
+        if self.show_overlay {
+            for (i, line) in self.overlay_display.iter().enumerate() {
+                let dest = graphics::Point2::new(10.0, 60.0 + 20.0 * i as f32);
+                graphics::draw(ctx, line, dest, 0.0)?;
+            }
+        }
+        let robots_y = ctx.conf.window_mode.height as f32 - 30.0;
+        let rows = self.robots_display.len();
+        for (i, line) in self.robots_display.iter().enumerate() {
+            let dest = graphics::Point2::new(10.0, robots_y - 20.0 * (rows - 1 - i) as f32);
+            graphics::draw(ctx, line, dest, 0.0)?;
+        }
+        let params_x = ctx.conf.window_mode.width as f32 - 320.0;
+        for (i, line) in self.params_display.iter().enumerate() {
+            let dest = graphics::Point2::new(params_x, 40.0 + 20.0 * i as f32);
+            graphics::draw(ctx, line, dest, 0.0)?;
+        }
+        let benchmark_x = ctx.conf.window_mode.width as f32 - 420.0;
+        let benchmark_y = ctx.conf.window_mode.height as f32 / 2.0;
+        let color = graphics::get_color(ctx);
+        for (i, &(ref line, passed)) in self.benchmark_display.iter().enumerate() {
+            let dest = graphics::Point2::new(benchmark_x, benchmark_y + 20.0 * i as f32);
+            let c = match passed {
+                Some(true) => PASS_COLOR,
+                Some(false) => FAIL_COLOR,
+                None => color,
+            };
+            graphics::set_color(ctx, c)?;
+            graphics::draw(ctx, line, dest, 0.0)?;
+        }
+        graphics::set_color(ctx, color)?;
 
         // Then we flip the screen...
         graphics::present(ctx);
+        self.stats.finish(Section::Draw, started);
 
         // And yield the timeslice
         // This tells the OS that we're done using the CPU but it should
@@ -381,13 +1400,177 @@ impl EventHandler for MainState {
 
     // Handle key events.  These just map keyboard events
     // and alter our input state appropriately.
-    fn key_down_event(&mut self, ctx: &mut Context, keycode: Keycode, _keymod: Mod, _repeat: bool) {
+    fn key_down_event(&mut self, ctx: &mut Context, keycode: Keycode, keymod: Mod, _repeat: bool) {
+        self.input_device = InputDevice::Keyboard;
+        // D alone turns, so shoving takes shift
+        if keycode == Keycode::D && keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+            self.sim.shove();
+            println!("Shove");
+            return;
+        }
+        if self.params.is_some() {
+            match keycode {
+                Keycode::Up | Keycode::Down | Keycode::Left | Keycode::Right => {
+                    self.edit_params(ctx, keycode);
+                    return;
+                }
+                _ => (),
+            }
+        }
+        if let Some(other) = self.others.first_mut() {
+            if wasd_input(&mut other.input, keycode, true) {
+                return;
+            }
+        }
+        match keycode {
+            Keycode::Up
+            | Keycode::Down
+            | Keycode::Left
+            | Keycode::Right
+            | Keycode::W
+            | Keycode::S => {
+                self.take_over();
+            }
+            _ => (),
+        }
+        // with WASD on the other robot, the arrows drive both sides in tank
+        let both_sides = !self.others.is_empty();
         match keycode {
             Keycode::Up => {
                 self.input.yaxis = 1.0;
+                self.input.right_axis = 1.0;
+                if both_sides {
+                    self.input.left_axis = 1.0;
+                }
             }
             Keycode::Down => {
                 self.input.yaxis = -1.0;
+                self.input.right_axis = -1.0;
+                if both_sides {
+                    self.input.left_axis = -1.0;
+                }
+            }
+            Keycode::W => {
+                self.input.left_axis = 1.0;
+            }
+            Keycode::S => {
+                self.input.left_axis = -1.0;
+            }
+            Keycode::Space => {
+                self.input.fire = true;
+            }
+            Keycode::Tab => {
+                let mode = self.sim.drive_mode().next();
+                self.sim.set_drive_mode(mode);
+                println!("Drive mode: {:?}", mode);
+            }
+            Keycode::V => {
+                let mode = match self.sim.control_mode() {
+                    ControlMode::Voltage => ControlMode::Velocity,
+                    ControlMode::Velocity => ControlMode::Voltage,
+                };
+                self.sim.set_control_mode(mode);
+                println!("Control mode: {:?}", mode);
+            }
+            Keycode::N => {
+                let mode = self.sim.brake_mode().next();
+                self.sim.set_brake_mode(mode);
+                self.overlay_age = OVERLAY_PERIOD;
+                println!("Brake mode: {:?}", mode);
+            }
+            Keycode::T => {
+                // start afresh when it comes back rather than with a line
+                // from wherever the robot was when it was hidden
+                self.show_trail = !self.show_trail;
+                self.clear_trails();
+            }
+            Keycode::C => {
+                self.clear_trails();
+            }
+            Keycode::B => {
+                let boundary = match self.sim.boundary() {
+                    Boundary::Wrap | Boundary::Open => Boundary::Walls,
+                    Boundary::Walls => Boundary::Wrap,
+                };
+                self.sim.set_boundary(boundary);
+                println!("Field boundary: {:?}", boundary);
+            }
+            Keycode::R => {
+                self.sim.reset();
+                self.clear_trails();
+                if let Some(ref mut ghost) = self.ghost {
+                    ghost.restart();
+                }
+                self.gui_dirty = true;
+            }
+            Keycode::A => {
+                let enabled = !self.sim.auton_enabled();
+                self.sim.set_auton(enabled);
+                println!("Auton: {}", if enabled { "on" } else { "off" });
+            }
+            Keycode::F => {
+                self.toggle_follow_camera();
+            }
+            Keycode::Equals | Keycode::KpPlus => {
+                self.camera.zoom_by(ZOOM_STEP);
+            }
+            Keycode::Minus | Keycode::KpMinus => {
+                self.camera.zoom_by(1.0 / ZOOM_STEP);
+            }
+            Keycode::K => {
+                self.start_sweep();
+            }
+            Keycode::G => {
+                self.show_chassis = !self.show_chassis;
+            }
+            Keycode::X => {
+                self.sim.start_benchmark();
+                self.clear_trails();
+                self.overlay_age = OVERLAY_PERIOD;
+                println!("Benchmark: full voltage from rest, then coasting to a stop");
+            }
+            Keycode::E => {
+                self.toggle_params(ctx);
+            }
+            Keycode::F5 => {
+                self.save_snapshot(ctx);
+            }
+            Keycode::F6 => {
+                self.toggle_playback(ctx);
+            }
+            Keycode::F7 => {
+                self.toggle_recording(ctx);
+            }
+            Keycode::F8 => {
+                self.toggle_replay(ctx);
+            }
+            Keycode::F9 => {
+                self.load_snapshot(ctx);
+            }
+            Keycode::F10 => {
+                self.toggle_ghost(ctx);
+            }
+            Keycode::F11 => {
+                if let Some(ref mut ghost) = self.ghost {
+                    let end = ghost.end().next();
+                    ghost.set_end(end);
+                    println!("Ghost at the end: {:?}", end);
+                }
+            }
+            Keycode::Pause => {
+                self.time.toggle_pause();
+                self.overlay_age = OVERLAY_PERIOD;
+            }
+            Keycode::Period => {
+                self.time.single_step();
+            }
+            Keycode::LeftBracket => {
+                self.time.slower();
+                self.overlay_age = OVERLAY_PERIOD;
+            }
+            Keycode::RightBracket => {
+                self.time.faster();
+                self.overlay_age = OVERLAY_PERIOD;
             }
             Keycode::Left => {
                 self.input.xaxis = -1.0;
@@ -395,6 +1578,32 @@ impl EventHandler for MainState {
             Keycode::Right => {
                 self.input.xaxis = 1.0;
             }
+            Keycode::M => {
+                let physics = match self.sim.physics() {
+                    PhysicsMode::Dynamics => PhysicsMode::Arcade,
+                    PhysicsMode::Arcade => PhysicsMode::Dynamics,
+                };
+                self.sim.set_physics(physics);
+                println!("Physics mode: {:?}", physics);
+            }
+            Keycode::F3 | Keycode::H => {
+                self.show_overlay = !self.show_overlay;
+                // rebuild on the next update rather than showing stale values
+                self.overlay_age = OVERLAY_PERIOD;
+            }
+            Keycode::F4 => {
+                let enabled = !self.stats.enabled();
+                self.stats.set_enabled(enabled);
+                self.overlay_age = OVERLAY_PERIOD;
+                println!("Frame timing: {}", if enabled { "on" } else { "off" });
+            }
+            Keycode::L => {
+                if self.telemetry.is_some() {
+                    self.stop_telemetry();
+                } else if let Err(e) = self.start_telemetry(ctx) {
+                    println!("Could not start telemetry: {}", e);
+                }
+            }
             Keycode::P => {
                 let img = graphics::screenshot(ctx).expect("Could not take screenshot");
                 img.encode(ctx, graphics::ImageFormat::Png, "/screenshot.png")
@@ -406,9 +1615,24 @@ impl EventHandler for MainState {
     }
 
     fn key_up_event(&mut self, _ctx: &mut Context, keycode: Keycode, _keymod: Mod, _repeat: bool) {
+        if let Some(other) = self.others.first_mut() {
+            if wasd_input(&mut other.input, keycode, false) {
+                return;
+            }
+        }
         match keycode {
             Keycode::Up | Keycode::Down => {
                 self.input.yaxis = 0.0;
+                self.input.right_axis = 0.0;
+                if !self.others.is_empty() {
+                    self.input.left_axis = 0.0;
+                }
+            }
+            Keycode::W | Keycode::S => {
+                self.input.left_axis = 0.0;
+            }
+            Keycode::Space => {
+                self.input.fire = false;
             }
             Keycode::Left | Keycode::Right => {
                 self.input.xaxis = 0.0;
@@ -416,14 +1640,165 @@ impl EventHandler for MainState {
             _ => (), // Do nothing
         }
     }
+
+    // Left click places an obstacle, right click removes the ones under the cursor
+    fn mouse_button_down_event(&mut self, _ctx: &mut Context, button: MouseButton, x: i32, y: i32) {
+        let pos = self.camera.to_world(Point2::new(x as f32, y as f32));
+        match button {
+            MouseButton::Left => {
+                self.sim.add_obstacle(Obstacle::circle(
+                    pos.x,
+                    pos.y,
+                    OBSTACLE_RADIUS * self.sim.px_per_meter(),
+                ));
+            }
+            MouseButton::Right => {
+                self.sim.remove_obstacles_at(pos);
+            }
+            _ => (),
+        }
+    }
+
+    // The left stick drives arcade throttle and the left side in tank drive,
+    // the right stick turns in arcade and drives the right side in tank.
+    fn controller_axis_event(&mut self, _ctx: &mut Context, axis: Axis, value: i16, _id: i32) {
+        let invert = match axis {
+            Axis::LeftY | Axis::RightY => gamepad::INVERT_Y,
+            _ => false,
+        };
+        let value = gamepad::stick_value(value, invert, &self.config.gamepad.shape());
+        if value != 0.0 {
+            self.input_device = InputDevice::Gamepad;
+            self.take_over();
+        } else if self.input_device != InputDevice::Gamepad {
+            // a stick settling back to center shouldn't cancel held keys
+            return;
+        }
+        match axis {
+            Axis::LeftY => {
+                self.input.yaxis = value;
+                self.input.left_axis = value;
+            }
+            Axis::RightX => {
+                self.input.xaxis = value;
+            }
+            Axis::RightY => {
+                self.input.right_axis = value;
+            }
+            _ => (),
+        }
+    }
+
+    fn controller_button_down_event(&mut self, _ctx: &mut Context, btn: Button, _id: i32) {
+        match btn {
+            Button::A | Button::RightShoulder => {
+                self.input_device = InputDevice::Gamepad;
+                self.input.fire = true;
+            }
+            Button::Back => {
+                let mode = self.sim.drive_mode().next();
+                self.sim.set_drive_mode(mode);
+                println!("Drive mode: {:?}", mode);
+            }
+            _ => (),
+        }
+    }
+
+    fn controller_button_up_event(&mut self, _ctx: &mut Context, btn: Button, _id: i32) {
+        match btn {
+            Button::A | Button::RightShoulder => {
+                self.input.fire = false;
+            }
+            _ => (),
+        }
+    }
 }
 
+/// Prints the frequency response of `config`'s robot to a chirp, then the
+/// feedforward constants fitted to a ramp
+fn run_sysid(config: &RobotConfig) {
+    let mut model = config.build(config.timing.physics_dt * dimensioned::si::S);
+    let chirp = ChirpConfig::default();
+    println!(
+        "Chirp of {} V from {} to {} Hz over {} s, m/s per V:",
+        chirp.amplitude, chirp.f_min, chirp.f_max, chirp.duration
+    );
+    print!(
+        "{}",
+        sysid::format_table(&sysid::run_chirp(&mut model, &chirp))
+    );
+    match sysid::quasistatic_gains(
+        &mut model,
+        SYSID_RAMP_RATE,
+        SYSID_RAMP_MAX * dimensioned::si::V,
+    ) {
+        Some(g) => println!(
+            "Ramp: kS = {:.4} V, kV = {:.4} V/(m/s), kA = {:.4} V/(m/s^2)",
+            g.ks.value_unsafe, g.kv.value_unsafe, g.ka.value_unsafe
+        ),
+        None => println!("Ramp: not enough motion to fit the constants"),
+    }
+}
+
+/// V/s and V, of the ramp `--sysid` fits the constants to
+const SYSID_RAMP_RATE: f64 = 0.25;
+const SYSID_RAMP_MAX: f64 = 7.;
+
 /// **********************************************************************
 /// Finally our main function!  Which merely sets up a config and calls
 /// `ggez::event::run()` with our `EventHandler` type.
 /// **********************************************************************
 
 pub fn main() {
+    // `--headless N` steps the simulation N times with a scripted input and
+    // exits without ever opening a window. `--telemetry FILE` logs that run.
+    // With `--script FILE` the robot is driven by a voltage playback CSV
+    // instead, with no game around it, for as long as the script runs unless
+    // N is given. `--config FILE` picks the robot for that, otherwise it's
+    // the built-in one so the run doesn't depend on what's in resources.
+    // `--listen ADDR` opens the game with the robot driven by whatever sends
+    // commands to that UDP address, see `drive_sim::remote`. It stops if
+    // they're more than `--remote-timeout S` seconds old, 0.1 by default.
+    // `--sysid` sweeps the `--config` robot with a voltage chirp, prints its
+    // frequency response and the constants from a slow ramp, and exits.
+    let args: Vec<String> = env::args().collect();
+    let arg = |flag: &str| {
+        args.iter().position(|a| a == flag).map(|j| {
+            args.get(j + 1)
+                .unwrap_or_else(|| panic!("{} needs a value", flag))
+        })
+    };
+    let config_arg = || match arg("--config") {
+        Some(path) => RobotConfig::load(path)
+            .unwrap_or_else(|e| panic!("Could not load config {}: {}", path, e)),
+        None => RobotConfig::default(),
+    };
+    if args.iter().any(|a| a == "--sysid") {
+        run_sysid(&config_arg());
+        return;
+    }
+    if let Some(i) = args.iter().position(|a| a == "--headless") {
+        let steps: Option<usize> = args.get(i + 1).and_then(|n| n.parse().ok());
+        let telemetry = arg("--telemetry").map(|path| {
+            let file = std::fs::File::create(path).expect("Could not create telemetry file");
+            Box::new(file) as Box<dyn std::io::Write>
+        });
+        if let Some(path) = arg("--script") {
+            let script = std::fs::File::open(path)
+                .map_err(|e| e.to_string())
+                .and_then(|f| Playback::from_reader(f).map_err(|e| e.to_string()))
+                .unwrap_or_else(|e| panic!("Could not load script {}: {}", path, e));
+            let config = config_arg();
+            let dt = config.timing.physics_dt;
+            let steps = steps.unwrap_or_else(|| (script.end_time() / dt).round() as usize);
+            sim::run_script(&config, &script, steps, telemetry);
+            return;
+        }
+        let steps = steps.unwrap_or(10 * sim::SIM_FPS as usize);
+        sim::run_headless(steps, telemetry);
+        return;
+    }
+
     let mut cb = ContextBuilder::new("drive-sim", "lytigas")
         .window_setup(conf::WindowSetup::default().title("Franken Sim"))
         .window_mode(conf::WindowMode::default().dimensions(640, 480));
@@ -442,6 +1817,20 @@ pub fn main() {
         println!("Not building from cargo?  Ok.");
     }
 
+    let remote = arg("--listen").map(|addr| {
+        let timeout = arg("--remote-timeout")
+            .map(|s| {
+                s.parse::<f64>()
+                    .unwrap_or_else(|_| panic!("Bad remote timeout {}", s))
+            })
+            .unwrap_or(0.1);
+        let timeout = Duration::from_millis((timeout * 1e3).round() as u64);
+        let link = RemoteLink::bind(addr.as_str(), timeout)
+            .unwrap_or_else(|e| panic!("Could not listen on {}: {}", addr, e));
+        println!("Listening for commands on {:?}", link.local_addr());
+        link
+    });
+
     let ctx = &mut cb.build().unwrap();
 
     match MainState::new(ctx) {
@@ -450,7 +1839,15 @@ pub fn main() {
             println!("Error: {}", e);
         }
         Ok(ref mut game) => {
+            game.remote = remote;
             let result = event::run(ctx, game);
+            game.stop_telemetry();
+            if game.stats.enabled() {
+                println!("Frame timing over the last {} frames:", FRAME_WINDOW);
+                for line in game.stats.lines() {
+                    println!("  {}", line);
+                }
+            }
             if let Err(e) = result {
                 println!("Error encountered running game: {}", e);
             } else {
@@ -0,0 +1,206 @@
+//! Tuning the robot while it drives. `ParamSet` lists the numbers in the
+//! chassis and motor configs by position, so the game can show them as rows
+//! and nudge one at a time without knowing what the fields are.
+
+use crate::config::{ChassisConfig, ConfigError, MotorConfig, RobotConfig};
+
+/// Numeric parameters that can be read and written by position
+pub trait ParamSet {
+    /// The name and unit of each parameter, in order
+    fn params(&self) -> &'static [(&'static str, &'static str)];
+    /// Panics past the end of `params`
+    fn get(&self, i: usize) -> f64;
+    /// Panics past the end of `params`
+    fn set(&mut self, i: usize, value: f64);
+}
+
+const CHASSIS_PARAMS: [(&str, &str); 6] = [
+    ("mass", "kg"),
+    ("wheel_radius", "m"),
+    ("half_wheelbase", "m"),
+    ("cg_offset", "m"),
+    ("moment_of_inertia", "kg m^2"),
+    ("wheel_inertia", "kg m^2"),
+];
+
+impl ParamSet for ChassisConfig {
+    fn params(&self) -> &'static [(&'static str, &'static str)] {
+        &CHASSIS_PARAMS
+    }
+
+    fn get(&self, i: usize) -> f64 {
+        match i {
+            0 => self.mass,
+            1 => self.wheel_radius,
+            2 => self.half_wheelbase,
+            3 => self.cg_offset,
+            4 => self.moment_of_inertia,
+            5 => self.wheel_inertia,
+            _ => panic!("no chassis parameter {}", i),
+        }
+    }
+
+    fn set(&mut self, i: usize, value: f64) {
+        match i {
+            0 => self.mass = value,
+            1 => self.wheel_radius = value,
+            2 => self.half_wheelbase = value,
+            3 => self.cg_offset = value,
+            4 => self.moment_of_inertia = value,
+            5 => self.wheel_inertia = value,
+            _ => panic!("no chassis parameter {}", i),
+        }
+    }
+}
+
+const MOTOR_PARAMS: [(&str, &str); 5] = [
+    ("resistance", "Ohm"),
+    ("inductance", "H"),
+    ("back_emf", "V s"),
+    ("torque_constant", "N m / A"),
+    ("gear_ratio", ""),
+];
+
+impl ParamSet for MotorConfig {
+    fn params(&self) -> &'static [(&'static str, &'static str)] {
+        &MOTOR_PARAMS
+    }
+
+    fn get(&self, i: usize) -> f64 {
+        match i {
+            0 => self.resistance,
+            1 => self.inductance,
+            2 => self.back_emf,
+            3 => self.torque_constant,
+            4 => self.gear_ratio,
+            _ => panic!("no motor parameter {}", i),
+        }
+    }
+
+    fn set(&mut self, i: usize, value: f64) {
+        match i {
+            0 => self.resistance = value,
+            1 => self.inductance = value,
+            2 => self.back_emf = value,
+            3 => self.torque_constant = value,
+            4 => self.gear_ratio = value,
+            _ => panic!("no motor parameter {}", i),
+        }
+    }
+}
+
+/// Fraction one nudge scales a value up by. Nudging down divides by the same
+/// factor, so up then down gets back where it started.
+pub const NUDGE: f64 = 0.1;
+
+/// Which row of the robot's parameters is selected: the chassis ones, then
+/// the motor ones, which apply to every motor at once
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct ParamEditor {
+    selected: usize,
+}
+
+impl ParamEditor {
+    pub fn rows() -> usize {
+        CHASSIS_PARAMS.len() + MOTOR_PARAMS.len()
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Moves the selection down a row, wrapping to the top
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1) % Self::rows();
+    }
+
+    /// Moves the selection up a row, wrapping to the bottom
+    pub fn select_prev(&mut self) {
+        self.selected = (self.selected + Self::rows() - 1) % Self::rows();
+    }
+
+    /// The name, unit and value of `row` in `config`. Motor values are the
+    /// left motor's.
+    pub fn row(config: &RobotConfig, row: usize) -> (&'static str, &'static str, f64) {
+        let chassis = CHASSIS_PARAMS.len();
+        let (set, i): (&dyn ParamSet, usize) = if row < chassis {
+            (&config.chassis, row)
+        } else {
+            (config.motors().l, row - chassis)
+        };
+        let (name, unit) = set.params()[i];
+        (name, unit, set.get(i))
+    }
+
+    /// Scales the selected value by `NUDGE`, up or down, on every motor for a
+    /// motor row. A value of zero stays zero. `config` is left as it was if
+    /// the result wouldn't simulate, e.g. a mass below the chassis mass.
+    pub fn nudge(&self, config: &mut RobotConfig, up: bool) -> Result<(), ConfigError> {
+        let factor = if up { 1. + NUDGE } else { 1. / (1. + NUDGE) };
+        let mut nudged = config.clone();
+        let chassis = CHASSIS_PARAMS.len();
+        if self.selected < chassis {
+            let i = self.selected;
+            let value = nudged.chassis.get(i) * factor;
+            nudged.chassis.set(i, value);
+        } else {
+            let i = self.selected - chassis;
+            for m in [
+                &mut nudged.motor,
+                &mut nudged.left_motor,
+                &mut nudged.right_motor,
+            ]
+            .iter_mut()
+            .filter_map(|m| m.as_mut())
+            {
+                let value = m.get(i) * factor;
+                m.set(i, value);
+            }
+        }
+        nudged.validate()?;
+        *config = nudged;
+        Ok(())
+    }
+
+    /// One line per row, the selected one marked
+    pub fn lines(&self, config: &RobotConfig) -> Vec<String> {
+        (0..Self::rows())
+            .map(|row| {
+                let (name, unit, value) = Self::row(config, row);
+                let mark = if row == self.selected { ">" } else { " " };
+                format!("{} {}: {:.6} {}", mark, name, value, unit)
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn param_editor_nudges_selected_row() {
+    let base = RobotConfig::default();
+    let mut config = base.clone();
+    let mut editor = ParamEditor::default();
+    assert_eq!(ParamEditor::row(&config, 0).0, "mass");
+    editor.nudge(&mut config, true).unwrap();
+    assert!((config.chassis.mass - base.chassis.mass * 1.1).abs() < 1e-9);
+    editor.nudge(&mut config, false).unwrap();
+    assert!((config.chassis.mass - base.chassis.mass).abs() < 1e-9);
+    // the wheels and motors are heavy enough already
+    let mut err = Ok(());
+    for _ in 0..20 {
+        err = editor.nudge(&mut config, false);
+    }
+    assert!(err.is_err());
+    assert!(config.chassis.mass > config.chassis.chassis_mass);
+
+    editor.select_prev();
+    assert_eq!(ParamEditor::row(&config, editor.selected()).0, "gear_ratio");
+    editor.nudge(&mut config, true).unwrap();
+    assert!((config.motors().r.gear_ratio - base.motors().r.gear_ratio * 1.1).abs() < 1e-9);
+    editor.select_next();
+    assert_eq!(editor.selected(), 0);
+
+    let lines = editor.lines(&config);
+    assert_eq!(lines.len(), ParamEditor::rows());
+    assert!(lines[0].starts_with("> mass"), "{}", lines[0]);
+    assert!(lines[1].starts_with("  wheel_radius"), "{}", lines[1]);
+}
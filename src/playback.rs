@@ -0,0 +1,233 @@
+//! Replaying recorded commands into the drive model, to compare the sim
+//! against logs from a real robot.
+//!
+//! Files are CSV with a header row. The `time` column (s) is required, plus
+//! either `volts_l`/`volts_r` (V) or `wheel_l`/`wheel_r` (rad/s, fed to the
+//! wheel velocity controller). Each can also go by its name in `Telemetry`,
+//! with the unit on the end, like `time_s`. Other columns are ignored, so a
+//! file written by `Telemetry` replays its voltages.
+
+use crate::telemetry;
+
+use std::fmt;
+use std::io::{self, Read};
+
+/// What the two command columns mean
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PlaybackKind {
+    Voltage,
+    Velocity,
+}
+
+#[derive(Debug)]
+pub enum PlaybackError {
+    Io(io::Error),
+    /// The header is missing `time` or a pair of command columns
+    MissingColumns,
+    /// The header is missing a column that isn't a command
+    MissingColumn(&'static str),
+    /// `line` is 1-based, counting the header
+    Parse {
+        line: usize,
+        message: String,
+    },
+    NonMonotonic {
+        line: usize,
+        time: f64,
+        previous: f64,
+    },
+    Empty,
+}
+
+impl fmt::Display for PlaybackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlaybackError::Io(e) => write!(f, "could not read playback file: {}", e),
+            PlaybackError::MissingColumns => write!(
+                f,
+                "playback file needs a time column and volts_l/volts_r or wheel_l/wheel_r"
+            ),
+            PlaybackError::MissingColumn(name) => write!(f, "file has no {} column", name),
+            PlaybackError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            PlaybackError::NonMonotonic {
+                line,
+                time,
+                previous,
+            } => write!(
+                f,
+                "line {}: time {} does not come after the previous time {}",
+                line, time, previous
+            ),
+            PlaybackError::Empty => write!(f, "playback file has no rows"),
+        }
+    }
+}
+
+impl From<io::Error> for PlaybackError {
+    fn from(e: io::Error) -> Self {
+        PlaybackError::Io(e)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Row {
+    time: f64,
+    l: f64,
+    r: f64,
+}
+
+/// A parsed command sequence with strictly increasing timestamps
+#[derive(Debug, Clone)]
+pub struct Playback {
+    kind: PlaybackKind,
+    rows: Vec<Row>,
+}
+
+impl Playback {
+    pub fn from_reader<R: Read>(mut r: R) -> Result<Self, PlaybackError> {
+        let mut s = String::new();
+        r.read_to_string(&mut s)?;
+        Self::from_csv(&s)
+    }
+
+    pub fn from_csv(s: &str) -> Result<Self, PlaybackError> {
+        let mut kind = PlaybackKind::Voltage;
+        let rows = read_columns(s, |col| {
+            let time = col("time").ok_or(PlaybackError::MissingColumns)?;
+            let (k, l, r) = match (
+                col("volts_l"),
+                col("volts_r"),
+                col("wheel_l"),
+                col("wheel_r"),
+            ) {
+                (Some(l), Some(r), _, _) => (PlaybackKind::Voltage, l, r),
+                (_, _, Some(l), Some(r)) => (PlaybackKind::Velocity, l, r),
+                _ => return Err(PlaybackError::MissingColumns),
+            };
+            kind = k;
+            Ok(vec![time, l, r])
+        })?;
+        let rows = rows
+            .iter()
+            .map(|row| Row {
+                time: row[0],
+                l: row[1],
+                r: row[2],
+            })
+            .collect();
+        Ok(Self { kind, rows })
+    }
+
+    pub fn kind(&self) -> PlaybackKind {
+        self.kind
+    }
+
+    /// Time of the last row
+    pub fn end_time(&self) -> f64 {
+        self.rows[self.rows.len() - 1].time
+    }
+
+    /// The left and right command at `time`, holding each row until the next.
+    /// Before the first row that row's values are used.
+    pub fn sample(&self, time: f64) -> (f64, f64) {
+        let next = self.rows.iter().position(|row| row.time > time);
+        let row = match next {
+            Some(0) => &self.rows[0],
+            Some(i) => &self.rows[i - 1],
+            None => &self.rows[self.rows.len() - 1],
+        };
+        (row.l, row.r)
+    }
+}
+
+/// Reads a CSV with a header row, keeping only the columns `pick` asks for
+/// by name. A name also finds the column `Telemetry` writes for it, with the
+/// unit on the end. The first one picked is the time, which has to go up
+/// every row.
+pub(crate) fn read_columns<F>(s: &str, pick: F) -> Result<Vec<Vec<f64>>, PlaybackError>
+where
+    F: FnOnce(&dyn Fn(&str) -> Option<usize>) -> Result<Vec<usize>, PlaybackError>,
+{
+    let mut lines = s.lines().enumerate().filter(|(_, l)| !l.trim().is_empty());
+    let header: Vec<&str> = match lines.next() {
+        Some((_, h)) => h.split(',').map(|c| c.trim()).collect(),
+        None => return Err(PlaybackError::Empty),
+    };
+    let find = |name: &str| header.iter().position(|c| *c == name);
+    let cols =
+        pick(&|name: &str| find(name).or_else(|| telemetry::with_unit(name).and_then(find)))?;
+
+    let mut rows: Vec<Vec<f64>> = Vec::new();
+    for (i, line) in lines {
+        let line_no = i + 1;
+        let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+        let field = |c: usize| -> Result<f64, PlaybackError> {
+            let text = fields.get(c).ok_or_else(|| PlaybackError::Parse {
+                line: line_no,
+                message: format!("expected {} columns, found {}", header.len(), fields.len()),
+            })?;
+            text.parse().map_err(|_| PlaybackError::Parse {
+                line: line_no,
+                message: format!("{:?} in column {} is not a number", text, header[c]),
+            })
+        };
+        let row = cols
+            .iter()
+            .map(|&c| field(c))
+            .collect::<Result<Vec<f64>, _>>()?;
+        if let Some(prev) = rows.last() {
+            if row[0] <= prev[0] {
+                return Err(PlaybackError::NonMonotonic {
+                    line: line_no,
+                    time: row[0],
+                    previous: prev[0],
+                });
+            }
+        }
+        rows.push(row);
+    }
+    if rows.is_empty() {
+        return Err(PlaybackError::Empty);
+    }
+    Ok(rows)
+}
+
+#[test]
+fn playback_parse() {
+    let p = Playback::from_csv("time,volts_l,volts_r\n0,6,-6\n1.5,12,0\n").unwrap();
+    assert_eq!(p.kind(), PlaybackKind::Voltage);
+    assert_eq!(p.end_time(), 1.5);
+    assert_eq!(p.sample(-1.), (6., -6.));
+    assert_eq!(p.sample(1.), (6., -6.));
+    assert_eq!(p.sample(1.5), (12., 0.));
+    assert_eq!(p.sample(10.), (12., 0.));
+
+    let p = Playback::from_csv("wheel_r, time, wheel_l\n3, 0, 4\n").unwrap();
+    assert_eq!(p.kind(), PlaybackKind::Velocity);
+    assert_eq!(p.sample(0.), (4., 3.));
+}
+
+#[test]
+fn playback_rejects_bad_files() {
+    let err = Playback::from_csv("time,volts_l,volts_r\n0,6,6\n2,6,6\n1,6,6\n").unwrap_err();
+    match err {
+        PlaybackError::NonMonotonic { line, .. } => assert_eq!(line, 4),
+        e => panic!("{}", e),
+    }
+    assert!(Playback::from_csv("time,volts_l,volts_r\n0,6,6\n0,6,6\n").is_err());
+    // as Telemetry names them
+    let p = Playback::from_csv("time_s,wheel_l_rad_per_s,wheel_r_rad_per_s\n0,4,3\n").unwrap();
+    assert_eq!(p.sample(0.), (4., 3.));
+    match Playback::from_csv("time,volts_l\n0,6\n") {
+        Err(PlaybackError::MissingColumns) => (),
+        r => panic!("{:?}", r),
+    }
+    match Playback::from_csv("time,volts_l,volts_r\n0,six,6\n") {
+        Err(PlaybackError::Parse { line: 2, .. }) => (),
+        r => panic!("{:?}", r),
+    }
+    match Playback::from_csv("time,volts_l,volts_r\n") {
+        Err(PlaybackError::Empty) => (),
+        r => panic!("{:?}", r),
+    }
+}
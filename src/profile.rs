@@ -0,0 +1,226 @@
+//! Where the frame time goes: how long the physics, the text rebuilds and the
+//! drawing take each frame, averaged over the last few frames. Timing is off
+//! by default, and while it's off nothing reads the clock.
+
+use crate::config::RobotConfig;
+use crate::dynamics::LR;
+
+use dimensioned::si::*;
+
+use std::time::{Duration, Instant};
+
+/// The last `capacity` samples pushed, with their mean kept as a running sum
+#[derive(Debug, Clone)]
+pub struct RollingWindow {
+    samples: Vec<f64>,
+    /// Where the next sample goes once full
+    next: usize,
+    capacity: usize,
+    sum: f64,
+}
+
+impl RollingWindow {
+    /// Panics on a zero capacity
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "rolling window needs room for a sample");
+        Self {
+            samples: Vec::with_capacity(capacity),
+            next: 0,
+            capacity,
+            sum: 0.,
+        }
+    }
+
+    /// Overwrites the oldest sample once full
+    pub fn push(&mut self, x: f64) {
+        if self.samples.len() < self.capacity {
+            self.samples.push(x);
+        } else {
+            self.sum -= self.samples[self.next];
+            self.samples[self.next] = x;
+            self.next = (self.next + 1) % self.capacity;
+        }
+        self.sum += x;
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.next = 0;
+        self.sum = 0.;
+    }
+
+    /// 0 when empty
+    pub fn mean(&self) -> f64 {
+        if self.samples.is_empty() {
+            0.
+        } else {
+            self.sum / self.samples.len() as f64
+        }
+    }
+
+    /// The largest sample still in the window, 0 when empty
+    pub fn max(&self) -> f64 {
+        self.samples.iter().cloned().fold(0., f64::max)
+    }
+}
+
+/// The parts of a frame that get timed
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Section {
+    /// All the simulation steps run in one update
+    Physics,
+    /// Rebuilding the score and overlay text
+    Text,
+    Draw,
+}
+
+/// Frames averaged over
+pub const FRAME_WINDOW: usize = 120;
+
+fn seconds(d: Duration) -> f64 {
+    d.as_secs() as f64 + f64::from(d.subsec_nanos()) * 1e-9
+}
+
+/// Rolling timings of each `Section`, in seconds
+#[derive(Debug, Clone)]
+pub struct FrameStats {
+    enabled: bool,
+    physics: RollingWindow,
+    text: RollingWindow,
+    draw: RollingWindow,
+}
+
+impl Default for FrameStats {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            physics: RollingWindow::new(FRAME_WINDOW),
+            text: RollingWindow::new(FRAME_WINDOW),
+            draw: RollingWindow::new(FRAME_WINDOW),
+        }
+    }
+}
+
+impl FrameStats {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Turning timing off forgets what was recorded, so turning it back on
+    /// doesn't average in frames from before
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.physics.clear();
+            self.text.clear();
+            self.draw.clear();
+        }
+    }
+
+    /// When a section started, `None` while disabled. Hand it to `finish`.
+    pub fn start(&self) -> Option<Instant> {
+        if self.enabled {
+            Some(Instant::now())
+        } else {
+            None
+        }
+    }
+
+    pub fn finish(&mut self, section: Section, started: Option<Instant>) {
+        if let Some(started) = started {
+            self.record(section, seconds(started.elapsed()));
+        }
+    }
+
+    /// Records `seconds` spent in `section` this frame, even while disabled
+    pub fn record(&mut self, section: Section, seconds: f64) {
+        self.window_mut(section).push(seconds);
+    }
+
+    pub fn window(&self, section: Section) -> &RollingWindow {
+        match section {
+            Section::Physics => &self.physics,
+            Section::Text => &self.text,
+            Section::Draw => &self.draw,
+        }
+    }
+
+    fn window_mut(&mut self, section: Section) -> &mut RollingWindow {
+        match section {
+            Section::Physics => &mut self.physics,
+            Section::Text => &mut self.text,
+            Section::Draw => &mut self.draw,
+        }
+    }
+
+    /// One line per section with its mean and worst time in ms
+    pub fn lines(&self) -> Vec<String> {
+        [Section::Physics, Section::Text, Section::Draw]
+            .iter()
+            .map(|&s| {
+                let w = self.window(s);
+                format!(
+                    "{:?}: {:6.3} ms avg {:6.3} ms worst",
+                    s,
+                    w.mean() * 1e3,
+                    w.max() * 1e3
+                )
+            })
+            .collect()
+    }
+}
+
+/// Steps the default robot's drive model `steps` times with a changing
+/// command and returns the average seconds per step, for catching physics
+/// slowdowns. Also returns where it ended up, so the work can't be skipped.
+pub fn bench_observe(steps: usize) -> (f64, f64) {
+    let mut model = RobotConfig::default().build(0.001 * S);
+    let started = Instant::now();
+    for k in 0..steps {
+        // keep reversing so the robot doesn't settle at top speed
+        let v = if k % 4000 < 2000 { 10. * V } else { -8. * V };
+        model.observe(LR { l: v, r: 0.9 * v });
+    }
+    let per_step = seconds(started.elapsed()) / steps.max(1) as f64;
+    (per_step, model.pose().x.value_unsafe)
+}
+
+#[test]
+fn rolling_window_drops_oldest() {
+    let mut w = RollingWindow::new(3);
+    assert_eq!(w.mean(), 0.);
+    w.push(5.);
+    w.push(1.);
+    assert_eq!(w.mean(), 3.);
+    w.push(3.);
+    w.push(2.);
+    // 5 has been pushed out
+    assert_eq!(w.len(), 3);
+    assert_eq!(w.mean(), 2.);
+    assert_eq!(w.max(), 3.);
+    w.clear();
+    assert!(w.is_empty());
+}
+
+#[test]
+fn frame_stats_off_reads_no_clock() {
+    let mut stats = FrameStats::default();
+    assert!(stats.start().is_none());
+    stats.finish(Section::Draw, stats.start());
+    assert!(stats.window(Section::Draw).is_empty());
+    stats.set_enabled(true);
+    stats.finish(Section::Physics, stats.start());
+    stats.record(Section::Physics, 0.002);
+    assert_eq!(stats.window(Section::Physics).len(), 2);
+    assert!(stats.window(Section::Physics).max() >= 0.002);
+    stats.set_enabled(false);
+    assert!(stats.window(Section::Physics).is_empty());
+}
@@ -0,0 +1,441 @@
+//! Driving the robot from another program over UDP, so real robot code can be
+//! pointed at the sim instead of hardware. The program sends `Command`
+//! datagrams and gets a `StateReport` back after every step.
+//!
+//! Both packets are fixed size and little-endian, and start with a magic
+//! number so stray traffic on the port is ignored.
+
+use crate::dynamics::{ActuatedDDMRModel, LR};
+use crate::sensors::{Imu, ImuReading};
+
+use dimensioned::si::{Volt, S, V};
+
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// "DSC1"
+pub const COMMAND_MAGIC: u32 = 0x3143_5344;
+/// "DSS1"
+pub const STATE_MAGIC: u32 = 0x3153_5344;
+pub const COMMAND_LEN: usize = 16;
+pub const STATE_LEN: usize = 104;
+
+/// How long the receiving thread waits for a datagram before checking
+/// whether it should stop
+const POLL: Duration = Duration::from_millis(50);
+
+fn put_u32(out: &mut Vec<u8>, x: u32) {
+    for i in 0..4 {
+        out.push((x >> (8 * i)) as u8);
+    }
+}
+
+fn put_u64(out: &mut Vec<u8>, x: u64) {
+    for i in 0..8 {
+        out.push((x >> (8 * i)) as u8);
+    }
+}
+
+fn get_u32(b: &[u8]) -> u32 {
+    (0..4).fold(0, |x, i| x | (u32::from(b[i]) << (8 * i)))
+}
+
+fn get_u64(b: &[u8]) -> u64 {
+    (0..8).fold(0, |x, i| x | (u64::from(b[i]) << (8 * i)))
+}
+
+/// Voltages for both sides of the drive, `COMMAND_LEN` bytes on the wire
+///
+/// | field     | type  |                                  |
+/// |-----------|-------|----------------------------------|
+/// | magic     | `u32` | `COMMAND_MAGIC`                  |
+/// | `seq`     | `u32` | counts up with each command      |
+/// | `volts_l` | `f32` | V applied to the left armature   |
+/// | `volts_r` | `f32` | V applied to the right armature  |
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Command {
+    /// Newer commands have higher numbers
+    pub seq: u32,
+    pub volts_l: f32,
+    pub volts_r: f32,
+}
+
+impl Command {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(COMMAND_LEN);
+        put_u32(&mut out, COMMAND_MAGIC);
+        put_u32(&mut out, self.seq);
+        put_u32(&mut out, self.volts_l.to_bits());
+        put_u32(&mut out, self.volts_r.to_bits());
+        out
+    }
+
+    /// `None` for anything that isn't a whole command packet, or whose
+    /// voltages aren't finite, since one NaN would wreck the model for good
+    pub fn decode(b: &[u8]) -> Option<Self> {
+        if b.len() != COMMAND_LEN || get_u32(b) != COMMAND_MAGIC {
+            return None;
+        }
+        let command = Self {
+            seq: get_u32(&b[4..]),
+            volts_l: f32::from_bits(get_u32(&b[8..])),
+            volts_r: f32::from_bits(get_u32(&b[12..])),
+        };
+        if command.volts_l.is_finite() && command.volts_r.is_finite() {
+            Some(command)
+        } else {
+            None
+        }
+    }
+
+    pub fn volts(&self) -> LR<Volt<f64>> {
+        LR {
+            l: f64::from(self.volts_l) * V,
+            r: f64::from(self.volts_r) * V,
+        }
+    }
+}
+
+/// The robot after a step in plain SI numbers, `STATE_LEN` bytes on the wire
+///
+/// | field     | type  |                                          |
+/// |-----------|-------|------------------------------------------|
+/// | magic     | `u32` | `STATE_MAGIC`                            |
+/// | `seq`     | `u32` | of the command the step was driven with  |
+/// | `time`    | `f64` | s of simulation                          |
+/// | `x`, `y`  | `f64` | m                                        |
+/// | `theta`   | `f64` | rad, CCW from the x axis                 |
+/// | `wheel_l`, `wheel_r` | `f64` | rad/s                         |
+/// | `amps_l`, `amps_r`   | `f64` | A                             |
+/// | `yaw`     | `f64` | rad, from the IMU, not wrapped           |
+/// | `yaw_rate` | `f64` | rad/s, from the IMU, CCW positive       |
+/// | `accel_x`, `accel_y` | `f64` | m/s^2, forward and to the left |
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct StateReport {
+    /// 0 before there was a command
+    pub seq: u32,
+    pub time: f64,
+    pub x: f64,
+    pub y: f64,
+    pub theta: f64,
+    pub wheel_l: f64,
+    pub wheel_r: f64,
+    pub amps_l: f64,
+    pub amps_r: f64,
+    pub yaw: f64,
+    pub yaw_rate: f64,
+    pub accel_x: f64,
+    pub accel_y: f64,
+}
+
+impl StateReport {
+    pub fn from_model(seq: u32, time: f64, model: &ActuatedDDMRModel, imu: &ImuReading) -> Self {
+        let pose = model.pose();
+        let wheels = model.ddmr().wheels();
+        let current = model.currents();
+        Self {
+            seq,
+            time,
+            x: pose.x.value_unsafe,
+            y: pose.y.value_unsafe,
+            theta: pose.theta,
+            wheel_l: wheels.l.value_unsafe,
+            wheel_r: wheels.r.value_unsafe,
+            amps_l: current.l.value_unsafe,
+            amps_r: current.r.value_unsafe,
+            yaw: imu.yaw,
+            yaw_rate: imu.yaw_rate.value_unsafe,
+            accel_x: imu.accel_x.value_unsafe,
+            accel_y: imu.accel_y.value_unsafe,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(STATE_LEN);
+        put_u32(&mut out, STATE_MAGIC);
+        put_u32(&mut out, self.seq);
+        for x in &[
+            self.time,
+            self.x,
+            self.y,
+            self.theta,
+            self.wheel_l,
+            self.wheel_r,
+            self.amps_l,
+            self.amps_r,
+            self.yaw,
+            self.yaw_rate,
+            self.accel_x,
+            self.accel_y,
+        ] {
+            put_u64(&mut out, x.to_bits());
+        }
+        out
+    }
+
+    /// `None` for anything that isn't a whole state packet
+    pub fn decode(b: &[u8]) -> Option<Self> {
+        if b.len() != STATE_LEN || get_u32(b) != STATE_MAGIC {
+            return None;
+        }
+        let f = |i: usize| f64::from_bits(get_u64(&b[8 + 8 * i..]));
+        Some(Self {
+            seq: get_u32(&b[4..]),
+            time: f(0),
+            x: f(1),
+            y: f(2),
+            theta: f(3),
+            wheel_l: f(4),
+            wheel_r: f(5),
+            amps_l: f(6),
+            amps_r: f(7),
+            yaw: f(8),
+            yaw_rate: f(9),
+            accel_x: f(10),
+            accel_y: f(11),
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Received {
+    command: Command,
+    at: Instant,
+    from: SocketAddr,
+}
+
+/// Whether `newer` should replace `held`. Datagrams can arrive out of order, so
+/// an older sequence number is dropped, unless what's held has gone stale,
+/// which is what a restarted client counting from zero again looks like.
+fn supersedes(newer: &Command, held: Option<&Received>, now: Instant, timeout: Duration) -> bool {
+    match held {
+        Some(held) => newer.seq > held.command.seq || now - held.at > timeout,
+        None => true,
+    }
+}
+
+/// A UDP socket listening for commands on a thread of its own, keeping only
+/// the newest one for the game loop to pick up
+pub struct RemoteLink {
+    socket: UdpSocket,
+    latest: Arc<Mutex<Option<Received>>>,
+    timeout: Duration,
+    /// s of simulation reported so far
+    time: f64,
+    /// Updated once per report
+    imu: Imu,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RemoteLink {
+    /// Listens on `addr`. A command counts for `timeout` after it arrives; past
+    /// that the robot is stopped, so a client that dies doesn't leave it
+    /// driving.
+    pub fn bind<A: ToSocketAddrs>(addr: A, timeout: Duration) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        let receiver = socket.try_clone()?;
+        receiver.set_read_timeout(Some(POLL))?;
+        let latest = Arc::new(Mutex::new(None));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let latest = latest.clone();
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let mut buf = [0; 64];
+                while !stop.load(Ordering::Relaxed) {
+                    let (n, from) = match receiver.recv_from(&mut buf) {
+                        Ok(got) => got,
+                        Err(ref e)
+                            if e.kind() == io::ErrorKind::WouldBlock
+                                || e.kind() == io::ErrorKind::TimedOut =>
+                        {
+                            continue
+                        }
+                        Err(e) => {
+                            println!("Remote link stopped: {}", e);
+                            return;
+                        }
+                    };
+                    if let Some(command) = Command::decode(&buf[..n]) {
+                        let now = Instant::now();
+                        let mut latest = latest.lock().unwrap();
+                        if supersedes(&command, latest.as_ref(), now, timeout) {
+                            *latest = Some(Received {
+                                command,
+                                at: now,
+                                from,
+                            });
+                        }
+                    }
+                }
+            })
+        };
+        Ok(Self {
+            socket,
+            latest,
+            timeout,
+            time: 0.,
+            imu: Imu::new(),
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// The newest command, or `None` before the first one and once it's older
+    /// than the timeout
+    pub fn command(&self) -> Option<Command> {
+        let latest = *self.latest.lock().unwrap();
+        latest
+            .filter(|r| r.at.elapsed() <= self.timeout)
+            .map(|r| r.command)
+    }
+
+    /// The voltages to drive with this step: the newest command's, or zero
+    /// without a current one
+    pub fn volts(&self) -> LR<Volt<f64>> {
+        match self.command() {
+            Some(c) => c.volts(),
+            None => LR {
+                l: 0. * V,
+                r: 0. * V,
+            },
+        }
+    }
+
+    /// Sends the state after the model's latest `observe`, `dt` seconds after
+    /// the last report, to whoever sent the newest command. Nothing is sent
+    /// before a command has arrived.
+    pub fn report(&mut self, model: &ActuatedDDMRModel, dt: f64) -> io::Result<()> {
+        self.time += dt;
+        // kept up to date before the first command too, so it doesn't start
+        // from a standstill when the robot is already moving
+        let imu = self.imu.update(model.ddmr(), dt * S);
+        let latest = *self.latest.lock().unwrap();
+        match latest {
+            Some(r) => {
+                let report = StateReport::from_model(r.command.seq, self.time, model, &imu);
+                self.socket.send_to(&report.encode(), r.from).map(|_| ())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for RemoteLink {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[test]
+fn packets_round_trip() {
+    let c = Command {
+        seq: 0x0102_0304,
+        volts_l: -11.5,
+        volts_r: 3.25,
+    };
+    let b = c.encode();
+    assert_eq!(b.len(), COMMAND_LEN);
+    assert_eq!(&b[..8], b"DSC1\x04\x03\x02\x01");
+    assert_eq!(Command::decode(&b), Some(c));
+    assert_eq!(Command::decode(&b[..15]), None);
+    for &bad in &[std::f32::NAN, std::f32::INFINITY, std::f32::NEG_INFINITY] {
+        let left = Command { volts_l: bad, ..c };
+        assert_eq!(Command::decode(&left.encode()), None);
+        let right = Command { volts_r: bad, ..c };
+        assert_eq!(Command::decode(&right.encode()), None);
+    }
+
+    let s = StateReport {
+        seq: 7,
+        time: 1.5,
+        x: -0.25,
+        theta: 3.,
+        amps_r: 120.,
+        accel_y: -2.5,
+        ..StateReport::default()
+    };
+    let b = s.encode();
+    assert_eq!(b.len(), STATE_LEN);
+    assert_eq!(&b[..4], b"DSS1");
+    assert_eq!(StateReport::decode(&b), Some(s));
+    // a command isn't a state report
+    assert_eq!(StateReport::decode(&c.encode()), None);
+}
+
+#[test]
+fn remote_link_loopback() {
+    use crate::config::RobotConfig;
+    use dimensioned::si::S;
+
+    let mut link = RemoteLink::bind("127.0.0.1:0", Duration::from_millis(200)).unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let addr = link.local_addr().unwrap();
+    let mut model = RobotConfig::default().build(0.005 * S);
+
+    // nothing to drive with or report to yet
+    assert_eq!(link.command(), None);
+    link.report(&model, 0.005).unwrap();
+
+    let send = |seq: u32, volts: f32| {
+        let c = Command {
+            seq,
+            volts_l: volts,
+            volts_r: volts,
+        };
+        client.send_to(&c.encode(), addr).unwrap();
+    };
+    let wait_for = |link: &RemoteLink, seq: u32| {
+        let started = Instant::now();
+        while link.command().map(|c| c.seq) != Some(seq) {
+            assert!(started.elapsed() < Duration::from_secs(5), "no command");
+            thread::sleep(Duration::from_millis(1));
+        }
+    };
+    send(3, 6.);
+    wait_for(&link, 3);
+    // an older one arriving late doesn't take over
+    send(2, -6.);
+    send(4, 12.);
+    wait_for(&link, 4);
+    assert_eq!(link.volts().l, 12. * V);
+
+    for _ in 0..20 {
+        model.observe(link.volts());
+        link.report(&model, 0.005).unwrap();
+    }
+    let mut buf = [0; 128];
+    let mut last = None;
+    for _ in 0..20 {
+        let (n, _) = client.recv_from(&mut buf).unwrap();
+        last = StateReport::decode(&buf[..n]);
+    }
+    let last = last.expect("a state report");
+    assert_eq!(last.seq, 4);
+    assert!((last.time - 0.105).abs() < 1e-9);
+    assert_eq!(last.x, model.pose().x.value_unsafe);
+    assert!(last.wheel_l > 0.);
+    // speeding up straight ahead
+    assert!(last.accel_x > 0.);
+    assert!(last.yaw_rate.abs() < 1e-9);
+
+    // the client goes quiet and the robot stops
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(link.command(), None);
+    assert_eq!(link.volts().r, 0. * V);
+}
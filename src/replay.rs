@@ -0,0 +1,228 @@
+//! Replays that rebuild a run exactly instead of just logging it. A replay is
+//! the state the run started from plus the input given to every step, and
+//! since the simulation only ever steps by a fixed `dt` and draws its rocks
+//! from a seed, feeding the same input back in gives the same run bit for bit.
+//!
+//! Only the input is recorded. Changing modes, timing or obstacles partway
+//! through a recording isn't part of it, so the replay will go its own way
+//! from there.
+
+use crate::config::{RobotConfig, TimingConfig};
+use crate::sim::{InputState, SimSnapshot, Simulation};
+
+use serde_derive::{Deserialize, Serialize};
+
+use std::fmt;
+use std::io::{Read, Write};
+
+/// Bumped whenever the file format changes in a way older replays can't be
+/// read back into
+pub const REPLAY_VERSION: u32 = 1;
+
+/// Why a replay file couldn't be read
+#[derive(Debug)]
+pub enum ReplayError {
+    Json(serde_json::Error),
+    /// Saved by a build with a different `REPLAY_VERSION`
+    Version {
+        saved: u32,
+        current: u32,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Json(e) => write!(f, "invalid replay: {}", e),
+            ReplayError::Version { saved, current } => write!(
+                f,
+                "replay is version {}, but this build reads version {}",
+                saved, current
+            ),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ReplayError {
+    fn from(e: serde_json::Error) -> Self {
+        ReplayError::Json(e)
+    }
+}
+
+/// The same input held for `steps` steps in a row, which is most of them
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+struct InputRun {
+    steps: usize,
+    input: InputState,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    /// Missing from replays older than versioning, which read as 0
+    #[serde(default)]
+    pub version: u32,
+    /// The robot the run was driven with
+    pub config: RobotConfig,
+    /// As set when recording started, which may not be the config's
+    pub timing: TimingConfig,
+    /// px
+    pub field_size: (f32, f32),
+    /// s, of every step
+    pub dt: f32,
+    pub start: SimSnapshot,
+    inputs: Vec<InputRun>,
+}
+
+impl Replay {
+    /// Starts recording from where `sim` is now. `config` has to be the one
+    /// `sim` was built from.
+    pub fn record(sim: &Simulation, config: &RobotConfig, dt: f32) -> Self {
+        Self {
+            version: REPLAY_VERSION,
+            config: config.clone(),
+            timing: sim.timing().clone(),
+            field_size: sim.field_size(),
+            dt,
+            start: sim.snapshot(),
+            inputs: Vec::new(),
+        }
+    }
+
+    /// Adds the input for the next step
+    pub fn push(&mut self, input: &InputState) {
+        if let Some(run) = self.inputs.last_mut() {
+            if run.input == *input {
+                run.steps += 1;
+                return;
+            }
+        }
+        self.inputs.push(InputRun {
+            steps: 1,
+            input: *input,
+        });
+    }
+
+    /// Steps recorded so far
+    pub fn steps(&self) -> usize {
+        self.inputs.iter().map(|r| r.steps).sum()
+    }
+
+    /// The input for each step in order
+    pub fn inputs<'a>(&'a self) -> impl Iterator<Item = InputState> + 'a {
+        self.inputs
+            .iter()
+            .flat_map(|r| std::iter::repeat(r.input).take(r.steps))
+    }
+
+    /// A simulation in the state the recording started from
+    pub fn simulation(&self) -> Simulation {
+        let (width, height) = self.field_size;
+        let mut sim = Simulation::with_seed(width, height, &self.config, self.start.wave_seed);
+        sim.set_timing(self.timing.clone());
+        sim.restore(&self.start);
+        sim
+    }
+
+    /// Writes the replay as JSON
+    pub fn save<W: Write>(&self, out: W) -> serde_json::Result<()> {
+        serde_json::to_writer(out, self)
+    }
+
+    /// Reads a replay back, as long as it was saved by this version
+    pub fn load<R: Read>(input: R) -> Result<Self, ReplayError> {
+        let value: serde_json::Value = serde_json::from_reader(input)?;
+        // the version first, since another version's replay might not parse
+        // as this one's at all
+        let version = match value.get("version") {
+            Some(version) => serde_json::from_value(version.clone())?,
+            None => 0,
+        };
+        if version != REPLAY_VERSION {
+            return Err(ReplayError::Version {
+                saved: version,
+                current: REPLAY_VERSION,
+            });
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+/// Feeds a replay's input back in, one step at a time
+#[derive(Debug, Clone)]
+pub struct ReplayRun {
+    inputs: Vec<InputState>,
+    next: usize,
+}
+
+impl ReplayRun {
+    /// Also returns the simulation to run it on
+    pub fn start(replay: &Replay) -> (Self, Simulation) {
+        let run = Self {
+            inputs: replay.inputs().collect(),
+            next: 0,
+        };
+        (run, replay.simulation())
+    }
+
+    /// The input for the next step, `None` once they've all been played
+    pub fn next_input(&mut self) -> Option<InputState> {
+        let input = self.inputs.get(self.next).cloned();
+        if input.is_some() {
+            self.next += 1;
+        }
+        input
+    }
+
+    pub fn finished(&self) -> bool {
+        self.next >= self.inputs.len()
+    }
+}
+
+#[test]
+fn replay_rebuilds_run_exactly() {
+    use crate::sim::{scripted_input, SIM_FPS};
+    let config = RobotConfig::default();
+    let mut sim = Simulation::with_seed(640., 480., &config, 42);
+    let dt = 1.0 / SIM_FPS as f32;
+    // a little way in, so the start isn't just a fresh simulation
+    for step in 0..30 {
+        sim.step(&scripted_input(step), dt);
+    }
+    let mut replay = Replay::record(&sim, &config, dt);
+    for step in 30..30 + 10 * SIM_FPS as usize {
+        let input = InputState {
+            fire: step % 45 < 10,
+            ..scripted_input(step)
+        };
+        sim.step(&input, dt);
+        replay.push(&input);
+    }
+    assert_eq!(replay.steps(), 10 * SIM_FPS as usize);
+
+    let mut saved = Vec::new();
+    replay.save(&mut saved).unwrap();
+    let loaded = Replay::load(&saved[..]).unwrap();
+    let (mut run, mut replayed) = ReplayRun::start(&loaded);
+    while let Some(input) = run.next_input() {
+        replayed.step(&input, loaded.dt);
+    }
+    assert!(run.finished());
+    let (a, b) = (sim.robot().pose(), replayed.robot().pose());
+    assert_eq!((a.x, a.y, a.theta), (b.x, b.y, b.theta));
+    assert_eq!(sim.score(), replayed.score());
+    assert_eq!(sim.snapshot(), replayed.snapshot());
+
+    // a replay from before versioning, or from a later build, is refused
+    let saved = String::from_utf8(saved).unwrap();
+    let tag = format!("\"version\":{}", REPLAY_VERSION);
+    assert!(saved.contains(&tag));
+    for other in &["", "\"version\":2,"] {
+        let err = Replay::load(saved.replace(&format!("{},", tag), other).as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("version"), "{}", err);
+    }
+    // even when the rest of it doesn't parse as a replay any more
+    match Replay::load(&b"{\"version\":2,\"inputs\":\"changed\"}"[..]) {
+        Err(ReplayError::Version { saved, current }) => assert_eq!((saved, current), (2, 1)),
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+}
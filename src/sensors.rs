@@ -0,0 +1,717 @@
+//! Noisy and late measurements of the drive model, for testing filters,
+//! estimators and control loops against a known ground truth. The model itself
+//! is never disturbed, so the clean values stay available next to the measured
+//! ones.
+
+use crate::angle;
+use crate::dynamics::{ActuatedDDMRModel, DDMRModel, Differentiator, Encoder, Vels, LR};
+
+use dimensioned::si::*;
+use dimensioned::tarr;
+use dimensioned::typenum::{N1, N2, P1, Z0};
+use rand::distributions::StandardNormal;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+/// Gaussian noise on top of a constant bias, in the units of the channel
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct ChannelNoise {
+    pub sigma: f64,
+    pub bias: f64,
+}
+
+/// The noise on each sensor. The default is noiseless.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Noise {
+    /// rad/s, on each wheel velocity
+    pub wheel: ChannelNoise,
+    /// rad/s, on the gyro
+    pub gyro: ChannelNoise,
+    /// Encoder ticks, rounded to whole ticks before they're added
+    pub encoder: ChannelNoise,
+    /// rad, on the heading. Only `SensorSuite` reads it.
+    pub heading: ChannelNoise,
+    /// Runs with the same seed see the same noise
+    pub seed: u64,
+}
+
+fn rng_from_seed(seed: u64) -> StdRng {
+    let mut bytes = [0; 32];
+    for (i, b) in bytes.iter_mut().take(8).enumerate() {
+        *b = (seed >> (8 * i)) as u8;
+    }
+    StdRng::from_seed(bytes)
+}
+
+/// Owns the model and reads noisy sensors off it. Every read draws fresh
+/// noise, so reading the same sensor twice in a step gives different values,
+/// like sampling a real sensor twice.
+#[derive(Debug, Clone)]
+pub struct NoisySensors {
+    model: ActuatedDDMRModel,
+    noise: Noise,
+    rng: StdRng,
+    encoder: Option<Encoder>,
+}
+
+impl NoisySensors {
+    pub fn new(model: ActuatedDDMRModel, noise: Noise) -> Self {
+        let rng = rng_from_seed(noise.seed);
+        Self {
+            model,
+            noise,
+            rng,
+            encoder: None,
+        }
+    }
+
+    /// Adds encoders on both wheels, stepped by `observe`
+    pub fn with_encoder(self, ticks_per_rev: u32) -> Self {
+        let dt = self.model.ddmr().dt();
+        Self {
+            encoder: Some(Encoder::new(dt, ticks_per_rev)),
+            ..self
+        }
+    }
+
+    pub fn noise(&self) -> &Noise {
+        &self.noise
+    }
+
+    /// Steps the model, and the encoders if there are any
+    pub fn observe(&mut self, v: LR<Volt<f64>>) -> Vels {
+        let vels = self.model.observe(v);
+        if let Some(ref mut e) = self.encoder {
+            e.update(self.model.ddmr().wheels());
+        }
+        vels
+    }
+
+    fn sample(&mut self, channel: ChannelNoise) -> f64 {
+        channel.bias + channel.sigma * self.rng.sample(StandardNormal)
+    }
+
+    /// The chassis angular velocity, CCW positive
+    pub fn gyro_rate(&mut self) -> Hertz<f64> {
+        let noise = self.noise.gyro;
+        self.model.vel().ang + self.sample(noise) * HZ
+    }
+
+    pub fn wheel_velocities(&mut self) -> LR<Hertz<f64>> {
+        let noise = self.noise.wheel;
+        let wheels = self.model.ddmr().wheels();
+        LR {
+            l: wheels.l + self.sample(noise) * HZ,
+            r: wheels.r + self.sample(noise) * HZ,
+        }
+    }
+
+    /// `None` without encoders
+    pub fn encoder_ticks(&mut self) -> Option<LR<i64>> {
+        let ticks = self.encoder.as_ref()?.ticks();
+        let noise = self.noise.encoder;
+        Some(LR {
+            l: ticks.l + self.sample(noise).round() as i64,
+            r: ticks.r + self.sample(noise).round() as i64,
+        })
+    }
+
+    /// Resets the model and encoders, and restarts the noise from the seed
+    pub fn reset(&mut self) {
+        self.model.reset();
+        self.rng = rng_from_seed(self.noise.seed);
+        if let Some(ref mut e) = self.encoder {
+            *e = Encoder::new(self.model.ddmr().dt(), e.ticks_per_rev());
+        }
+    }
+
+    /// The clean model, for ground truth
+    pub fn model(&self) -> &ActuatedDDMRModel {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut ActuatedDDMRModel {
+        &mut self.model
+    }
+}
+
+/// One sensor's noise on values in SI units, with its own seeded stream so
+/// it can be restarted or resumed apart from any other
+#[derive(Debug, Clone)]
+pub struct NoisySensor<U>
+where
+    SI<f64, U>: Copy + Clone + Debug,
+{
+    /// In the units of the sensor
+    noise: ChannelNoise,
+    seed: u64,
+    rng: StdRng,
+    /// Draws since the seed, for `resume`
+    samples: u64,
+    unit: PhantomData<U>,
+}
+
+impl<U> NoisySensor<U>
+where
+    SI<f64, U>: Copy + Clone + Debug,
+{
+    pub fn new(noise: ChannelNoise, seed: u64) -> Self {
+        Self {
+            noise,
+            seed,
+            rng: rng_from_seed(seed),
+            samples: 0,
+            unit: PhantomData,
+        }
+    }
+
+    pub fn noise(&self) -> ChannelNoise {
+        self.noise
+    }
+
+    /// `truth` with the bias and a fresh draw of the noise added. With no
+    /// sigma and no bias it comes back exactly as it went in.
+    pub fn measure(&mut self, truth: SI<f64, U>) -> SI<f64, U> {
+        let noise: f64 = self.rng.sample(StandardNormal);
+        self.samples += 1;
+        let mut measured = truth;
+        measured.value_unsafe += self.noise.bias + self.noise.sigma * noise;
+        measured
+    }
+
+    /// Starts the noise over from `seed`
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.reset();
+    }
+
+    /// Starts the noise over from the seed
+    pub fn reset(&mut self) {
+        self.rng = rng_from_seed(self.seed);
+        self.samples = 0;
+    }
+
+    /// How many values have been measured since the seed
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    /// Carries on as if `samples` values had been measured since the seed
+    pub fn resume(&mut self, samples: u64) {
+        self.reset();
+        for _ in 0..samples {
+            let _: f64 = self.rng.sample(StandardNormal);
+        }
+        self.samples = samples;
+    }
+}
+
+/// rad/s
+type PerSecond = tarr![Z0, Z0, N1, Z0, Z0, Z0, Z0];
+/// rad
+type Radians = tarr![Z0, Z0, Z0, Z0, Z0, Z0, Z0];
+/// m/s
+type Speed = tarr![P1, Z0, N1, Z0, Z0, Z0, Z0];
+/// m/s^2
+type Acceleration = tarr![P1, Z0, N2, Z0, Z0, Z0, Z0];
+
+/// What the control loops see of the robot at one control update
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SensorReadings {
+    pub wheels: LR<Hertz<f64>>,
+    /// The chassis angular velocity, CCW positive
+    pub gyro: Hertz<f64>,
+    /// rad, CCW from the model's +x
+    pub heading: f64,
+}
+
+fn zero_readings() -> SensorReadings {
+    SensorReadings {
+        wheels: LR {
+            l: 0. * HZ,
+            r: 0. * HZ,
+        },
+        gyro: 0. * HZ,
+        heading: 0.,
+    }
+}
+
+/// Noisy wheel speeds, gyro rate and heading, read off the model once per
+/// control update. Each channel has its own stream from `Noise::seed`, so
+/// turning one up doesn't change the noise on the others.
+#[derive(Debug, Clone)]
+pub struct SensorSuite {
+    wheels: LR<NoisySensor<PerSecond>>,
+    gyro: NoisySensor<PerSecond>,
+    heading: NoisySensor<Radians>,
+    readings: SensorReadings,
+}
+
+impl SensorSuite {
+    /// Reads zero on every sensor until the first `measure`
+    pub fn new(noise: &Noise) -> Self {
+        let seed = noise.seed;
+        Self {
+            wheels: LR {
+                l: NoisySensor::new(noise.wheel, seed),
+                r: NoisySensor::new(noise.wheel, seed.wrapping_add(1)),
+            },
+            gyro: NoisySensor::new(noise.gyro, seed.wrapping_add(2)),
+            heading: NoisySensor::new(noise.heading, seed.wrapping_add(3)),
+            readings: zero_readings(),
+        }
+    }
+
+    /// Takes a fresh reading from every sensor
+    pub fn measure(&mut self, model: &ActuatedDDMRModel) -> SensorReadings {
+        let wheels = model.ddmr().wheels();
+        self.readings = SensorReadings {
+            wheels: LR {
+                l: self.wheels.l.measure(wheels.l),
+                r: self.wheels.r.measure(wheels.r),
+            },
+            gyro: self.gyro.measure(model.vel().ang),
+            heading: self
+                .heading
+                .measure(Unitless::new(model.pose().theta))
+                .value_unsafe,
+        };
+        self.readings
+    }
+
+    /// What the last `measure` read
+    pub fn readings(&self) -> SensorReadings {
+        self.readings
+    }
+
+    /// Starts the noise over with `seed` in place of the one it was made with
+    pub fn set_seed(&mut self, seed: u64) {
+        self.wheels.l.set_seed(seed);
+        self.wheels.r.set_seed(seed.wrapping_add(1));
+        self.gyro.set_seed(seed.wrapping_add(2));
+        self.heading.set_seed(seed.wrapping_add(3));
+    }
+
+    /// Starts the noise over from the seed
+    pub fn reset(&mut self) {
+        self.wheels.l.reset();
+        self.wheels.r.reset();
+        self.gyro.reset();
+        self.heading.reset();
+        self.readings = zero_readings();
+    }
+
+    /// Times `measure` has been called since the seed
+    pub fn samples(&self) -> u64 {
+        self.gyro.samples()
+    }
+
+    /// Carries on as if `measure` had been called `samples` times since the
+    /// seed, though `readings` stays as it is until the next one
+    pub fn resume(&mut self, samples: u64) {
+        self.wheels.l.resume(samples);
+        self.wheels.r.resume(samples);
+        self.gyro.resume(samples);
+        self.heading.resume(samples);
+    }
+}
+
+/// What an IMU on the chassis reads, in its own frame: x forward, y to the
+/// left
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ImuReading {
+    /// rad, CCW from where the IMU started. Not wrapped, so it keeps
+    /// counting through whole turns like a real gyro's angle.
+    pub yaw: f64,
+    /// CCW positive
+    pub yaw_rate: Hertz<f64>,
+    /// Along the direction of travel
+    pub accel_x: MeterPerSecond2<f64>,
+    /// Toward the left, which is where a CCW turn pulls
+    pub accel_y: MeterPerSecond2<f64>,
+}
+
+impl Default for ImuReading {
+    fn default() -> Self {
+        Self {
+            yaw: 0.,
+            yaw_rate: 0. * HZ,
+            accel_x: 0. * MPS2,
+            accel_y: 0. * MPS2,
+        }
+    }
+}
+
+/// A gyro and accelerometer worked out from the drive model's velocities,
+/// updated once per step. The longitudinal acceleration is the change in
+/// speed since the last update, the lateral one the centripetal `v * w`.
+#[derive(Debug, Clone)]
+pub struct Imu {
+    /// Added to the yaw rate, and so piles up in the yaw
+    drift: Hertz<f64>,
+    gyro: NoisySensor<PerSecond>,
+    accel: LR<NoisySensor<Acceleration>>,
+    /// Made on the first update, so it starts from the speed the robot has then
+    speed: Option<Differentiator<Speed>>,
+    /// rad, the model's heading at the last update
+    heading: f64,
+    reading: ImuReading,
+}
+
+impl Imu {
+    /// A perfect IMU, reading zero until the first update
+    pub fn new() -> Self {
+        Self::with_noise(ChannelNoise::default(), ChannelNoise::default(), 0)
+    }
+
+    /// Noise on the yaw rate in rad/s and on each acceleration in m/s^2, each
+    /// from its own stream from `seed`. The gyro's noise goes into the yaw
+    /// too, though its bias doesn't; that's what `with_drift` is for.
+    pub fn with_noise(gyro: ChannelNoise, accel: ChannelNoise, seed: u64) -> Self {
+        Self {
+            drift: 0. * HZ,
+            gyro: NoisySensor::new(gyro, seed),
+            accel: LR {
+                l: NoisySensor::new(accel, seed.wrapping_add(1)),
+                r: NoisySensor::new(accel, seed.wrapping_add(2)),
+            },
+            speed: None,
+            heading: 0.,
+            reading: ImuReading::default(),
+        }
+    }
+
+    /// A gyro that reads `drift` while standing still, so its yaw creeps away
+    /// from the truth
+    pub fn with_drift(self, drift: Hertz<f64>) -> Self {
+        Self { drift, ..self }
+    }
+
+    /// Reads the model `dt` after the last update
+    pub fn update(&mut self, model: &DDMRModel, dt: Second<f64>) -> ImuReading {
+        let vel = model.vel();
+        let theta = model.pose().theta;
+        let accel_x = match self.speed {
+            Some(ref mut d) => d.add_dt(vel.lin, dt),
+            None => {
+                self.speed = Some(Differentiator::new(dt, vel.lin));
+                self.heading = theta;
+                0. * MPS2
+            }
+        };
+        let true_rate = vel.ang + self.drift;
+        let yaw_rate = self.gyro.measure(true_rate);
+        // the model's own heading change is exact, where summing the rate
+        // once a step would pick up integration error of its own
+        let turned = angle::difference(self.heading, theta);
+        self.heading = theta;
+        self.reading = ImuReading {
+            yaw: self.reading.yaw + turned + *((yaw_rate - vel.ang) * dt),
+            yaw_rate,
+            accel_x: self.accel.l.measure(accel_x),
+            accel_y: self.accel.r.measure(vel.lin * vel.ang),
+        };
+        self.reading
+    }
+
+    /// What the last `update` read
+    pub fn reading(&self) -> ImuReading {
+        self.reading
+    }
+
+    /// Back to reading zero until the next update, with the noise started over
+    pub fn reset(&mut self) {
+        self.gyro.reset();
+        self.accel.l.reset();
+        self.accel.r.reset();
+        self.speed = None;
+        self.heading = 0.;
+        self.reading = ImuReading::default();
+    }
+}
+
+impl Default for Imu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A transport delay line: each value comes back out `depth` pushes after it
+/// went in, and until then it puts out the initial value. A depth of zero
+/// passes values straight through.
+#[derive(Debug, Clone)]
+pub struct Delay<T> {
+    /// Oldest first, always `depth` long
+    pending: VecDeque<T>,
+}
+
+impl<T: Clone> Delay<T> {
+    pub fn new(depth: usize, initial: T) -> Self {
+        Self {
+            pending: std::iter::repeat(initial).take(depth).collect(),
+        }
+    }
+
+    /// A delay of `delay` for values pushed every `dt`, rounded to whole steps
+    pub fn from_duration(delay: Second<f64>, dt: Second<f64>, initial: T) -> Self {
+        let depth = (*(delay / dt)).round().max(0.) as usize;
+        Self::new(depth, initial)
+    }
+
+    pub fn depth(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Puts in a new value and returns the one that's come due
+    pub fn push(&mut self, value: T) -> T {
+        match self.pending.pop_front() {
+            Some(due) => {
+                self.pending.push_back(value);
+                due
+            }
+            None => value,
+        }
+    }
+
+    /// Forgets everything in flight, as if `value` had always been pushed
+    pub fn fill(&mut self, value: T) {
+        for p in self.pending.iter_mut() {
+            *p = value.clone();
+        }
+    }
+
+    /// The values still to come out, oldest first
+    pub fn pending(&self) -> Vec<T> {
+        self.pending.iter().cloned().collect()
+    }
+
+    /// Replaces the values in flight with ones from `pending`. Panics unless
+    /// there's one for each step of the delay.
+    pub fn restore(&mut self, pending: &[T]) {
+        assert_eq!(pending.len(), self.pending.len(), "delay depth changed");
+        self.pending = pending.iter().cloned().collect();
+    }
+}
+
+#[test]
+fn delay_shifts_by_depth() {
+    let mut d = Delay::new(3, -1);
+    let out: Vec<i32> = (0..10).map(|n| d.push(n)).collect();
+    assert_eq!(out[..3], [-1, -1, -1]);
+    for (n, &o) in out.iter().enumerate().skip(3) {
+        assert_eq!(o, n as i32 - 3);
+    }
+    assert_eq!(d.pending(), vec![7, 8, 9]);
+
+    let mut through = Delay::new(0, 0.);
+    assert_eq!(through.push(2.5), 2.5);
+    assert_eq!(through.push(-1.), -1.);
+    let d = Delay::from_duration(0.06 * S, 0.02 * S, 0.);
+    assert_eq!(d.depth(), 3);
+}
+
+#[cfg(test)]
+fn test_sensors(noise: Noise) -> NoisySensors {
+    use crate::config::RobotConfig;
+    NoisySensors::new(RobotConfig::default().build(0.005 * S), noise)
+}
+
+#[test]
+fn noise_is_reproducible() {
+    let noise = Noise {
+        wheel: ChannelNoise {
+            sigma: 0.5,
+            bias: 0.,
+        },
+        gyro: ChannelNoise {
+            sigma: 0.1,
+            bias: 0.,
+        },
+        ..Noise::default()
+    };
+    let run = || {
+        let mut s = test_sensors(noise.clone());
+        (0..100)
+            .map(|_| {
+                s.observe(LR {
+                    l: 6. * V,
+                    r: 3. * V,
+                });
+                (s.gyro_rate(), s.wheel_velocities())
+            })
+            .collect::<Vec<_>>()
+    };
+    let a = run();
+    assert_eq!(a, run());
+
+    let mut s = test_sensors(noise.clone());
+    s.observe(LR {
+        l: 6. * V,
+        r: 3. * V,
+    });
+    let first = s.gyro_rate();
+    s.reset();
+    s.observe(LR {
+        l: 6. * V,
+        r: 3. * V,
+    });
+    assert_eq!(s.gyro_rate(), first);
+    assert_ne!(first, s.model().vel().ang);
+}
+
+#[test]
+fn noise_matches_sigma() {
+    let noise = Noise {
+        gyro: ChannelNoise {
+            sigma: 0.2,
+            bias: 0.05,
+        },
+        encoder: ChannelNoise {
+            sigma: 0.,
+            bias: 3.,
+        },
+        ..Noise::default()
+    };
+    let mut s = test_sensors(noise).with_encoder(360);
+    let n = 10000;
+    let errors: Vec<f64> = (0..n)
+        .map(|_| {
+            s.observe(LR {
+                l: 4. * V,
+                r: -4. * V,
+            });
+            let truth = s.model().vel().ang;
+            *((s.gyro_rate() - truth) / HZ)
+        })
+        .collect();
+    let mean = errors.iter().sum::<f64>() / n as f64;
+    let var = errors.iter().map(|e| (e - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+    assert!((mean - 0.05).abs() < 0.01, "{}", mean);
+    assert!((var.sqrt() - 0.2).abs() < 0.01, "{}", var.sqrt());
+    // the clean wheels don't see the gyro's noise
+    assert_eq!(s.wheel_velocities(), s.model().ddmr().wheels());
+
+    let ticks = s.encoder_ticks().unwrap();
+    let mut clean = s.clone();
+    clean.noise.encoder.bias = 0.;
+    let truth = clean.encoder_ticks().unwrap();
+    assert_eq!(ticks.l, truth.l + 3);
+    assert_eq!(ticks.r, truth.r + 3);
+}
+
+#[test]
+fn noisy_sensor_without_noise_passes_through() {
+    let mut s: NoisySensor<PerSecond> = NoisySensor::new(ChannelNoise::default(), 3);
+    for &x in &[0., 1.5, -7.25, 1e9, 1e-12] {
+        assert_eq!(s.measure(x * HZ), x * HZ);
+    }
+    let mut suite = SensorSuite::new(&Noise::default());
+    let mut model = crate::config::RobotConfig::default().build(0.005 * S);
+    for _ in 0..50 {
+        model.observe(LR {
+            l: 8. * V,
+            r: 2. * V,
+        });
+    }
+    let r = suite.measure(&model);
+    assert_eq!(r.wheels, model.ddmr().wheels());
+    assert_eq!(r.gyro, model.vel().ang);
+    assert_eq!(r.heading, model.pose().theta);
+}
+
+#[test]
+fn noisy_sensor_mean_and_variance() {
+    let noise = ChannelNoise {
+        sigma: 0.3,
+        bias: -0.1,
+    };
+    let mut s: NoisySensor<Radians> = NoisySensor::new(noise, 42);
+    let n = 20000;
+    let errors: Vec<f64> = (0..n)
+        .map(|k| {
+            let truth = k as f64 * 0.001;
+            (s.measure(Unitless::new(truth)) - Unitless::new(truth)).value_unsafe
+        })
+        .collect();
+    let mean = errors.iter().sum::<f64>() / n as f64;
+    let var = errors.iter().map(|e| (e - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+    assert!((mean + 0.1).abs() < 0.01, "{}", mean);
+    assert!((var - 0.09).abs() < 0.005, "{}", var);
+
+    // resuming picks the stream up where it was
+    let mut a: NoisySensor<Radians> = NoisySensor::new(noise, 7);
+    let mut b = a.clone();
+    for _ in 0..10 {
+        a.measure(Unitless::new(0.));
+    }
+    b.resume(a.samples());
+    assert_eq!(a.measure(Unitless::new(1.)), b.measure(Unitless::new(1.)));
+    b.set_seed(8);
+    assert_ne!(a.measure(Unitless::new(1.)), b.measure(Unitless::new(1.)));
+}
+
+#[cfg(test)]
+fn run_imu(volts: LR<Volt<f64>>, steps: usize) -> (ActuatedDDMRModel, Imu) {
+    use crate::config::RobotConfig;
+    let dt = 0.005 * S;
+    let mut model = RobotConfig::default().build(dt);
+    let mut imu = Imu::new().with_drift(0.01 * HZ);
+    for _ in 0..steps {
+        model.observe(volts);
+        imu.update(model.ddmr(), dt);
+    }
+    (model, imu)
+}
+
+#[test]
+fn imu_straight_at_speed() {
+    let volts = LR {
+        l: 6. * V,
+        r: 6. * V,
+    };
+    let (model, mut imu) = run_imu(volts, 2000);
+    let r = imu.reading();
+    assert!(model.vel().lin > 1. * MPS);
+    assert!(r.accel_x.value_unsafe.abs() < 1e-2, "{:?}", r.accel_x);
+    assert!(r.accel_y.value_unsafe.abs() < 1e-9, "{:?}", r.accel_y);
+    // nothing but the drift, which has piled up over the 10 s
+    assert!((r.yaw_rate - 0.01 * HZ).value_unsafe.abs() < 1e-9);
+    assert!((r.yaw - 0.1).abs() < 1e-6, "{}", r.yaw);
+
+    imu.reset();
+    assert_eq!(imu.reading(), ImuReading::default());
+    // speeding up from a standstill reads forward
+    let (_, imu) = run_imu(volts, 20);
+    assert!(imu.reading().accel_x > 1. * MPS2);
+}
+
+#[test]
+fn imu_steady_turn() {
+    let (model, imu) = run_imu(
+        LR {
+            l: 4. * V,
+            r: 8. * V,
+        },
+        2000,
+    );
+    let r = imu.reading();
+    let vel = model.vel();
+    assert!(vel.ang > 0.5 * HZ && vel.lin > 0.5 * MPS);
+    let centripetal = vel.lin * vel.ang;
+    assert!(
+        (r.accel_y - centripetal).value_unsafe.abs() < 1e-3 * centripetal.value_unsafe,
+        "{:?} {:?}",
+        r.accel_y,
+        centripetal
+    );
+    assert!(r.accel_x.value_unsafe.abs() < 1e-2);
+    // the yaw counts past pi rather than wrapping, and otherwise agrees with
+    // the model but for the drift
+    assert!(r.yaw > std::f64::consts::PI, "{}", r.yaw);
+    let off = angle::difference(model.pose().theta + 0.1, r.yaw);
+    assert!(off.abs() < 1e-3, "{}", off);
+}
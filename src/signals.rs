@@ -0,0 +1,260 @@
+//! Filters for signals in SI units, alongside the `Integrator` and
+//! `Differentiator` in `dynamics`. Each one takes a sample per timestep with
+//! `add`, which also returns the filtered value, and `get`s it again later.
+
+use dimensioned::si::*;
+use dimensioned::tarr;
+use dimensioned::typenum::{P1, Z0};
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::ops::{Div, Mul, Sub};
+
+/// First order low-pass, discretized the same way as the other filters in the
+/// crate so swapping one in doesn't change any numbers. A step input gets
+/// 63% of the way there after `tau`.
+#[derive(Debug, Clone)]
+pub struct Lowpass<U>
+where
+    SI<f64, U>: Copy + Clone + Debug,
+{
+    /// s
+    tau: f64,
+    alpha: f64,
+    initial: SI<f64, U>,
+    value: SI<f64, U>,
+}
+
+fn lowpass_alpha(dt: Second<f64>, tau: f64) -> f64 {
+    let dt = *(dt / S);
+    dt / (tau + dt)
+}
+
+impl<U> Lowpass<U>
+where
+    SI<f64, U>: Copy + Clone + Debug,
+{
+    /// A `tau` of zero passes the input straight through
+    pub fn new(dt: Second<f64>, tau: Second<f64>, initial: SI<f64, U>) -> Self {
+        let tau = (*(tau / S)).max(0.);
+        Self {
+            tau,
+            alpha: lowpass_alpha(dt, tau),
+            initial,
+            value: initial,
+        }
+    }
+
+    /// The timestep `add` assumes from now on
+    pub fn set_dt(&mut self, dt: Second<f64>) {
+        self.alpha = lowpass_alpha(dt, self.tau);
+    }
+
+    pub fn get(&self) -> SI<f64, U> {
+        self.value
+    }
+
+    /// Jumps straight to `val`, as if it had been the input forever
+    pub fn set(&mut self, val: SI<f64, U>) {
+        self.value = val;
+    }
+
+    /// Back to the state it was constructed in
+    pub fn reset(&mut self) {
+        self.value = self.initial;
+    }
+
+    pub fn add(&mut self, val: SI<f64, U>) -> SI<f64, U> {
+        let alpha = self.alpha;
+        self.step(val, alpha)
+    }
+
+    /// `add`, for a sample `dt` after the last one
+    pub fn add_dt(&mut self, val: SI<f64, U>, dt: Second<f64>) -> SI<f64, U> {
+        let alpha = lowpass_alpha(dt, self.tau);
+        self.step(val, alpha)
+    }
+
+    fn step(&mut self, val: SI<f64, U>, alpha: f64) -> SI<f64, U> {
+        self.value = self.value + (val - self.value) * alpha;
+        self.value
+    }
+}
+
+/// Follows the input, but no faster than `rate` in either direction, like a
+/// motor controller's voltage ramp
+#[derive(Debug, Clone)]
+pub struct SlewLimiter<U>
+where
+    U: Sub<tarr![Z0, Z0, P1, Z0, Z0, Z0, Z0]>, // seconds
+    SI<f64, U>: Copy + Clone + Debug,
+{
+    /// The most the output moves in one step
+    max_step: SI<f64, U>,
+    initial: SI<f64, U>,
+    value: SI<f64, U>,
+}
+
+impl<U> SlewLimiter<U>
+where
+    U: Sub<tarr![Z0, Z0, P1, Z0, Z0, Z0, Z0]>, // seconds
+    SI<f64, U>: Copy + Clone + Debug,
+    <SI<f64, U> as Div<Second<f64>>>::Output: Mul<Second<f64>, Output = SI<f64, U>>,
+{
+    /// Panics on a negative `rate`
+    pub fn new(
+        dt: Second<f64>,
+        rate: <SI<f64, U> as Div<Second<f64>>>::Output,
+        initial: SI<f64, U>,
+    ) -> Self {
+        let max_step = rate * dt;
+        assert!(max_step.value_unsafe >= 0., "slew rate can't be negative");
+        Self {
+            max_step,
+            initial,
+            value: initial,
+        }
+    }
+
+    pub fn get(&self) -> SI<f64, U> {
+        self.value
+    }
+
+    /// Jumps straight to `val`, ignoring the rate
+    pub fn set(&mut self, val: SI<f64, U>) {
+        self.value = val;
+    }
+
+    /// Back to the state it was constructed in
+    pub fn reset(&mut self) {
+        self.value = self.initial;
+    }
+
+    pub fn add(&mut self, val: SI<f64, U>) -> SI<f64, U> {
+        let step = val - self.value;
+        self.value = if step > self.max_step {
+            self.value + self.max_step
+        } else if step < -self.max_step {
+            self.value - self.max_step
+        } else {
+            val
+        };
+        self.value
+    }
+}
+
+/// Mean of the last `n` samples
+#[derive(Debug, Clone)]
+pub struct MovingAverage<U>
+where
+    SI<f64, U>: Copy + Clone + Debug,
+{
+    initial: SI<f64, U>,
+    /// Oldest first, always `n` of them
+    samples: VecDeque<SI<f64, U>>,
+    sum: SI<f64, U>,
+}
+
+impl<U> MovingAverage<U>
+where
+    SI<f64, U>: Copy + Clone + Debug,
+{
+    /// Starts out as if it had seen `initial` `n` times
+    pub fn new(n: usize, initial: SI<f64, U>) -> Self {
+        assert!(n >= 1, "an average needs at least one sample");
+        Self {
+            initial,
+            samples: std::iter::repeat(initial).take(n).collect(),
+            sum: initial * n as f64,
+        }
+    }
+
+    pub fn get(&self) -> SI<f64, U> {
+        self.sum / self.samples.len() as f64
+    }
+
+    /// Back to the state it was constructed in
+    pub fn reset(&mut self) {
+        let initial = self.initial;
+        for s in self.samples.iter_mut() {
+            *s = initial;
+        }
+        self.sum = initial * self.samples.len() as f64;
+    }
+
+    pub fn add(&mut self, val: SI<f64, U>) -> SI<f64, U> {
+        if let Some(oldest) = self.samples.pop_front() {
+            self.sum = self.sum - oldest;
+        }
+        self.samples.push_back(val);
+        self.sum = self.sum + val;
+        self.get()
+    }
+}
+
+#[test]
+fn lowpass_time_constant() {
+    let dt = 0.0001 * S;
+    let tau = 0.1 * S;
+    let mut f = Lowpass::new(dt, tau, 0. * V);
+    let steps = (*(tau / dt)).round() as usize;
+    let mut last = 0. * V;
+    for _ in 0..steps {
+        let out = f.add(10. * V);
+        // a step response never overshoots or turns back
+        assert!(out > last && out < 10. * V);
+        last = out;
+    }
+    let expected = 10. * (1. - (-1f64).exp());
+    assert!((*(f.get() / V) - expected).abs() < 0.01, "{:?}", f.get());
+    for _ in 0..10 * steps {
+        f.add(10. * V);
+    }
+    assert!((f.get() - 10. * V).value_unsafe.abs() < 1e-3);
+    f.reset();
+    assert_eq!(f.get(), 0. * V);
+    // no time constant, no lag
+    let mut passthrough = Lowpass::new(dt, 0. * S, 0. * M);
+    assert_eq!(passthrough.add(3. * M), 3. * M);
+}
+
+#[test]
+fn slew_limiter_holds_rate() {
+    let dt = 0.01 * S;
+    let rate = 12. * V / S;
+    let mut s = SlewLimiter::new(dt, rate, 0. * V);
+    let target = |k: usize| match k {
+        0..=49 => 12. * V,
+        50..=199 => -3. * V,
+        // changes slower than the rate are followed exactly
+        _ => -3. * V + ((k - 200) as f64 * 0.001) * V,
+    };
+    let mut last = s.get();
+    for k in 0..300 {
+        let out = s.add(target(k));
+        let step = (out - last) / dt;
+        assert!(step.value_unsafe.abs() <= 12. + 1e-9, "{:?} at {}", step, k);
+        last = out;
+    }
+    assert_eq!(s.get(), target(299));
+    // from zero to the full 12 V takes a second
+    s.reset();
+    for _ in 0..99 {
+        s.add(12. * V);
+    }
+    assert!(s.get() < 11.9 * V);
+    assert!((s.add(12. * V) - 12. * V).value_unsafe.abs() < 1e-9);
+}
+
+#[test]
+fn moving_average_window() {
+    let mut a = MovingAverage::new(4, 0. * M);
+    assert_eq!(a.add(4. * M), 1. * M);
+    for _ in 0..3 {
+        a.add(4. * M);
+    }
+    assert_eq!(a.get(), 4. * M);
+    a.add(0. * M);
+    assert_eq!(a.get(), 3. * M);
+    a.reset();
+    assert_eq!(a.get(), 0. * M);
+}
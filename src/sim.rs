@@ -0,0 +1,3065 @@
+//! The game world without any of the graphics, so it can be stepped
+//! headless from tests or the command line.
+
+use crate::angle;
+use crate::auton::{
+    Auton, PursuitParams, Routine, RoutineParams, RoutineProgress, RoutineRun, Waypoint,
+};
+use crate::benchmark::StepBenchmark;
+use crate::config::{
+    ArenaConfig, BatteryConfig, ChassisConfig, DisturbanceConfig, MotorConfig, RobotConfig,
+    SensorConfig, ThermalConfig, TimingConfig,
+};
+use crate::control::{self, ControllerState, PidGains, WheelVelocityController};
+use crate::disturbances::{DisturbanceProgress, Disturbances};
+use crate::drive_mixer;
+use crate::dynamics::{self, raw::RawVels, state::ModelState};
+use crate::playback::{Playback, PlaybackKind};
+use crate::sensors::{Delay, Noise, SensorReadings, SensorSuite};
+use crate::telemetry::Telemetry;
+use crate::trail::TrailState;
+
+use ggez::graphics::{Point2, Vector2};
+use ggez::nalgebra as na;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_derive::{Deserialize, Serialize};
+
+use std::fmt;
+use std::io::{Read, Write};
+
+/// *********************************************************************
+/// Basic stuff, make some helpers for vector functions.
+/// ggez includes the nalgebra math library to provide lots of
+/// math stuff  We just add some helpers.
+/// **********************************************************************
+
+/// Create a unit vector representing the
+/// given angle (in radians)
+fn vec_from_angle(angle: f32) -> Vector2 {
+    let vx = angle.sin();
+    let vy = angle.cos();
+    Vector2::new(vx, vy)
+}
+
+/// Makes a random `Vector2` with the given max magnitude.
+fn random_vec<R: Rng>(rng: &mut R, max_magnitude: f32) -> Vector2 {
+    let angle = rng.gen::<f32>() * 2.0 * std::f32::consts::PI;
+    let mag = rng.gen::<f32>() * max_magnitude;
+    vec_from_angle(angle) * (mag)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ActorType {
+    /// The player's robot or another one. Saved under its old name, so older
+    /// snapshots still load.
+    #[serde(rename = "Player")]
+    Robot,
+    Rock,
+    Shot,
+}
+
+#[derive(Debug)]
+pub struct Actor {
+    pub tag: ActorType,
+    pub pos: Point2,
+    pub facing: f32,
+    pub velocity: Vector2,
+    pub ang_vel: f32,
+    /// Radius of the bounding circle in world units, which the sprite is
+    /// drawn to fill
+    pub bbox_size: f32,
+
+    // I am going to lazily overload "life" with a
+    // double meaning:
+    // for shots, it is the time left to live,
+    // for players and rocks, it is the actual hit points.
+    pub life: f32,
+}
+
+const PLAYER_LIFE: f32 = 3.0;
+const SHOT_LIFE: f32 = 2.0;
+const ROCK_LIFE: f32 = 1.0;
+
+/// m, of the bounding circles
+const SHOT_RADIUS: f32 = 0.06;
+const ROCK_RADIUS: f32 = 0.12;
+
+/// Hit points a rock takes off the player when they collide
+const ROCK_DAMAGE: f32 = 1.0;
+
+/// *********************************************************************
+/// Now we have some constructor functions for different game objects.
+/// **********************************************************************
+
+/// The player's bounding circle matches the drawn wheelbase circle,
+/// so it's passed in rather than fixed.
+fn create_player(bbox_size: f32) -> Actor {
+    Actor {
+        tag: ActorType::Robot,
+        pos: Point2::origin(),
+        facing: 0.,
+        velocity: na::zero(),
+        ang_vel: 0.,
+        bbox_size,
+        life: PLAYER_LIFE,
+    }
+}
+
+fn create_rock() -> Actor {
+    Actor {
+        tag: ActorType::Rock,
+        pos: Point2::origin(),
+        facing: 0.,
+        velocity: na::zero(),
+        ang_vel: 0.,
+        bbox_size: ROCK_RADIUS * PX_PER_METER,
+        life: ROCK_LIFE,
+    }
+}
+
+fn create_shot() -> Actor {
+    Actor {
+        tag: ActorType::Shot,
+        pos: Point2::origin(),
+        facing: 0.,
+        velocity: na::zero(),
+        ang_vel: SHOT_ANG_VEL,
+        bbox_size: SHOT_RADIUS * PX_PER_METER,
+        life: SHOT_LIFE,
+    }
+}
+
+const MAX_ROCK_VEL: f32 = 50.0;
+
+/// Distance from the player that a new wave's rocks keep clear of, px
+const WAVE_EXCLUSION: f32 = 100.0;
+const WAVE_MAX_RADIUS: f32 = 250.0;
+/// Score for each rock shot down
+const ROCK_SCORE: i32 = 1;
+
+/// Create the given number of rocks.
+/// Makes sure that none of them are within the
+/// given exclusion zone (nominally the player)
+/// Note that this *could* create rocks outside the
+/// bounds of the playing field, so it should be
+/// called before `wrap_actor_position()` happens.
+fn create_rocks<R: Rng>(
+    rng: &mut R,
+    num: i32,
+    exclusion: Point2,
+    min_radius: f32,
+    max_radius: f32,
+    max_vel: f32,
+) -> Vec<Actor> {
+    assert!(max_radius > min_radius);
+    let new_rock = |_| {
+        let mut rock = create_rock();
+        let r_angle = rng.gen::<f32>() * 2.0 * std::f32::consts::PI;
+        let r_distance = rng.gen::<f32>() * (max_radius - min_radius) + min_radius;
+        rock.pos = exclusion + vec_from_angle(r_angle) * r_distance;
+        rock.velocity = random_vec(rng, max_vel);
+        rock
+    };
+    (0..num).map(new_rock).collect()
+}
+
+/// Rocks speed up by a tenth of `MAX_ROCK_VEL` each level, up to the speed
+/// limit every actor is held to
+fn rock_speed(level: i32) -> f32 {
+    (MAX_ROCK_VEL * (1.0 + 0.1 * level as f32)).min(MAX_PHYSICS_VEL)
+}
+
+/// The rocks for `level`, drawn from an RNG seeded with `seed`. Also returns
+/// the seed for the wave after, so a run can be replayed from one number.
+fn create_wave(seed: u64, level: i32, player: Point2) -> (Vec<Actor>, u64) {
+    let mut bytes = [0; 32];
+    for (i, b) in bytes.iter_mut().enumerate() {
+        *b = (seed >> (8 * (i % 8))) as u8;
+    }
+    let mut rng = StdRng::from_seed(bytes);
+    let rocks = create_rocks(
+        &mut rng,
+        level + 4,
+        player,
+        WAVE_EXCLUSION,
+        WAVE_MAX_RADIUS,
+        rock_speed(level),
+    );
+    (rocks, rng.gen())
+}
+
+#[test]
+fn rocks_spawn_clear_of_player() {
+    let mut rng = StdRng::from_seed([7; 32]);
+    let player = Point2::new(30., -40.);
+    for _ in 0..200 {
+        for rock in create_rocks(&mut rng, 10, player, 100., 250., MAX_ROCK_VEL) {
+            let dist = (rock.pos - player).norm();
+            assert!(dist >= 100. - 1e-3 && dist <= 250. + 1e-3, "{}", dist);
+            assert!(rock.velocity.norm() <= MAX_ROCK_VEL + 1e-3);
+        }
+    }
+    assert!(rock_speed(2) > rock_speed(1));
+    assert_eq!(rock_speed(1000), MAX_PHYSICS_VEL);
+}
+
+fn create_robot(config: &RobotConfig) -> dynamics::ActuatedDDMRModel {
+    config.build(config.timing.physics_dt * dimensioned::si::S)
+}
+
+/// Which physics drives the player
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PhysicsMode {
+    /// The differential drive model in `dynamics`
+    Dynamics,
+    /// The original thrust-and-rotate physics
+    Arcade,
+}
+
+/// *********************************************************************
+/// Arcade physics, kept around for comparison with the drive model.
+/// **********************************************************************
+
+/// Default speed of a shot relative to the player, px/s
+const SHOT_SPEED: f32 = 200.0;
+/// rad/s, just for looks
+const SHOT_ANG_VEL: f32 = 6.0;
+/// N s, the kick backward each shot gives the robot in dynamics mode
+const RECOIL_IMPULSE: f64 = 2.0;
+
+const PLAYER_THRUST: f32 = 100.0;
+// Rotation in radians per second.
+const PLAYER_TURN_RATE: f32 = 3.0;
+
+const MAX_PHYSICS_VEL: f32 = 250.0;
+
+fn player_thrust(actor: &mut Actor, dt: f32) {
+    let direction_vector = vec_from_angle(actor.facing);
+    let thrust_vector = direction_vector * (PLAYER_THRUST);
+    actor.velocity += thrust_vector * (dt);
+}
+
+fn player_handle_input(actor: &mut Actor, input: &InputState, dt: f32) {
+    actor.facing = angle::wrap_f32(actor.facing + dt * PLAYER_TURN_RATE * input.xaxis as f32);
+
+    if input.yaxis > 0.0 {
+        player_thrust(actor, dt);
+    }
+}
+
+fn update_actor_position(actor: &mut Actor, dt: f32) {
+    // Clamp the velocity to the max efficiently
+    let norm_sq = actor.velocity.norm_squared();
+    if norm_sq > MAX_PHYSICS_VEL.powi(2) {
+        actor.velocity = actor.velocity / norm_sq.sqrt() * MAX_PHYSICS_VEL;
+    }
+    let dv = actor.velocity * (dt);
+    actor.pos += dv;
+    actor.facing = angle::wrap_f32(actor.facing + actor.ang_vel * dt);
+}
+
+#[test]
+fn constant_spin_makes_one_revolution() {
+    use std::f32::consts::PI;
+    let mut actor = create_rock();
+    actor.ang_vel = 2. * PI;
+    let dt = 1.0 / SIM_FPS as f32;
+    for step in 1..=SIM_FPS {
+        update_actor_position(&mut actor, dt);
+        assert!(actor.facing > -PI && actor.facing <= PI);
+        if step == SIM_FPS / 2 {
+            let half = angle::difference(f64::from(actor.facing), std::f64::consts::PI);
+            assert!(half.abs() < 1e-4, "{}", actor.facing);
+        }
+    }
+    // a second at 2 pi rad/s is exactly once around
+    assert!(actor.facing.abs() < 1e-4, "{}", actor.facing);
+}
+
+fn handle_timed_life(actor: &mut Actor, dt: f32) {
+    actor.life -= dt;
+}
+
+/// *********************************************************************
+/// Drive model physics.
+/// **********************************************************************
+
+/// How the joystick axes are turned into wheel commands
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DriveMode {
+    /// One axis for throttle, one for turning
+    Arcade,
+    /// One axis per side of the drivetrain
+    Tank,
+    /// Throttle and a curve that stays the same at any speed, turning in
+    /// place when the throttle is near zero
+    Curvature,
+}
+
+impl DriveMode {
+    pub fn next(self) -> Self {
+        match self {
+            DriveMode::Arcade => DriveMode::Tank,
+            DriveMode::Tank => DriveMode::Curvature,
+            DriveMode::Curvature => DriveMode::Arcade,
+        }
+    }
+}
+
+/// What the mixed joystick outputs command
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ControlMode {
+    /// A fraction of the configured max voltage, straight to the motors
+    Voltage,
+    /// A fraction of the free wheel speed, held by a velocity loop on each wheel
+    Velocity,
+}
+
+/// The wheel speed both sides can reach at `max_voltage`, which is the
+/// slower side's free speed when the motors differ
+pub fn max_wheel_speed(
+    robot: &dynamics::ActuatedDDMRModel,
+    max_voltage: dimensioned::si::Volt<f64>,
+) -> dimensioned::si::Hertz<f64> {
+    let p = robot.motor_params();
+    let (l, r) = (
+        control::free_wheel_speed(&p.l, max_voltage),
+        control::free_wheel_speed(&p.r, max_voltage),
+    );
+    if l < r {
+        l
+    } else {
+        r
+    }
+}
+
+/// Gains for the wheel velocity loop, in volts per rad/s
+fn wheel_velocity_gains(motor: &dynamics::DCMotorParams) -> PidGains {
+    PidGains {
+        kp: 1.,
+        ki: 5.,
+        kd: 0.,
+        kf: control::back_emf_feedforward(motor),
+    }
+}
+
+/// The loop runs once per control period, not per physics step, and sees the
+/// wheel speeds `sensor_delay` late. Both sides share the left motor's gains.
+fn create_velocity_controller(
+    robot: &dynamics::ActuatedDDMRModel,
+    timing: &TimingConfig,
+    max_voltage: dimensioned::si::Volt<f64>,
+) -> WheelVelocityController {
+    use dimensioned::si::S;
+    WheelVelocityController::new(
+        wheel_velocity_gains(&robot.motor_params().l),
+        timing.control_period * S,
+        max_voltage,
+    )
+    .with_delay(timing.sensor_delay * S)
+    .with_derivative_filter(0.05 * dimensioned::si::S)
+}
+
+/// Holds the motor commands back `command_delay`, putting out zero volts
+/// until the first one comes through. Pushed once per physics step.
+fn create_command_delay(timing: &TimingConfig) -> Delay<dynamics::LR<dimensioned::si::Volt<f64>>> {
+    use dimensioned::si::S;
+    Delay::from_duration(
+        timing.command_delay * S,
+        timing.physics_dt * S,
+        zero_volts(),
+    )
+}
+
+/// Voltages in a delay line, as plain numbers for a snapshot
+fn pending_volts(
+    delay: &Delay<dynamics::LR<dimensioned::si::Volt<f64>>>,
+) -> Vec<dynamics::LR<f64>> {
+    delay
+        .pending()
+        .iter()
+        .map(|v| dynamics::LR {
+            l: v.l.value_unsafe,
+            r: v.r.value_unsafe,
+        })
+        .collect()
+}
+
+/// Puts `pending_volts` back, unless the delay has changed length since
+fn restore_pending_volts(
+    delay: &mut Delay<dynamics::LR<dimensioned::si::Volt<f64>>>,
+    pending: &[dynamics::LR<f64>],
+) {
+    use dimensioned::si::V;
+    if pending.len() == delay.depth() {
+        let volts: Vec<_> = pending
+            .iter()
+            .map(|v| dynamics::LR {
+                l: v.l * V,
+                r: v.r * V,
+            })
+            .collect();
+        delay.restore(&volts);
+    }
+}
+
+/// The motor voltages the joystick asks for
+fn input_command(
+    robot: &dynamics::ActuatedDDMRModel,
+    controller: &mut WheelVelocityController,
+    sensors: &SensorReadings,
+    input: &InputState,
+    mode: DriveMode,
+    control_mode: ControlMode,
+    max_voltage: dimensioned::si::Volt<f64>,
+) -> dynamics::LR<dimensioned::si::Volt<f64>> {
+    let out = match mode {
+        DriveMode::Arcade => drive_mixer::arcade(input.xaxis, input.yaxis),
+        DriveMode::Tank => drive_mixer::tank(input.left_axis, input.right_axis),
+        DriveMode::Curvature => {
+            let quick_turn = input.yaxis.abs() < drive_mixer::QUICK_TURN_THROTTLE;
+            drive_mixer::curvature(input.xaxis, input.yaxis, quick_turn)
+        }
+    };
+    match control_mode {
+        ControlMode::Voltage => dynamics::LR {
+            l: out.l * max_voltage,
+            r: out.r * max_voltage,
+        },
+        ControlMode::Velocity => {
+            let max = max_wheel_speed(robot, max_voltage);
+            let target = dynamics::LR {
+                l: out.l * max,
+                r: out.r * max,
+            };
+            controller.update_measured(target, sensors.wheels)
+        }
+    }
+}
+
+pub const PX_PER_METER: f32 = 100.;
+
+/// Moves the actor according to the chassis velocities of the drive model.
+/// `facing` is clockwise from screen up while the model's heading is
+/// counterclockwise, hence the sign flip on the angular velocity.
+fn update_robot_actor(actor: &mut Actor, vels: RawVels, px_per_meter: f32, dt: f32) {
+    let RawVels { lin, ang } = vels;
+    actor.velocity = vec_from_angle(actor.facing) * lin as f32 * px_per_meter;
+    actor.ang_vel = -ang as f32;
+    actor.pos += actor.velocity * dt;
+    actor.facing = angle::wrap_f32(actor.facing + actor.ang_vel * dt);
+}
+
+/// Takes an actor and wraps its position to the bounds of the
+/// screen, so if it goes off the left side of the screen it
+/// will re-enter on the right side and so on.
+fn wrap_actor_position(actor: &mut Actor, sx: f32, sy: f32) {
+    // Wrap screen
+    let screen_x_bounds = sx / 2.0;
+    let screen_y_bounds = sy / 2.0;
+    if actor.pos.x > screen_x_bounds {
+        actor.pos.x -= sx;
+    } else if actor.pos.x < -screen_x_bounds {
+        actor.pos.x += sx;
+    };
+    if actor.pos.y > screen_y_bounds {
+        actor.pos.y -= sy;
+    } else if actor.pos.y < -screen_y_bounds {
+        actor.pos.y += sy;
+    }
+}
+
+/// What happens when the player reaches the edge of the field
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Boundary {
+    /// Come back in on the opposite side
+    Wrap,
+    /// Stop against the edge
+    Walls,
+    /// No edge at all, the field goes on forever
+    Open,
+}
+
+/// Pushes the actor's bounding circle back inside the field and zeroes any
+/// velocity into the walls. Returns the sum of the outward normals of the walls
+/// it touched, which has both components set in a corner.
+fn clamp_actor_to_walls(actor: &mut Actor, sx: f32, sy: f32) -> Vector2 {
+    let mut normal = Vector2::new(0.0, 0.0);
+    let x_bound = sx / 2.0 - actor.bbox_size;
+    let y_bound = sy / 2.0 - actor.bbox_size;
+    if actor.pos.x > x_bound {
+        actor.pos.x = x_bound;
+        normal.x = 1.0;
+    } else if actor.pos.x < -x_bound {
+        actor.pos.x = -x_bound;
+        normal.x = -1.0;
+    }
+    if actor.pos.y > y_bound {
+        actor.pos.y = y_bound;
+        normal.y = 1.0;
+    } else if actor.pos.y < -y_bound {
+        actor.pos.y = -y_bound;
+        normal.y = -1.0;
+    }
+    if actor.velocity.x * normal.x > 0.0 {
+        actor.velocity.x = 0.0;
+    }
+    if actor.velocity.y * normal.y > 0.0 {
+        actor.velocity.y = 0.0;
+    }
+    normal
+}
+
+/// Keeps the robot inside the walls. It can't slide sideways along a wall, so
+/// driving into one stops it outright, or sends it straight back with
+/// `restitution` of its speed, but it's still free to turn in place.
+///
+/// Hitting at an angle catches a corner of the robot on the wall. Rather than
+/// work out the contact, that takes the same fraction of its spin as the sine
+/// of the angle it came in at: none head on, nearly all of it just grazing.
+fn constrain_robot_to_walls(
+    actor: &mut Actor,
+    robot: &mut dynamics::ActuatedDDMRModel,
+    restitution: f64,
+    sx: f32,
+    sy: f32,
+) {
+    let into_wall = actor.velocity;
+    let normal = clamp_actor_to_walls(actor, sx, sy);
+    if into_wall.dot(&normal) > 0.0 {
+        let heading = vec_from_angle(actor.facing);
+        let glancing = (heading.x * normal.y - heading.y * normal.x).abs() / normal.norm();
+        let vel = robot.vel();
+        robot.set_vels(dynamics::Vels {
+            lin: vel.lin * -restitution,
+            ang: vel.ang * (1. - f64::from(glancing)),
+        });
+        actor.velocity = into_wall * -(restitution as f32);
+    }
+}
+
+/// Keeps the robot on the field however `boundary` says to
+fn constrain_robot_to_field(
+    actor: &mut Actor,
+    robot: &mut dynamics::ActuatedDDMRModel,
+    boundary: Boundary,
+    restitution: f64,
+    sx: f32,
+    sy: f32,
+) {
+    match boundary {
+        Boundary::Wrap => wrap_actor_position(actor, sx, sy),
+        Boundary::Walls => constrain_robot_to_walls(actor, robot, restitution, sx, sy),
+        Boundary::Open => (),
+    }
+}
+
+#[test]
+fn walls_clamp_corner() {
+    let mut a = create_player(10.);
+    a.pos = Point2::new(400., -300.);
+    a.velocity = Vector2::new(5., -5.);
+    let normal = clamp_actor_to_walls(&mut a, 640., 480.);
+    assert_eq!(normal, Vector2::new(1., -1.));
+    assert_eq!(a.pos, Point2::new(310., -230.));
+    assert_eq!(a.velocity, Vector2::new(0., 0.));
+}
+
+#[test]
+fn walls_stop_robot() {
+    use dimensioned::si::*;
+    let mut robot = create_robot(&RobotConfig::default());
+    let mut a = create_player(10.);
+    // at the top wall, facing straight into it
+    a.pos = Point2::new(0., 235.);
+    robot.set_vels(dynamics::Vels {
+        lin: 2. * MPS,
+        ang: 1. / S,
+    });
+    a.velocity = vec_from_angle(a.facing) * 200.;
+    constrain_robot_to_walls(&mut a, &mut robot, 0., 640., 480.);
+    assert_eq!(robot.vel().lin, 0. * MPS);
+    assert_eq!(robot.vel().ang, 1. / S);
+    assert_eq!(a.pos.y, 230.);
+}
+
+/// What an `Obstacle` is shaped like, in the same pixels as the actors
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Shape {
+    Circle {
+        radius: f32,
+    },
+    /// Lined up with the axes
+    Rect {
+        half_width: f32,
+        half_height: f32,
+    },
+}
+
+/// A fixed shape the robot can't drive through, in the same pixels as the
+/// actors
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Obstacle {
+    pub x: f32,
+    pub y: f32,
+    /// Flattened, so a circle saves the same as before there were rectangles
+    #[serde(flatten)]
+    pub shape: Shape,
+}
+
+impl Obstacle {
+    pub fn circle(x: f32, y: f32, radius: f32) -> Self {
+        Self {
+            x,
+            y,
+            shape: Shape::Circle { radius },
+        }
+    }
+
+    pub fn rect(x: f32, y: f32, half_width: f32, half_height: f32) -> Self {
+        Self {
+            x,
+            y,
+            shape: Shape::Rect {
+                half_width,
+                half_height,
+            },
+        }
+    }
+
+    pub fn center(&self) -> Point2 {
+        Point2::new(self.x, self.y)
+    }
+
+    /// How a circle of `radius` at `pos` overlaps the obstacle: the outward
+    /// normal and how far along it the circle has to move to just touch.
+    /// From inside a rectangle that's out through the nearest side. The normal
+    /// is zero dead center in a circle, where no way out is nearer.
+    pub fn penetration(&self, pos: Point2, radius: f32) -> Option<(Vector2, f32)> {
+        let offset = pos - self.center();
+        match self.shape {
+            Shape::Circle { radius: own } => {
+                let dist = offset.norm();
+                let contact = own + radius;
+                if dist >= contact {
+                    None
+                } else if dist > 0.0 {
+                    Some((offset / dist, contact - dist))
+                } else {
+                    Some((Vector2::new(0., 0.), contact))
+                }
+            }
+            Shape::Rect {
+                half_width,
+                half_height,
+            } => {
+                if offset.x.abs() <= half_width && offset.y.abs() <= half_height {
+                    let gap_x = half_width - offset.x.abs();
+                    let gap_y = half_height - offset.y.abs();
+                    if gap_x <= gap_y {
+                        Some((Vector2::new(offset.x.signum(), 0.), gap_x + radius))
+                    } else {
+                        Some((Vector2::new(0., offset.y.signum()), gap_y + radius))
+                    }
+                } else {
+                    let closest = Vector2::new(
+                        offset.x.max(-half_width).min(half_width),
+                        offset.y.max(-half_height).min(half_height),
+                    );
+                    let out = offset - closest;
+                    let dist = out.norm();
+                    if dist >= radius {
+                        None
+                    } else {
+                        Some((out / dist, radius - dist))
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn contains(&self, point: Point2) -> bool {
+        self.penetration(point, 0.).is_some()
+    }
+}
+
+/// Pushes the actor's bounding circle out of the obstacle by the shortest way,
+/// or back the way it came if there's none. Returns the outward normal if they
+/// overlapped.
+fn push_out_of_obstacle(actor: &mut Actor, obstacle: &Obstacle) -> Option<Vector2> {
+    let (normal, depth) = obstacle.penetration(actor.pos, actor.bbox_size)?;
+    let normal = if normal == Vector2::new(0., 0.) {
+        -vec_from_angle(actor.facing)
+    } else {
+        normal
+    };
+    actor.pos += normal * depth;
+    Some(normal)
+}
+
+/// Keeps the robot out of the obstacles. The part of its velocity into an
+/// obstacle is taken out, but since it can only drive along its heading, what
+/// it keeps is the rest projected back onto the heading. Head on that stops it,
+/// and at a glancing angle it scrapes around the obstacle.
+fn constrain_robot_to_obstacles(
+    actor: &mut Actor,
+    robot: &mut dynamics::ActuatedDDMRModel,
+    obstacles: &[Obstacle],
+) {
+    for obstacle in obstacles {
+        let normal = match push_out_of_obstacle(actor, obstacle) {
+            Some(normal) => normal,
+            None => continue,
+        };
+        if actor.velocity.dot(&normal) < 0.0 {
+            let along = vec_from_angle(actor.facing).dot(&normal);
+            let kept = 1.0 - along * along;
+            let vel = robot.vel();
+            robot.set_vels(dynamics::Vels {
+                lin: vel.lin * f64::from(kept),
+                ..vel
+            });
+            actor.velocity *= kept;
+        }
+    }
+}
+
+#[test]
+fn obstacle_keeps_tangential_speed() {
+    use dimensioned::si::*;
+    let mut robot = create_robot(&RobotConfig::default());
+    let mut a = create_player(10.);
+    let obstacle = Obstacle::circle(0., 0., 30.);
+    // just inside the obstacle's right edge, driving straight up along it
+    a.pos = Point2::new(39.5, 0.);
+    robot.set_vels(dynamics::Vels {
+        lin: 2. * MPS,
+        ang: 0. / S,
+    });
+    a.velocity = vec_from_angle(a.facing) * 200.;
+    constrain_robot_to_obstacles(&mut a, &mut robot, &[obstacle]);
+    assert_eq!(a.pos, Point2::new(40., 0.));
+    assert_eq!(robot.vel().lin, 2. * MPS);
+    assert_eq!(a.velocity, Vector2::new(0., 200.));
+}
+
+#[test]
+fn rect_penetration_depth_and_normal() {
+    let wall = Obstacle::rect(0., 0., 50., 20.);
+    // past each side
+    assert_eq!(
+        wall.penetration(Point2::new(55., 0.), 10.),
+        Some((Vector2::new(1., 0.), 5.))
+    );
+    assert_eq!(
+        wall.penetration(Point2::new(0., -25.), 10.),
+        Some((Vector2::new(0., -1.), 5.))
+    );
+    // off the corner, out from it rather than either side
+    assert_eq!(
+        wall.penetration(Point2::new(53., 24.), 10.),
+        Some((Vector2::new(0.6, 0.8), 5.))
+    );
+    assert_eq!(wall.penetration(Point2::new(59., 28.), 10.), None);
+    // inside, out through the nearest side
+    assert_eq!(
+        wall.penetration(Point2::new(45., 5.), 10.),
+        Some((Vector2::new(1., 0.), 15.))
+    );
+    assert!(wall.contains(Point2::new(-50., 20.)));
+    assert!(!wall.contains(Point2::new(-51., 0.)));
+}
+
+/// Simulates joystick axes for arcade and tank drive
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputState {
+    pub xaxis: f64,
+    pub yaxis: f64,
+    /// Left side in tank drive, on W/S
+    pub left_axis: f64,
+    /// Right side in tank drive, on Up/Down
+    pub right_axis: f64,
+    pub fire: bool,
+}
+
+/// Rate of the fixed update loop. The drive model is sub-stepped at its own
+/// rate from `TimingConfig`, so this only sets how often the world is drawn
+/// and everything other than the robot moves.
+pub const SIM_FPS: u32 = 60;
+
+/// Seconds between player shots
+const PLAYER_SHOT_TIME: f32 = 0.5;
+
+/// Counts the shot cooldown down by `dt`. Returns the new cooldown and whether
+/// to fire. Whatever part of a step the cooldown ran over is taken off the
+/// next one, so the rate of fire doesn't depend on the step size, but it never
+/// banks time while the trigger is up, so there's no burst when it's pressed.
+fn fire_timer(cooldown: f32, dt: f32, fire: bool) -> (f32, bool) {
+    let cooldown = cooldown - dt;
+    if cooldown > 0.0 {
+        (cooldown, false)
+    } else if fire {
+        (cooldown + PLAYER_SHOT_TIME, true)
+    } else {
+        (0.0, false)
+    }
+}
+
+#[test]
+fn fire_timer_rate() {
+    let shots_in = |seconds: f32, dt: f32| {
+        let mut cooldown = 0.0;
+        let mut shots = 0;
+        for _ in 0..(seconds / dt).round() as usize {
+            let (c, fired) = fire_timer(cooldown, dt, true);
+            cooldown = c;
+            shots += fired as u32;
+        }
+        shots
+    };
+    // one right away, then one every PLAYER_SHOT_TIME
+    assert_eq!(shots_in(4.9, 1. / 60.), 10);
+    assert_eq!(shots_in(4.9, 1. / 240.), 10);
+    assert_eq!(shots_in(4.9, 0.125), 10);
+
+    // releasing the trigger doesn't bank shots
+    let (cooldown, fired) = fire_timer(0.0, 0.1, true);
+    assert!(fired);
+    let mut cooldown = cooldown;
+    for _ in 0..50 {
+        cooldown = fire_timer(cooldown, 0.1, false).0;
+    }
+    assert_eq!(
+        fire_timer(cooldown, 0.1, true),
+        (PLAYER_SHOT_TIME - 0.1, true)
+    );
+    assert!(!fire_timer(PLAYER_SHOT_TIME - 0.1, 0.1, true).1);
+}
+
+/// Things that happened during a step that the frontend may want to react to,
+/// e.g. by playing a sound
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SimEvent {
+    ShotFired,
+    RockDestroyed,
+    PlayerHit,
+    /// The last row of a playback file was reached
+    PlaybackFinished,
+    /// The robot reached the last auton waypoint or finished its routine
+    AutonFinished,
+    /// The step benchmark stopped the robot and has its result
+    BenchmarkFinished,
+    /// The last rock of a wave was destroyed and the next, bigger one spawned
+    LevelCleared,
+    /// One of the robot's motors got hotter than the thermal config's warning
+    /// temperature. Sent again only once both have cooled back below it.
+    MotorOverheated,
+}
+
+fn zero_volts() -> dynamics::LR<dimensioned::si::Volt<f64>> {
+    dynamics::LR {
+        l: 0. * dimensioned::si::V,
+        r: 0. * dimensioned::si::V,
+    }
+}
+
+/// A square to the right of the starting position, in meters, ending back at the start
+fn default_auton_path() -> Vec<Waypoint> {
+    vec![
+        Waypoint::new(0., 1.5),
+        Waypoint::new(1.5, 1.5),
+        Waypoint::new(1.5, 0.),
+        Waypoint::new(0., 0.),
+    ]
+}
+
+/// Where the player actor is, as a pose in meters. Unlike the model's own pose
+/// this follows the actor through walls and wrapping, so it lines up with what's
+/// on screen. The heading is converted to counterclockwise from +x.
+fn actor_pose(actor: &Actor, px_per_meter: f32) -> dynamics::Pose {
+    dynamics::Pose {
+        x: f64::from(actor.pos.x / px_per_meter) * dimensioned::si::M,
+        y: f64::from(actor.pos.y / px_per_meter) * dimensioned::si::M,
+        theta: std::f64::consts::FRAC_PI_2 - f64::from(actor.facing),
+    }
+}
+
+/// The voltages to drive the robot toward the auton's current waypoint through
+/// the wheel velocity loop, or zero once it's done. Also returns whether this
+/// update finished the path.
+fn auton_command(
+    auton: &mut Auton,
+    pose: dynamics::Pose,
+    robot: &dynamics::ActuatedDDMRModel,
+    controller: &mut WheelVelocityController,
+    sensors: &SensorReadings,
+) -> (dynamics::LR<dimensioned::si::Volt<f64>>, bool) {
+    let was_finished = auton.finished();
+    let volts = match auton.update(pose) {
+        Some(vels) => {
+            let target = robot.ddmr().vels_to_wheel(vels);
+            controller.update_measured(target, sensors.wheels)
+        }
+        None => {
+            controller.reset();
+            zero_volts()
+        }
+    };
+    (volts, !was_finished && auton.finished())
+}
+
+/// The voltages for the routine's current step through the wheel velocity
+/// loop, or zero once it's done. Also returns whether this update finished it.
+fn routine_command(
+    run: &mut RoutineRun,
+    dt: dimensioned::si::Second<f64>,
+    robot: &dynamics::ActuatedDDMRModel,
+    controller: &mut WheelVelocityController,
+    sensors: &SensorReadings,
+) -> (dynamics::LR<dimensioned::si::Volt<f64>>, bool) {
+    let was_finished = run.finished();
+    let volts = match run.update(robot.ddmr(), sensors.heading, dt) {
+        Some(vels) => {
+            let target = robot.ddmr().vels_to_wheel(vels);
+            controller.update_measured(target, sensors.wheels)
+        }
+        None => {
+            controller.reset();
+            zero_volts()
+        }
+    };
+    (volts, !was_finished && run.finished())
+}
+
+/// A playback file being fed to the robot in place of the input
+struct PlaybackRun {
+    playback: Playback,
+    /// Seconds since the playback started
+    time: f64,
+}
+
+impl PlaybackRun {
+    /// The voltages for the row at the current time
+    fn command(
+        &self,
+        controller: &mut WheelVelocityController,
+        sensors: &SensorReadings,
+    ) -> dynamics::LR<dimensioned::si::Volt<f64>> {
+        use dimensioned::si::{HZ, V};
+        let (l, r) = self.playback.sample(self.time);
+        match self.playback.kind() {
+            PlaybackKind::Voltage => dynamics::LR { l: l * V, r: r * V },
+            PlaybackKind::Velocity => controller.update_measured(
+                dynamics::LR {
+                    l: l * HZ,
+                    r: r * HZ,
+                },
+                sensors.wheels,
+            ),
+        }
+    }
+
+    /// Moves the playback on by `dt` seconds. Returns whether the file has
+    /// run out.
+    fn advance(&mut self, dt: f64) -> bool {
+        self.time += dt;
+        self.time > self.playback.end_time()
+    }
+}
+
+/// Slack for float error when counting time in steps, so that a frame of
+/// exactly n steps never comes out as n - 1
+const STEP_EPSILON: f64 = 1e-6;
+
+/// Splits each frame into physics steps for the drive model, and picks the
+/// steps where the motor commands are recomputed
+#[derive(Debug, Clone)]
+struct Scheduler {
+    timing: TimingConfig,
+    /// Fraction of a physics step left over from earlier frames
+    backlog: f64,
+    /// Seconds until the next control update
+    until_control: f64,
+}
+
+impl Scheduler {
+    fn new(timing: TimingConfig) -> Self {
+        Self {
+            timing,
+            backlog: 0.,
+            until_control: 0.,
+        }
+    }
+
+    /// Drops any partial step and makes the next step a control update
+    fn reset(&mut self) {
+        self.backlog = 0.;
+        self.until_control = 0.;
+    }
+
+    /// The number of whole physics steps in a frame of `dt` seconds
+    fn physics_steps(&mut self, dt: f64) -> u32 {
+        let total = self.backlog + dt / self.timing.physics_dt;
+        let steps = (total + STEP_EPSILON).floor();
+        self.backlog = (total - steps).max(0.);
+        steps as u32
+    }
+
+    /// Call once before each physics step. Returns whether that step should
+    /// compute a new command rather than hold the last one.
+    fn control_due(&mut self) -> bool {
+        let due = self.until_control <= STEP_EPSILON * self.timing.physics_dt;
+        if due {
+            self.until_control += self.timing.control_period;
+        }
+        self.until_control -= self.timing.physics_dt;
+        due
+    }
+}
+
+/// How far apart the other robots start, in meters, side by side to the
+/// right of the player
+const ROBOT_SPACING: f64 = 1.5;
+
+/// A robot on the field besides the player's, with its own drive model,
+/// velocity loop and sensors, driven only by its own input. It shares the
+/// field, timing and modes with the player's robot, but doesn't shoot, the
+/// rocks pass it by, and it drives straight through the other robots.
+pub struct Robot {
+    actor: Actor,
+    /// Where it goes back to on reset
+    start: Point2,
+    model: dynamics::ActuatedDDMRModel,
+    controller: WheelVelocityController,
+    sensors: SensorSuite,
+    /// Held between control updates
+    command: dynamics::LR<dimensioned::si::Volt<f64>>,
+    /// Between `command` and the motors
+    command_delay: Delay<dynamics::LR<dimensioned::si::Volt<f64>>>,
+    max_voltage: dimensioned::si::Volt<f64>,
+}
+
+impl Robot {
+    /// Steps at `timing` rather than the config's own
+    fn new(config: &RobotConfig, timing: &TimingConfig, start: Point2, px_per_meter: f32) -> Self {
+        let model = config.build(timing.physics_dt * dimensioned::si::S);
+        let max_voltage = config.max_voltage * dimensioned::si::V;
+        let controller = create_velocity_controller(&model, timing, max_voltage);
+        let wb_r = *(model.ddmr().params().L / dimensioned::si::M) as f32 * px_per_meter;
+        let mut actor = create_player(wb_r);
+        actor.pos = start;
+        Self {
+            actor,
+            start,
+            model,
+            controller,
+            sensors: SensorSuite::new(&config.sensors.noise()),
+            command: zero_volts(),
+            command_delay: create_command_delay(timing),
+            max_voltage,
+        }
+    }
+
+    pub fn actor(&self) -> &Actor {
+        &self.actor
+    }
+
+    pub fn model(&self) -> &dynamics::ActuatedDDMRModel {
+        &self.model
+    }
+
+    /// What full stick commands
+    pub fn max_voltage(&self) -> dimensioned::si::Volt<f64> {
+        self.max_voltage
+    }
+
+    /// Back where it started, at rest
+    fn reset(&mut self) {
+        self.model.reset();
+        self.controller.reset();
+        self.sensors.reset();
+        self.command = zero_volts();
+        self.command_delay.fill(zero_volts());
+        self.actor.pos = self.start;
+        self.actor.facing = 0.;
+        self.actor.velocity = na::zero();
+        self.actor.ang_vel = 0.;
+    }
+
+    fn state(&self) -> RobotState {
+        RobotState {
+            actor: (&self.actor).into(),
+            model: self.model.state(),
+            controller: self.controller.state(),
+            command: dynamics::LR {
+                l: self.command.l.value_unsafe,
+                r: self.command.r.value_unsafe,
+            },
+            sensor_samples: self.sensors.samples(),
+            pending_commands: pending_volts(&self.command_delay),
+        }
+    }
+
+    fn restore(&mut self, state: &RobotState) {
+        use dimensioned::si::V;
+        self.actor = state.actor.into();
+        self.model.restore(&state.model);
+        self.controller.restore(&state.controller);
+        self.command = dynamics::LR {
+            l: state.command.l * V,
+            r: state.command.r * V,
+        };
+        self.sensors.resume(state.sensor_samples);
+        restore_pending_volts(&mut self.command_delay, &state.pending_commands);
+    }
+}
+
+/// One of the other robots in a `SimSnapshot`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RobotState {
+    pub actor: ActorState,
+    pub model: ModelState,
+    pub controller: ControllerState,
+    /// In volts
+    pub command: dynamics::LR<f64>,
+    pub sensor_samples: u64,
+    /// Commands still on their way to the motors, oldest first, in volts
+    #[serde(default)]
+    pub pending_commands: Vec<dynamics::LR<f64>>,
+}
+
+/// An `Actor` on plain numbers so it can be serialized
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActorState {
+    pub tag: ActorType,
+    pub pos: [f32; 2],
+    pub facing: f32,
+    pub velocity: [f32; 2],
+    pub ang_vel: f32,
+    pub bbox_size: f32,
+    pub life: f32,
+}
+
+impl From<&Actor> for ActorState {
+    fn from(a: &Actor) -> Self {
+        Self {
+            tag: a.tag,
+            pos: [a.pos.x, a.pos.y],
+            facing: a.facing,
+            velocity: [a.velocity.x, a.velocity.y],
+            ang_vel: a.ang_vel,
+            bbox_size: a.bbox_size,
+            life: a.life,
+        }
+    }
+}
+
+impl From<ActorState> for Actor {
+    fn from(a: ActorState) -> Self {
+        Self {
+            tag: a.tag,
+            pos: Point2::new(a.pos[0], a.pos[1]),
+            facing: a.facing,
+            velocity: Vector2::new(a.velocity[0], a.velocity[1]),
+            ang_vel: a.ang_vel,
+            bbox_size: a.bbox_size,
+            life: a.life,
+        }
+    }
+}
+
+/// Everything a `Simulation` needs to carry on exactly where it was, from
+/// `Simulation::snapshot`. It's restored into a simulation built from the same
+/// robot config, which supplies the parameters and timing.
+///
+/// Each wave of rocks is drawn from its own seed, and the sensor noise from
+/// the config's seed, so `wave_seed` and `sensor_samples` are all the random
+/// number state there is. A playback in progress isn't saved, since the file
+/// it plays isn't.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SimSnapshot {
+    pub player: ActorState,
+    pub shots: Vec<ActorState>,
+    pub rocks: Vec<ActorState>,
+    pub obstacles: Vec<Obstacle>,
+    pub score: i32,
+    pub level: i32,
+    pub wave_seed: u64,
+    /// s simulated so far
+    #[serde(default)]
+    pub time: f64,
+    pub robot: ModelState,
+    pub controller: ControllerState,
+    /// Motor command held between control updates, in volts
+    pub command: dynamics::LR<f64>,
+    /// Commands still on their way to the motors, oldest first, in volts
+    #[serde(default)]
+    pub pending_commands: Vec<dynamics::LR<f64>>,
+    /// See `Scheduler`
+    pub step_backlog: f64,
+    pub until_control: f64,
+    /// The auton's current waypoint
+    pub auton_waypoint: Option<usize>,
+    pub auton_enabled: bool,
+    /// Sensor readings taken since the noise was seeded
+    #[serde(default)]
+    pub sensor_samples: u64,
+    /// How far through the routine, if one is loaded
+    #[serde(default)]
+    pub routine: Option<RoutineProgress>,
+    /// The robots besides the player's, restored into as many as the
+    /// simulation has
+    #[serde(default)]
+    pub others: Vec<RobotState>,
+    /// Where the bumps and any shove are up to
+    #[serde(default)]
+    pub disturbances: DisturbanceProgress,
+    pub physics: PhysicsMode,
+    pub drive_mode: DriveMode,
+    pub control_mode: ControlMode,
+    pub brake_mode: dynamics::BrakeMode,
+    pub boundary: Boundary,
+    pub player_shot_timeout: f32,
+    pub shot_speed: f32,
+}
+
+impl SimSnapshot {
+    /// Writes the snapshot as JSON
+    pub fn save<W: Write>(&self, out: W) -> serde_json::Result<()> {
+        serde_json::to_writer_pretty(out, self)
+    }
+
+    pub fn load<R: Read>(input: R) -> serde_json::Result<Self> {
+        serde_json::from_reader(input)
+    }
+}
+
+/// Bumped whenever `SimSnapshot` changes in a way older sessions can't be
+/// read back into
+pub const SESSION_VERSION: u32 = 1;
+
+/// The parts of a `RobotConfig` that change how the robot moves
+#[derive(Serialize)]
+struct PhysicsConfig<'a> {
+    efficiency: f64,
+    rolling_resistance: f64,
+    viscous_friction: f64,
+    coulomb_friction: f64,
+    wheel_friction: Option<f64>,
+    voltage_ramp: Option<f64>,
+    current_limit: Option<f64>,
+    max_voltage: f64,
+    chassis: &'a ChassisConfig,
+    motor: &'a Option<MotorConfig>,
+    left_motor: &'a Option<MotorConfig>,
+    right_motor: &'a Option<MotorConfig>,
+    battery: &'a Option<BatteryConfig>,
+    thermal: &'a Option<ThermalConfig>,
+    timing: &'a TimingConfig,
+    sensors: &'a SensorConfig,
+    disturbances: &'a DisturbanceConfig,
+    /// The walls aren't in the snapshot, unlike the obstacles
+    arena: &'a Option<ArenaConfig>,
+}
+
+/// FNV-1a over the physics in the config, so a session can tell whether it
+/// was saved with the same robot on the same field. Settings that only change
+/// how it's driven or shown, like the gamepad or the trail, are left out.
+pub fn config_hash(config: &RobotConfig) -> u64 {
+    let physics = PhysicsConfig {
+        efficiency: config.efficiency,
+        rolling_resistance: config.rolling_resistance,
+        viscous_friction: config.viscous_friction,
+        coulomb_friction: config.coulomb_friction,
+        wheel_friction: config.wheel_friction,
+        voltage_ramp: config.voltage_ramp,
+        current_limit: config.current_limit,
+        max_voltage: config.max_voltage,
+        chassis: &config.chassis,
+        motor: &config.motor,
+        left_motor: &config.left_motor,
+        right_motor: &config.right_motor,
+        battery: &config.battery,
+        thermal: &config.thermal,
+        timing: &config.timing,
+        sensors: &config.sensors,
+        disturbances: &config.disturbances,
+        arena: &config.arena,
+    };
+    serde_json::to_vec(&physics)
+        .expect("the physics config is always representable as JSON")
+        .into_iter()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+            (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+/// Why a saved session couldn't be picked back up
+#[derive(Debug)]
+pub enum SessionError {
+    Json(serde_json::Error),
+    /// Saved by a build with a different `SESSION_VERSION`
+    Version {
+        saved: u32,
+        current: u32,
+    },
+    /// Saved with a different robot config
+    Config {
+        saved: u64,
+        current: u64,
+    },
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SessionError::Json(e) => write!(f, "invalid session: {}", e),
+            SessionError::Version { saved, current } => write!(
+                f,
+                "session is version {}, but this build reads version {}",
+                saved, current
+            ),
+            SessionError::Config { saved, current } => write!(
+                f,
+                "session was saved with a different robot config ({:016x}, now {:016x})",
+                saved, current
+            ),
+        }
+    }
+}
+
+impl From<serde_json::Error> for SessionError {
+    fn from(e: serde_json::Error) -> Self {
+        SessionError::Json(e)
+    }
+}
+
+/// A snapshot stamped with what it needs to resume: the format version and
+/// the robot it was taken with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    /// Missing from sessions older than versioning, which read as 0
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default)]
+    pub config_hash: u64,
+    pub snapshot: SimSnapshot,
+    /// The player's trail, then each other robot's. The trails are drawn, not
+    /// simulated, so they're kept beside the snapshot rather than in it.
+    #[serde(default)]
+    pub trails: Vec<TrailState>,
+}
+
+impl Session {
+    pub fn new(snapshot: SimSnapshot, config: &RobotConfig) -> Self {
+        Self {
+            version: SESSION_VERSION,
+            config_hash: config_hash(config),
+            snapshot,
+            trails: Vec::new(),
+        }
+    }
+
+    /// Writes the session as JSON
+    pub fn save<W: Write>(&self, out: W) -> serde_json::Result<()> {
+        serde_json::to_writer_pretty(out, self)
+    }
+
+    /// Reads a session back, as long as it was saved by this version with
+    /// `config`
+    pub fn load<R: Read>(input: R, config: &RobotConfig) -> Result<Self, SessionError> {
+        let value: serde_json::Value = serde_json::from_reader(input)?;
+        // the version first, since another version's session might not parse
+        // as this one's at all
+        let version = match value.get("version") {
+            Some(version) => serde_json::from_value(version.clone())?,
+            None => 0,
+        };
+        if version != SESSION_VERSION {
+            return Err(SessionError::Version {
+                saved: version,
+                current: SESSION_VERSION,
+            });
+        }
+        let session: Session = serde_json::from_value(value)?;
+        let current = config_hash(config);
+        if session.config_hash != current {
+            return Err(SessionError::Config {
+                saved: session.config_hash,
+                current,
+            });
+        }
+        Ok(session)
+    }
+}
+
+/// All of the game state that gets stepped each frame.
+/// Positions are in pixels with the origin at the center of the field.
+pub struct Simulation {
+    player: Actor,
+    shots: Vec<Actor>,
+    rocks: Vec<Actor>,
+    obstacles: Vec<Obstacle>,
+    score: i32,
+    /// Starts at 1, and goes up each time a wave of rocks is cleared
+    level: i32,
+    /// Seeds the next wave of rocks
+    wave_seed: u64,
+    /// Seconds simulated so far
+    time: f64,
+    robot: dynamics::ActuatedDDMRModel,
+    playback: Option<PlaybackRun>,
+    /// Voltages from a program driving the robot from outside
+    remote: Option<dynamics::LR<dimensioned::si::Volt<f64>>>,
+    auton: Auton,
+    /// Run by the auton in place of the waypoints, if set
+    routine: Option<RoutineRun>,
+    auton_enabled: bool,
+    /// Drives the robot ahead of everything else while running, and holds
+    /// its result once finished
+    benchmark: Option<StepBenchmark>,
+    /// Pushing the player's robot around, on top of whatever drives it
+    disturbances: Disturbances,
+    /// Every robot but the player's, see `add_robot`
+    others: Vec<Robot>,
+    physics: PhysicsMode,
+    drive_mode: DriveMode,
+    control_mode: ControlMode,
+    boundary: Boundary,
+    velocity_controller: WheelVelocityController,
+    /// What the control loops see, read at each control update
+    sensors: SensorSuite,
+    scheduler: Scheduler,
+    /// Held between control updates
+    command: dynamics::LR<dimensioned::si::Volt<f64>>,
+    /// Between `command` and the motors
+    command_delay: Delay<dynamics::LR<dimensioned::si::Volt<f64>>>,
+    /// What full stick commands
+    max_voltage: dimensioned::si::Volt<f64>,
+    px_per_meter: f32,
+    field_width: f32,
+    field_height: f32,
+    /// From the arena, see `constrain_robot_to_walls`
+    wall_restitution: f64,
+    /// Where the motors warn, with a thermal model
+    overheat: Option<dimensioned::si::Kelvin<f64>>,
+    /// Whether either motor was past `overheat` after the last step
+    motors_hot: bool,
+    player_shot_timeout: f32,
+    shot_speed: f32,
+    events: Vec<SimEvent>,
+}
+
+impl Simulation {
+    /// A simulation of the built-in robot
+    pub fn new(field_width: f32, field_height: f32) -> Self {
+        Self::with_config(field_width, field_height, &RobotConfig::default())
+    }
+
+    pub fn with_config(field_width: f32, field_height: f32, config: &RobotConfig) -> Self {
+        Self::with_seed(field_width, field_height, config, rand::random())
+    }
+
+    /// The same `seed` always gives the same rocks. The field is `field_width`
+    /// by `field_height` pixels unless the config has an arena.
+    pub fn with_seed(field_width: f32, field_height: f32, config: &RobotConfig, seed: u64) -> Self {
+        let robot = create_robot(config);
+        let max_voltage = config.max_voltage * dimensioned::si::V;
+        let velocity_controller = create_velocity_controller(&robot, &config.timing, max_voltage);
+        let px_per_meter = PX_PER_METER;
+        let player_wb_r = *(robot.ddmr().params().L / dimensioned::si::M) as f32 * px_per_meter;
+        let (field_width, field_height, wall_restitution) = match config.arena {
+            Some(ref a) => (
+                a.width as f32 * px_per_meter,
+                a.height as f32 * px_per_meter,
+                a.restitution,
+            ),
+            None => (field_width, field_height, 0.),
+        };
+
+        let player = create_player(player_wb_r);
+        let (rocks, wave_seed) = create_wave(seed, 1, player.pos);
+        let obstacles = config
+            .obstacles
+            .iter()
+            .map(|o| {
+                let (x, y) = (o.x as f32 * px_per_meter, o.y as f32 * px_per_meter);
+                // validated to be one or the other
+                match o.radius {
+                    Some(radius) => Obstacle::circle(x, y, radius as f32 * px_per_meter),
+                    None => Obstacle::rect(
+                        x,
+                        y,
+                        o.width.unwrap_or(0.) as f32 * px_per_meter / 2.,
+                        o.height.unwrap_or(0.) as f32 * px_per_meter / 2.,
+                    ),
+                }
+            })
+            .collect();
+
+        Self {
+            player,
+            shots: Vec::new(),
+            rocks,
+            obstacles,
+            score: 0,
+            level: 1,
+            wave_seed,
+            time: 0.,
+            robot,
+            playback: None,
+            remote: None,
+            auton: Auton::new(default_auton_path(), PursuitParams::default()),
+            routine: None,
+            auton_enabled: false,
+            benchmark: None,
+            disturbances: config.disturbances.disturbances(),
+            others: Vec::new(),
+            physics: PhysicsMode::Dynamics,
+            drive_mode: DriveMode::Arcade,
+            control_mode: ControlMode::Voltage,
+            boundary: Boundary::Walls,
+            velocity_controller,
+            sensors: SensorSuite::new(&config.sensors.noise()),
+            scheduler: Scheduler::new(config.timing.clone()),
+            command: zero_volts(),
+            command_delay: create_command_delay(&config.timing),
+            max_voltage,
+            px_per_meter,
+            field_width,
+            field_height,
+            wall_restitution,
+            overheat: config
+                .thermal
+                .as_ref()
+                .map(ThermalConfig::warning_temperature),
+            motors_hot: false,
+            player_shot_timeout: 0.0,
+            shot_speed: SHOT_SPEED,
+            events: Vec::new(),
+        }
+    }
+
+    /// Advances the world by `dt` seconds. In dynamics mode the robot takes as
+    /// many physics steps as fit in `dt`, carrying any remainder to the next call.
+    /// Any other robots get no input.
+    pub fn step(&mut self, input: &InputState, dt: f32) {
+        self.step_all(input, &[], dt);
+    }
+
+    /// `step`, with `others[i]` driving `others()[i]`. Robots past the end of
+    /// `others` get no input.
+    pub fn step_all(&mut self, input: &InputState, others: &[InputState], dt: f32) {
+        self.time += f64::from(dt);
+        let idle = InputState::default();
+        match self.physics {
+            PhysicsMode::Dynamics => {
+                let physics_dt = self.scheduler.timing.physics_dt;
+                for _ in 0..self.scheduler.physics_steps(f64::from(dt)) {
+                    let control = self.scheduler.control_due();
+                    if control {
+                        self.sensors.measure(&self.robot);
+                        self.command = self.robot_command(input);
+                    }
+                    let volts = self.command_delay.push(self.command);
+                    let push = self.disturbances.update(
+                        self.robot.vel(),
+                        self.robot.ddmr().params().m,
+                        physics_dt * dimensioned::si::S,
+                    );
+                    self.robot.apply_disturbance(push.force, push.torque);
+                    self.robot.observe(volts);
+                    let finished = match self.playback {
+                        Some(ref mut run) => run.advance(physics_dt),
+                        None => false,
+                    };
+                    if finished {
+                        self.playback = None;
+                        self.events.push(SimEvent::PlaybackFinished);
+                    }
+                    update_robot_actor(
+                        &mut self.player,
+                        self.robot.vel().into(),
+                        self.px_per_meter,
+                        physics_dt as f32,
+                    );
+                    constrain_robot_to_field(
+                        &mut self.player,
+                        &mut self.robot,
+                        self.boundary,
+                        self.wall_restitution,
+                        self.field_width,
+                        self.field_height,
+                    );
+                    constrain_robot_to_obstacles(
+                        &mut self.player,
+                        &mut self.robot,
+                        &self.obstacles,
+                    );
+                    for (i, other) in self.others.iter_mut().enumerate() {
+                        if control {
+                            let input = others.get(i).unwrap_or(&idle);
+                            other.sensors.measure(&other.model);
+                            other.command = input_command(
+                                &other.model,
+                                &mut other.controller,
+                                &other.sensors.readings(),
+                                input,
+                                self.drive_mode,
+                                self.control_mode,
+                                other.max_voltage,
+                            );
+                        }
+                        let volts = other.command_delay.push(other.command);
+                        other.model.observe(volts);
+                        update_robot_actor(
+                            &mut other.actor,
+                            other.model.vel().into(),
+                            self.px_per_meter,
+                            physics_dt as f32,
+                        );
+                        constrain_robot_to_field(
+                            &mut other.actor,
+                            &mut other.model,
+                            self.boundary,
+                            self.wall_restitution,
+                            self.field_width,
+                            self.field_height,
+                        );
+                        constrain_robot_to_obstacles(
+                            &mut other.actor,
+                            &mut other.model,
+                            &self.obstacles,
+                        );
+                    }
+                }
+                let hot = self.motors_overheated();
+                if hot && !self.motors_hot {
+                    self.events.push(SimEvent::MotorOverheated);
+                }
+                self.motors_hot = hot;
+            }
+            PhysicsMode::Arcade => {
+                // Update the player state based on the user input.
+                player_handle_input(&mut self.player, input, dt);
+
+                // Update the physics for all actors.
+                // First the player...
+                update_actor_position(&mut self.player, dt);
+                wrap_actor_position(&mut self.player, self.field_width, self.field_height);
+
+                // and any other robots.
+                for (i, other) in self.others.iter_mut().enumerate() {
+                    player_handle_input(&mut other.actor, others.get(i).unwrap_or(&idle), dt);
+                    update_actor_position(&mut other.actor, dt);
+                    wrap_actor_position(&mut other.actor, self.field_width, self.field_height);
+                }
+            }
+        }
+
+        let (cooldown, fire) = fire_timer(self.player_shot_timeout, dt, input.fire);
+        self.player_shot_timeout = cooldown;
+        if fire {
+            self.fire_player_shot();
+        }
+
+        // Then the shots...
+        for act in &mut self.shots {
+            update_actor_position(act, dt);
+            wrap_actor_position(act, self.field_width, self.field_height);
+            handle_timed_life(act, dt);
+        }
+
+        // And finally the rocks.
+        for act in &mut self.rocks {
+            update_actor_position(act, dt);
+            wrap_actor_position(act, self.field_width, self.field_height);
+        }
+
+        // Handle the results of things moving:
+        // collision detection and object death.
+        let had_rocks = !self.rocks.is_empty();
+        self.handle_collisions();
+
+        self.clear_dead_stuff();
+        if had_rocks && self.rocks.is_empty() {
+            self.next_level();
+        }
+    }
+
+    /// Spawns the next wave, one rock bigger and faster than the last
+    fn next_level(&mut self) {
+        self.level += 1;
+        let (rocks, seed) = create_wave(self.wave_seed, self.level, self.player.pos);
+        self.rocks = rocks;
+        self.wave_seed = seed;
+        self.events.push(SimEvent::LevelCleared);
+    }
+
+    /// A new motor command from whatever is driving the robot: playback, then
+    /// a remote program, then the auton, then the input
+    fn robot_command(&mut self, input: &InputState) -> dynamics::LR<dimensioned::si::Volt<f64>> {
+        if let Some(ref mut bench) = self.benchmark {
+            if !bench.finished() {
+                let volts = bench.update(self.robot.vel(), self.robot.currents());
+                if bench.finished() {
+                    self.events.push(SimEvent::BenchmarkFinished);
+                }
+                return volts;
+            }
+        }
+        if let (None, Some(volts)) = (self.playback.as_ref(), self.remote) {
+            return volts;
+        }
+        let sensors = self.sensors.readings();
+        match self.playback {
+            Some(ref run) => run.command(&mut self.velocity_controller, &sensors),
+            None if self.auton_enabled => {
+                let (volts, finished) = match self.routine {
+                    Some(ref mut run) => {
+                        let dt = self.scheduler.timing.control_period * dimensioned::si::S;
+                        routine_command(
+                            run,
+                            dt,
+                            &self.robot,
+                            &mut self.velocity_controller,
+                            &sensors,
+                        )
+                    }
+                    None => {
+                        let pose = actor_pose(&self.player, self.px_per_meter);
+                        auton_command(
+                            &mut self.auton,
+                            pose,
+                            &self.robot,
+                            &mut self.velocity_controller,
+                            &sensors,
+                        )
+                    }
+                };
+                if finished {
+                    // done, so the input has the robot again from the next update
+                    self.auton_enabled = false;
+                    self.events.push(SimEvent::AutonFinished);
+                }
+                volts
+            }
+            None => input_command(
+                &self.robot,
+                &mut self.velocity_controller,
+                &sensors,
+                input,
+                self.drive_mode,
+                self.control_mode,
+                self.max_voltage,
+            ),
+        }
+    }
+
+    /// Returns the events since the last call
+    pub fn take_events(&mut self) -> Vec<SimEvent> {
+        std::mem::replace(&mut self.events, Vec::new())
+    }
+
+    /// Launches a shot along the player's heading, carried along by the
+    /// player's own velocity. In dynamics mode the robot takes the recoil
+    /// over its next physics step.
+    fn fire_player_shot(&mut self) {
+        let player = &self.player;
+        let mut shot = create_shot();
+        shot.pos = player.pos;
+        shot.facing = player.facing;
+        shot.velocity = vec_from_angle(shot.facing) * self.shot_speed + player.velocity;
+
+        if self.physics == PhysicsMode::Dynamics {
+            let recoil = RECOIL_IMPULSE / self.scheduler.timing.physics_dt;
+            self.robot.push(-recoil * dimensioned::si::N);
+        }
+        self.shots.push(shot);
+        self.events.push(SimEvent::ShotFired);
+    }
+
+    fn clear_dead_stuff(&mut self) {
+        self.shots.retain(|s| s.life > 0.0);
+        self.rocks.retain(|r| r.life > 0.0);
+    }
+
+    fn handle_collisions(&mut self) {
+        for rock in &mut self.rocks {
+            let pdistance = rock.pos - self.player.pos;
+            if pdistance.norm() < (self.player.bbox_size + rock.bbox_size) {
+                self.player.life -= ROCK_DAMAGE;
+                rock.life = 0.0;
+                self.events.push(SimEvent::PlayerHit);
+                continue;
+            }
+            for shot in &mut self.shots {
+                let distance = shot.pos - rock.pos;
+                if distance.norm() < (shot.bbox_size + rock.bbox_size) {
+                    shot.life = 0.0;
+                    rock.life = 0.0;
+                    self.score += ROCK_SCORE;
+                    self.events.push(SimEvent::RockDestroyed);
+                }
+            }
+        }
+    }
+
+    /// Whether either of the robot's motors is past the warning temperature
+    fn motors_overheated(&self) -> bool {
+        match self.overheat {
+            Some(limit) => {
+                let t = self.robot.temperatures();
+                t.l > limit || t.r > limit
+            }
+            None => false,
+        }
+    }
+
+    /// Puts the robot back at the center of the field, at rest
+    fn reset_robot(&mut self) {
+        self.robot.reset();
+        self.disturbances.reset();
+        self.motors_hot = false;
+        self.velocity_controller.reset();
+        self.sensors.reset();
+        self.scheduler.reset();
+        self.command = zero_volts();
+        self.command_delay.fill(zero_volts());
+        self.player.pos = Point2::origin();
+        self.player.facing = 0.;
+        self.player.velocity = na::zero();
+        self.player.ang_vel = 0.;
+        for other in &mut self.others {
+            other.reset();
+        }
+    }
+
+    /// Resets the robot and drives it from `playback` instead of the input
+    /// until the file runs out. Only runs in dynamics mode.
+    pub fn start_playback(&mut self, playback: Playback) {
+        self.reset_robot();
+        self.playback = Some(PlaybackRun { playback, time: 0. });
+    }
+
+    /// Resets the robot and the score, and clears the shots in flight
+    pub fn reset(&mut self) {
+        self.reset_robot();
+        self.playback = None;
+        self.benchmark = None;
+        self.shots.clear();
+        self.score = 0;
+        self.player_shot_timeout = 0.0;
+    }
+
+    /// Hands the robot to the auton, starting from the first waypoint or step,
+    /// or back to the input. The auton hands it back itself once it's done.
+    /// Playback takes priority over both.
+    pub fn set_auton(&mut self, enabled: bool) {
+        self.auton.restart();
+        if let Some(ref mut run) = self.routine {
+            run.restart();
+        }
+        self.velocity_controller.reset();
+        self.auton_enabled = enabled;
+    }
+
+    /// Has the auton run `routine` instead of following its waypoints, or go
+    /// back to them for `None`. Takes effect the next time it's enabled.
+    pub fn set_routine(&mut self, routine: Option<Routine>) {
+        self.routine = routine.map(|r| RoutineRun::new(r, RoutineParams::default()));
+        self.set_auton(false);
+    }
+
+    pub fn routine(&self) -> Option<&RoutineRun> {
+        self.routine.as_ref()
+    }
+
+    pub fn auton_enabled(&self) -> bool {
+        self.auton_enabled
+    }
+
+    /// Resets the robot and runs the step benchmark on it at full voltage,
+    /// ahead of playback, the auton and the input. Only runs in dynamics mode.
+    pub fn start_benchmark(&mut self) {
+        use dimensioned::si::S;
+        self.reset_robot();
+        self.playback = None;
+        self.auton_enabled = false;
+        let dt = self.scheduler.timing.control_period * S;
+        self.benchmark = Some(StepBenchmark::new(self.max_voltage, dt));
+    }
+
+    /// Stops a running benchmark and forgets it. A finished one is kept.
+    pub fn cancel_benchmark(&mut self) {
+        if self.benchmark_running() {
+            self.benchmark = None;
+        }
+    }
+
+    pub fn benchmark_running(&self) -> bool {
+        self.benchmark.as_ref().map_or(false, |b| !b.finished())
+    }
+
+    /// The running or last finished benchmark
+    pub fn benchmark(&self) -> Option<&StepBenchmark> {
+        self.benchmark.as_ref()
+    }
+
+    /// Shoves the player's robot with the configured force and torque, in
+    /// dynamics mode
+    pub fn shove(&mut self) {
+        self.disturbances.start_shove();
+    }
+
+    pub fn disturbances(&self) -> &Disturbances {
+        &self.disturbances
+    }
+
+    pub fn auton(&self) -> &Auton {
+        &self.auton
+    }
+
+    pub fn stop_playback(&mut self) {
+        self.playback = None;
+    }
+
+    /// Drives the robot with `volts` in place of the auton and the input, taken
+    /// up at the next control update like any other command. They're clipped
+    /// to `max_voltage` like the rest. `None` hands it back. Replays don't
+    /// record it.
+    pub fn set_remote_command(&mut self, volts: Option<dynamics::LR<dimensioned::si::Volt<f64>>>) {
+        let max = self.max_voltage;
+        self.remote = volts.map(|v| dynamics::clip_volts(v, max));
+    }
+
+    pub fn playing_back(&self) -> bool {
+        self.playback.is_some()
+    }
+
+    pub fn player(&self) -> &Actor {
+        &self.player
+    }
+
+    /// Adds a robot built from `config` to the right of the last one, at rest
+    /// and facing up, stepping at this simulation's timing. Returns its index
+    /// in `others`.
+    pub fn add_robot(&mut self, config: &RobotConfig) -> usize {
+        let i = self.others.len();
+        let x = ROBOT_SPACING * (i + 1) as f64 * f64::from(self.px_per_meter);
+        let start = Point2::new(x as f32, 0.);
+        let mut robot = Robot::new(config, &self.scheduler.timing, start, self.px_per_meter);
+        robot.model.set_brake_mode(self.brake_mode());
+        self.others.push(robot);
+        i
+    }
+
+    /// The robots besides the player's, in the order they were added
+    pub fn others(&self) -> &[Robot] {
+        &self.others
+    }
+
+    pub fn shots(&self) -> &[Actor] {
+        &self.shots
+    }
+
+    pub fn rocks(&self) -> &[Actor] {
+        &self.rocks
+    }
+
+    /// Only the drive model collides with these
+    pub fn obstacles(&self) -> &[Obstacle] {
+        &self.obstacles
+    }
+
+    pub fn add_obstacle(&mut self, obstacle: Obstacle) {
+        self.obstacles.push(obstacle);
+    }
+
+    /// Removes the obstacles covering `point`, returning whether there were any
+    pub fn remove_obstacles_at(&mut self, point: Point2) -> bool {
+        let before = self.obstacles.len();
+        self.obstacles.retain(|o| !o.contains(point));
+        self.obstacles.len() != before
+    }
+
+    pub fn score(&self) -> i32 {
+        self.score
+    }
+
+    pub fn level(&self) -> i32 {
+        self.level
+    }
+
+    pub fn robot(&self) -> &dynamics::ActuatedDDMRModel {
+        &self.robot
+    }
+
+    pub fn physics(&self) -> PhysicsMode {
+        self.physics
+    }
+
+    pub fn set_physics(&mut self, physics: PhysicsMode) {
+        self.physics = physics;
+    }
+
+    pub fn drive_mode(&self) -> DriveMode {
+        self.drive_mode
+    }
+
+    pub fn set_drive_mode(&mut self, mode: DriveMode) {
+        self.drive_mode = mode;
+    }
+
+    /// Only applies to the drive model, the arcade physics always wraps
+    pub fn boundary(&self) -> Boundary {
+        self.boundary
+    }
+
+    pub fn set_boundary(&mut self, boundary: Boundary) {
+        self.boundary = boundary;
+    }
+
+    pub fn px_per_meter(&self) -> f32 {
+        self.px_per_meter
+    }
+
+    pub fn field_size(&self) -> (f32, f32) {
+        (self.field_width, self.field_height)
+    }
+
+    pub fn control_mode(&self) -> ControlMode {
+        self.control_mode
+    }
+
+    /// Switching modes starts the velocity loop over, so it doesn't act on
+    /// history from the last time it ran
+    pub fn set_control_mode(&mut self, mode: ControlMode) {
+        self.velocity_controller.reset();
+        self.control_mode = mode;
+    }
+
+    /// The mode on the left side; `set_brake_mode` always sets both the same
+    pub fn brake_mode(&self) -> dynamics::BrakeMode {
+        self.robot.brake_mode().l
+    }
+
+    /// Sets it on every robot
+    pub fn set_brake_mode(&mut self, mode: dynamics::BrakeMode) {
+        self.robot.set_brake_mode(mode);
+        for other in &mut self.others {
+            other.model.set_brake_mode(mode);
+        }
+    }
+
+    /// Wheel speeds as the velocity loop last saw them, `timing().sensor_delay`
+    /// behind the model's own
+    pub fn measured_wheels(&self) -> dynamics::LR<dimensioned::si::Hertz<f64>> {
+        self.velocity_controller.measured()
+    }
+
+    /// What the sensors read at the last control update, before any delay.
+    /// The robot itself is drawn from the model's true state.
+    pub fn sensor_readings(&self) -> SensorReadings {
+        self.sensors.readings()
+    }
+
+    /// Swaps the noise on the sensors, starting it over from `noise.seed`
+    pub fn set_sensor_noise(&mut self, noise: &Noise) {
+        self.sensors = SensorSuite::new(noise);
+    }
+
+    pub fn timing(&self) -> &TimingConfig {
+        &self.scheduler.timing
+    }
+
+    /// What full stick commands, from `RobotConfig::max_voltage`
+    pub fn max_voltage(&self) -> dimensioned::si::Volt<f64> {
+        self.max_voltage
+    }
+
+    /// Seconds simulated so far, which stands still while paused and runs
+    /// faster or slower with the time scale
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// Rebuilds the robots to step at the new physics rate, and resets them
+    pub fn set_timing(&mut self, timing: TimingConfig) {
+        use dimensioned::si::S;
+        self.robot = self.robot.with_dt(timing.physics_dt * S);
+        self.velocity_controller =
+            create_velocity_controller(&self.robot, &timing, self.max_voltage);
+        self.command_delay = create_command_delay(&timing);
+        for other in &mut self.others {
+            other.model = other.model.with_dt(timing.physics_dt * S);
+            other.controller = create_velocity_controller(&other.model, &timing, other.max_voltage);
+            other.command_delay = create_command_delay(&timing);
+        }
+        self.scheduler = Scheduler::new(timing);
+        self.benchmark = None;
+        self.reset_robot();
+    }
+
+    /// Rebuilds the robot from `config` and carries on at the same speed and
+    /// pose, for tuning it while it drives. The timing, brake mode, arena and
+    /// obstacles stay as they are.
+    pub fn set_robot_config(&mut self, config: &RobotConfig) {
+        use dimensioned::si::{M, S, V};
+        let state = self.robot.state();
+        let brake_modes = self.robot.brake_mode();
+        let controller = self.velocity_controller.state();
+        self.robot = config.build(self.scheduler.timing.physics_dt * S);
+        self.robot.restore(&state);
+        self.robot.set_brake_modes(brake_modes);
+        self.max_voltage = config.max_voltage * V;
+        self.velocity_controller =
+            create_velocity_controller(&self.robot, &self.scheduler.timing, self.max_voltage);
+        self.velocity_controller.restore(&controller);
+        self.overheat = config
+            .thermal
+            .as_ref()
+            .map(ThermalConfig::warning_temperature);
+        self.player.bbox_size = *(self.robot.ddmr().params().L / M) as f32 * self.px_per_meter;
+        let progress = self.disturbances.progress();
+        self.disturbances = config.disturbances.disturbances();
+        self.disturbances.resume(progress);
+    }
+
+    /// Speed of new shots relative to the player, in px/s
+    pub fn shot_speed(&self) -> f32 {
+        self.shot_speed
+    }
+
+    pub fn set_shot_speed(&mut self, speed: f32) {
+        self.shot_speed = speed;
+    }
+
+    pub fn game_over(&self) -> bool {
+        self.player.life <= 0.0
+    }
+
+    pub fn snapshot(&self) -> SimSnapshot {
+        SimSnapshot {
+            player: (&self.player).into(),
+            shots: self.shots.iter().map(ActorState::from).collect(),
+            rocks: self.rocks.iter().map(ActorState::from).collect(),
+            obstacles: self.obstacles.clone(),
+            score: self.score,
+            level: self.level,
+            wave_seed: self.wave_seed,
+            time: self.time,
+            robot: self.robot.state(),
+            controller: self.velocity_controller.state(),
+            command: dynamics::LR {
+                l: self.command.l.value_unsafe,
+                r: self.command.r.value_unsafe,
+            },
+            pending_commands: pending_volts(&self.command_delay),
+            step_backlog: self.scheduler.backlog,
+            until_control: self.scheduler.until_control,
+            auton_waypoint: self.auton.current(),
+            auton_enabled: self.auton_enabled,
+            sensor_samples: self.sensors.samples(),
+            routine: self.routine.as_ref().map(|run| run.progress()),
+            others: self.others.iter().map(Robot::state).collect(),
+            disturbances: self.disturbances.progress(),
+            physics: self.physics,
+            drive_mode: self.drive_mode,
+            control_mode: self.control_mode,
+            brake_mode: self.brake_mode(),
+            boundary: self.boundary,
+            player_shot_timeout: self.player_shot_timeout,
+            shot_speed: self.shot_speed,
+        }
+    }
+
+    /// Puts the world back the way it was when `snapshot` was taken, so that
+    /// the same inputs from here give the same run bit for bit. Stops any
+    /// playback and drops pending events.
+    pub fn restore(&mut self, snapshot: &SimSnapshot) {
+        use dimensioned::si::V;
+        self.player = snapshot.player.into();
+        self.shots = snapshot.shots.iter().map(|&a| a.into()).collect();
+        self.rocks = snapshot.rocks.iter().map(|&a| a.into()).collect();
+        self.obstacles = snapshot.obstacles.clone();
+        self.score = snapshot.score;
+        self.level = snapshot.level;
+        self.wave_seed = snapshot.wave_seed;
+        self.time = snapshot.time;
+        self.robot.restore(&snapshot.robot);
+        self.velocity_controller.restore(&snapshot.controller);
+        self.command = dynamics::LR {
+            l: snapshot.command.l * V,
+            r: snapshot.command.r * V,
+        };
+        restore_pending_volts(&mut self.command_delay, &snapshot.pending_commands);
+        self.scheduler.backlog = snapshot.step_backlog;
+        self.scheduler.until_control = snapshot.until_control;
+        self.auton.resume(snapshot.auton_waypoint);
+        self.auton_enabled = snapshot.auton_enabled;
+        self.sensors.resume(snapshot.sensor_samples);
+        if let (Some(run), Some(progress)) = (self.routine.as_mut(), snapshot.routine) {
+            run.resume(progress);
+        }
+        for (other, state) in self.others.iter_mut().zip(&snapshot.others) {
+            other.restore(state);
+        }
+        self.disturbances.resume(snapshot.disturbances);
+        self.physics = snapshot.physics;
+        self.drive_mode = snapshot.drive_mode;
+        self.control_mode = snapshot.control_mode;
+        self.set_brake_mode(snapshot.brake_mode);
+        self.boundary = snapshot.boundary;
+        self.player_shot_timeout = snapshot.player_shot_timeout;
+        self.shot_speed = snapshot.shot_speed;
+        self.playback = None;
+        self.benchmark = None;
+        self.events.clear();
+        self.motors_hot = self.motors_overheated();
+    }
+}
+
+/// A fixed input sequence for headless runs: drive forward, arc to the right,
+/// spin in place, then coast.
+pub fn scripted_input(step: usize) -> InputState {
+    let t = step as f64 / SIM_FPS as f64;
+    let mut input = InputState::default();
+    if t < 2.0 {
+        input.yaxis = 1.0;
+    } else if t < 4.0 {
+        input.yaxis = 1.0;
+        input.xaxis = 0.5;
+    } else if t < 6.0 {
+        input.xaxis = -1.0;
+    }
+    input
+}
+
+/// Steps a fresh simulation with `scripted_input` and prints where the robot ended up,
+/// optionally logging every step as CSV
+pub fn run_headless(steps: usize, telemetry: Option<Box<dyn Write>>) {
+    let mut sim = Simulation::new(640., 480.);
+    let mut telemetry =
+        telemetry.map(|out| Telemetry::new(out).expect("Could not write telemetry"));
+    let dt = 1.0 / SIM_FPS as f32;
+    for i in 0..steps {
+        sim.step(&scripted_input(i), dt);
+        if let Some(ref mut t) = telemetry {
+            t.record(sim.robot(), f64::from(dt))
+                .expect("Could not write telemetry");
+        }
+    }
+    if let Some(mut t) = telemetry {
+        t.flush().expect("Could not write telemetry");
+    }
+    print_end_state(steps, sim.robot());
+}
+
+fn print_end_state(steps: usize, robot: &dynamics::ActuatedDDMRModel) {
+    let pose = robot.pose();
+    let vel = robot.vel();
+    println!(
+        "after {} steps: x = {}, y = {}, theta = {}",
+        steps, pose.x, pose.y, pose.theta
+    );
+    println!("lin = {}, ang = {}", vel.lin, vel.ang);
+}
+
+/// Feeds a voltage script straight into a robot built from `config`, with no
+/// game around it: no walls, no control period, just `steps` steps of
+/// `timing.physics_dt` from time zero, each holding the script's row at the
+/// start of the step. Returns the robot at the end, after printing where it
+/// ended up and optionally logging every step as CSV.
+///
+/// Panics on a velocity script, which needs the game's velocity loop. Play
+/// those with `Simulation::start_playback`.
+pub fn run_script(
+    config: &RobotConfig,
+    script: &Playback,
+    steps: usize,
+    telemetry: Option<Box<dyn Write>>,
+) -> dynamics::ActuatedDDMRModel {
+    use dimensioned::si::{S, V};
+    assert_eq!(
+        script.kind(),
+        PlaybackKind::Voltage,
+        "only voltage scripts run without the game"
+    );
+    let dt = config.timing.physics_dt;
+    let mut robot = config.build(dt * S);
+    let mut telemetry =
+        telemetry.map(|out| Telemetry::new(out).expect("Could not write telemetry"));
+    for step in 0..steps {
+        let (l, r) = script.sample(step as f64 * dt);
+        robot.observe(dynamics::LR { l: l * V, r: r * V });
+        if let Some(ref mut t) = telemetry {
+            t.record(&robot, dt).expect("Could not write telemetry");
+        }
+    }
+    if let Some(mut t) = telemetry {
+        t.flush().expect("Could not write telemetry");
+    }
+    print_end_state(steps, &robot);
+    robot
+}
+
+#[test]
+fn headless_long_run() {
+    let mut sim = Simulation::new(640., 480.);
+    let dt = 1.0 / SIM_FPS as f32;
+    for i in 0..6000 {
+        sim.step(&scripted_input(i), dt);
+    }
+    let pose = sim.robot().pose();
+    assert!(pose.x.value_unsafe.is_finite() && pose.y.value_unsafe.is_finite());
+    assert!(pose.theta.is_finite());
+    assert!(sim.player().pos.x.abs() <= 320. && sim.player().pos.y.abs() <= 240.);
+}
+
+#[test]
+fn headless_drives_into_wall() {
+    let mut sim = Simulation::new(640., 480.);
+    sim.rocks.clear();
+    let dt = 1.0 / SIM_FPS as f32;
+    let input = InputState {
+        yaxis: 1.0,
+        ..Default::default()
+    };
+    for _ in 0..5 * SIM_FPS {
+        sim.step(&input, dt);
+    }
+    let limit = 240. - sim.player().bbox_size;
+    assert_eq!(sim.player().pos.y, limit);
+    assert_eq!(sim.robot().vel().lin, 0. * dimensioned::si::MPS);
+}
+
+#[test]
+fn playback_matches_observe() {
+    use dimensioned::si::V;
+    let mut sim = Simulation::new(10000., 10000.);
+    sim.rocks.clear();
+    let playback = Playback::from_csv("time,volts_l,volts_r\n0,6,6\n2,6,6\n").unwrap();
+    sim.start_playback(playback);
+    let dt = 1.0 / SIM_FPS as f32;
+    for _ in 0..2 * SIM_FPS {
+        sim.step(&InputState::default(), dt);
+    }
+    let mut model = create_robot(&RobotConfig::default());
+    for _ in 0..2 * SIM_FPS {
+        model.observe(dynamics::LR {
+            l: 6. * V,
+            r: 6. * V,
+        });
+    }
+    assert_eq!(sim.robot().vel().lin, model.vel().lin);
+    assert!(sim.robot().vel().lin > 0. * dimensioned::si::MPS);
+}
+
+#[test]
+fn auton_drives_square() {
+    let mut sim = Simulation::new(640., 480.);
+    sim.rocks.clear();
+    sim.set_auton(true);
+    let dt = 1.0 / SIM_FPS as f32;
+    let mut steps = 0;
+    while !sim.auton().finished() && steps < 10 * SIM_FPS {
+        sim.step(&InputState::default(), dt);
+        steps += 1;
+    }
+    assert!(sim.auton().finished());
+    assert!(sim.take_events().contains(&SimEvent::AutonFinished));
+    // ended within tolerance of the start, give or take the step it took to notice
+    let pose = actor_pose(sim.player(), sim.px_per_meter);
+    let dist = pose.x.value_unsafe.hypot(pose.y.value_unsafe);
+    assert!(
+        dist < sim.auton().params().tolerance.value_unsafe + 0.02,
+        "{}",
+        dist
+    );
+    sim.step(&InputState::default(), dt);
+    let volts = sim.robot().last_observation().voltage;
+    assert_eq!(volts.l, 0. * dimensioned::si::V);
+    assert_eq!(volts.r, 0. * dimensioned::si::V);
+}
+
+#[test]
+fn headless_drives_forward() {
+    let mut sim = Simulation::new(640., 480.);
+    sim.rocks.clear();
+    let dt = 1.0 / SIM_FPS as f32;
+    let input = InputState {
+        yaxis: 1.0,
+        ..Default::default()
+    };
+    for _ in 0..30 {
+        sim.step(&input, dt);
+    }
+    // facing starts at screen up
+    assert!(sim.player().pos.y > 0.);
+    assert!(sim.player().pos.x.abs() < 1e-3);
+    assert!(sim.robot().pose().x > 0. * dimensioned::si::M);
+}
+
+#[cfg(test)]
+fn timed_config(physics_dt: f64, control_period: f64) -> RobotConfig {
+    RobotConfig {
+        timing: TimingConfig {
+            physics_dt,
+            control_period,
+            ..TimingConfig::default()
+        },
+        ..RobotConfig::default()
+    }
+}
+
+#[test]
+fn physics_dt_converges() {
+    // same commands every frame, so only the physics rate differs
+    let drive = |physics_dt: f64| {
+        let mut sim = Simulation::with_config(10000., 10000., &timed_config(physics_dt, 1. / 60.));
+        sim.rocks.clear();
+        let input = InputState {
+            yaxis: 1.0,
+            xaxis: 0.5,
+            ..Default::default()
+        };
+        for _ in 0..SIM_FPS {
+            sim.step(&input, 1.0 / SIM_FPS as f32);
+        }
+        sim.robot().pose()
+    };
+    let reference = drive(1. / 6000.);
+    let error = |physics_dt: f64| {
+        let p = drive(physics_dt);
+        (p.x - reference.x)
+            .value_unsafe
+            .hypot((p.y - reference.y).value_unsafe)
+    };
+    let errors: Vec<f64> = [1. / 60., 1. / 300., 1. / 1200.]
+        .iter()
+        .map(|&dt| error(dt))
+        .collect();
+    assert!(
+        errors[0] > errors[1] && errors[1] > errors[2],
+        "{:?}",
+        errors
+    );
+    assert!(errors[2] < 0.01, "{:?}", errors);
+}
+
+#[test]
+fn control_period_holds_commands() {
+    let mut sim = Simulation::with_config(10000., 10000., &timed_config(0.001, 0.02));
+    sim.rocks.clear();
+    let input = |step: usize| InputState {
+        yaxis: if (step / 7) % 2 == 0 { 1.0 } else { -1.0 },
+        ..Default::default()
+    };
+    for step in 0..100 {
+        // one physics step per call
+        sim.step(&input(step), 0.001);
+        // the command only changes every 20 steps, from the input at that step
+        let held = input(step / 20 * 20).yaxis * sim.max_voltage();
+        let volts = sim.robot().last_observation().voltage;
+        assert_eq!(volts.l, held, "step {}", step);
+        assert_eq!(volts.r, held, "step {}", step);
+    }
+}
+
+#[test]
+fn command_delay_holds_robot_still() {
+    use dimensioned::si::{MPS, V};
+    let mut config = timed_config(0.005, 0.005);
+    config.timing.command_delay = 0.05;
+    let mut sim = Simulation::with_config(10000., 10000., &config);
+    sim.rocks.clear();
+    let input = InputState {
+        yaxis: 1.0,
+        ..Default::default()
+    };
+    // ten physics steps of zero volts before the first command arrives
+    for step in 0..10 {
+        sim.step(&input, 0.005);
+        assert_eq!(
+            sim.robot().last_observation().voltage.l,
+            0. * V,
+            "step {}",
+            step
+        );
+        assert_eq!(sim.robot().vel().lin, 0. * MPS, "step {}", step);
+    }
+    sim.step(&input, 0.005);
+    assert_eq!(sim.robot().last_observation().voltage.l, sim.max_voltage());
+    for _ in 0..4 {
+        sim.step(&input, 0.005);
+    }
+    assert!(sim.robot().vel().lin > 0. * MPS);
+}
+
+#[test]
+fn hot_motor_warns_once() {
+    let config = RobotConfig {
+        thermal: Some(ThermalConfig {
+            heat_capacity: 150.,
+            resistance: 2.,
+            ambient: 25.,
+            warning: 25.5,
+            cutoff: None,
+            hysteresis: 10.,
+        }),
+        ..RobotConfig::default()
+    };
+    let mut sim = Simulation::with_config(10000., 10000., &config);
+    sim.rocks.clear();
+    let input = InputState {
+        yaxis: 1.0,
+        ..Default::default()
+    };
+    let mut warnings = 0;
+    for _ in 0..SIM_FPS {
+        sim.step(&input, 1.0 / SIM_FPS as f32);
+        warnings += sim
+            .take_events()
+            .iter()
+            .filter(|&&e| e == SimEvent::MotorOverheated)
+            .count();
+    }
+    assert_eq!(warnings, 1);
+}
+
+#[test]
+fn full_stick_commands_max_voltage() {
+    use dimensioned::si::V;
+    let config = RobotConfig {
+        max_voltage: 6.,
+        ..timed_config(0.001, 0.001)
+    };
+    let mut sim = Simulation::with_config(10000., 10000., &config);
+    sim.rocks.clear();
+    let input = InputState {
+        yaxis: 1.0,
+        xaxis: 0.5,
+        ..Default::default()
+    };
+    sim.step(&input, 0.001);
+    // arcade mixing saturates the left side and takes half off the right
+    let volts = sim.robot().last_observation().voltage;
+    assert_eq!((volts.l, volts.r), (6. * V, 3. * V));
+}
+
+#[test]
+fn recoil_slows_coasting_robot() {
+    let dt = 1.0 / SIM_FPS as f32;
+    let drive = InputState {
+        yaxis: 1.,
+        ..InputState::default()
+    };
+    let coast = |fire: bool| {
+        let mut sim = Simulation::with_seed(640., 480., &RobotConfig::default(), 1);
+        sim.rocks.clear();
+        for _ in 0..SIM_FPS / 2 {
+            sim.step(&drive, dt);
+        }
+        let trigger = InputState {
+            fire,
+            ..InputState::default()
+        };
+        sim.step(&trigger, dt);
+        // the kick lands on the next step
+        for _ in 0..2 {
+            sim.step(&InputState::default(), dt);
+        }
+        sim.robot().vel().lin
+    };
+    let (quiet, fired) = (coast(false), coast(true));
+    assert!(fired > 0. * dimensioned::si::MPS);
+    // about the impulse over the mass
+    let mass = RobotConfig::default().chassis.mass;
+    let slowed = (quiet - fired).value_unsafe;
+    assert!(
+        slowed > 0.5 * RECOIL_IMPULSE / mass && slowed < 1.5 * RECOIL_IMPULSE / mass,
+        "{}",
+        slowed
+    );
+}
+
+#[test]
+fn shots_fire_and_expire() {
+    let mut sim = Simulation::new(640., 480.);
+    sim.rocks.clear();
+    let dt = 1.0 / SIM_FPS as f32;
+    let fire = InputState {
+        fire: true,
+        ..Default::default()
+    };
+    sim.step(&fire, dt);
+    assert_eq!(sim.shots().len(), 1);
+    assert!(sim.take_events().contains(&SimEvent::ShotFired));
+    // straight up, plus however the player was moving
+    let expected = Vector2::new(0., SHOT_SPEED) + sim.player().velocity;
+    assert!((sim.shots()[0].velocity - expected).norm() < 1e-3);
+
+    let idle = InputState::default();
+    for _ in 0..(SHOT_LIFE * SIM_FPS as f32) as usize + 1 {
+        sim.step(&idle, dt);
+    }
+    assert!(sim.shots().is_empty());
+}
+
+#[test]
+fn snapshot_restores_exactly() {
+    let mut sim = Simulation::new(640., 480.);
+    let dt = 1.0 / SIM_FPS as f32;
+    let input = |step: usize| InputState {
+        fire: step % 40 < 20,
+        ..scripted_input(step)
+    };
+    for step in 0..100 {
+        sim.step(&input(step), dt);
+    }
+    let mut saved = Vec::new();
+    sim.snapshot().save(&mut saved).unwrap();
+    let run = |sim: &mut Simulation| -> Vec<SimSnapshot> {
+        (100..200)
+            .map(|step| {
+                sim.step(&input(step), dt);
+                sim.snapshot()
+            })
+            .collect()
+    };
+    let first = run(&mut sim);
+    sim.restore(&SimSnapshot::load(&saved[..]).unwrap());
+    let second = run(&mut sim);
+    assert_eq!(first, second);
+}
+
+#[test]
+fn heavier_robot_carries_on_sluggishly() {
+    let config = RobotConfig::default();
+    let dt = 1.0 / SIM_FPS as f32;
+    let forward = InputState {
+        yaxis: 1.0,
+        ..Default::default()
+    };
+    let mut plain = Simulation::with_seed(640., 480., &config, 7);
+    let mut heavy = Simulation::with_seed(640., 480., &config, 7);
+    for _ in 0..10 {
+        plain.step(&forward, dt);
+        heavy.step(&forward, dt);
+    }
+    let mut heavier = config.clone();
+    heavier.chassis.mass *= 2.;
+    heavy.set_robot_config(&heavier);
+    // picks up right where it was
+    assert_eq!(heavy.robot().vel().lin, plain.robot().vel().lin);
+    assert_eq!(heavy.robot().state(), plain.robot().state());
+    for _ in 0..10 {
+        plain.step(&forward, dt);
+        heavy.step(&forward, dt);
+    }
+    let (p, h) = (plain.robot().vel().lin, heavy.robot().vel().lin);
+    assert!(
+        h > 0. * dimensioned::si::MPS && h < 0.9 * p,
+        "{:?} {:?}",
+        h,
+        p
+    );
+}
+
+#[test]
+fn robot_stops_against_obstacle() {
+    let mut sim = Simulation::new(640., 480.);
+    sim.rocks.clear();
+    let obstacle = Obstacle::circle(0., 150., 30.);
+    sim.add_obstacle(obstacle);
+    let forward = InputState {
+        yaxis: 1.0,
+        ..Default::default()
+    };
+    for _ in 0..3 * SIM_FPS {
+        sim.step(&forward, 1.0 / SIM_FPS as f32);
+    }
+    let dist = (sim.player().pos - obstacle.center()).norm();
+    let contact = 30. + sim.player().bbox_size;
+    assert!((dist - contact).abs() < 1e-3, "{} {}", dist, contact);
+    assert_eq!(sim.robot().vel().lin, 0. * dimensioned::si::MPS);
+}
+
+#[test]
+fn robot_stops_against_wall_obstacle() {
+    let mut sim = Simulation::new(640., 480.);
+    sim.rocks.clear();
+    // thin, so a robot moving too far in one step would come out the far side
+    sim.add_obstacle(Obstacle::rect(0., 150., 100., 5.));
+    let forward = InputState {
+        yaxis: 1.0,
+        ..Default::default()
+    };
+    for _ in 0..3 * SIM_FPS {
+        sim.step(&forward, 1.0 / SIM_FPS as f32);
+    }
+    let contact = 145. - sim.player().bbox_size;
+    let y = sim.player().pos.y;
+    assert!((y - contact).abs() < 1e-3, "{} {}", y, contact);
+    assert_eq!(sim.player().pos.x, 0.);
+    assert_eq!(sim.robot().vel().lin, 0. * dimensioned::si::MPS);
+}
+
+#[test]
+fn clearing_rocks_starts_next_level() {
+    let mut sim = Simulation::new(640., 480.);
+    // one rock left, right in front of a shot
+    sim.rocks.truncate(1);
+    sim.rocks[0].pos = Point2::new(0., 200.);
+    sim.rocks[0].velocity = na::zero();
+    let mut shot = create_shot();
+    shot.pos = sim.rocks[0].pos;
+    sim.shots.push(shot);
+    sim.step(&InputState::default(), 1.0 / SIM_FPS as f32);
+    assert_eq!(sim.score(), ROCK_SCORE);
+    assert_eq!(sim.level(), 2);
+    assert_eq!(sim.rocks().len(), 6);
+    assert!(sim.take_events().contains(&SimEvent::LevelCleared));
+    for rock in sim.rocks() {
+        assert!((rock.pos - sim.player().pos).norm() >= WAVE_EXCLUSION - 1e-3);
+    }
+}
+
+#[test]
+fn rock_hits_end_game() {
+    let mut sim = Simulation::new(640., 480.);
+    sim.rocks.truncate(1);
+    // one left out of the way, so the hits don't clear the level
+    sim.rocks[0].pos = Point2::new(300., 220.);
+    sim.rocks[0].velocity = na::zero();
+    let dt = 1.0 / SIM_FPS as f32;
+    for hit in 1..=3 {
+        assert!(!sim.game_over());
+        let mut rock = create_rock();
+        rock.pos = sim.player().pos;
+        sim.rocks.push(rock);
+        sim.step(&InputState::default(), dt);
+        assert!(sim.take_events().contains(&SimEvent::PlayerHit));
+        assert_eq!(sim.player().life, PLAYER_LIFE - hit as f32 * ROCK_DAMAGE);
+        // the rock that hit is gone
+        assert_eq!(sim.rocks().len(), 1);
+    }
+    assert!(sim.game_over());
+}
+
+#[test]
+fn script_matches_golden_run() {
+    let config = RobotConfig {
+        rolling_resistance: 0.,
+        wheel_friction: None,
+        timing: TimingConfig {
+            physics_dt: 0.005,
+            control_period: 0.005,
+            ..TimingConfig::default()
+        },
+        ..RobotConfig::default()
+    };
+    // straight, an arc, then reversing out of it
+    let script =
+        Playback::from_csv("time,volts_l,volts_r\n0,12,12\n0.5,12,6\n1.0,-6,-6\n1.5,0,0\n")
+            .unwrap();
+    let robot = run_script(&config, &script, 300, None);
+    // from the model's own equations, stepped outside the crate. A change
+    // here means the dynamics changed, so check it was meant to.
+    let pose = robot.pose();
+    let vel = robot.vel();
+    let golden = [
+        3.7406559599615723,
+        -1.086248830214248,
+        -0.8753500435986684,
+        1.2000686414126256,
+        -0.593438490973295,
+    ];
+    let got = [
+        pose.x.value_unsafe,
+        pose.y.value_unsafe,
+        pose.theta,
+        vel.lin.value_unsafe,
+        vel.ang.value_unsafe,
+    ];
+    for (g, v) in golden.iter().zip(got.iter()) {
+        assert!((g - v).abs() < 1e-9, "{:?} {:?}", got, golden);
+    }
+}
+
+#[test]
+fn routine_drives_l_shape() {
+    let mut sim = Simulation::new(800., 800.);
+    sim.rocks.clear();
+    let routine = Routine::from_text(include_str!("../resources/routine.txt")).unwrap();
+    sim.set_routine(Some(routine));
+    sim.set_auton(true);
+    let dt = 1.0 / SIM_FPS as f32;
+    let mut steps = 0;
+    while sim.auton_enabled() && steps < 15 * SIM_FPS {
+        sim.step(&InputState::default(), dt);
+        steps += 1;
+    }
+    // handed back by itself
+    assert!(!sim.auton_enabled());
+    assert!(sim.routine().unwrap().finished());
+    assert!(sim.take_events().contains(&SimEvent::AutonFinished));
+    // up 2 m then left 1 m, the way the player starts out facing
+    let pose = actor_pose(sim.player(), sim.px_per_meter);
+    let (x, y) = (pose.x.value_unsafe, pose.y.value_unsafe);
+    assert!(
+        (x + 1.).abs() < 0.05 && (y - 2.).abs() < 0.05,
+        "{} {}",
+        x,
+        y
+    );
+    assert!(angle::difference(pose.theta, std::f64::consts::PI).abs() < 0.05);
+}
+
+#[test]
+fn velocity_loop_sees_noisy_wheels() {
+    use crate::sensors::ChannelNoise;
+    let mut sim = Simulation::new(640., 480.);
+    sim.rocks.clear();
+    sim.set_control_mode(ControlMode::Velocity);
+    sim.set_sensor_noise(&Noise {
+        wheel: ChannelNoise {
+            sigma: 2.,
+            bias: 0.,
+        },
+        gyro: ChannelNoise {
+            sigma: 0.,
+            bias: 0.1,
+        },
+        seed: 5,
+        ..Noise::default()
+    });
+    let dt = 1.0 / SIM_FPS as f32;
+    let input = InputState {
+        yaxis: 0.5,
+        ..InputState::default()
+    };
+    let mut wheel_error = 0.;
+    for _ in 0..SIM_FPS {
+        // one control update a step, read before the model moves on
+        let truth = (sim.robot().ddmr().wheels(), sim.robot().vel().ang);
+        sim.step(&input, dt);
+        let readings = sim.sensor_readings();
+        // the loop acts on the noise, while the robot itself is the model's
+        assert_eq!(sim.measured_wheels(), readings.wheels);
+        wheel_error += (readings.wheels.l - truth.0.l).value_unsafe.abs();
+        let gyro_error = (readings.gyro - truth.1).value_unsafe;
+        assert!((gyro_error - 0.1).abs() < 1e-9, "{}", gyro_error);
+    }
+    // the mean of |N(0, 2)| is about 1.6
+    wheel_error /= SIM_FPS as f64;
+    assert!(wheel_error > 1. && wheel_error < 2.5, "{}", wheel_error);
+    assert!(sim.robot().vel().lin > 0.5 * dimensioned::si::MPS);
+
+    // and the noise picks up where it was after a restore
+    let saved = sim.snapshot();
+    let run = |sim: &mut Simulation| -> Vec<SimSnapshot> {
+        (0..30)
+            .map(|_| {
+                sim.step(&input, dt);
+                sim.snapshot()
+            })
+            .collect()
+    };
+    let first = run(&mut sim);
+    sim.restore(&saved);
+    assert_eq!(first, run(&mut sim));
+}
+
+#[test]
+fn robots_drive_independently() {
+    let config = RobotConfig::default();
+    let mut heavier = config.clone();
+    heavier.chassis.mass *= 2.;
+    let dt = 1.0 / SIM_FPS as f32;
+    let forward = InputState {
+        yaxis: 1.0,
+        ..Default::default()
+    };
+    let mut sim = Simulation::with_seed(1280., 960., &config, 7);
+    assert_eq!(sim.add_robot(&config), 0);
+    assert_eq!(sim.add_robot(&heavier), 1);
+    let mut alone = Simulation::with_seed(1280., 960., &config, 7);
+    for _ in 0..20 {
+        sim.step_all(&forward, &[forward, forward], dt);
+        alone.step(&forward, dt);
+    }
+    // the same robot under the same input does exactly what it would alone
+    assert_eq!(sim.robot().state(), alone.robot().state());
+    assert_eq!(sim.others()[0].model().state(), alone.robot().state());
+    let (p, h) = (
+        sim.others()[0].model().vel().lin,
+        sim.others()[1].model().vel().lin,
+    );
+    assert!(
+        h > 0. * dimensioned::si::MPS && h < 0.9 * p,
+        "{:?} {:?}",
+        h,
+        p
+    );
+    assert!(sim.others()[1].actor().pos.y < sim.others()[0].actor().pos.y);
+    assert!(sim.others()[0].actor().pos.x > sim.player().pos.x);
+
+    // with no input they just sit there
+    sim.reset();
+    sim.step(&forward, dt);
+    assert_eq!(sim.others()[1].model().vel().lin, 0. * dimensioned::si::MPS);
+}
+
+#[test]
+fn time_counts_what_was_stepped() {
+    let mut sim = Simulation::new(640., 480.);
+    for _ in 0..16 {
+        sim.step(&InputState::default(), 0.125);
+    }
+    assert_eq!(sim.time(), 2.);
+    let mut resumed = Simulation::new(640., 480.);
+    resumed.restore(&sim.snapshot());
+    assert_eq!(resumed.time(), 2.);
+}
+
+#[test]
+fn arena_walls_hold_at_any_time_scale() {
+    let mut config = RobotConfig::default();
+    config.arena = Some(ArenaConfig {
+        width: 4.,
+        height: 3.,
+        restitution: 0.,
+    });
+    let input = InputState {
+        yaxis: 1.0,
+        ..Default::default()
+    };
+    // whole seconds at a time, as if sped far up
+    let mut sim = Simulation::with_seed(640., 480., &config, 7);
+    sim.rocks.clear();
+    assert_eq!(sim.field_size(), (400., 300.));
+    let limit = 150. - sim.player().bbox_size;
+    for _ in 0..5 {
+        sim.step(&input, 1.0);
+        assert!(sim.player().pos.y <= limit);
+    }
+    assert_eq!(sim.player().pos.y, limit);
+    assert_eq!(sim.robot().vel().lin, 0. * dimensioned::si::MPS);
+
+    config.arena = Some(ArenaConfig {
+        restitution: 0.5,
+        ..config.arena.unwrap()
+    });
+    let mut sim = Simulation::with_seed(640., 480., &config, 7);
+    sim.rocks.clear();
+    let mut bounced = false;
+    for _ in 0..3 * SIM_FPS {
+        sim.step(&input, 1.0 / SIM_FPS as f32);
+        assert!(sim.player().pos.y <= limit);
+        bounced |= sim.robot().vel().lin < 0. * dimensioned::si::MPS;
+    }
+    assert!(bounced);
+}
+
+#[test]
+fn session_resumes_and_checks_config() {
+    let config = RobotConfig::default();
+    let mut sim = Simulation::new(640., 480.);
+    let dt = 1.0 / SIM_FPS as f32;
+    for step in 0..100 {
+        sim.step(&scripted_input(step), dt);
+    }
+    let mut saved = Vec::new();
+    Session::new(sim.snapshot(), &config)
+        .save(&mut saved)
+        .unwrap();
+    let run = |sim: &mut Simulation| -> Vec<RawVels> {
+        (100..600)
+            .map(|step| {
+                sim.step(&scripted_input(step), dt);
+                sim.robot().vel().into()
+            })
+            .collect()
+    };
+    let first = run(&mut sim);
+    let mut resumed = Simulation::new(640., 480.);
+    resumed.restore(&Session::load(&saved[..], &config).unwrap().snapshot);
+    assert_eq!(first, run(&mut resumed));
+
+    let mut heavier = config.clone();
+    heavier.chassis.mass += 1.;
+    match Session::load(&saved[..], &heavier) {
+        Err(SessionError::Config { .. }) => {}
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+    // the field pushes back on it just as much as the robot does
+    let mut carpeted = config.clone();
+    carpeted.disturbances.carpet += 20.;
+    let mut noisy = config.clone();
+    noisy.sensors.wheel_sigma += 0.1;
+    for changed in &[carpeted, noisy] {
+        match Session::load(&saved[..], changed) {
+            Err(SessionError::Config { .. }) => {}
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+    }
+    // only the physics count, not how the robot is driven or drawn
+    let mut restyled = config.clone();
+    restyled.trail.length += 1;
+    restyled.gamepad.expo += 0.25;
+    assert!(Session::load(&saved[..], &restyled).is_ok());
+    let old = String::from_utf8(saved).unwrap().replace(
+        &format!("\"version\": {}", SESSION_VERSION),
+        "\"version\": 0",
+    );
+    let err = Session::load(old.as_bytes(), &config).unwrap_err();
+    assert!(err.to_string().contains("version 0"), "{}", err);
+    // a later version is refused for its version, not for not parsing
+    let later = format!("{{\"version\": {}, \"snapshot\": []}}", SESSION_VERSION + 1);
+    match Session::load(later.as_bytes(), &config) {
+        Err(SessionError::Version { saved, .. }) => assert_eq!(saved, SESSION_VERSION + 1),
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn velocity_loop_rejects_carpet() {
+    use dimensioned::si::{MPS, V};
+    let carpet = RobotConfig {
+        disturbances: DisturbanceConfig {
+            carpet: 20.,
+            ..DisturbanceConfig::default()
+        },
+        ..RobotConfig::default()
+    };
+    let dt = 1.0 / SIM_FPS as f32;
+    let input = InputState {
+        yaxis: 0.3,
+        ..InputState::default()
+    };
+    let speed = |config: &RobotConfig, mode: ControlMode| {
+        let mut sim = Simulation::with_seed(10000., 10000., config, 1);
+        sim.rocks.clear();
+        sim.set_control_mode(mode);
+        for _ in 0..5 * SIM_FPS {
+            sim.step(&input, dt);
+        }
+        let r = sim.robot().ddmr().params().R;
+        let setpoint = 0.3 * max_wheel_speed(sim.robot(), 12. * V) * r;
+        (*(sim.robot().vel().lin / MPS), *(setpoint / MPS))
+    };
+    let (smooth, _) = speed(&RobotConfig::default(), ControlMode::Voltage);
+    let (dragged, _) = speed(&carpet, ControlMode::Voltage);
+    assert!(dragged < 0.9 * smooth, "{} {}", dragged, smooth);
+    let (held, setpoint) = speed(&carpet, ControlMode::Velocity);
+    assert!(
+        (held - setpoint).abs() < 0.01 * setpoint,
+        "{} {}",
+        held,
+        setpoint
+    );
+}
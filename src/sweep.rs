@@ -0,0 +1,227 @@
+//! Answers "what gear ratio should we run" by putting several versions of a
+//! robot through the same maneuver and comparing how they do. Each version
+//! sprints from rest at a fixed voltage, then separately turns in place from
+//! rest. Nothing here is random, so the same inputs always give the same table.
+
+use crate::config::RobotConfig;
+use crate::dynamics::{ActuatedDDMRModel, LR};
+
+use dimensioned::si::*;
+
+use std::fmt::Write;
+
+/// One version of the base robot
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Variation {
+    /// Rotor turns per wheel turn
+    pub gear_ratio: f64,
+    /// m, `None` to keep the base robot's wheels
+    pub wheel_radius: Option<f64>,
+}
+
+impl Variation {
+    /// The base robot's wheels with a different gear ratio
+    pub fn gear_ratio(gear_ratio: f64) -> Self {
+        Self {
+            gear_ratio,
+            wheel_radius: None,
+        }
+    }
+
+    fn apply(&self, base: &RobotConfig) -> RobotConfig {
+        let mut config = base.clone();
+        config.set_gear_ratio(self.gear_ratio);
+        if let Some(r) = self.wheel_radius {
+            config.chassis.wheel_radius = r;
+        }
+        config
+    }
+}
+
+/// What every variation is put through
+#[derive(Debug, Clone, PartialEq)]
+pub struct Maneuver {
+    /// m, length of the sprint
+    pub distance: f64,
+    /// rad, counterclockwise, of the turn in place
+    pub turn: f64,
+    /// V on the motors throughout
+    pub voltage: f64,
+    /// s, each part gives up after this long
+    pub timeout: f64,
+    /// s, timestep of the drive model
+    pub dt: f64,
+    /// s, from the start of the sprint over which `initial_acceleration` is measured
+    pub launch_time: f64,
+}
+
+impl Default for Maneuver {
+    /// A full-voltage sprint of 5 m and a half turn
+    fn default() -> Self {
+        Self {
+            distance: 5.,
+            turn: std::f64::consts::PI,
+            voltage: 12.,
+            timeout: 10.,
+            dt: 0.001,
+            launch_time: 0.1,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SweepResult {
+    pub variation: Variation,
+    /// s to cover the sprint distance, `None` if it timed out
+    pub time_to_distance: Option<f64>,
+    /// m/s, the fastest it went during the sprint
+    pub top_speed: f64,
+    /// m/s^2, average over the `launch_time` at the start of the sprint
+    pub initial_acceleration: f64,
+    /// A, the most drawn by either motor during either part
+    pub peak_current: f64,
+    /// s to turn through the maneuver's angle, `None` if it timed out
+    pub time_to_turn: Option<f64>,
+}
+
+/// Holds `volts` on the model from rest until `done` or the timeout. Calls
+/// `each` after every step with the time so far. Returns the time it took
+/// and the peak current.
+fn drive<D, E>(
+    model: &mut ActuatedDDMRModel,
+    maneuver: &Maneuver,
+    volts: LR<f64>,
+    done: D,
+    mut each: E,
+) -> (Option<f64>, f64)
+where
+    D: Fn(&ActuatedDDMRModel) -> bool,
+    E: FnMut(&ActuatedDDMRModel, f64),
+{
+    model.reset();
+    let v = LR {
+        l: volts.l * V,
+        r: volts.r * V,
+    };
+    let steps = (maneuver.timeout / maneuver.dt).round() as usize;
+    let mut peak: f64 = 0.;
+    for k in 1..=steps {
+        model.observe(v);
+        let i = model.currents();
+        peak = peak.max(i.l.value_unsafe.abs()).max(i.r.value_unsafe.abs());
+        let time = k as f64 * maneuver.dt;
+        each(model, time);
+        if done(model) {
+            return (Some(time), peak);
+        }
+    }
+    (None, peak)
+}
+
+fn run_one(base: &RobotConfig, variation: Variation, maneuver: &Maneuver) -> SweepResult {
+    let mut model = variation.apply(base).build(maneuver.dt * S);
+    let mut top_speed: f64 = 0.;
+    let mut launch_speed = 0.;
+    let (time_to_distance, sprint_peak) = drive(
+        &mut model,
+        maneuver,
+        LR {
+            l: maneuver.voltage,
+            r: maneuver.voltage,
+        },
+        |m| {
+            let p = m.pose();
+            p.x.value_unsafe.hypot(p.y.value_unsafe) >= maneuver.distance
+        },
+        |m, time| {
+            let speed = m.vel().lin.value_unsafe;
+            top_speed = top_speed.max(speed);
+            if time <= maneuver.launch_time {
+                launch_speed = speed;
+            }
+        },
+    );
+    let (time_to_turn, turn_peak) = drive(
+        &mut model,
+        maneuver,
+        LR {
+            l: -maneuver.voltage,
+            r: maneuver.voltage,
+        },
+        |m| m.pose().theta >= maneuver.turn,
+        |_, _| (),
+    );
+    SweepResult {
+        variation,
+        time_to_distance,
+        top_speed,
+        initial_acceleration: launch_speed / maneuver.launch_time,
+        peak_current: sprint_peak.max(turn_peak),
+        time_to_turn,
+    }
+}
+
+/// Runs `maneuver` on `base` with each of the variations, returning the
+/// results in the same order
+pub fn run(base: &RobotConfig, variations: &[Variation], maneuver: &Maneuver) -> Vec<SweepResult> {
+    variations
+        .iter()
+        .map(|&v| run_one(base, v, maneuver))
+        .collect()
+}
+
+/// The results as a plain text table, one variation per row
+pub fn format_table(results: &[SweepResult]) -> String {
+    let time = |t: Option<f64>| match t {
+        Some(t) => format!("{:.3}", t),
+        None => "-".to_string(),
+    };
+    let mut out = format!(
+        "{:>8} {:>10} {:>12} {:>12} {:>12} {:>10} {:>10}\n",
+        "ratio", "wheel (m)", "sprint (s)", "top (m/s)", "accel (m/s2)", "peak (A)", "turn (s)"
+    );
+    for r in results {
+        let wheel = match r.variation.wheel_radius {
+            Some(w) => format!("{:.4}", w),
+            None => "base".to_string(),
+        };
+        // writing to a String can't fail
+        let _ = writeln!(
+            out,
+            "{:>8.2} {:>10} {:>12} {:>12.3} {:>12.2} {:>10.1} {:>10}",
+            r.variation.gear_ratio,
+            wheel,
+            time(r.time_to_distance),
+            r.top_speed,
+            r.initial_acceleration,
+            r.peak_current,
+            time(r.time_to_turn)
+        );
+    }
+    out
+}
+
+#[test]
+fn higher_ratio_trades_speed_for_acceleration() {
+    let base = RobotConfig {
+        wheel_friction: None,
+        ..RobotConfig::default()
+    };
+    let maneuver = Maneuver {
+        distance: 10.,
+        dt: 0.002,
+        ..Maneuver::default()
+    };
+    let variations = [Variation::gear_ratio(5.), Variation::gear_ratio(10.)];
+    let results = run(&base, &variations, &maneuver);
+    let (low, high) = (&results[0], &results[1]);
+    assert!(high.top_speed < low.top_speed, "{:?}", results);
+    assert!(
+        high.initial_acceleration > low.initial_acceleration,
+        "{:?}",
+        results
+    );
+    assert!(low.time_to_distance.is_some() && high.time_to_turn.is_some());
+    // the same inputs give the same numbers
+    assert_eq!(run(&base, &variations, &maneuver), results);
+}
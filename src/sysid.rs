@@ -0,0 +1,226 @@
+//! System identification of the simulated drivetrain from its response to a
+//! voltage sweep, the way a real one is identified on blocks or on the carpet.
+//! `run_chirp` measures the frequency response from volts to chassis speed,
+//! and `quasistatic_gains` fits the feedforward constants to a slow ramp.
+
+use crate::characterize;
+use crate::dynamics::{ActuatedDDMRModel, LR};
+
+use dimensioned::si::*;
+use dimensioned::tarr;
+use dimensioned::typenum::{N1, N2, P1, Z0};
+
+use std::f64::consts::PI;
+
+/// V per m/s
+pub type VoltPerMeterPerSecond<V> = SI<V, tarr![P1, P1, N2, N1, Z0, Z0, Z0]>;
+/// V per m/s^2
+pub type VoltPerMeterPerSecond2<V> = SI<V, tarr![P1, P1, N1, N1, Z0, Z0, Z0]>;
+
+/// A sine on both motors whose frequency rises exponentially from `f_min` to
+/// `f_max`, so each decade gets the same time
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChirpConfig {
+    /// Hz
+    pub f_min: f64,
+    /// Hz
+    pub f_max: f64,
+    /// s
+    pub duration: f64,
+    /// V
+    pub amplitude: f64,
+    /// How many frequencies to work out the response at, evenly spaced on a
+    /// log scale and clear of the ends of the sweep
+    pub points: usize,
+}
+
+impl Default for ChirpConfig {
+    fn default() -> Self {
+        Self {
+            f_min: 0.1,
+            f_max: 5.,
+            duration: 40.,
+            amplitude: 6.,
+            points: 10,
+        }
+    }
+}
+
+impl ChirpConfig {
+    /// The voltage `time` seconds into the sweep
+    pub fn voltage(&self, time: f64) -> f64 {
+        let k = self.f_max / self.f_min;
+        let phase =
+            2. * PI * self.f_min * self.duration / k.ln() * (k.powf(time / self.duration) - 1.);
+        self.amplitude * phase.sin()
+    }
+
+    /// Where the response is worked out, lowest first
+    pub fn frequencies(&self) -> Vec<f64> {
+        let k = self.f_max / self.f_min;
+        (0..self.points)
+            .map(|i| self.f_min * k.powf((i as f64 + 0.5) / self.points as f64))
+            .collect()
+    }
+}
+
+/// One step of the sweep
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ChirpSample {
+    /// s since the start of the sweep
+    pub time: f64,
+    /// V, the average of the two sides
+    pub voltage: f64,
+    /// m/s, at the start of the step
+    pub velocity: f64,
+}
+
+/// The response from volts to chassis speed at one frequency
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FreqPoint {
+    pub hz: f64,
+    /// Of m/s per V
+    pub gain_db: f64,
+    /// Negative for the speed lagging the voltage
+    pub phase_deg: f64,
+}
+
+/// Runs the sweep on `model` from rest, recording every physics step
+pub fn chirp(model: &mut ActuatedDDMRModel, cfg: &ChirpConfig) -> Vec<ChirpSample> {
+    model.reset();
+    let dt = *(model.ddmr().dt() / S);
+    let steps = (cfg.duration / dt).round() as usize;
+    (0..steps)
+        .map(|k| {
+            let time = k as f64 * dt;
+            let velocity = *(model.vel().lin / MPS);
+            let v = cfg.voltage(time) * V;
+            model.observe(LR { l: v, r: v });
+            let applied = model.last_observation().voltage;
+            ChirpSample {
+                time,
+                voltage: *((applied.l + applied.r) / V) / 2.,
+                velocity,
+            }
+        })
+        .collect()
+}
+
+/// The real and imaginary parts of the discrete Fourier transform of `x` at
+/// `hz`
+fn dft<F: Fn(&ChirpSample) -> f64>(samples: &[ChirpSample], hz: f64, x: F) -> (f64, f64) {
+    samples.iter().fold((0., 0.), |(re, im), s| {
+        let angle = 2. * PI * hz * s.time;
+        (re + x(s) * angle.cos(), im - x(s) * angle.sin())
+    })
+}
+
+/// The speed's spectrum over the voltage's at each of `frequencies`
+pub fn response(samples: &[ChirpSample], frequencies: &[f64]) -> Vec<FreqPoint> {
+    frequencies
+        .iter()
+        .map(|&hz| {
+            let (ur, ui) = dft(samples, hz, |s| s.voltage);
+            let (yr, yi) = dft(samples, hz, |s| s.velocity);
+            // y / u, as complex numbers
+            let norm = ur * ur + ui * ui;
+            let (hr, hi) = ((yr * ur + yi * ui) / norm, (yi * ur - yr * ui) / norm);
+            FreqPoint {
+                hz,
+                gain_db: 20. * (hr * hr + hi * hi).sqrt().log10(),
+                phase_deg: hi.atan2(hr).to_degrees(),
+            }
+        })
+        .collect()
+}
+
+/// Sweeps `model` from rest and measures its frequency response
+pub fn run_chirp(model: &mut ActuatedDDMRModel, cfg: &ChirpConfig) -> Vec<FreqPoint> {
+    response(&chirp(model, cfg), &cfg.frequencies())
+}
+
+/// One row per point, with a header
+pub fn format_table(points: &[FreqPoint]) -> String {
+    let mut out = format!("{:>8} {:>10} {:>10}\n", "Hz", "gain dB", "phase deg");
+    for p in points {
+        out += &format!("{:8.3} {:10.2} {:10.1}\n", p.hz, p.gain_db, p.phase_deg);
+    }
+    out
+}
+
+/// Feedforward constants for driving straight, in units
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MotionGains {
+    pub ks: Volt<f64>,
+    pub kv: VoltPerMeterPerSecond<f64>,
+    pub ka: VoltPerMeterPerSecond2<f64>,
+}
+
+/// Fits the feedforward constants to a ramp from rest at `rate` volts per
+/// second up to `max`, as `characterize::quasistatic` runs it. `None` if the
+/// robot barely moved.
+pub fn quasistatic_gains(
+    model: &mut ActuatedDDMRModel,
+    rate: f64,
+    max: Volt<f64>,
+) -> Option<MotionGains> {
+    let samples = characterize::quasistatic(model, rate, *(max / V));
+    let g = characterize::fit(&samples)?;
+    Some(MotionGains {
+        ks: g.ks * V,
+        kv: g.kv * V / MPS,
+        ka: g.ka * V / MPS2,
+    })
+}
+
+#[cfg(test)]
+fn test_model() -> ActuatedDDMRModel {
+    use crate::config::RobotConfig;
+    let config = RobotConfig {
+        wheel_friction: None,
+        ..RobotConfig::default()
+    };
+    config.build(0.005 * S)
+}
+
+#[test]
+fn chirp_matches_first_order_response() {
+    let mut model = test_model();
+    // driving straight, the model is first order from volts to speed
+    let gain = (model.steady_speed(6. * V) - model.steady_speed(3. * V)) / (3. * V);
+    let tau = *(model.time_constant() / S);
+    let cfg = ChirpConfig::default();
+    let points = run_chirp(&mut model, &cfg);
+    assert_eq!(points.len(), cfg.points);
+    for p in points.iter().filter(|p| p.hz < 2.) {
+        let wt = 2. * PI * p.hz * tau;
+        let expected_db = 20. * (*(gain / (MPS / V)) / (1. + wt * wt).sqrt()).log10();
+        let expected_deg = -wt.atan().to_degrees();
+        assert!(
+            (p.gain_db - expected_db).abs() < 1.,
+            "{:?} {}",
+            p,
+            expected_db
+        );
+        assert!(
+            (p.phase_deg - expected_deg).abs() < 5.,
+            "{:?} {}",
+            p,
+            expected_deg
+        );
+    }
+    let table = format_table(&points);
+    assert_eq!(table.lines().count(), cfg.points + 1);
+}
+
+#[test]
+fn quasistatic_kv_matches_steady_speed() {
+    let mut model = test_model();
+    let gains = quasistatic_gains(&mut model, 0.25, 6. * V).unwrap();
+    // friction takes the same off at any voltage, so the slope is kV alone
+    let expected: VoltPerMeterPerSecond<f64> =
+        (3. * V) / (model.steady_speed(6. * V) - model.steady_speed(3. * V));
+    let error = *((gains.kv - expected) / expected);
+    assert!(error.abs() < 0.03, "{:?} {:?}", gains, expected);
+    assert!(gains.ka > 0. * V / MPS2);
+}
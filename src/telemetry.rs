@@ -0,0 +1,310 @@
+//! Per-step CSV logging of the drive model, for plotting against real robot logs.
+
+use crate::dynamics::{ActuatedDDMRModel, ZERO_CELSIUS};
+use crate::sensors::{Imu, ImuReading};
+
+use dimensioned::si::S;
+
+use std::fmt;
+use std::io::{self, BufWriter, Write};
+
+/// The CSV columns, in order. Scripts can rely on these names and this order.
+/// Each name ends in its unit, except for the flags.
+///
+/// | column               |                                         |
+/// |----------------------|-----------------------------------------|
+/// | `time_s`             | simulation time at the end of the frame |
+/// | `volts_l_V`          | voltage applied to the left armature    |
+/// | `volts_r_V`          | voltage applied to the right armature   |
+/// | `wheel_l_rad_per_s`  | left wheel angular velocity             |
+/// | `wheel_r_rad_per_s`  | right wheel angular velocity            |
+/// | `lin_m_per_s`        | chassis linear velocity                 |
+/// | `ang_rad_per_s`      | chassis angular velocity, CCW positive  |
+/// | `x_m`                | pose                                    |
+/// | `y_m`                | pose                                    |
+/// | `theta_rad`          | heading, CCW from the x axis            |
+/// | `amps_l_A`           | left armature current                   |
+/// | `amps_r_A`           | right armature current                  |
+/// | `yaw_rad`            | IMU heading, not wrapped                |
+/// | `yaw_rate_rad_per_s` | IMU gyro, CCW positive                  |
+/// | `accel_x_m_per_s2`   | IMU acceleration, forward               |
+/// | `accel_y_m_per_s2`   | IMU acceleration, to the left           |
+/// | `temp_l_C`           | left motor winding temperature          |
+/// | `temp_r_C`           | right motor winding temperature         |
+/// | `limit_l`            | 1 if the left current was limited       |
+/// | `limit_r`            | 1 if the right current was limited      |
+/// | `dist_force_N`       | outside force along the heading         |
+/// | `dist_torque_N_m`    | outside torque, CCW positive            |
+pub const COLUMNS: [&str; 22] = [
+    "time_s",
+    "volts_l_V",
+    "volts_r_V",
+    "wheel_l_rad_per_s",
+    "wheel_r_rad_per_s",
+    "lin_m_per_s",
+    "ang_rad_per_s",
+    "x_m",
+    "y_m",
+    "theta_rad",
+    "amps_l_A",
+    "amps_r_A",
+    "yaw_rad",
+    "yaw_rate_rad_per_s",
+    "accel_x_m_per_s2",
+    "accel_y_m_per_s2",
+    "temp_l_C",
+    "temp_r_C",
+    "limit_l",
+    "limit_r",
+    "dist_force_N",
+    "dist_torque_N_m",
+];
+
+/// What the names in `COLUMNS` end in
+const UNITS: [&str; 11] = [
+    "_s",
+    "_V",
+    "_rad_per_s",
+    "_m_per_s",
+    "_m",
+    "_rad",
+    "_A",
+    "_m_per_s2",
+    "_C",
+    "_N",
+    "_N_m",
+];
+
+/// The column in `COLUMNS` for `name` without its unit, like `time_s` for
+/// `time`
+pub fn with_unit(name: &str) -> Option<&'static str> {
+    COLUMNS
+        .iter()
+        .find(|c| c.starts_with(name) && UNITS.contains(&&c[name.len()..]))
+        .cloned()
+}
+
+/// One row of telemetry in plain SI numbers, fields in `COLUMNS` order
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct TelemetryRow {
+    pub time: f64,
+    pub volts_l: f64,
+    pub volts_r: f64,
+    pub wheel_l: f64,
+    pub wheel_r: f64,
+    pub lin: f64,
+    pub ang: f64,
+    pub x: f64,
+    pub y: f64,
+    pub theta: f64,
+    pub amps_l: f64,
+    pub amps_r: f64,
+    pub yaw: f64,
+    pub yaw_rate: f64,
+    pub accel_x: f64,
+    pub accel_y: f64,
+    pub temp_l: f64,
+    pub temp_r: f64,
+    pub limit_l: f64,
+    pub limit_r: f64,
+    pub dist_force: f64,
+    pub dist_torque: f64,
+}
+
+impl TelemetryRow {
+    /// The state after the model's latest `observe`, and what an IMU on it read
+    pub fn from_model(time: f64, model: &ActuatedDDMRModel, imu: &ImuReading) -> Self {
+        let obs = model.last_observation();
+        let wheels = model.ddmr().wheels();
+        let pose = model.pose();
+        let temps = model.temperatures();
+        let flag = |limited: bool| if limited { 1. } else { 0. };
+        let disturbance = model.ddmr().disturbance();
+        Self {
+            time,
+            volts_l: obs.voltage.l.value_unsafe,
+            volts_r: obs.voltage.r.value_unsafe,
+            wheel_l: wheels.l.value_unsafe,
+            wheel_r: wheels.r.value_unsafe,
+            lin: obs.vels.lin.value_unsafe,
+            ang: obs.vels.ang.value_unsafe,
+            x: pose.x.value_unsafe,
+            y: pose.y.value_unsafe,
+            theta: pose.theta,
+            amps_l: obs.current.l.value_unsafe,
+            amps_r: obs.current.r.value_unsafe,
+            yaw: imu.yaw,
+            yaw_rate: imu.yaw_rate.value_unsafe,
+            accel_x: imu.accel_x.value_unsafe,
+            accel_y: imu.accel_y.value_unsafe,
+            temp_l: temps.l.value_unsafe - ZERO_CELSIUS,
+            temp_r: temps.r.value_unsafe - ZERO_CELSIUS,
+            limit_l: flag(obs.limited.l),
+            limit_r: flag(obs.limited.r),
+            dist_force: disturbance.force.value_unsafe,
+            dist_torque: disturbance.torque.value_unsafe,
+        }
+    }
+}
+
+/// The CSV line, without the newline. Numbers are written in full, never in
+/// exponent form, and read back to exactly the same value.
+impl fmt::Display for TelemetryRow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.time,
+            self.volts_l,
+            self.volts_r,
+            self.wheel_l,
+            self.wheel_r,
+            self.lin,
+            self.ang,
+            self.x,
+            self.y,
+            self.theta,
+            self.amps_l,
+            self.amps_r,
+            self.yaw,
+            self.yaw_rate,
+            self.accel_x,
+            self.accel_y,
+            self.temp_l,
+            self.temp_r,
+            self.limit_l,
+            self.limit_r,
+            self.dist_force,
+            self.dist_torque,
+        )
+    }
+}
+
+/// Writes one CSV row per `record`, usually once a frame. Rows are buffered, so
+/// nothing is guaranteed to be on disk until `flush` or the recorder is dropped.
+pub struct Telemetry<W: Write> {
+    out: BufWriter<W>,
+    time: f64,
+    rows: usize,
+    /// Updated once per row
+    imu: Imu,
+}
+
+impl<W: Write> Telemetry<W> {
+    /// Writes the header right away
+    pub fn new(out: W) -> io::Result<Self> {
+        let mut out = BufWriter::new(out);
+        writeln!(out, "{}", COLUMNS.join(","))?;
+        Ok(Self {
+            out,
+            time: 0.,
+            rows: 0,
+            imu: Imu::new(),
+        })
+    }
+
+    /// Records the state after the model's latest `observe`, `dt` seconds
+    /// after the last row. The model may have been stepped several times since.
+    pub fn record(&mut self, model: &ActuatedDDMRModel, dt: f64) -> io::Result<()> {
+        self.time += dt;
+        let imu = self.imu.update(model.ddmr(), dt * S);
+        writeln!(
+            self.out,
+            "{}",
+            TelemetryRow::from_model(self.time, model, &imu)
+        )?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Data rows written so far, not counting the header
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Flushes and hands back the underlying writer
+    pub fn into_inner(self) -> io::Result<W> {
+        self.out.into_inner().map_err(|e| e.into())
+    }
+}
+
+#[test]
+fn telemetry_row_format() {
+    let row = TelemetryRow {
+        time: 0.5,
+        volts_l: 12.,
+        volts_r: -3.25,
+        wheel_l: 1.,
+        wheel_r: 2.,
+        lin: 3.,
+        ang: -0.1,
+        x: 1e-7,
+        y: 1e7,
+        theta: std::f64::consts::PI,
+        amps_l: 0.,
+        amps_r: 150.25,
+        yaw: -7.,
+        yaw_rate: 0.,
+        accel_x: 2.5,
+        accel_y: -0.125,
+        temp_l: 25.,
+        temp_r: 81.5,
+        limit_l: 0.,
+        limit_r: 1.,
+        dist_force: -20.,
+        dist_torque: 0.,
+    };
+    assert_eq!(with_unit("yaw"), Some("yaw_rad"));
+    assert_eq!(with_unit("yaw_rate"), Some("yaw_rate_rad_per_s"));
+    assert_eq!(with_unit("limit_l"), None);
+    let line = row.to_string();
+    assert_eq!(
+        line,
+        "0.5,12,-3.25,1,2,3,-0.1,0.0000001,10000000,3.141592653589793,0,150.25,-7,0,2.5,-0.125,25,81.5,0,1,-20,0"
+    );
+    // one field per column, and nothing lost on the way back
+    let fields: Vec<f64> = line.split(',').map(|f| f.parse().unwrap()).collect();
+    assert_eq!(fields.len(), COLUMNS.len());
+    assert_eq!(
+        fields[COLUMNS.iter().position(|&c| c == "theta_rad").unwrap()],
+        row.theta
+    );
+    assert_eq!(
+        fields[COLUMNS.iter().position(|&c| c == "volts_r_V").unwrap()],
+        row.volts_r
+    );
+}
+
+#[test]
+fn telemetry_csv() {
+    use crate::sim::{scripted_input, Simulation, SIM_FPS};
+    let mut sim = Simulation::new(640., 480.);
+    let mut t = Telemetry::new(Vec::new()).unwrap();
+    for i in 0..120 {
+        sim.step(&scripted_input(i), 1. / SIM_FPS as f32);
+        t.record(sim.robot(), 1. / SIM_FPS as f64).unwrap();
+    }
+    assert_eq!(t.rows(), 120);
+    let csv = String::from_utf8(t.into_inner().unwrap()).unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(lines.next().unwrap(), COLUMNS.join(","));
+    let mut rows = 0;
+    for line in lines {
+        let fields: Vec<f64> = line.split(',').map(|f| f.parse().unwrap()).collect();
+        assert_eq!(fields.len(), COLUMNS.len());
+        rows += 1;
+    }
+    assert_eq!(rows, 120);
+    // and it plays back, or drives a ghost, by the names with units
+    use crate::ghost::Ghost;
+    use crate::playback::{Playback, PlaybackKind};
+    assert_eq!(
+        Playback::from_csv(&csv).unwrap().kind(),
+        PlaybackKind::Voltage
+    );
+    assert!(Ghost::from_csv(&csv).is_ok());
+}
@@ -0,0 +1,131 @@
+//! Pausing and speeding up the fixed update loop. The timestep itself never
+//! changes, only how many steps run per tick of the real-time loop, so the
+//! drive model always integrates at the dt it was built with.
+
+/// The speeds `faster`/`slower` step through
+const SCALES: [f64; 5] = [0.25, 0.5, 1., 2., 4.];
+const NORMAL_SCALE: usize = 2;
+
+/// How many whole steps to run this tick at `scale`, given the fraction of a
+/// step left over from earlier ticks. Returns the step count and the new
+/// leftover, so fractional scales run a step every few ticks.
+pub fn steps_for_tick(scale: f64, accumulator: f64) -> (u32, f64) {
+    let total = accumulator + scale;
+    let steps = total.floor();
+    (steps as u32, total - steps)
+}
+
+#[derive(Debug, Clone)]
+pub struct TimeControl {
+    paused: bool,
+    scale: usize,
+    accumulator: f64,
+    single_step: bool,
+}
+
+impl Default for TimeControl {
+    fn default() -> Self {
+        Self {
+            paused: false,
+            scale: NORMAL_SCALE,
+            accumulator: 0.,
+            single_step: false,
+        }
+    }
+}
+
+impl TimeControl {
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn scale(&self) -> f64 {
+        SCALES[self.scale]
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.accumulator = 0.;
+    }
+
+    /// Runs exactly one step on the next tick. Only does anything while paused.
+    pub fn single_step(&mut self) {
+        if self.paused {
+            self.single_step = true;
+        }
+    }
+
+    pub fn faster(&mut self) {
+        self.scale = (self.scale + 1).min(SCALES.len() - 1);
+    }
+
+    pub fn slower(&mut self) {
+        self.scale = self.scale.saturating_sub(1);
+    }
+
+    /// Call once per tick of the real-time loop for the number of steps to run
+    pub fn steps_this_tick(&mut self) -> u32 {
+        if self.paused {
+            let step = self.single_step;
+            self.single_step = false;
+            return step as u32;
+        }
+        let (steps, accumulator) = steps_for_tick(self.scale(), self.accumulator);
+        self.accumulator = accumulator;
+        steps
+    }
+
+    /// Shown by the debug overlay, e.g. "PAUSED" or "0.5x"
+    pub fn label(&self) -> String {
+        if self.paused {
+            "PAUSED".to_string()
+        } else {
+            format!("{}x", self.scale())
+        }
+    }
+}
+
+#[test]
+fn fractional_scales() {
+    let run = |scale: f64, ticks: usize| {
+        let mut acc = 0.;
+        (0..ticks)
+            .map(|_| {
+                let (steps, rest) = steps_for_tick(scale, acc);
+                acc = rest;
+                steps
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(run(1., 4), vec![1, 1, 1, 1]);
+    assert_eq!(run(4., 2), vec![4, 4]);
+    assert_eq!(run(0.25, 8), vec![0, 0, 0, 1, 0, 0, 0, 1]);
+    assert_eq!(run(0.5, 4), vec![0, 1, 0, 1]);
+    // nothing lost over a long run
+    assert_eq!(run(0.25, 4000).iter().sum::<u32>(), 1000);
+}
+
+#[test]
+fn pause_and_single_step() {
+    let mut t = TimeControl::default();
+    assert_eq!(t.steps_this_tick(), 1);
+    t.toggle_pause();
+    assert_eq!(t.steps_this_tick(), 0);
+    t.single_step();
+    assert_eq!(t.steps_this_tick(), 1);
+    assert_eq!(t.steps_this_tick(), 0);
+    assert_eq!(t.label(), "PAUSED");
+    t.toggle_pause();
+    for _ in 0..10 {
+        t.faster();
+    }
+    assert_eq!(t.scale(), 4.);
+    assert_eq!(t.steps_this_tick(), 4);
+    for _ in 0..10 {
+        t.slower();
+    }
+    assert_eq!(t.label(), "0.25x");
+    // single steps only happen while paused
+    t.single_step();
+    assert_eq!(t.steps_this_tick(), 0);
+}
@@ -0,0 +1,246 @@
+//! A fixed-size history of where the player has been, for drawing its path.
+
+use ggez::graphics::Point2;
+use ggez::nalgebra as na;
+use serde_derive::{Deserialize, Serialize};
+
+use std::collections::VecDeque;
+
+#[derive(Debug, Copy, Clone)]
+struct TrailPoint {
+    pos: Point2,
+    /// Don't connect this point to the one before it
+    break_before: bool,
+}
+
+/// Everything a trail has kept, for saving it with a session
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrailState {
+    /// x, y, and whether the path breaks before the point, oldest first
+    pub points: Vec<(f32, f32, bool)>,
+    /// Pushes since the last kept one
+    pub skipped: usize,
+}
+
+/// Ring buffer of positions. Once full, each push drops the oldest point.
+///
+/// A jump longer than `max_jump` between consecutive points, like the one
+/// `wrap_actor_position` makes at the edge of the field, breaks the path
+/// instead of being drawn as a line across the screen.
+#[derive(Debug, Clone)]
+pub struct Trail {
+    points: VecDeque<TrailPoint>,
+    capacity: usize,
+    max_jump: f32,
+    /// Keep one position out of every `stride` pushed
+    stride: usize,
+    /// Pushes since the last kept one
+    skipped: usize,
+}
+
+impl Trail {
+    pub fn new(capacity: usize, max_jump: f32) -> Self {
+        Self::with_stride(capacity, max_jump, 1)
+    }
+
+    /// Keeps only every `stride`th position pushed, starting with the first,
+    /// so the same capacity covers a longer path. A `stride` of zero is taken
+    /// as one.
+    pub fn with_stride(capacity: usize, max_jump: f32, stride: usize) -> Self {
+        Self {
+            points: VecDeque::with_capacity(capacity),
+            capacity,
+            max_jump,
+            stride: stride.max(1),
+            skipped: 0,
+        }
+    }
+
+    pub fn push(&mut self, pos: Point2) {
+        if self.capacity == 0 {
+            return;
+        }
+        if !self.points.is_empty() && self.skipped + 1 < self.stride {
+            self.skipped += 1;
+            return;
+        }
+        self.skipped = 0;
+        let break_before = match self.points.back() {
+            Some(last) => na::distance(&last.pos, &pos) > self.max_jump,
+            None => false,
+        };
+        if self.points.len() == self.capacity {
+            self.points.pop_front();
+        }
+        self.points.push_back(TrailPoint { pos, break_before });
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+        self.skipped = 0;
+    }
+
+    pub fn state(&self) -> TrailState {
+        TrailState {
+            points: self
+                .points
+                .iter()
+                .map(|p| (p.pos.x, p.pos.y, p.break_before))
+                .collect(),
+            skipped: self.skipped,
+        }
+    }
+
+    /// Picks up from `state`, keeping only the newest points if it held more
+    /// than fit
+    pub fn resume(&mut self, state: &TrailState) {
+        let from = state.points.len().saturating_sub(self.capacity);
+        self.points = state.points[from..]
+            .iter()
+            .map(|&(x, y, break_before)| TrailPoint {
+                pos: Point2::new(x, y),
+                break_before,
+            })
+            .collect();
+        self.skipped = state.skipped;
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Splits the trail into polylines for drawing, oldest first. Each one is
+    /// tagged with its brightness in (0, 1], growing with the age band it falls
+    /// in, so the trail fades out in `bands` steps. Consecutive lines in the
+    /// same run share an endpoint so there are no gaps between bands.
+    pub fn polylines(&self, bands: usize) -> Vec<(f32, Vec<Point2>)> {
+        let bands = bands.max(1);
+        let len = self.points.len();
+        let band_of = |i: usize| i * bands / len.max(1);
+        let mut lines = Vec::new();
+        let mut current: Vec<Point2> = Vec::new();
+        let mut current_band = 0;
+        for (i, p) in self.points.iter().enumerate() {
+            let band = band_of(i);
+            if p.break_before {
+                if current.len() > 1 {
+                    lines.push((current_band, current));
+                }
+                current = Vec::new();
+            } else if band != current_band && !current.is_empty() {
+                let last = current[current.len() - 1];
+                if current.len() > 1 {
+                    lines.push((current_band, current));
+                }
+                current = vec![last];
+            }
+            current_band = band;
+            current.push(p.pos);
+        }
+        if current.len() > 1 {
+            lines.push((current_band, current));
+        }
+        lines
+            .into_iter()
+            .map(|(band, pts)| ((band + 1) as f32 / bands as f32, pts))
+            .collect()
+    }
+}
+
+#[test]
+fn trail_capacity() {
+    let mut t = Trail::new(100, 50.);
+    for i in 0..250 {
+        t.push(Point2::new(i as f32, 0.));
+    }
+    assert_eq!(t.len(), 100);
+    let lines = t.polylines(1);
+    assert_eq!(lines.len(), 1);
+    // the oldest points were dropped
+    assert_eq!(lines[0].1[0].x, 150.);
+    assert_eq!(lines[0].1[99].x, 249.);
+    t.clear();
+    assert!(t.is_empty());
+}
+
+#[test]
+fn trail_breaks_on_wrap() {
+    let mut t = Trail::new(100, 50.);
+    for i in 0..10 {
+        t.push(Point2::new(300. + 2. * i as f32, 0.));
+    }
+    // wrapped to the other side of the field
+    for i in 0..10 {
+        t.push(Point2::new(-320. + 2. * i as f32, 0.));
+    }
+    let lines = t.polylines(1);
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0].1.len(), 10);
+    assert_eq!(lines[1].1.len(), 10);
+    for (_, pts) in &lines {
+        for w in pts.windows(2) {
+            assert!(na::distance(&w[0], &w[1]) <= 50.);
+        }
+    }
+}
+
+#[test]
+fn trail_fades() {
+    let mut t = Trail::new(100, 50.);
+    for i in 0..100 {
+        t.push(Point2::new(i as f32, 0.));
+    }
+    let lines = t.polylines(4);
+    assert_eq!(lines.len(), 4);
+    assert!(lines.windows(2).all(|w| w[0].0 < w[1].0));
+    assert_eq!(lines[3].0, 1.);
+    // bands join up
+    assert_eq!(lines[0].1.last(), lines[1].1.first());
+}
+
+#[test]
+fn trail_stride() {
+    let mut t = Trail::with_stride(10, 50., 3);
+    for i in 0..100 {
+        t.push(Point2::new(i as f32, 0.));
+    }
+    assert_eq!(t.len(), 10);
+    let lines = t.polylines(1);
+    // every third point, up to the newest one kept
+    assert_eq!(lines[0].1[9].x, 99.);
+    assert_eq!(lines[0].1[8].x, 96.);
+    // the first point after a clear is always kept
+    t.clear();
+    t.push(Point2::new(5., 0.));
+    assert_eq!(t.len(), 1);
+}
+
+#[test]
+fn trail_resumes() {
+    let mut t = Trail::with_stride(10, 50., 3);
+    let push = |t: &mut Trail, from: usize, to: usize| {
+        for i in from..to {
+            t.push(Point2::new(i as f32 * 10., 0.));
+        }
+    };
+    push(&mut t, 0, 25);
+    let mut resumed = Trail::with_stride(10, 50., 3);
+    resumed.resume(&t.state());
+    assert_eq!(resumed.state(), t.state());
+    // the stride carries on where it left off
+    push(&mut t, 25, 40);
+    push(&mut resumed, 25, 40);
+    assert_eq!(resumed.state(), t.state());
+    // a smaller trail keeps the newest points
+    let mut short = Trail::new(4, 50.);
+    short.resume(&t.state());
+    assert_eq!(short.state().points[..], t.state().points[6..]);
+}